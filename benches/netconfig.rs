@@ -0,0 +1,61 @@
+// `cargo bench` tracks how long generating the configuration of a 10k address deployment takes
+// and the size of the scripts it produces. the throughput of the script benchmark is that size.
+
+use std::{hint::black_box, sync::Arc, time::Duration};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use oar_p2p::{
+    address_allocation_policy::AddressAllocationPolicy,
+    latency_matrix::LatencyMatrix,
+    machine::Machine,
+    netconfig::{self, MachineConfig},
+};
+
+const ADDRESSES: u32 = 10_000;
+const MACHINES: usize = 8;
+/// the matrix is wrapped, a 10k matrix alone would take more than a gigabyte
+const MATRIX_DIMENSION: usize = 1000;
+
+fn matrix() -> Arc<LatencyMatrix> {
+    Arc::new(LatencyMatrix::from_fn(MATRIX_DIMENSION, |row, col| {
+        Duration::from_millis(((row * 31 + col * 7) % 500) as u64)
+    }))
+}
+
+fn generate(matrix: &Arc<LatencyMatrix>) -> Vec<MachineConfig> {
+    let machines = Machine::all().take(MACHINES).collect::<Vec<_>>();
+    netconfig::machine_generate_configs(
+        matrix,
+        true,
+        &machines,
+        &AddressAllocationPolicy::Total(ADDRESSES),
+        &[],
+        false,
+    )
+    .unwrap()
+}
+
+/// size of the scripts that configure `config`, what `net up` sends to the machine.
+fn script_bytes(config: &MachineConfig) -> usize {
+    netconfig::net_plan(config, netconfig::DEFAULT_NFT_CHUNK_SIZE.get()).script_bytes
+}
+
+fn bench_netconfig(c: &mut Criterion) {
+    let matrix = matrix();
+    let mut group = c.benchmark_group("netconfig");
+    group.sample_size(10);
+
+    group.bench_function("generate_configs", |b| {
+        b.iter(|| generate(black_box(&matrix)))
+    });
+
+    let configs = generate(&matrix);
+    let config = &configs[0];
+    // criterion reports the size of the scripts as the throughput of the benchmark
+    group.throughput(Throughput::Bytes(script_bytes(config) as u64));
+    group.bench_function("scripts", |b| b.iter(|| script_bytes(black_box(config))));
+    group.finish();
+}
+
+criterion_group!(benches, bench_netconfig);
+criterion_main!(benches);
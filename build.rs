@@ -5,7 +5,7 @@ fn main() {
     println!("cargo:rerun-if-changed=.git/refs/tags");
 
     let output = Command::new("git")
-        .args(&["describe", "--tags", "--dirty", "--always"])
+        .args(["describe", "--tags", "--dirty", "--always"])
         .output()
         .unwrap();
     let git_version = String::from_utf8(output.stdout).unwrap();
@@ -3,6 +3,155 @@ pub enum AddressAllocationPolicy {
     PerCpu(u32),
     PerMachine(u32),
     Total(u32),
+    /// the addresses of the inner policy but never more than this many on a single machine
+    Capped(Box<AddressAllocationPolicy>, u32),
+}
+
+impl AddressAllocationPolicy {
+    /// number of addresses of each machine, given the number of cpus of each machine.
+    ///
+    /// a total is split proportionally to the cpus of each machine, the addresses left over by
+    /// rounding down go to the machines with the largest remainders, ties going to the first
+    /// machines.
+    pub fn addresses_per_machine(&self, cpus: &[u32]) -> Vec<u32> {
+        self.addresses_per_machine_weighted(cpus, &vec![1.0; cpus.len()])
+    }
+
+    /// same as [`Self::addresses_per_machine`] but the addresses of each machine are scaled by
+    /// its weight. a total is split proportionally to the cpus times the weight of each machine.
+    pub fn addresses_per_machine_weighted(&self, cpus: &[u32], weights: &[f64]) -> Vec<u32> {
+        // weights are kept in thousandths so the split stays exact integer arithmetic
+        let weights = weights
+            .iter()
+            .map(|w| (w * 1000.0).round() as u64)
+            .collect::<Vec<_>>();
+        let scale = |count: u64, weight: u64| ((count * weight + 500) / 1000) as u32;
+        match *self {
+            Self::PerCpu(n) => cpus
+                .iter()
+                .zip(&weights)
+                .map(|(&c, &w)| scale(u64::from(n) * u64::from(c), w))
+                .collect(),
+            Self::PerMachine(n) => weights.iter().map(|&w| scale(u64::from(n), w)).collect(),
+            Self::Total(n) => {
+                let capacities = cpus
+                    .iter()
+                    .zip(&weights)
+                    .map(|(&c, &w)| u64::from(c) * w)
+                    .collect::<Vec<_>>();
+                split_total(n, &capacities)
+            }
+            Self::Capped(ref policy, max) => match **policy {
+                // what a capped machine cannot take is split between the others
+                Self::Total(n) => {
+                    let capacities = cpus
+                        .iter()
+                        .zip(&weights)
+                        .map(|(&c, &w)| u64::from(c) * w)
+                        .collect::<Vec<_>>();
+                    let mut counts = vec![0; cpus.len()];
+                    let mut open = (0..cpus.len()).collect::<Vec<_>>();
+                    let mut remaining = n;
+                    while remaining > 0 && !open.is_empty() {
+                        let open_capacities =
+                            open.iter().map(|&i| capacities[i]).collect::<Vec<_>>();
+                        let shares = split_total(remaining, &open_capacities);
+                        let mut capped = false;
+                        for (&i, share) in open.iter().zip(shares) {
+                            let share = share.min(max - counts[i]);
+                            counts[i] += share;
+                            remaining -= share;
+                            capped |= counts[i] == max;
+                        }
+                        if !capped {
+                            break;
+                        }
+                        open.retain(|&i| counts[i] < max);
+                    }
+                    counts
+                }
+                ref policy => {
+                    let weights = weights
+                        .iter()
+                        .map(|&w| w as f64 / 1000.0)
+                        .collect::<Vec<_>>();
+                    policy
+                        .addresses_per_machine_weighted(cpus, &weights)
+                        .into_iter()
+                        .map(|c| c.min(max))
+                        .collect()
+                }
+            },
+        }
+    }
+}
+
+/// split `n` proportionally to `capacities` using the largest remainders.
+fn split_total(n: u32, capacities: &[u64]) -> Vec<u32> {
+    let total = capacities.iter().sum::<u64>();
+    if total == 0 {
+        return capacities.iter().map(|_| 0).collect();
+    }
+    let shares = capacities
+        .iter()
+        .map(|&c| u64::from(n) * c)
+        .collect::<Vec<_>>();
+    let mut counts = shares
+        .iter()
+        .map(|s| (s / total) as u32)
+        .collect::<Vec<_>>();
+    let mut order = (0..capacities.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| std::cmp::Reverse(shares[i] % total));
+    let leftover = n - counts.iter().sum::<u32>();
+    for &i in order.iter().take(leftover as usize) {
+        counts[i] += 1;
+    }
+    counts
+}
+
+/// scales the number of addresses of the machines it selects, see `net up --weight`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineWeight {
+    /// a hostname, like `gengar-1`, or a machine class, like `gengar`
+    pub selector: String,
+    pub weight: f64,
+}
+
+impl MachineWeight {
+    /// the weight of a machine, a hostname takes precedence over a class and the last matching
+    /// weight wins. machines without a weight have a weight of 1.
+    pub fn resolve(weights: &[MachineWeight], hostname: &str) -> f64 {
+        let class = hostname
+            .rsplit_once('-')
+            .map(|(class, _)| class)
+            .unwrap_or(hostname);
+        let find = |selector: &str| weights.iter().rev().find(|w| w.selector == selector);
+        find(hostname)
+            .or_else(|| find(class))
+            .map(|w| w.weight)
+            .unwrap_or(1.0)
+    }
+}
+
+impl std::str::FromStr for MachineWeight {
+    type Err = InvalidAddressAllocationPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = || {
+            InvalidAddressAllocationPolicy(format!(
+                "invalid weight '{s}', expected '<machine or class>=<weight>'"
+            ))
+        };
+        let (selector, weight) = s.split_once('=').ok_or_else(err_fn)?;
+        let weight = weight.parse::<f64>().map_err(|_| err_fn())?;
+        if selector.is_empty() || !weight.is_finite() || weight < 0.0 {
+            return Err(err_fn());
+        }
+        Ok(Self {
+            selector: selector.to_string(),
+            weight,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -27,7 +176,20 @@ impl std::str::FromStr for AddressAllocationPolicy {
     type Err = InvalidAddressAllocationPolicy;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some(n) = s.strip_suffix("/cpu") {
+        if let Some((policy, max)) = s.split_once(",max=") {
+            let max = max.strip_suffix("/machine").ok_or_else(|| {
+                InvalidAddressAllocationPolicy(format!(
+                    "invalid cap '{max}', expected '<n>/machine'"
+                ))
+            })?;
+            let policy = policy.parse::<Self>()?;
+            if matches!(policy, Self::Capped(..)) {
+                return Err(InvalidAddressAllocationPolicy(String::from(
+                    "only one cap can be given",
+                )));
+            }
+            Ok(Self::Capped(Box::new(policy), max.parse()?))
+        } else if let Some(n) = s.strip_suffix("/cpu") {
             Ok(Self::PerCpu(n.parse()?))
         } else if let Some(n) = s.strip_suffix("/machine") {
             Ok(Self::PerMachine(n.parse()?))
@@ -145,6 +307,114 @@ mod tests {
         assert!(AddressAllocationPolicy::from_str("18446744073709551616").is_err());
     }
 
+    #[test]
+    fn test_addresses_per_machine() {
+        let cpus = [16, 32, 16];
+        assert_eq!(
+            AddressAllocationPolicy::PerCpu(2).addresses_per_machine(&cpus),
+            vec![32, 64, 32]
+        );
+        assert_eq!(
+            AddressAllocationPolicy::PerMachine(5).addresses_per_machine(&cpus),
+            vec![5, 5, 5]
+        );
+        assert_eq!(
+            AddressAllocationPolicy::Total(64).addresses_per_machine(&cpus),
+            vec![16, 32, 16]
+        );
+        assert_eq!(
+            AddressAllocationPolicy::Total(10).addresses_per_machine(&cpus),
+            vec![3, 5, 2]
+        );
+        assert_eq!(
+            AddressAllocationPolicy::Total(3).addresses_per_machine(&[1, 1, 1, 1]),
+            vec![1, 1, 1, 0]
+        );
+        assert_eq!(
+            AddressAllocationPolicy::Total(7).addresses_per_machine(&[]),
+            Vec::<u32>::new()
+        );
+    }
+
+    #[test]
+    fn test_capped_parsing() {
+        assert_eq!(
+            AddressAllocationPolicy::from_str("4/cpu,max=32/machine").unwrap(),
+            AddressAllocationPolicy::Capped(Box::new(AddressAllocationPolicy::PerCpu(4)), 32)
+        );
+        assert_eq!(
+            AddressAllocationPolicy::from_str("100,max=10/machine").unwrap(),
+            AddressAllocationPolicy::Capped(Box::new(AddressAllocationPolicy::Total(100)), 10)
+        );
+        assert!(AddressAllocationPolicy::from_str("4/cpu,max=32").is_err());
+        assert!(AddressAllocationPolicy::from_str("4/cpu,max=32/cpu").is_err());
+        assert!(AddressAllocationPolicy::from_str("4/cpu,max=1/machine,max=2/machine").is_err());
+    }
+
+    #[test]
+    fn test_capped_addresses_per_machine() {
+        let cpus = [4, 32, 32];
+        let capped = |policy, max| AddressAllocationPolicy::Capped(Box::new(policy), max);
+        assert_eq!(
+            capped(AddressAllocationPolicy::PerCpu(4), 64).addresses_per_machine(&cpus),
+            vec![16, 64, 64]
+        );
+        assert_eq!(
+            capped(AddressAllocationPolicy::Total(68), 10).addresses_per_machine(&cpus),
+            vec![10, 10, 10]
+        );
+        // the share the small machine cannot take goes to the others
+        assert_eq!(
+            capped(AddressAllocationPolicy::Total(40), 2).addresses_per_machine(&[1, 1, 2]),
+            vec![2, 2, 2]
+        );
+        assert_eq!(
+            capped(AddressAllocationPolicy::Total(20), 4).addresses_per_machine(&[16, 8, 8]),
+            vec![4, 4, 4]
+        );
+        assert_eq!(
+            capped(AddressAllocationPolicy::Total(12), 5).addresses_per_machine(&[8, 2, 2]),
+            vec![5, 4, 3]
+        );
+    }
+
+    #[test]
+    fn test_weighted_addresses_per_machine() {
+        let cpus = [16, 16, 32];
+        let weights = [0.5, 1.0, 1.0];
+        assert_eq!(
+            AddressAllocationPolicy::PerCpu(2).addresses_per_machine_weighted(&cpus, &weights),
+            vec![16, 32, 64]
+        );
+        assert_eq!(
+            AddressAllocationPolicy::PerMachine(5).addresses_per_machine_weighted(&cpus, &weights),
+            vec![3, 5, 5]
+        );
+        assert_eq!(
+            AddressAllocationPolicy::Total(56).addresses_per_machine_weighted(&cpus, &weights),
+            vec![8, 16, 32]
+        );
+        assert_eq!(
+            AddressAllocationPolicy::Total(10).addresses_per_machine_weighted(&cpus, &[0.0; 3]),
+            vec![0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_machine_weight() {
+        let weights = ["gengar=0.5", "gengar-2=2", "oddish=0.25"]
+            .iter()
+            .map(|w| w.parse::<MachineWeight>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(MachineWeight::resolve(&weights, "gengar-1"), 0.5);
+        assert_eq!(MachineWeight::resolve(&weights, "gengar-2"), 2.0);
+        assert_eq!(MachineWeight::resolve(&weights, "oddish-1"), 0.25);
+        assert_eq!(MachineWeight::resolve(&weights, "charmander-1"), 1.0);
+        assert!("gengar".parse::<MachineWeight>().is_err());
+        assert!("gengar=-1".parse::<MachineWeight>().is_err());
+        assert!("=1".parse::<MachineWeight>().is_err());
+    }
+
     #[test]
     fn test_whitespace_handling() {
         assert!(AddressAllocationPolicy::from_str(" 10/cpu").is_err());
@@ -0,0 +1,406 @@
+use std::{
+    collections::HashMap,
+    os::unix::process::ExitStatusExt as _,
+    process::{ExitStatus, Output, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use eyre::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    process::{Child, ChildStdin, Command},
+    sync::{OnceCell, oneshot},
+};
+
+use crate::{machine::Machine, runtime::ContainerRuntime};
+
+/// name of the networking container kept running by the agent, network scripts are executed in it
+/// instead of in a new container every time.
+pub const CONTAINER_NAME: &str = "oar-p2p-agent";
+
+const IMAGE: &str = "local/oar-p2p-networking";
+
+/// path, on the machines, of the agent binary with the given fingerprint. a different build is
+/// copied to a different path.
+pub fn remote_path(fingerprint: &str) -> String {
+    format!("/tmp/oar-p2p-agent-{fingerprint}")
+}
+
+/// a script to execute, sent as a json line on the agent's stdin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    pub id: u64,
+    pub script: String,
+    /// execute the script in the networking container instead of on the machine
+    #[serde(default)]
+    pub net: bool,
+}
+
+/// the outcome of a [`Request`], sent as a json line on the agent's stdout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Response {
+    pub id: u64,
+    /// the exit code, or -1 if the script was killed by a signal
+    pub code: i32,
+    #[serde(with = "base64_bytes")]
+    pub stdout: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    pub stderr: Vec<u8>,
+}
+
+/// the output of a script is not necessarily utf-8, it is sent as base64 so the bytes are the same
+/// as when executing the script over ssh.
+mod base64_bytes {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+    use serde::{Deserialize as _, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Response {
+    fn new(id: u64, output: &Output) -> Self {
+        Self {
+            id,
+            code: output.status.code().unwrap_or(-1),
+            stdout: output.stdout.clone(),
+            stderr: output.stderr.clone(),
+        }
+    }
+
+    fn into_output(self) -> Output {
+        let status = match self.code {
+            code if code >= 0 => ExitStatus::from_raw(code << 8),
+            // the signal is not known, report it as killed
+            _ => ExitStatus::from_raw(9),
+        };
+        Output {
+            status,
+            stdout: self.stdout,
+            stderr: self.stderr,
+        }
+    }
+}
+
+/// run the agent, executing the requests read from stdin concurrently until stdin is closed.
+pub async fn serve(runtime: ContainerRuntime) -> Result<()> {
+    let stdout = Arc::new(tokio::sync::Mutex::new(tokio::io::stdout()));
+    let container = Arc::new(tokio::sync::Mutex::new(false));
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut tasks = tokio::task::JoinSet::new();
+    while let Some(line) = lines.next_line().await.context("reading request")? {
+        let request = serde_json::from_str::<Request>(&line).context("parsing request")?;
+        let stdout = stdout.clone();
+        let container = container.clone();
+        tasks.spawn(async move {
+            let output = execute(runtime, &container, &request).await;
+            let response = match output {
+                Ok(output) => Response::new(request.id, &output),
+                Err(err) => Response {
+                    id: request.id,
+                    code: 1,
+                    stdout: Vec::default(),
+                    stderr: format!("agent: {err:#}").into_bytes(),
+                },
+            };
+            let mut line = serde_json::to_string(&response)?;
+            line.push('\n');
+            let mut stdout = stdout.lock().await;
+            stdout.write_all(line.as_bytes()).await?;
+            stdout.flush().await?;
+            Ok::<_, eyre::Report>(())
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        result??;
+    }
+    if *container.lock().await {
+        let _ = Command::new(runtime.command())
+            .args(["rm", "-f", CONTAINER_NAME])
+            .output()
+            .await;
+    }
+    Ok(())
+}
+
+async fn execute(
+    runtime: ContainerRuntime,
+    container: &tokio::sync::Mutex<bool>,
+    request: &Request,
+) -> Result<Output> {
+    if !request.net {
+        return run(Command::new("bash"), &request.script).await;
+    }
+    for attempt in 0..2 {
+        {
+            let mut started = container.lock().await;
+            if !*started {
+                *started = start_container(runtime).await?;
+            }
+        }
+        let mut command = Command::new(runtime.command());
+        command.args(["exec", "-i", CONTAINER_NAME, "sh"]);
+        let output = run(command, &request.script).await?;
+        // the container is removed when the networking image is rebuilt
+        if attempt == 0
+            && !output.status.success()
+            && String::from_utf8_lossy(&output.stderr)
+                .to_lowercase()
+                .contains("no such container")
+        {
+            *container.lock().await = false;
+            continue;
+        }
+        return Ok(output);
+    }
+    unreachable!("the second attempt always returns")
+}
+
+async fn start_container(runtime: ContainerRuntime) -> Result<bool> {
+    let _ = Command::new(runtime.command())
+        .args(["rm", "-f", CONTAINER_NAME])
+        .output()
+        .await;
+    let output = Command::new(runtime.command())
+        .args([
+            "run",
+            "-d",
+            "--name",
+            CONTAINER_NAME,
+            "--net=host",
+            "--privileged",
+        ])
+        .args([IMAGE, "sleep", "infinity"])
+        .output()
+        .await
+        .context("starting networking container")?;
+    output
+        .status
+        .exit_ok()
+        .map_err(|_| eyre::eyre!("{}", String::from_utf8_lossy(&output.stderr).trim()))
+        .context("starting networking container")?;
+    Ok(true)
+}
+
+async fn run(mut command: Command, script: &str) -> Result<Output> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("spawning process")?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    stdin
+        .write_all(script.as_bytes())
+        .await
+        .context("writing stdin")?;
+    drop(stdin);
+    child
+        .wait_with_output()
+        .await
+        .context("waiting for process to exit")
+}
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Response>>>>;
+
+/// the agent's process exited or its connection dropped, it cannot run scripts anymore.
+#[derive(Debug, Error)]
+#[error("agent exited: {reason}")]
+pub struct AgentExited {
+    pub reason: String,
+}
+
+/// the connection to an agent running on a machine, requests can be sent concurrently.
+#[derive(Debug)]
+pub struct AgentClient {
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    pending: Pending,
+    next_id: AtomicU64,
+    /// killed when the client is dropped, which closes the agent's stdin
+    _child: Child,
+}
+
+impl AgentClient {
+    /// `child` is the agent process, usually an ssh command, with piped stdin and stdout.
+    pub fn new(mut child: Child) -> Self {
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let pending = Pending::default();
+        tokio::spawn({
+            let pending = pending.clone();
+            async move {
+                let mut lines = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    match serde_json::from_str::<Response>(&line) {
+                        Ok(response) => {
+                            let sender = pending.lock().unwrap().remove(&response.id);
+                            if let Some(sender) = sender {
+                                let _ = sender.send(response);
+                            }
+                        }
+                        Err(err) => tracing::warn!("invalid agent response: {err}"),
+                    }
+                }
+                // the agent exited, the requests still waiting fail
+                pending.lock().unwrap().clear();
+            }
+        });
+        Self {
+            stdin: tokio::sync::Mutex::new(stdin),
+            pending,
+            next_id: AtomicU64::new(0),
+            _child: child,
+        }
+    }
+
+    /// execute `script` on the machine, or in its networking container if `net` is set.
+    pub async fn run(&self, script: &str, net: bool) -> Result<Output> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        let request = Request {
+            id,
+            script: script.to_string(),
+            net,
+        };
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        {
+            let mut stdin = self.stdin.lock().await;
+            let sent = match stdin.write_all(line.as_bytes()).await {
+                Ok(()) => stdin.flush().await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = sent {
+                self.pending.lock().unwrap().remove(&id);
+                return Err(AgentExited {
+                    reason: format!("sending request: {err}"),
+                }
+                .into());
+            }
+        }
+        let response = receiver.await.map_err(|_| AgentExited {
+            reason: String::from("no answer"),
+        })?;
+        Ok(response.into_output())
+    }
+}
+
+/// the agent of a machine once started, `None` if it failed to start.
+type AgentCell = OnceCell<Option<Arc<AgentClient>>>;
+
+/// the agents of a context, started the first time a script is sent to a machine.
+#[derive(Debug, Default)]
+pub struct Agents {
+    clients: Mutex<HashMap<Machine, Arc<AgentCell>>>,
+}
+
+impl Agents {
+    /// the agent of `machine`, running `start` if this is the first request for it. `None` if the
+    /// agent could not be started, the scripts are then executed over ssh.
+    pub async fn get_or_start<F>(&self, machine: Machine, start: F) -> Option<Arc<AgentClient>>
+    where
+        F: std::future::Future<Output = Option<AgentClient>>,
+    {
+        let cell = self
+            .clients
+            .lock()
+            .unwrap()
+            .entry(machine)
+            .or_default()
+            .clone();
+        cell.get_or_init(|| async { start.await.map(Arc::new) })
+            .await
+            .clone()
+    }
+
+    /// stop using the agent of `machine`, after it exited, so its scripts are executed over ssh.
+    pub fn disable(&self, machine: Machine) {
+        self.clients
+            .lock()
+            .unwrap()
+            .insert(machine, Arc::new(OnceCell::new_with(Some(None))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_output() {
+        let response = Response {
+            id: 3,
+            code: 2,
+            stdout: b"out".to_vec(),
+            stderr: Vec::default(),
+        };
+        let output = response.clone().into_output();
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(output.stdout, b"out");
+        assert_eq!(Response::new(3, &output), response);
+
+        let killed = Response {
+            code: -1,
+            ..response
+        };
+        assert_eq!(killed.into_output().status.code(), None);
+    }
+
+    #[test]
+    fn test_response_non_utf8_roundtrip() {
+        let response = Response {
+            id: 1,
+            code: 0,
+            stdout: vec![0x66, 0xff, 0x00, 0xc3, 0x28],
+            stderr: vec![0x80],
+        };
+        let line = serde_json::to_string(&response).unwrap();
+        let parsed = serde_json::from_str::<Response>(&line).unwrap();
+        assert_eq!(parsed, response);
+        let output = parsed.into_output();
+        assert_eq!(output.stdout, [0x66, 0xff, 0x00, 0xc3, 0x28]);
+        assert_eq!(output.stderr, [0x80]);
+    }
+
+    #[tokio::test]
+    async fn test_agents_disable() {
+        let agents = Agents::default();
+        agents.disable(Machine::Gengar1);
+        let agent = agents
+            .get_or_start(Machine::Gengar1, async {
+                unreachable!("a disabled agent is not started again")
+            })
+            .await;
+        assert!(agent.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_client_roundtrip() {
+        // a fake agent that answers every request with its id
+        let script = r#"while read -r line ; do id=$(echo "$line" | sed 's/.*"id":\([0-9]*\).*/\1/') ; out=$(printf 'ok %s' "$id" | base64) ; echo "{\"id\":$id,\"code\":0,\"stdout\":\"$out\",\"stderr\":\"\"}" ; done"#;
+        let child = Command::new("bash")
+            .args(["-c", script])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let client = AgentClient::new(child);
+        let (a, b) = tokio::join!(client.run("true", false), client.run("true", true));
+        assert_eq!(a.unwrap().stdout, b"ok 0");
+        assert_eq!(b.unwrap().stdout, b"ok 1");
+    }
+}
@@ -0,0 +1,209 @@
+use std::{net::Ipv4Addr, str::FromStr};
+
+use serde::Serialize;
+
+use crate::runtime::ContainerRuntime;
+
+/// rate of the htb classes created by `net up`, in megabits per second.
+pub const HTB_RATE_MBITS: u64 = 10_000;
+
+/// port of the iperf3 server of the first pair, with `--parallel` the following pairs use the
+/// next ports.
+pub const BASE_PORT: u16 = 5201;
+
+/// a throughput above this fraction of the interface speed means the interface is the bottleneck.
+pub const SATURATION_FRACTION: f64 = 0.9;
+
+const IMAGE: &str = "local/oar-p2p-networking:latest";
+
+/// two addresses to measure the throughput between, traffic goes from `src` to `dst`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchPair {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid pair '{0}', expected two addresses like '10.16.0.1-10.17.0.1'")]
+pub struct InvalidBenchPair(String);
+
+impl FromStr for BenchPair {
+    type Err = InvalidBenchPair;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = || InvalidBenchPair(s.to_string());
+        let (src, dst) = s.trim().split_once('-').ok_or_else(err_fn)?;
+        Ok(Self {
+            src: src.parse().map_err(|_| err_fn())?,
+            dst: dst.parse().map_err(|_| err_fn())?,
+        })
+    }
+}
+
+/// the outcome of a single iperf3 client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Throughput {
+    /// bits per second received by the server
+    pub bits_per_second: f64,
+    pub retransmits: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidIperfOutput {
+    #[error("iperf3 failed: {0}")]
+    Failed(String),
+    #[error("invalid iperf3 output: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("iperf3 output has no received throughput")]
+    MissingThroughput,
+}
+
+/// parse the `--json` output of an iperf3 client.
+pub fn parse_iperf_output(output: &str) -> Result<Throughput, InvalidIperfOutput> {
+    let json = serde_json::from_str::<serde_json::Value>(output)?;
+    if let Some(error) = json["error"].as_str() {
+        return Err(InvalidIperfOutput::Failed(error.to_string()));
+    }
+    let end = &json["end"];
+    Ok(Throughput {
+        bits_per_second: end["sum_received"]["bits_per_second"]
+            .as_f64()
+            .ok_or(InvalidIperfOutput::MissingThroughput)?,
+        retransmits: end["sum_sent"]["retransmits"].as_u64().unwrap_or(0),
+    })
+}
+
+/// script that starts an iperf3 server for a single client on `address`.
+pub fn server_script(runtime: ContainerRuntime, address: Ipv4Addr, port: u16) -> String {
+    let name = format!("oar-p2p-bench-{port}");
+    let mut script = String::default();
+    script.push_str(&format!("{runtime} rm -f {name} >/dev/null 2>&1 || true\n"));
+    script.push_str(&format!(
+        "{runtime} run -d --rm --name {name} --net=host {IMAGE} iperf3 --server --one-off --bind {address} --port {port} >/dev/null\n"
+    ));
+    script
+}
+
+/// script that measures the throughput from `pair.src` to the server on `pair.dst` and prints
+/// the json output of iperf3.
+///
+/// the client retries for a few seconds since the server may not be listening yet.
+pub fn client_script(
+    runtime: ContainerRuntime,
+    pair: BenchPair,
+    port: u16,
+    duration: u64,
+    streams: u32,
+) -> String {
+    let BenchPair { src, dst } = pair;
+    let mut script = String::default();
+    script.push_str("for attempt in $(seq 10) ; do\n");
+    script.push_str(&format!(
+        "\toutput=$({runtime} run --rm --net=host {IMAGE} iperf3 --json --client {dst} --bind {src} --port {port} --time {duration} --parallel {streams})\n"
+    ));
+    script.push_str("\tif echo \"$output\" | grep -q '\"sum_received\"' ; then break ; fi\n");
+    script.push_str("\tsleep 1\n");
+    script.push_str("done\n");
+    script.push_str("echo \"$output\"\n");
+    script
+}
+
+/// script that prints the speed of the interface in megabits per second, or -1 when unknown.
+pub fn interface_speed_script(interface: &str) -> String {
+    format!("cat /sys/class/net/{interface}/speed 2>/dev/null || echo -1\n")
+}
+
+/// a line of the `net bench` report.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    /// megabits per second
+    pub throughput: f64,
+    pub retransmits: u64,
+    /// the throughput the emulation should allow, the htb rate or the interface speed if lower,
+    /// in megabits per second. unknown for pairs on the same machine
+    pub expected: Option<f64>,
+    /// whether the throughput is close to the speed of the interface of either machine
+    pub saturated: bool,
+}
+
+impl BenchReport {
+    /// `interface_speeds` are the speeds, in megabits per second, of the interfaces of the source
+    /// and destination machines when the pair spans two machines.
+    pub fn new(
+        pair: BenchPair,
+        throughput: Throughput,
+        interface_speeds: Option<(Option<u64>, Option<u64>)>,
+    ) -> Self {
+        let throughput_mbits = throughput.bits_per_second / 1e6;
+        let slowest_interface = interface_speeds.and_then(|(a, b)| match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        });
+        let expected = interface_speeds.map(|_| match slowest_interface {
+            Some(speed) => speed.min(HTB_RATE_MBITS) as f64,
+            None => HTB_RATE_MBITS as f64,
+        });
+        let saturated = slowest_interface
+            .is_some_and(|speed| throughput_mbits >= speed as f64 * SATURATION_FRACTION);
+        Self {
+            src: pair.src,
+            dst: pair.dst,
+            throughput: throughput_mbits,
+            retransmits: throughput.retransmits,
+            expected,
+            saturated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pair() {
+        let pair = "10.16.0.1-10.17.0.2".parse::<BenchPair>().unwrap();
+        assert_eq!(pair.src, Ipv4Addr::new(10, 16, 0, 1));
+        assert_eq!(pair.dst, Ipv4Addr::new(10, 17, 0, 2));
+        assert!("10.16.0.1".parse::<BenchPair>().is_err());
+        assert!("10.16.0.1-gengar-1".parse::<BenchPair>().is_err());
+    }
+
+    #[test]
+    fn test_parse_iperf_output() {
+        let output = r#"{"start": {}, "end": {"sum_sent": {"bits_per_second": 9.5e8, "retransmits": 12}, "sum_received": {"bits_per_second": 9.4e8}}}"#;
+        let throughput = parse_iperf_output(output).unwrap();
+        assert_eq!(throughput.bits_per_second, 9.4e8);
+        assert_eq!(throughput.retransmits, 12);
+
+        let output = r#"{"start": {}, "end": {}, "error": "unable to connect to server"}"#;
+        assert!(matches!(
+            parse_iperf_output(output),
+            Err(InvalidIperfOutput::Failed(_))
+        ));
+        assert!(parse_iperf_output("").is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let pair = "10.16.0.1-10.17.0.1".parse::<BenchPair>().unwrap();
+        let throughput = Throughput {
+            bits_per_second: 9.6e8,
+            retransmits: 0,
+        };
+        let report = BenchReport::new(pair, throughput, Some((Some(1000), Some(10_000))));
+        assert_eq!(report.throughput, 960.0);
+        assert_eq!(report.expected, Some(1000.0));
+        assert!(report.saturated);
+
+        let report = BenchReport::new(pair, throughput, Some((None, None)));
+        assert_eq!(report.expected, Some(HTB_RATE_MBITS as f64));
+        assert!(!report.saturated);
+
+        let report = BenchReport::new(pair, throughput, None);
+        assert_eq!(report.expected, None);
+        assert!(!report.saturated);
+    }
+}
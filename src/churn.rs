@@ -0,0 +1,179 @@
+use std::{str::FromStr, time::Duration};
+
+use serde::Serialize;
+
+use crate::{placement::SplitMix64, runtime::ContainerRuntime};
+
+/// directory on the machines with a marker file for every container stopped by the churn, the
+/// wait script keeps waiting for containers that have a marker.
+pub const CHURN_MARKER_DIR: &str = "/tmp/oar-p2p-churn";
+
+/// name of the file, in the output directory, with the churn events.
+pub const CHURN_EVENTS_FILE_NAME: &str = "churn.jsonl";
+
+const DEFAULT_DOWNTIME: Duration = Duration::from_secs(30);
+
+/// how containers are stopped and restarted during a run, see `run --churn`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChurnSpec {
+    /// average number of containers stopped per second
+    pub rate: f64,
+    /// seed of the random generator
+    pub seed: u64,
+    /// how long a container stays stopped before it is restarted
+    pub downtime: Duration,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "invalid churn '{0}', expected comma separated options like 'rate=0.1/min,seed=42,downtime=30'"
+)]
+pub struct InvalidChurnSpec(String);
+
+impl FromStr for ChurnSpec {
+    type Err = InvalidChurnSpec;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = || InvalidChurnSpec(s.to_string());
+        let mut rate = None;
+        let mut seed = 0;
+        let mut downtime = DEFAULT_DOWNTIME;
+        for option in s.split(',') {
+            let (key, value) = option.split_once('=').ok_or_else(err_fn)?;
+            let value = value.trim();
+            match key.trim() {
+                "rate" => {
+                    let (count, unit) = value.split_once('/').unwrap_or((value, "s"));
+                    let count = count.trim().parse::<f64>().map_err(|_| err_fn())?;
+                    let seconds = match unit.trim() {
+                        "s" | "sec" => 1.0,
+                        "m" | "min" => 60.0,
+                        "h" | "hour" => 3600.0,
+                        _ => return Err(err_fn()),
+                    };
+                    if !count.is_finite() || count <= 0.0 {
+                        return Err(err_fn());
+                    }
+                    rate = Some(count / seconds);
+                }
+                "seed" => seed = value.parse().map_err(|_| err_fn())?,
+                "downtime" => downtime = Duration::from_secs(value.parse().map_err(|_| err_fn())?),
+                _ => return Err(err_fn()),
+            }
+        }
+        Ok(Self {
+            rate: rate.ok_or_else(err_fn)?,
+            seed,
+            downtime,
+        })
+    }
+}
+
+/// the random process that decides when and which containers are stopped.
+///
+/// stops follow a poisson process with the spec's rate, the same seed always produces the same
+/// sequence of intervals and choices.
+pub struct ChurnProcess {
+    rate: f64,
+    rng: SplitMix64,
+}
+
+impl ChurnProcess {
+    pub fn new(spec: &ChurnSpec) -> Self {
+        Self {
+            rate: spec.rate,
+            rng: SplitMix64(spec.seed),
+        }
+    }
+
+    /// time until the next container is stopped.
+    pub fn next_interval(&mut self) -> Duration {
+        // uniform in (0, 1] so the logarithm is finite
+        let uniform = ((self.rng.next() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+        Duration::from_secs_f64(-uniform.ln() / self.rate)
+    }
+
+    /// index of the container to stop out of `count` candidates.
+    pub fn choose(&mut self, count: usize) -> usize {
+        (self.rng.next() % count as u64) as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChurnAction {
+    Stop,
+    Start,
+}
+
+/// a line of the churn events file.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChurnEvent {
+    /// unix timestamp, in milliseconds
+    pub timestamp: u64,
+    pub container: String,
+    pub action: ChurnAction,
+}
+
+/// script that stops a container if it is still running and prints `stopped` if it did.
+///
+/// the marker is created before stopping so the wait script never sees the container exit
+/// without it.
+pub fn stop_script(runtime: ContainerRuntime, name: &str) -> String {
+    let marker = format!("{CHURN_MARKER_DIR}/{name}");
+    let mut script = String::default();
+    script.push_str(&format!("mkdir -p {CHURN_MARKER_DIR}\n"));
+    script.push_str(&format!("touch {marker}\n"));
+    script.push_str(&format!(
+        "if [ \"$({runtime} inspect -f '{{{{.State.Running}}}}' {name})\" != \"true\" ] ; then\n"
+    ));
+    script.push_str(&format!("\trm -f {marker}\n"));
+    script.push_str("\texit 0\n");
+    script.push_str("fi\n");
+    script.push_str(&format!("{runtime} stop {name} >/dev/null\n"));
+    script.push_str("echo stopped\n");
+    script
+}
+
+/// script that restarts a container stopped by [`stop_script`].
+pub fn start_script(runtime: ContainerRuntime, name: &str) -> String {
+    format!("{runtime} start {name} >/dev/null\nrm -f {CHURN_MARKER_DIR}/{name}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let spec = "rate=0.1/min,seed=42".parse::<ChurnSpec>().unwrap();
+        assert!((spec.rate - 0.1 / 60.0).abs() < 1e-12);
+        assert_eq!(spec.seed, 42);
+        assert_eq!(spec.downtime, DEFAULT_DOWNTIME);
+
+        let spec = "rate=2,downtime=5".parse::<ChurnSpec>().unwrap();
+        assert_eq!(spec.rate, 2.0);
+        assert_eq!(spec.downtime, Duration::from_secs(5));
+
+        assert!("seed=42".parse::<ChurnSpec>().is_err());
+        assert!("rate=0/min".parse::<ChurnSpec>().is_err());
+        assert!("rate=1/day".parse::<ChurnSpec>().is_err());
+        assert!("rate=1,colour=red".parse::<ChurnSpec>().is_err());
+    }
+
+    #[test]
+    fn test_process_is_reproducible() {
+        let spec = "rate=1/s,seed=7".parse::<ChurnSpec>().unwrap();
+        let sample = |mut process: ChurnProcess| {
+            (0..16)
+                .map(|_| (process.next_interval(), process.choose(5)))
+                .collect::<Vec<_>>()
+        };
+        let a = sample(ChurnProcess::new(&spec));
+        assert_eq!(a, sample(ChurnProcess::new(&spec)));
+        assert!(
+            a.iter()
+                .all(|(interval, choice)| interval.as_secs_f64().is_finite() && *choice < 5)
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use clap_complete::{CompletionCandidate, env::Shells};
+
+use crate::machine::Machine;
+
+/// environment variable the completion scripts set when they ask oar-p2p for the completions of
+/// a command line, its value is the shell.
+pub const ENV: &str = "COMPLETE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+        }
+    }
+}
+
+/// the script that registers the completions of `bin` with `shell`.
+///
+/// the script does not contain the completions, it runs `completer` with `ENV` set for every
+/// command line, so values that change, like the running jobs, are completed too.
+pub fn registration(bin: &str, shell: Shell, completer: &str) -> String {
+    let shells = Shells::builtins();
+    let shell = shells
+        .completer(shell.name())
+        .expect("builtin shells include bash, zsh and fish");
+    let mut script = Vec::default();
+    shell
+        .write_registration(ENV, bin, bin, completer, &mut script)
+        .expect("writing to a vec does not fail");
+    String::from_utf8(script).expect("registration scripts are utf-8")
+}
+
+/// the hostnames of the machines, what `--machine` is completed with.
+pub fn machines() -> Vec<CompletionCandidate> {
+    Machine::all()
+        .map(|machine| CompletionCandidate::new(machine.hostname()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete(args: &[&str]) -> Vec<String> {
+        let mut command = clap::Command::new("oar-p2p").subcommand(
+            clap::Command::new("gc").arg(
+                clap::Arg::new("machine")
+                    .long("machine")
+                    .add(clap_complete::ArgValueCandidates::new(machines)),
+            ),
+        );
+        let args = args.iter().map(Into::into).collect::<Vec<_>>();
+        let index = args.len() - 1;
+        clap_complete::engine::complete(&mut command, args, index, None)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| candidate.get_value().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_machines() {
+        let candidates = complete(&["oar-p2p", "gc", "--machine", "gengar-"]);
+        assert!(candidates.contains(&String::from("gengar-1")));
+        assert!(candidates.iter().all(|c| c.starts_with("gengar-")));
+    }
+
+    #[test]
+    fn test_registration() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = registration("oar-p2p", shell, "/usr/bin/oar-p2p");
+            assert!(script.contains("COMPLETE"), "{shell:?}");
+            assert!(script.contains("/usr/bin/oar-p2p"), "{shell:?}");
+        }
+    }
+}
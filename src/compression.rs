@@ -0,0 +1,25 @@
+/// compression used to stream the logs from the machines.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    #[default]
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// command that compresses stdin into stdout, executed on the machine.
+    pub fn compress_command(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip -c",
+            Compression::Zstd => "zstd -c -q",
+        }
+    }
+
+    /// flag given to the local `tar` to decompress the stream.
+    pub fn tar_flag(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "--gzip",
+            Compression::Zstd => "--zstd",
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// environment variable with the path of the configuration file, replacing the default one.
+pub const CONFIG_ENV: &str = "OAR_P2P_CONFIG";
+
+/// environment variable with the profile to use, like `--profile`.
+pub const PROFILE_ENV: &str = "OAR_P2P_PROFILE";
+
+#[derive(Debug, Error)]
+#[error("{path}: {message}")]
+pub struct InvalidConfig {
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown profile '{name}', the configuration file defines: {available}")]
+pub struct UnknownProfile {
+    pub name: String,
+    pub available: String,
+}
+
+/// the user's configuration file, with defaults for the flags that are the same in every
+/// invocation. command line flags and environment variables take precedence over it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// default of `--frontend-hostname`
+    pub frontend_hostname: Option<String>,
+    /// default of `--cluster-username`
+    pub cluster_username: Option<String>,
+    /// default of `--oar-api-url`
+    pub oar_api_url: Option<String>,
+    /// default of `--runtime`
+    pub runtime: Option<String>,
+    /// default of `--ssh-option`
+    #[serde(default)]
+    pub ssh_options: Vec<String>,
+    /// maximum number of machines a command operates on at the same time, 0 for no limit. the
+    /// `OAR_P2P_CONCURRENCY_LIMIT` environment variable takes precedence.
+    pub concurrency_limit: Option<usize>,
+    /// default of `net up --addresses`
+    pub addresses: Option<String>,
+    /// default of `run --placement`
+    pub placement: Option<String>,
+    /// default of `run --output-dir`, placeholders included
+    pub output_dir: Option<String>,
+    /// named profiles, selected with `--profile`
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
+}
+
+/// an experiment setup, its values take precedence over the rest of the configuration file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// default of `--latency-matrix`
+    pub latency_matrix: Option<String>,
+    /// default of `net up --addresses`
+    pub addresses: Option<String>,
+    /// default of `run --placement`
+    pub placement: Option<String>,
+    /// default schedule of `run`
+    pub schedule: Option<String>,
+    /// default of `run --output-dir`
+    pub output_dir: Option<String>,
+}
+
+/// the default value of a flag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgDefault {
+    /// the subcommand the flag belongs to, like `net up`, or `None` for every subcommand that
+    /// has it
+    pub command: Option<&'static str>,
+    /// the flag's id, its field name
+    pub arg: &'static str,
+    pub values: Vec<String>,
+}
+
+/// the path of the configuration file: `$OAR_P2P_CONFIG`, or `oar-p2p/config.toml` under
+/// `$XDG_CONFIG_HOME` or `~/.config`.
+pub fn path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(base) if !base.is_empty() => PathBuf::from(base),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("oar-p2p").join("config.toml"))
+}
+
+/// the value of `--profile` in the command line arguments, found without parsing them since the
+/// profile changes the defaults the parser needs.
+pub fn profile_argument(args: impl IntoIterator<Item = String>) -> Option<String> {
+    let mut args = args.into_iter();
+    let mut profile = None;
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--profile" {
+            profile = args.next();
+        } else if let Some(value) = arg.strip_prefix("--profile=") {
+            profile = Some(value.to_string());
+        }
+    }
+    profile
+}
+
+/// load the configuration file at `path`. a missing file is an empty configuration.
+pub fn load(path: &Path) -> Result<Config, InvalidConfig> {
+    let err_fn = |message: String| InvalidConfig {
+        path: path.display().to_string(),
+        message,
+    };
+    match std::fs::read_to_string(path) {
+        Ok(content) => parse(&content).map_err(err_fn),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(err_fn(err.to_string())),
+    }
+}
+
+fn parse(content: &str) -> Result<Config, String> {
+    toml::from_str(content).map_err(|err| toml_error_message(content, &err))
+}
+
+/// the message of a toml error with the line and column of `content` it points at, if any.
+pub fn toml_error_message(content: &str, err: &toml::de::Error) -> String {
+    let Some(span) = err.span() else {
+        return err.message().to_string();
+    };
+    let before = content.get(..span.start).unwrap_or(content);
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    format!("line {line}, column {column}: {}", err.message())
+}
+
+impl Config {
+    /// the flag defaults set by the configuration and by `profile`, if one is selected.
+    pub fn defaults(&self, profile: Option<&str>) -> Result<Vec<ArgDefault>, UnknownProfile> {
+        let profile = match profile {
+            Some(name) => Some(self.profile.get(name).ok_or_else(|| UnknownProfile {
+                name: name.to_string(),
+                available: if self.profile.is_empty() {
+                    String::from("none")
+                } else {
+                    self.profile.keys().cloned().collect::<Vec<_>>().join(", ")
+                },
+            })?),
+            None => None,
+        };
+        let mut defaults = Vec::default();
+        let mut push = |command, arg, values: Vec<String>| {
+            if !values.is_empty() {
+                defaults.push(ArgDefault {
+                    command,
+                    arg,
+                    values,
+                });
+            }
+        };
+        let one = |value: &Option<String>| value.iter().cloned().collect::<Vec<_>>();
+        push(None, "frontend_hostname", one(&self.frontend_hostname));
+        push(None, "cluster_username", one(&self.cluster_username));
+        push(None, "oar_api_url", one(&self.oar_api_url));
+        push(None, "runtime", one(&self.runtime));
+        push(None, "ssh_option", self.ssh_options.clone());
+        push(Some("net up"), "addresses", one(&self.addresses));
+        push(Some("run"), "placement", one(&self.placement));
+        push(Some("run"), "output_dir", one(&self.output_dir));
+        // pushed last so they replace the ones above
+        if let Some(profile) = profile {
+            push(None, "latency_matrix", one(&profile.latency_matrix));
+            push(Some("net up"), "addresses", one(&profile.addresses));
+            push(Some("run"), "placement", one(&profile.placement));
+            push(Some("run"), "schedule", one(&profile.schedule));
+            push(Some("run"), "output_dir", one(&profile.output_dir));
+        }
+        Ok(defaults)
+    }
+}
+
+/// set the defaults of the flags of `command` and its subcommands. a flag with a default is no
+/// longer required.
+pub fn apply(command: clap::Command, defaults: &[ArgDefault]) -> clap::Command {
+    apply_at(command, defaults, "")
+}
+
+fn apply_at(mut command: clap::Command, defaults: &[ArgDefault], path: &str) -> clap::Command {
+    let ids = command
+        .get_arguments()
+        .map(|arg| arg.get_id().to_string())
+        .collect::<Vec<_>>();
+    for default in defaults {
+        if default.command.is_some_and(|c| c != path) || !ids.iter().any(|id| id == default.arg) {
+            continue;
+        }
+        // clap keeps references to the default values for the lifetime of the program
+        let values = default
+            .values
+            .iter()
+            .map(|value| &*value.clone().leak())
+            .collect::<Vec<&'static str>>();
+        command = command.mut_arg(default.arg, |arg| {
+            arg.default_values(values).required(false)
+        });
+    }
+    let names = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect::<Vec<_>>();
+    for name in names {
+        let sub_path = match path {
+            "" => name.clone(),
+            path => format!("{path} {name}"),
+        };
+        command = command.mut_subcommand(&name, |sub| apply_at(sub, defaults, &sub_path));
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = parse(
+            r#"
+frontend_hostname = "grenoble.g5k"
+concurrency_limit = 8
+ssh_options = ["ServerAliveInterval=30"]
+output_dir = "results/{schedule_stem}-{timestamp}"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.frontend_hostname.as_deref(), Some("grenoble.g5k"));
+        assert_eq!(config.concurrency_limit, Some(8));
+        assert_eq!(
+            config.defaults(None).unwrap(),
+            [
+                ArgDefault {
+                    command: None,
+                    arg: "frontend_hostname",
+                    values: vec![String::from("grenoble.g5k")],
+                },
+                ArgDefault {
+                    command: None,
+                    arg: "ssh_option",
+                    values: vec![String::from("ServerAliveInterval=30")],
+                },
+                ArgDefault {
+                    command: Some("run"),
+                    arg: "output_dir",
+                    values: vec![String::from("results/{schedule_stem}-{timestamp}")],
+                },
+            ]
+        );
+        assert!(parse("frontend = \"x\"\n").is_err());
+        assert_eq!(
+            parse("frontend_hostname = \"x\"\nconcurrency_limit = \"8\"\n").unwrap_err(),
+            "line 2, column 21: invalid type: string \"8\", expected usize"
+        );
+        assert_eq!(
+            load(Path::new("/nonexistent/oar-p2p.toml")).unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn test_profile() {
+        let config = parse(
+            r#"
+output_dir = "results"
+
+[profile.smalltest]
+latency_matrix = "matrices/small.txt"
+schedule = "schedules/small.json"
+output_dir = "results/small"
+
+[profile.paper-eval]
+addresses = "4/cpu"
+"#,
+        )
+        .unwrap();
+        let defaults = config.defaults(Some("smalltest")).unwrap();
+        let output_dir = defaults
+            .iter()
+            .filter(|d| d.arg == "output_dir")
+            .map(|d| d.values[0].as_str())
+            .collect::<Vec<_>>();
+        // the profile's value is applied last
+        assert_eq!(output_dir, ["results", "results/small"]);
+        assert!(defaults.iter().any(|d| d.arg == "schedule"));
+        assert_eq!(config.defaults(Some("paper-eval")).unwrap().len(), 2);
+        assert_eq!(
+            config.defaults(Some("large")).unwrap_err().to_string(),
+            "unknown profile 'large', the configuration file defines: paper-eval, smalltest"
+        );
+        let args = |args: &[&str]| profile_argument(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(
+            args(&["run", "--profile", "a", "s.json"]).as_deref(),
+            Some("a")
+        );
+        assert_eq!(args(&["--profile=b", "net", "up"]).as_deref(), Some("b"));
+        assert_eq!(args(&["run", "--", "--profile", "a"]), None);
+        // only experiment settings can be in a profile
+        assert!(parse("[profile.a]\nruntime = 'docker'\n").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let command = clap::Command::new("oar-p2p")
+            .subcommand(
+                clap::Command::new("run")
+                    .arg(
+                        clap::Arg::new("output_dir")
+                            .long("output-dir")
+                            .required(true),
+                    )
+                    .subcommand(
+                        clap::Command::new("logs")
+                            .arg(clap::Arg::new("output_dir").long("output-dir")),
+                    ),
+            )
+            .subcommand(
+                clap::Command::new("clean")
+                    .arg(clap::Arg::new("frontend_hostname").long("frontend-hostname")),
+            );
+        let defaults = Config {
+            frontend_hostname: Some(String::from("frontend")),
+            output_dir: Some(String::from("results")),
+            ..Default::default()
+        }
+        .defaults(None)
+        .unwrap();
+        let command = apply(command, &defaults);
+        let value = |args: &[&str], path: &[&str], id: &str| {
+            let matches = command.clone().try_get_matches_from(args).unwrap();
+            let matches = path.iter().fold(&matches, |matches, name| {
+                matches.subcommand_matches(name).unwrap()
+            });
+            matches.get_one::<String>(id).cloned()
+        };
+        assert_eq!(
+            value(&["oar-p2p", "run"], &["run"], "output_dir").as_deref(),
+            Some("results")
+        );
+        assert_eq!(
+            value(
+                &["oar-p2p", "run", "--output-dir", "x"],
+                &["run"],
+                "output_dir"
+            )
+            .as_deref(),
+            Some("x")
+        );
+        // scoped to `run`, not its subcommands
+        assert_eq!(
+            value(&["oar-p2p", "run", "logs"], &["run", "logs"], "output_dir"),
+            None
+        );
+        assert_eq!(
+            value(&["oar-p2p", "clean"], &["clean"], "frontend_hostname").as_deref(),
+            Some("frontend")
+        );
+    }
+}
@@ -1,6 +1,14 @@
-use eyre::Result;
+use std::sync::Arc;
 
-use crate::machine::Machine;
+use eyre::{Context as _, Result};
+
+use crate::{
+    agent::Agents,
+    machine::Machine,
+    native_ssh::{NativeSsh, Route},
+    runtime::ContainerRuntime,
+    ssh::SshControl,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ExecutionNode {
@@ -12,39 +20,62 @@ pub enum ExecutionNode {
 #[derive(Debug, Clone)]
 pub struct Context {
     pub node: ExecutionNode,
-    job_id: Option<u32>,
+    pub runtime: ContainerRuntime,
+    job_ids: Vec<u32>,
     infer_job_id: bool,
     frontend_hostname: Option<String>,
     cluster_username: Option<String>,
+    oar_api_url: Option<String>,
+    oar_api_token: Option<String>,
+    ssh_control: Option<Arc<SshControl>>,
+    agents: Option<Arc<Agents>>,
+    native_ssh: Option<Arc<NativeSsh>>,
+    remote_retries: u32,
+    ssh_options: Vec<String>,
 }
 
 impl Context {
     pub async fn new(
-        job_id: Option<u32>,
+        job_ids: Vec<u32>,
         infer_job_id: bool,
         frontend_hostname: Option<String>,
         cluster_username: Option<String>,
+        oar_api_url: Option<String>,
+        oar_api_token: Option<String>,
+        runtime: ContainerRuntime,
     ) -> Result<Self> {
         Ok(Self {
             node: get_execution_node().await?,
-            job_id,
+            runtime,
+            job_ids,
             infer_job_id,
             frontend_hostname,
             cluster_username,
+            oar_api_url,
+            oar_api_token,
+            ssh_control: None,
+            agents: None,
+            native_ssh: None,
+            remote_retries: crate::retry::DEFAULT_REMOTE_RETRIES,
+            ssh_options: Vec::default(),
         })
     }
 
-    pub async fn job_id(&self) -> Result<u32> {
-        tracing::debug!("obtaining job id");
-        if let Some(job_id) = self.job_id {
-            tracing::debug!("job id was set, using {job_id}");
-            Ok(job_id)
+    /// the job ids to operate on.
+    ///
+    /// multiple job ids can be given, in which case the machines of all jobs are used together.
+    /// if no job id was given and inference is enabled then this returns the single running job.
+    pub async fn job_ids(&self) -> Result<Vec<u32>> {
+        tracing::debug!("obtaining job ids");
+        if !self.job_ids.is_empty() {
+            tracing::debug!("job ids were set, using {:?}", self.job_ids);
+            Ok(self.job_ids.clone())
         } else if self.infer_job_id {
             tracing::debug!("job id was not set but inference is enabled, finding job id");
             let job_ids = crate::oar::list_user_job_ids(self).await?;
             match job_ids.len() {
                 0 => Err(eyre::eyre!("cannot infer job id, no jobs are running")),
-                1 => Ok(job_ids[0]),
+                1 => Ok(job_ids),
                 _ => Err(eyre::eyre!(
                     "cannot infer job id, multiple jobs are running"
                 )),
@@ -66,6 +97,82 @@ impl Context {
             .as_deref()
             .ok_or_else(|| eyre::eyre!("missing cluster username"))
     }
+
+    /// base url of the oar rest api, if configured.
+    pub fn oar_api_url(&self) -> Option<&str> {
+        self.oar_api_url.as_deref()
+    }
+
+    pub fn oar_api_token(&self) -> Option<&str> {
+        self.oar_api_token.as_deref()
+    }
+
+    /// keep a master connection per machine that all commands sent to it reuse.
+    pub fn enable_ssh_multiplexing(&mut self) -> Result<()> {
+        self.ssh_control = Some(Arc::new(SshControl::new()?));
+        Ok(())
+    }
+
+    /// the ssh master connections to the machines, if multiplexing is enabled.
+    pub fn ssh_control(&self) -> Option<&SshControl> {
+        self.ssh_control.as_deref()
+    }
+
+    /// send the scripts to an agent on each machine instead of opening a connection per script.
+    pub fn enable_agents(&mut self) {
+        self.agents = Some(Arc::default());
+    }
+
+    /// the agents running on the machines, if enabled.
+    pub fn agents(&self) -> Option<&Agents> {
+        self.agents.as_deref()
+    }
+
+    /// send the commands to the machines over an embedded ssh client instead of the ssh binary.
+    ///
+    /// from the frontend the machines are connected to directly, from anywhere else through the
+    /// frontend. on a machine of the job the ssh binary is still used.
+    pub fn enable_native_ssh(&mut self) -> Result<()> {
+        let (route, user) = match self.node {
+            ExecutionNode::Frontend => {
+                let user = match &self.cluster_username {
+                    Some(user) => user.clone(),
+                    None => std::env::var("USER").context("reading the user from $USER")?,
+                };
+                (Route::Direct, user)
+            }
+            ExecutionNode::Unknown => (
+                Route::Jump(self.frontend_hostname()?.to_string()),
+                self.cluster_username()?.to_string(),
+            ),
+            ExecutionNode::Machine(_) => return Ok(()),
+        };
+        self.native_ssh = Some(Arc::new(NativeSsh::new(route, user)));
+        Ok(())
+    }
+
+    /// the embedded ssh client, if enabled.
+    pub fn native_ssh(&self) -> Option<&NativeSsh> {
+        self.native_ssh.as_deref()
+    }
+
+    /// number of times a command sent to a machine is retried after a transient failure.
+    pub fn remote_retries(&self) -> u32 {
+        self.remote_retries
+    }
+
+    pub fn set_remote_retries(&mut self, retries: u32) {
+        self.remote_retries = retries;
+    }
+
+    /// options passed to ssh, with `-o`, when connecting to the machines.
+    pub fn ssh_options(&self) -> &[String] {
+        &self.ssh_options
+    }
+
+    pub fn set_ssh_options(&mut self, options: Vec<String>) {
+        self.ssh_options = options;
+    }
 }
 
 async fn get_execution_node() -> Result<ExecutionNode> {
@@ -0,0 +1,182 @@
+use serde::Serialize;
+
+use crate::{machine::Machine, runtime::ContainerRuntime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+    /// not checked because a check it depends on failed or there was nothing to check
+    Skip,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "ok",
+            Self::Warn => "warn",
+            Self::Fail => "FAIL",
+            Self::Skip => "skip",
+        })
+    }
+}
+
+/// the outcome of checking one part of the environment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    /// what to do about a failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Check {
+    pub fn new(name: impl Into<String>, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+/// the checks as a table, with the hint of every failure under it.
+pub fn format(checks: &[Check]) -> String {
+    let width = checks
+        .iter()
+        .map(|c| c.name.len())
+        .max()
+        .unwrap_or_default();
+    let mut output = String::default();
+    for check in checks {
+        output.push_str(&format!(
+            "{:<5} {:<width$}  {}\n",
+            check.status.to_string(),
+            check.name,
+            check.detail
+        ));
+        if let Some(hint) = &check.hint {
+            output.push_str(&format!("{:<5} {:<width$}  -> {hint}\n", "", ""));
+        }
+    }
+    output
+}
+
+/// script, executed on a machine, that prints one `<name>\t<status>\t<detail>` line for the
+/// container runtime, the kernel and the nft and tc of the networking image.
+pub fn machine_script(runtime: ContainerRuntime, image: &str) -> String {
+    format!(
+        r#"if v=$({runtime} --version 2>&1) && e=$({runtime} info 2>&1 >/dev/null) ; then
+	printf 'runtime\tok\t%s\n' "$(echo "$v" | head -n1)"
+else
+	printf 'runtime\tfail\t%s\n' "$(echo "$v$e" | grep . | tail -n1)"
+fi
+printf 'kernel\tok\t%s\n' "$(uname -r)"
+if {runtime} image inspect {image} >/dev/null 2>&1 ; then
+	if v=$({runtime} run --rm {image} nft --version 2>&1) ; then
+		printf 'nft\tok\t%s\n' "$(echo "$v" | head -n1)"
+	else
+		printf 'nft\tfail\t%s\n' "$(echo "$v" | tail -n1)"
+	fi
+	if v=$({runtime} run --rm {image} tc -V 2>&1) ; then
+		printf 'tc\tok\t%s\n' "$(echo "$v" | head -n1)"
+	else
+		printf 'tc\tfail\t%s\n' "$(echo "$v" | tail -n1)"
+	fi
+else
+	printf 'nft\tskip\tthe networking image is not built yet, net up builds it\n'
+	printf 'tc\tskip\tthe networking image is not built yet, net up builds it\n'
+fi
+true
+"#
+    )
+}
+
+/// parse the output of [`machine_script`] executed on `machine`.
+pub fn parse_machine(machine: Machine, runtime: ContainerRuntime, output: &str) -> Vec<Check> {
+    let mut checks = Vec::default();
+    for line in output.lines() {
+        let mut fields = line.splitn(3, '\t');
+        let (Some(name), Some(status), Some(detail)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let status = match status {
+            "ok" => Status::Ok,
+            "warn" => Status::Warn,
+            "fail" => Status::Fail,
+            _ => Status::Skip,
+        };
+        let hint = match (name, status) {
+            ("runtime", Status::Fail) => Some(format!(
+                "is {runtime} installed and running on {machine}, and can your user run `{runtime} info` without sudo? pass --runtime if the machines use another runtime"
+            )),
+            ("nft" | "tc", Status::Fail) => Some(format!(
+                "the networking image on {machine} is broken, `oar-p2p clean` removes it and the next `net up` builds it again"
+            )),
+            _ => None,
+        };
+        let name = match name {
+            "runtime" => format!("{machine} {runtime}"),
+            name => format!("{machine} {name}"),
+        };
+        let mut check = Check::new(name, status, detail.trim());
+        check.hint = hint;
+        checks.push(check);
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_machine() {
+        let output = "runtime\tok\tDocker version 27.1.1, build 6312585\nkernel\tok\t6.1.0-21-amd64\nnft\tfail\tnft: not found\ntc\tskip\tthe networking image is not built yet, net up builds it\nmotd\n";
+        let checks = parse_machine(Machine::Gengar1, ContainerRuntime::Docker, output);
+        let statuses = checks
+            .iter()
+            .map(|c| (c.name.as_str(), c.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            [
+                ("gengar-1 docker", Status::Ok),
+                ("gengar-1 kernel", Status::Ok),
+                ("gengar-1 nft", Status::Fail),
+                ("gengar-1 tc", Status::Skip),
+            ]
+        );
+        assert!(checks[2].hint.is_some());
+        assert!(checks[1].hint.is_none());
+        assert!(
+            machine_script(ContainerRuntime::Docker, "local/oar-p2p-networking")
+                .contains("docker run --rm local/oar-p2p-networking nft --version")
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let checks = [
+            Check::new("ssh", Status::Ok, "OpenSSH_9.6p1"),
+            Check::new("frontend", Status::Fail, "connection timed out")
+                .with_hint("check that `ssh dicluster` works"),
+        ];
+        assert_eq!(
+            format(&checks),
+            "ok    ssh       OpenSSH_9.6p1\nFAIL  frontend  connection timed out\n                -> check that `ssh dicluster` works\n"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// keys whose values are paths, relative paths are resolved against the experiment file's
+/// directory so an experiment can be run from anywhere.
+const PATH_KEYS: &[&str] = &[
+    "latency_matrix",
+    "snapshot_dir",
+    "mapping_out",
+    "schedule",
+    "output_dir",
+    "registry_config",
+];
+
+/// keys of the `run` table that are positional arguments instead of flags.
+const RUN_POSITIONAL_KEYS: &[&str] = &["schedule"];
+
+#[derive(Debug, Error)]
+pub enum InvalidExperiment {
+    #[error("{source_name}: {message}")]
+    Syntax {
+        source_name: String,
+        message: String,
+    },
+    #[error("{source_name}: {table}.{key}: {message}")]
+    Value {
+        source_name: String,
+        table: &'static str,
+        key: String,
+        message: String,
+    },
+}
+
+/// an experiment file, see [`parse`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    /// flags of `net up`
+    pub network: toml::Table,
+    /// flags of `run`
+    pub run: toml::Table,
+    /// leave the network configured after the run instead of running `net down`
+    #[serde(default)]
+    pub keep_network: bool,
+}
+
+/// parse an experiment file.
+///
+/// the `network` and `run` tables hold the flags of `net up` and `run`, with `_` in place of `-`.
+/// `source_name` is only used to produce error messages.
+pub fn parse(source_name: &str, content: &str) -> Result<Experiment, InvalidExperiment> {
+    toml::from_str(content).map_err(|err| InvalidExperiment::Syntax {
+        source_name: source_name.to_string(),
+        message: crate::config::toml_error_message(content, &err),
+    })
+}
+
+impl Experiment {
+    /// the arguments of `net up`, paths are resolved against `base`.
+    pub fn net_up_arguments(
+        &self,
+        source_name: &str,
+        base: &Path,
+    ) -> Result<Vec<String>, InvalidExperiment> {
+        table_arguments(source_name, "network", &self.network, base, &[])
+    }
+
+    /// the arguments of `run`, paths are resolved against `base`.
+    pub fn run_arguments(
+        &self,
+        source_name: &str,
+        base: &Path,
+    ) -> Result<Vec<String>, InvalidExperiment> {
+        table_arguments(source_name, "run", &self.run, base, RUN_POSITIONAL_KEYS)
+    }
+}
+
+/// convert a table into command line arguments.
+///
+/// `key = value` becomes `--key value`, `key = true` becomes `--key`, `key = false` is omitted and
+/// arrays repeat the flag once per element. keys in `positional` are appended as plain arguments.
+fn table_arguments(
+    source_name: &str,
+    table_name: &'static str,
+    table: &toml::Table,
+    base: &Path,
+    positional: &[&str],
+) -> Result<Vec<String>, InvalidExperiment> {
+    let err_fn = |key: &str, message: &str| InvalidExperiment::Value {
+        source_name: source_name.to_string(),
+        table: table_name,
+        key: key.to_string(),
+        message: message.to_string(),
+    };
+    let scalar = |key: &str, value: &toml::Value| -> Result<String, InvalidExperiment> {
+        let value = match value {
+            toml::Value::String(value) => value.clone(),
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            _ => return Err(err_fn(key, "expected a string or a number")),
+        };
+        if PATH_KEYS.contains(&key) && value == "-" {
+            // the files are copied to the output directory, which stdin cannot be
+            Err(err_fn(
+                key,
+                "standard input cannot be used in an experiment file",
+            ))
+        } else if PATH_KEYS.contains(&key) {
+            Ok(base.join(value).display().to_string())
+        } else {
+            Ok(value)
+        }
+    };
+
+    let mut flags = Vec::default();
+    let mut positionals = Vec::default();
+    for (key, value) in table {
+        if positional.contains(&key.as_str()) {
+            positionals.push(scalar(key, value)?);
+            continue;
+        }
+        let flag = format!("--{}", key.replace('_', "-"));
+        match value {
+            toml::Value::Boolean(true) => flags.push(flag),
+            toml::Value::Boolean(false) => {}
+            toml::Value::Array(values) => {
+                for value in values {
+                    flags.push(flag.clone());
+                    flags.push(scalar(key, value)?);
+                }
+            }
+            value => {
+                flags.push(flag);
+                flags.push(scalar(key, value)?);
+            }
+        }
+    }
+    flags.extend(positionals);
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPERIMENT: &str = r#"
+keep_network = true
+
+[network]
+latency_matrix = "matrix.txt"
+addresses = "4/cpu"
+matrix_wrap = false
+
+[run]
+schedule = "schedule.json"
+output_dir = "/results"
+signal = ["start:10", "stop:600"]
+stats_interval = 5
+barrier = true
+"#;
+
+    #[test]
+    fn test_parse() {
+        let experiment = parse("experiment.toml", EXPERIMENT).unwrap();
+        assert!(experiment.keep_network);
+        let base = Path::new("/experiments/a");
+        assert_eq!(
+            experiment
+                .net_up_arguments("experiment.toml", base)
+                .unwrap(),
+            vec![
+                "--addresses",
+                "4/cpu",
+                "--latency-matrix",
+                "/experiments/a/matrix.txt",
+            ]
+        );
+        assert_eq!(
+            experiment.run_arguments("experiment.toml", base).unwrap(),
+            vec![
+                "--barrier",
+                "--output-dir",
+                "/results",
+                "--signal",
+                "start:10",
+                "--signal",
+                "stop:600",
+                "--stats-interval",
+                "5",
+                "/experiments/a/schedule.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let err = parse("experiment.toml", "[network]\n").unwrap_err();
+        assert!(err.to_string().contains("missing field `run`"), "{err}");
+
+        let err = parse("experiment.toml", "keep_network = 1\n[network]\n[run]\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "experiment.toml: line 1, column 16: invalid type: integer `1`, expected a boolean"
+        );
+
+        let experiment = parse(
+            "experiment.toml",
+            "[network]\n[run]\nsignal = [{ name = \"start\" }]\n",
+        )
+        .unwrap();
+        let err = experiment
+            .run_arguments("experiment.toml", Path::new("."))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "experiment.toml: run.signal: expected a string or a number"
+        );
+
+        let experiment = parse(
+            "experiment.toml",
+            "[network]\nlatency_matrix = \"-\"\n[run]\n",
+        )
+        .unwrap();
+        let err = experiment
+            .net_up_arguments("experiment.toml", Path::new(""))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "experiment.toml: network.latency_matrix: standard input cannot be used in an experiment file"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::str::FromStr;
+
+/// how many containers of a run are allowed to exit with a non-zero code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureAllowance {
+    /// at most this many containers
+    Count(usize),
+    /// at most this percentage, between 0 and 100, of all containers
+    Percentage(f64),
+}
+
+impl FailureAllowance {
+    /// the maximum number of failed containers out of `total`.
+    pub fn max_failures(&self, total: usize) -> usize {
+        match *self {
+            FailureAllowance::Count(count) => count,
+            FailureAllowance::Percentage(percentage) => {
+                (total as f64 * percentage / 100.0).floor() as usize
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "invalid failure allowance '{0}', expected a number of containers or a percentage like '10%'"
+)]
+pub struct InvalidFailureAllowance(String);
+
+impl FromStr for FailureAllowance {
+    type Err = InvalidFailureAllowance;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = || InvalidFailureAllowance(s.to_string());
+        match s.trim().strip_suffix('%') {
+            Some(percentage) => {
+                let percentage = percentage.trim().parse::<f64>().map_err(|_| err_fn())?;
+                if !(0.0..=100.0).contains(&percentage) {
+                    return Err(err_fn());
+                }
+                Ok(Self::Percentage(percentage))
+            }
+            None => Ok(Self::Count(s.trim().parse().map_err(|_| err_fn())?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "3".parse::<FailureAllowance>().unwrap(),
+            FailureAllowance::Count(3)
+        );
+        assert_eq!(
+            "12.5%".parse::<FailureAllowance>().unwrap(),
+            FailureAllowance::Percentage(12.5)
+        );
+        assert!("-1".parse::<FailureAllowance>().is_err());
+        assert!("101%".parse::<FailureAllowance>().is_err());
+        assert!("abc".parse::<FailureAllowance>().is_err());
+        assert!("%".parse::<FailureAllowance>().is_err());
+    }
+
+    #[test]
+    fn test_max_failures() {
+        assert_eq!(FailureAllowance::Count(3).max_failures(100), 3);
+        assert_eq!(FailureAllowance::Percentage(10.0).max_failures(25), 2);
+        assert_eq!(FailureAllowance::Percentage(100.0).max_failures(25), 25);
+    }
+}
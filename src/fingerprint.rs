@@ -0,0 +1,99 @@
+use crate::runtime::ContainerRuntime;
+
+/// file, on every machine, with the fingerprint of the network configuration applied by `net up`.
+pub const FINGERPRINT_PATH: &str = "/tmp/oar-p2p-network.fingerprint";
+
+const IMAGE: &str = "local/oar-p2p-networking";
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit fnv-1a hash, unlike the hashers of the standard library its output is the same for every
+/// build so fingerprints written by one version can be compared by another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Fingerprint {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// hash a string followed by a separator, so `("ab", "c")` and `("a", "bc")` differ.
+    pub fn write_str(&mut self, s: &str) {
+        self.write(s.as_bytes());
+        self.write(&[0xff]);
+    }
+
+    pub fn hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// script that prints the fingerprint of the configuration applied to the machine, or nothing if
+/// the network is not configured.
+///
+/// the fingerprint only counts if the nft table still exists, the network might have been removed
+/// by something other than `net down`.
+pub fn read_script(runtime: ContainerRuntime) -> String {
+    format!(
+        "fingerprint=$(cat {FINGERPRINT_PATH} 2>/dev/null || true)\nif [ -n \"$fingerprint\" ] && {runtime} run --rm --net=host --privileged {IMAGE} nft list table ip oar-p2p >/dev/null 2>&1 ; then echo \"$fingerprint\" ; fi\n"
+    )
+}
+
+/// the fingerprint in the output of [`read_script`].
+pub fn parse_read_output(output: &str) -> Option<String> {
+    let fingerprint = output.trim();
+    match !fingerprint.is_empty() && fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+        true => Some(fingerprint.to_string()),
+        false => None,
+    }
+}
+
+pub fn write_script(fingerprint: &str) -> String {
+    format!("echo {fingerprint} > {FINGERPRINT_PATH}\n")
+}
+
+pub fn remove_script() -> String {
+    format!("rm -f {FINGERPRINT_PATH}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(Fingerprint::default().hex(), "cbf29ce484222325");
+        let mut fingerprint = Fingerprint::default();
+        fingerprint.write(b"a");
+        assert_eq!(fingerprint.hex(), "af63dc4c8601ec8c");
+
+        let hash = |parts: &[&str]| {
+            let mut fingerprint = Fingerprint::default();
+            parts.iter().for_each(|p| fingerprint.write_str(p));
+            fingerprint
+        };
+        assert_ne!(hash(&["ab", "c"]), hash(&["a", "bc"]));
+        assert_eq!(hash(&["ab", "c"]), hash(&["ab", "c"]));
+    }
+
+    #[test]
+    fn test_parse_read_output() {
+        assert_eq!(
+            parse_read_output("af63dc4c8601ec8c\n").as_deref(),
+            Some("af63dc4c8601ec8c")
+        );
+        assert_eq!(parse_read_output("\n"), None);
+        assert_eq!(parse_read_output("cat: permission denied"), None);
+        assert!(read_script(ContainerRuntime::Docker).contains(FINGERPRINT_PATH));
+    }
+}
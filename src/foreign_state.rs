@@ -0,0 +1,97 @@
+/// root qdiscs the kernel or the distribution sets up on their own.
+const DEFAULT_QDISCS: &[&str] = &["noqueue", "mq", "fq_codel", "pfifo_fast", "fq", "pfifo"];
+
+/// script, executed in the networking container, that lists the root and ingress qdiscs of the
+/// devices oar-p2p configures, one per line prefixed with the device.
+pub fn detect_script(interface: &str) -> String {
+    format!(
+        "for dev in lo {interface} ; do\n\ttc qdisc show dev $dev root | sed \"s/^/$dev /\"\n\ttc qdisc show dev $dev ingress | sed \"s/^/$dev /\"\ndone\n"
+    )
+}
+
+/// a qdisc that oar-p2p did not create and that cleaning the network would delete.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignQdisc {
+    pub device: String,
+    /// the qdisc as printed by `tc`
+    pub qdisc: String,
+}
+
+impl std::fmt::Display for ForeignQdisc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.device, self.qdisc)
+    }
+}
+
+/// the qdiscs in the output of [`detect_script`] that are neither a default nor the root qdisc
+/// oar-p2p creates, an htb with handle `1:` whose default class is `1:9999`.
+///
+/// oar-p2p never creates ingress qdiscs so all of them are foreign.
+pub fn parse(output: &str) -> Vec<ForeignQdisc> {
+    let mut foreign = Vec::default();
+    for line in output.lines() {
+        let Some((device, qdisc)) = line.trim().split_once(' ') else {
+            continue;
+        };
+        let mut words = qdisc.split_whitespace();
+        if words.next() != Some("qdisc") {
+            continue;
+        }
+        let kind = words.next().unwrap_or_default();
+        if is_oar_p2p(qdisc) || DEFAULT_QDISCS.contains(&kind) {
+            continue;
+        }
+        foreign.push(ForeignQdisc {
+            device: device.to_string(),
+            qdisc: qdisc.trim().to_string(),
+        });
+    }
+    foreign
+}
+
+/// whether the output of [`detect_script`] has the root qdisc oar-p2p creates.
+pub fn has_oar_p2p_qdisc(output: &str) -> bool {
+    output
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .any(|(_, qdisc)| is_oar_p2p(qdisc))
+}
+
+fn is_oar_p2p(qdisc: &str) -> bool {
+    qdisc.starts_with("qdisc htb 1: ") && qdisc.contains("default 0x270f")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = "\
+lo qdisc noqueue 0: root refcnt 2
+eno1 qdisc htb 1: root refcnt 65 r2q 100000 default 0x270f direct_packets_stat 0 direct_qlen 1000
+";
+        assert_eq!(parse(output), Vec::default());
+
+        let output = "\
+lo qdisc tbf 8001: root refcnt 2 rate 1Mbit burst 32Kb lat 400ms
+eno1 qdisc mq 0: root
+eno1 qdisc htb 1: root refcnt 65 r2q 10 default 0x10 direct_packets_stat 0
+eno1 qdisc ingress ffff: parent ffff:fff1 ----------------
+";
+        let foreign = parse(output);
+        assert_eq!(
+            foreign
+                .iter()
+                .map(|q| q.device.as_str())
+                .collect::<Vec<_>>(),
+            ["lo", "eno1", "eno1"]
+        );
+        assert_eq!(
+            foreign[0].to_string(),
+            "lo: qdisc tbf 8001: root refcnt 2 rate 1Mbit burst 32Kb lat 400ms"
+        );
+        assert!(foreign[2].qdisc.starts_with("qdisc ingress"));
+        assert!(detect_script("eno1").contains("for dev in lo eno1"));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{foreign_state, ps, runtime::ContainerRuntime};
+
+/// file, on every machine, with the ids of the jobs that last used the machine. written by
+/// `net up` and `run` before they change anything so `gc` can tell state of a running job apart
+/// from state left behind by one that ended.
+pub const JOB_MARKER_PATH: &str = "/tmp/oar-p2p.job";
+
+/// directory on the machines where the container logs are collected.
+pub const LOGS_DIR: &str = "/tmp/oar-p2p-logs";
+
+pub fn job_marker_script(job_ids: &[u32]) -> String {
+    let job_ids = job_ids
+        .iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("echo {job_ids} > {JOB_MARKER_PATH}\n")
+}
+
+/// script, executed on the machine, that prints the job marker and the leftovers outside the
+/// network: the containers started by `run` and the logs directory.
+pub fn host_scan_script(runtime: ContainerRuntime) -> String {
+    format!(
+        "[ -f {JOB_MARKER_PATH} ] && echo \"job $(cat {JOB_MARKER_PATH})\"\n{runtime} ps -a --filter label={} --format 'container {{{{.Names}}}}' 2>/dev/null || true\n[ -d {LOGS_DIR} ] && echo logs\ntrue\n",
+        ps::ADDRESS_LABEL
+    )
+}
+
+/// script, executed in the networking container, that prints the network leftovers.
+pub fn net_scan_script(interface: &str) -> String {
+    format!(
+        "echo \"addresses $(ip addr show {interface} | grep -cE '10\\.[0-9]+\\.[0-9]+\\.[0-9]+/32' || true)\"\nnft list table ip oar-p2p >/dev/null 2>&1 && echo nft\n{}",
+        foreign_state::detect_script(interface)
+    )
+}
+
+/// who the leftovers of a machine belong to, according to its job marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Owner {
+    /// a job that oar has not ended yet, in any state and of any user
+    Running,
+    /// only jobs that ended
+    Ended,
+    /// there is no job marker: the state could come from an older oar-p2p or /tmp was cleaned,
+    /// so it might still be in use
+    Unknown,
+}
+
+/// what oar-p2p left on a machine.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Leftovers {
+    /// the jobs in the job marker, `None` if there is no marker
+    pub job_ids: Option<Vec<u32>>,
+    pub containers: Vec<String>,
+    pub logs: bool,
+    /// number of oar-p2p addresses on the interface
+    pub addresses: usize,
+    pub nft_table: bool,
+    pub qdisc: bool,
+    /// qdiscs oar-p2p did not create, the network is not cleaned if there are any
+    pub foreign: Vec<foreign_state::ForeignQdisc>,
+}
+
+impl Leftovers {
+    /// parse the outputs of [`host_scan_script`] and [`net_scan_script`].
+    pub fn parse(host: &str, net: &str) -> Self {
+        let mut leftovers = Self::default();
+        for line in host.lines().map(str::trim) {
+            if let Some(job_ids) = line.strip_prefix("job") {
+                leftovers.job_ids = Some(
+                    job_ids
+                        .split_whitespace()
+                        .filter_map(|id| id.parse().ok())
+                        .collect(),
+                );
+            } else if let Some(name) = line.strip_prefix("container ") {
+                leftovers.containers.push(name.to_string());
+            } else if line == "logs" {
+                leftovers.logs = true;
+            }
+        }
+        for line in net.lines().map(str::trim) {
+            if let Some(count) = line.strip_prefix("addresses ") {
+                leftovers.addresses = count.parse().unwrap_or_default();
+            } else if line == "nft" {
+                leftovers.nft_table = true;
+            }
+        }
+        leftovers.qdisc = foreign_state::has_oar_p2p_qdisc(net);
+        leftovers.foreign = foreign_state::parse(net);
+        leftovers
+    }
+
+    pub fn network(&self) -> bool {
+        self.addresses > 0 || self.nft_table || self.qdisc
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.network() && self.containers.is_empty() && !self.logs && self.job_ids.is_none()
+    }
+
+    /// who the leftovers belong to, given `live`, the jobs of the marker that oar has not ended.
+    pub fn owner(&self, live: &[u32]) -> Owner {
+        match &self.job_ids {
+            None => Owner::Unknown,
+            Some(ids) if ids.iter().any(|id| live.contains(id)) => Owner::Running,
+            Some(_) => Owner::Ended,
+        }
+    }
+
+    /// whether `gc` removes the leftovers, those of unknown owner only with `force`.
+    pub fn removable(&self, live: &[u32], force: bool) -> bool {
+        match self.owner(live) {
+            Owner::Running => false,
+            Owner::Ended => true,
+            Owner::Unknown => force,
+        }
+    }
+}
+
+impl std::fmt::Display for Leftovers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::default();
+        if self.addresses > 0 {
+            parts.push(format!("{} addresses", self.addresses));
+        }
+        if self.qdisc {
+            parts.push(String::from("tc qdiscs"));
+        }
+        if self.nft_table {
+            parts.push(String::from("nft table"));
+        }
+        if !self.containers.is_empty() {
+            parts.push(format!("{} containers", self.containers.len()));
+        }
+        if self.logs {
+            parts.push(LOGS_DIR.to_string());
+        }
+        if parts.is_empty() {
+            parts.push(String::from("job marker"));
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// script, executed on the machine, that removes the leftovers outside the network.
+pub fn host_remove_script(runtime: ContainerRuntime, leftovers: &Leftovers) -> String {
+    let mut script = String::default();
+    if !leftovers.containers.is_empty() {
+        script.push_str(&format!(
+            "{runtime} rm -f {} >/dev/null\n",
+            leftovers.containers.join(" ")
+        ));
+    }
+    script.push_str(&format!("rm -rf {LOGS_DIR}\nrm -f {JOB_MARKER_PATH}\n"));
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_leftovers() {
+        let host = "job 10 12\ncontainer c0\ncontainer c1\nlogs\n";
+        let net = "addresses 4\nnft\nlo qdisc htb 1: root refcnt 2 r2q 100000 default 0x270f\neno1 qdisc mq 0: root\n";
+        let leftovers = Leftovers::parse(host, net);
+        assert_eq!(
+            leftovers,
+            Leftovers {
+                job_ids: Some(vec![10, 12]),
+                containers: vec![String::from("c0"), String::from("c1")],
+                logs: true,
+                addresses: 4,
+                nft_table: true,
+                qdisc: true,
+                foreign: Vec::default(),
+            }
+        );
+        assert_eq!(leftovers.owner(&[12]), Owner::Running);
+        assert_eq!(leftovers.owner(&[11]), Owner::Ended);
+        assert_eq!(
+            leftovers.to_string(),
+            "4 addresses, tc qdiscs, nft table, 2 containers, /tmp/oar-p2p-logs"
+        );
+        assert_eq!(
+            host_remove_script(ContainerRuntime::Docker, &leftovers),
+            "docker rm -f c0 c1 >/dev/null\nrm -rf /tmp/oar-p2p-logs\nrm -f /tmp/oar-p2p.job\n"
+        );
+
+        let empty = Leftovers::parse("", "addresses 0\nlo qdisc noqueue 0: root refcnt 2\n");
+        assert!(empty.is_empty());
+        // state without a marker might still be in use
+        assert_eq!(Leftovers::parse("logs\n", "").owner(&[10]), Owner::Unknown);
+        let unmarked = Leftovers::parse("", "addresses 4\nnft\n");
+        assert_eq!(unmarked.owner(&[]), Owner::Unknown);
+        assert!(!unmarked.removable(&[10], false));
+        assert!(unmarked.removable(&[10], true));
+        assert!(!leftovers.removable(&[12], true));
+        assert!(leftovers.removable(&[11], false));
+        // a job that is still launching or finishing, or belongs to another user, is live
+        let launching = Leftovers::parse("job 13\n", "addresses 4\n");
+        assert_eq!(launching.owner(&[13]), Owner::Running);
+        assert!(!launching.removable(&[13], true));
+        assert_eq!(
+            job_marker_script(&[10, 12]),
+            "echo 10 12 > /tmp/oar-p2p.job\n"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::str::FromStr;
 use std::time::Duration;
 use thiserror::Error;
@@ -12,8 +13,54 @@ pub enum InvalidLatencyMatrix {
         dimension: usize,
         expected: usize,
     },
-    #[error("invalid latency value '{value}': {error}")]
-    InvalidLatencyValue { value: String, error: String },
+    #[error("latency matrix must be square but it has {rows} rows and {columns} columns")]
+    NotSquare { rows: usize, columns: usize },
+    #[error("line {line}, column {column}: invalid latency value '{value}': {error}")]
+    InvalidLatencyValue {
+        line: usize,
+        column: usize,
+        value: String,
+        error: String,
+    },
+}
+
+/// how the latencies between different addresses change when truncated to a resolution, see
+/// [`LatencyMatrix::precision_loss`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrecisionLoss {
+    /// number of latencies between different addresses.
+    pub total: usize,
+    /// latencies that are not a multiple of the resolution.
+    pub truncated: usize,
+    /// non-zero latencies that become zero.
+    pub zeroed: usize,
+    /// distinct latencies that end up equal to another one.
+    pub collapsed: usize,
+    /// largest difference between a latency and its truncated value.
+    pub max_error: Duration,
+}
+
+impl std::fmt::Display for PrecisionLoss {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} latencies are truncated by up to {:.3}ms",
+            self.truncated,
+            self.total,
+            self.max_error.as_secs_f64() * 1000.0
+        )?;
+        if self.zeroed > 0 {
+            write!(f, ", {} become 0ms", self.zeroed)?;
+        }
+        if self.collapsed > 0 {
+            write!(
+                f,
+                ", {} distinct latencies become equal to another",
+                self.collapsed
+            )?;
+        }
+        Ok(())
+    }
 }
 
 pub enum TimeUnit {
@@ -36,6 +83,15 @@ impl LatencyMatrix {
         }
     }
 
+    /// matrix with `latency(row, col)` at every position.
+    pub fn from_fn(dimension: usize, latency: impl Fn(usize, usize) -> Duration) -> Self {
+        let latencies = (0..dimension)
+            .flat_map(|row| (0..dimension).map(move |col| (row, col)))
+            .map(|(row, col)| latency(row, col))
+            .collect();
+        Self::new(dimension, latencies)
+    }
+
     pub fn latency(&self, row: usize, col: usize) -> Duration {
         self.latencies[self.dimension * row + col]
     }
@@ -44,8 +100,43 @@ impl LatencyMatrix {
         self.dimension
     }
 
+    /// what is lost when the latencies between different addresses are truncated to a multiple of
+    /// `resolution`, `None` if they all are multiples already.
+    pub fn precision_loss(&self, resolution: Duration) -> Option<PrecisionLoss> {
+        let resolution = resolution.as_nanos();
+        let mut loss = PrecisionLoss {
+            total: 0,
+            truncated: 0,
+            zeroed: 0,
+            collapsed: 0,
+            max_error: Duration::ZERO,
+        };
+        let mut distinct = HashSet::new();
+        let mut buckets = HashSet::new();
+        for row in 0..self.dimension {
+            for col in (0..self.dimension).filter(|&col| col != row) {
+                let latency = self.latency(row, col).as_nanos();
+                let error = latency % resolution;
+                loss.total += 1;
+                distinct.insert(latency);
+                buckets.insert(latency / resolution);
+                if error == 0 {
+                    continue;
+                }
+                loss.truncated += 1;
+                if latency < resolution {
+                    loss.zeroed += 1;
+                }
+                loss.max_error = loss.max_error.max(Duration::from_nanos(error as u64));
+            }
+        }
+        loss.collapsed = distinct.len() - buckets.len();
+        (loss.truncated > 0).then_some(loss)
+    }
+
     pub fn parse(content: &str, unit: TimeUnit) -> Result<Self, InvalidLatencyMatrix> {
         let mut dimension = None;
+        let mut rows = 0;
         let mut latencies = Vec::default();
         for (line_idx, line) in content.lines().enumerate() {
             let line = line.trim();
@@ -57,9 +148,19 @@ impl LatencyMatrix {
             for component in line.split_whitespace() {
                 current_dimension += 1;
                 let component_value = match component.parse::<f64>() {
-                    Ok(value) => value,
+                    Ok(value) if value.is_finite() && value >= 0.0 => value,
+                    Ok(_) => {
+                        return Err(InvalidLatencyMatrix::InvalidLatencyValue {
+                            line: line_idx + 1,
+                            column: current_dimension,
+                            value: component.to_string(),
+                            error: String::from("latency must be a finite, non-negative number"),
+                        });
+                    }
                     Err(err) => {
                         return Err(InvalidLatencyMatrix::InvalidLatencyValue {
+                            line: line_idx + 1,
+                            column: current_dimension,
                             value: component.to_string(),
                             error: err.to_string(),
                         });
@@ -76,7 +177,7 @@ impl LatencyMatrix {
                 Some(dimension) => {
                     if current_dimension != dimension {
                         return Err(InvalidLatencyMatrix::InvalidLineDimension {
-                            line: line_idx,
+                            line: line_idx + 1,
                             dimension: current_dimension,
                             expected: dimension,
                         });
@@ -84,9 +185,34 @@ impl LatencyMatrix {
                 }
                 None => dimension = Some(current_dimension),
             }
+            rows += 1;
+        }
+
+        let dimension = dimension.unwrap_or(0);
+        if rows != dimension {
+            return Err(InvalidLatencyMatrix::NotSquare {
+                rows,
+                columns: dimension,
+            });
         }
 
-        Ok(Self::new(dimension.unwrap_or(0), latencies))
+        Ok(Self::new(dimension, latencies))
+    }
+}
+
+/// the matrix in milliseconds, in the format accepted by [`LatencyMatrix::parse`].
+impl std::fmt::Display for LatencyMatrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in 0..self.dimension {
+            for col in 0..self.dimension {
+                if col > 0 {
+                    f.write_str(" ")?;
+                }
+                write!(f, "{:.3}", self.latency(row, col).as_secs_f64() * 1000.0)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
 }
 
@@ -97,3 +223,106 @@ impl FromStr for LatencyMatrix {
         Self::parse(s, TimeUnit::Milliseconds)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_valid() {
+        let matrix = LatencyMatrix::parse("0 10\n10 0\n", TimeUnit::Milliseconds).unwrap();
+        assert_eq!(matrix.dimension(), 2);
+        assert_eq!(matrix.latency(0, 1), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        let matrix = LatencyMatrix::from_fn(2, |row, col| {
+            Duration::from_micros((row * 2 + col) as u64 * 1500)
+        });
+        let content = matrix.to_string();
+        assert_eq!(content, "0.000 1.500\n3.000 4.500\n");
+        let parsed = content.parse::<LatencyMatrix>().unwrap();
+        assert_eq!(parsed.latency(1, 0), Duration::from_millis(3));
+    }
+
+    #[test]
+    fn test_precision_loss() {
+        let millis = Duration::from_millis(1);
+        let matrix = LatencyMatrix::parse("0 10\n10 0\n", TimeUnit::Milliseconds).unwrap();
+        assert_eq!(matrix.precision_loss(millis), None);
+
+        // the diagonal is never used and does not count
+        let matrix = LatencyMatrix::parse(
+            "0.7 0.5 10.25\n0.5 0 10\n10.25 10 0\n",
+            TimeUnit::Milliseconds,
+        )
+        .unwrap();
+        let loss = matrix.precision_loss(millis).unwrap();
+        assert_eq!(
+            loss,
+            PrecisionLoss {
+                total: 6,
+                truncated: 4,
+                zeroed: 2,
+                collapsed: 1,
+                max_error: Duration::from_micros(500),
+            }
+        );
+        assert_eq!(
+            loss.to_string(),
+            "4 of 6 latencies are truncated by up to 0.500ms, 2 become 0ms, 1 distinct latencies become equal to another"
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_value_position() {
+        let err = LatencyMatrix::parse("0 10\n10 abc\n", TimeUnit::Milliseconds).unwrap_err();
+        assert!(matches!(
+            err,
+            InvalidLatencyMatrix::InvalidLatencyValue {
+                line: 2,
+                column: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_negative_value() {
+        let err = LatencyMatrix::parse("0 -1\n-1 0\n", TimeUnit::Milliseconds).unwrap_err();
+        assert!(matches!(
+            err,
+            InvalidLatencyMatrix::InvalidLatencyValue {
+                line: 1,
+                column: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_not_square() {
+        let err = LatencyMatrix::parse("0 1 2\n1 0 2\n", TimeUnit::Milliseconds).unwrap_err();
+        assert!(matches!(
+            err,
+            InvalidLatencyMatrix::NotSquare {
+                rows: 2,
+                columns: 3
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_invalid_dimension() {
+        let err = LatencyMatrix::parse("0 1\n1 0 2\n", TimeUnit::Milliseconds).unwrap_err();
+        assert!(matches!(
+            err,
+            InvalidLatencyMatrix::InvalidLineDimension {
+                line: 2,
+                dimension: 3,
+                expected: 2
+            }
+        ));
+    }
+}
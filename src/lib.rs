@@ -0,0 +1,11 @@
+// the configuration generation of `net up` and the modules it needs, so the benchmarks can use
+// it. every other module is private to the binary.
+
+pub mod address_allocation_policy;
+pub mod fingerprint;
+pub mod latency_matrix;
+pub mod machine;
+pub mod netconfig;
+pub mod progress;
+pub mod runtime;
+pub mod timing;
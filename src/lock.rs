@@ -0,0 +1,274 @@
+use std::{
+    process::Stdio,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use eyre::{Context as _, Result};
+use tokio::{
+    io::{AsyncBufReadExt as _, BufReader},
+    process::{Child, Command},
+};
+
+use crate::{
+    context::{Context, ExecutionNode},
+    shell,
+};
+
+/// how long to wait for the frontend to answer before continuing without the lock.
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn lock_path(job_id: u32) -> String {
+    format!("/tmp/oar-p2p-job-{job_id}.lock")
+}
+
+/// file next to the lock that describes the invocation holding it.
+pub fn owner_path(job_id: u32) -> String {
+    format!("/tmp/oar-p2p-job-{job_id}.owner")
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "job {job_id} is being used by another oar-p2p invocation ({owner}), wait for it to finish or pass --no-lock"
+)]
+pub struct JobLocked {
+    pub job_id: u32,
+    pub owner: String,
+}
+
+/// the locks of the jobs, held until the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct JobLock {
+    _child: Arc<Child>,
+}
+
+/// locks held by this process, so a command that runs others, like `experiment run`, does not
+/// conflict with itself.
+static HELD: Mutex<Vec<(Vec<u32>, Weak<Child>)>> = Mutex::new(Vec::new());
+
+/// script, executed on the frontend, that takes an exclusive `flock` on every job and holds it
+/// until its stdin is closed, which happens when the process holding the lock exits or its
+/// connection to the frontend drops.
+///
+/// prints `locked` once all locks are taken, or `busy <job id> <owner>` if one of them is held.
+pub fn acquire_script(job_ids: &[u32], owner: &str) -> String {
+    let mut script = String::from(
+        "set -e\ncommand -v flock >/dev/null || { echo 'error flock is not installed' ; exit 0 ; }\n",
+    );
+    for (fd, &job_id) in (3..).zip(job_ids) {
+        let lock = lock_path(job_id);
+        let owner = owner_path(job_id);
+        script.push_str(&format!(
+            "exec {fd}>{lock}\nif ! flock -n {fd} ; then echo \"busy {job_id} $(cat {owner} 2>/dev/null || true)\" ; exit 0 ; fi\n"
+        ));
+    }
+    for &job_id in job_ids {
+        script.push_str(&format!(
+            "echo {} > {}\n",
+            shell::quote(owner),
+            owner_path(job_id)
+        ));
+    }
+    script.push_str("echo locked\ncat >/dev/null\n");
+    script
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Answer {
+    Locked,
+    Busy { job_id: u32, owner: String },
+    Error(String),
+}
+
+fn parse_answer(line: &str) -> Option<Answer> {
+    let line = line.trim();
+    if line == "locked" {
+        return Some(Answer::Locked);
+    }
+    if let Some(rest) = line.strip_prefix("busy ") {
+        let (job_id, owner) = rest.split_once(' ').unwrap_or((rest, ""));
+        let owner = match owner.trim() {
+            "" => "unknown owner".to_string(),
+            owner => owner.to_string(),
+        };
+        return Some(Answer::Busy {
+            job_id: job_id.parse().ok()?,
+            owner,
+        });
+    }
+    line.strip_prefix("error ")
+        .map(|message| Answer::Error(message.to_string()))
+}
+
+/// description of this invocation, shown to another invocation that finds the lock taken.
+fn owner() -> String {
+    let host = std::fs::read_to_string("/etc/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_default();
+    let since = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    format!(
+        "pid {} on {}, started at {since}: oar-p2p {command}",
+        std::process::id(),
+        host.trim()
+    )
+}
+
+/// take the lock of every job of the context, failing with [`JobLocked`] if another invocation
+/// holds one of them.
+///
+/// the locks live on the frontend so invocations from different hosts see each other. if the lock
+/// cannot be taken for another reason, like `flock` missing on the frontend, this only warns.
+pub async fn acquire(ctx: &Context) -> Result<Option<JobLock>> {
+    let mut job_ids = ctx.job_ids().await?;
+    job_ids.sort_unstable();
+    job_ids.dedup();
+    if let Some(held) = held(&job_ids) {
+        return Ok(Some(held));
+    }
+
+    let script = acquire_script(&job_ids, &owner());
+    let command = match ctx.node {
+        // running from a machine is not supported, the lock is at least kept on that machine
+        ExecutionNode::Frontend | ExecutionNode::Machine(_) => {
+            let mut command = Command::new("bash");
+            command.arg("-c").arg(&script);
+            command
+        }
+        ExecutionNode::Unknown => {
+            let mut command = Command::new("ssh");
+            command
+                .arg(ctx.frontend_hostname()?)
+                .args(["bash", "-c", &shell::quote(&script)]);
+            command
+        }
+    };
+    match wait_acquired(command).await? {
+        Some(child) => {
+            let child = Arc::new(child);
+            HELD.lock()
+                .unwrap()
+                .push((job_ids.clone(), Arc::downgrade(&child)));
+            tracing::debug!("locked jobs {job_ids:?}");
+            Ok(Some(JobLock { _child: child }))
+        }
+        None => Ok(None),
+    }
+}
+
+fn held(job_ids: &[u32]) -> Option<JobLock> {
+    let mut held = HELD.lock().unwrap();
+    held.retain(|(_, child)| child.strong_count() > 0);
+    held.iter()
+        .find(|(ids, _)| job_ids.iter().all(|id| ids.contains(id)))
+        .and_then(|(_, child)| child.upgrade())
+        .map(|child| JobLock { _child: child })
+}
+
+/// spawn the script that takes the locks and wait for its answer. returns the process holding
+/// them, or `None` if it failed without saying the jobs are busy.
+async fn wait_acquired(mut command: Command) -> Result<Option<Child>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()
+        .context("spawning lock process")?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut lines = BufReader::new(stdout).lines();
+    let answer = tokio::time::timeout(ACQUIRE_TIMEOUT, async {
+        while let Some(line) = lines.next_line().await? {
+            if let Some(answer) = parse_answer(&line) {
+                return Ok(Some(answer));
+            }
+        }
+        Ok::<_, std::io::Error>(None)
+    })
+    .await;
+    match answer {
+        Ok(Ok(Some(Answer::Locked))) => Ok(Some(child)),
+        Ok(Ok(Some(Answer::Busy { job_id, owner }))) => Err(JobLocked { job_id, owner }.into()),
+        Ok(Ok(Some(Answer::Error(message)))) => {
+            tracing::warn!("unable to lock the job, continuing without the lock: {message}");
+            Ok(None)
+        }
+        Ok(Ok(None)) | Ok(Err(_)) => {
+            tracing::warn!("unable to lock the job, continuing without the lock");
+            Ok(None)
+        }
+        Err(_) => {
+            tracing::warn!("timed out locking the job, continuing without the lock");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_answer() {
+        assert_eq!(parse_answer("locked\n"), Some(Answer::Locked));
+        assert_eq!(
+            parse_answer("busy 12 pid 3 on frontend, started at 5: oar-p2p net up"),
+            Some(Answer::Busy {
+                job_id: 12,
+                owner: "pid 3 on frontend, started at 5: oar-p2p net up".to_string()
+            })
+        );
+        assert_eq!(
+            parse_answer("busy 12 "),
+            Some(Answer::Busy {
+                job_id: 12,
+                owner: "unknown owner".to_string()
+            })
+        );
+        assert_eq!(
+            parse_answer("error flock is not installed"),
+            Some(Answer::Error("flock is not installed".to_string()))
+        );
+        assert_eq!(parse_answer("motd"), None);
+    }
+
+    #[tokio::test]
+    async fn test_lock_conflict() {
+        // a job id no real job has, unique to this process
+        let job_id = u32::MAX - std::process::id();
+        let command = |owner: &str| {
+            let mut command = Command::new("bash");
+            command.arg("-c").arg(acquire_script(&[job_id], owner));
+            command
+        };
+        let first = wait_acquired(command("first")).await.unwrap();
+        assert!(first.is_some());
+
+        let err = wait_acquired(command("second")).await.unwrap_err();
+        let locked = err.downcast_ref::<JobLocked>().unwrap();
+        assert_eq!(locked.job_id, job_id);
+        assert_eq!(locked.owner, "first");
+
+        drop(first);
+        // the lock is released once the holder notices its stdin was closed
+        let mut acquired = None;
+        for _ in 0..50 {
+            match wait_acquired(command("third")).await {
+                Ok(child) => {
+                    acquired = child;
+                    break;
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+        assert!(acquired.is_some());
+        drop(acquired);
+        let _ = std::fs::remove_file(lock_path(job_id));
+        let _ = std::fs::remove_file(owner_path(job_id));
+    }
+}
@@ -1,8 +1,14 @@
-use std::sync::Arc;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
 
 use eyre::{Context as _, Result};
 use futures::{StreamExt as _, stream::FuturesUnordered};
 use tokio::sync::Semaphore;
+use tracing::Instrument as _;
+
+use crate::progress::{MachineState, Progress};
 
 macro_rules! define_machines {
     ($(($name:ident, $idx:expr, $hostname:expr, $cpus:expr, $interface:expr)),*) => {
@@ -39,6 +45,20 @@ macro_rules! define_machines {
             }
         }
 
+        impl serde::Serialize for Machine {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.hostname())
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for Machine {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let hostname = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+                Self::from_hostname(&hostname)
+                    .ok_or_else(|| serde::de::Error::custom(format!("unknown machine '{hostname}'")))
+            }
+        }
+
         impl Machine {
             pub fn hostname(&self) -> &'static str {
                 match self {
@@ -59,6 +79,11 @@ macro_rules! define_machines {
                 }
             }
 
+            /// every machine of the cluster.
+            pub fn all() -> impl Iterator<Item = Self> {
+                [$(Self::$name,)*].into_iter()
+            }
+
             pub fn from_index(index: usize) -> Option<Self> {
                 match index {
                     $($idx => Some(Self::$name),)*
@@ -156,7 +181,53 @@ where
     RET: Send + 'static,
     FUT: std::future::Future<Output = Result<RET>>,
 {
-    let limit = match std::env::var("OAR_P2P_CONCURRENCY_LIMIT") {
+    for_each_with_limit(machines, concurrency_limit(), f).await
+}
+
+/// like [`for_each`] but shows which machines are pending, running, done or failed, see
+/// [`Progress`].
+pub async fn for_each_progress<F, FUT, RET>(
+    label: &str,
+    machines: impl IntoIterator<Item = &Machine>,
+    f: F,
+) -> Result<Vec<(Machine, RET)>>
+where
+    F: Fn(Machine) -> FUT,
+    RET: Send + 'static,
+    FUT: std::future::Future<Output = Result<RET>>,
+{
+    for_each_with_limit_progress(label, machines, concurrency_limit(), f).await
+}
+
+/// like [`for_each_with_limit`] but shows the progress, see [`for_each_progress`].
+pub async fn for_each_with_limit_progress<F, FUT, RET>(
+    label: &str,
+    machines: impl IntoIterator<Item = &Machine>,
+    limit: usize,
+    f: F,
+) -> Result<Vec<(Machine, RET)>>
+where
+    F: Fn(Machine) -> FUT,
+    RET: Send + 'static,
+    FUT: std::future::Future<Output = Result<RET>>,
+{
+    let machines = machines.into_iter().collect::<Vec<_>>();
+    let progress = Mutex::new(Progress::new(label, machines.iter().copied()));
+    let span = tracing::debug_span!("phase", phase = label, machines = machines.len());
+    for_each_inner(machines, limit, Some(&progress), f)
+        .instrument(span)
+        .await
+}
+
+/// concurrency limit used when `OAR_P2P_CONCURRENCY_LIMIT` is not set, 0 for no limit.
+static DEFAULT_CONCURRENCY_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_default_concurrency_limit(limit: usize) {
+    DEFAULT_CONCURRENCY_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+fn concurrency_limit() -> usize {
+    match std::env::var("OAR_P2P_CONCURRENCY_LIMIT") {
         Ok(value) => {
             tracing::trace!("parsing concurrency limit value '{value}'");
             let limit = value
@@ -165,9 +236,8 @@ where
             tracing::debug!("using concurrency limit = {limit}");
             limit
         }
-        Err(_) => 0,
-    };
-    for_each_with_limit(machines, limit, f).await
+        Err(_) => DEFAULT_CONCURRENCY_LIMIT.load(Ordering::Relaxed),
+    }
 }
 
 pub async fn for_each_with_limit<F, FUT, RET>(
@@ -180,6 +250,25 @@ where
     RET: Send + 'static,
     FUT: std::future::Future<Output = Result<RET>>,
 {
+    for_each_inner(machines, limit, None, f).await
+}
+
+async fn for_each_inner<F, FUT, RET>(
+    machines: impl IntoIterator<Item = &Machine>,
+    limit: usize,
+    progress: Option<&Mutex<Progress>>,
+    f: F,
+) -> Result<Vec<(Machine, RET)>>
+where
+    F: Fn(Machine) -> FUT,
+    RET: Send + 'static,
+    FUT: std::future::Future<Output = Result<RET>>,
+{
+    let set_state = |machine: Machine, state: MachineState| {
+        if let Some(progress) = progress {
+            progress.lock().unwrap().set(machine, state);
+        }
+    };
     let sem = Arc::new(Semaphore::new(if limit == 0 {
         Semaphore::MAX_PERMITS
     } else {
@@ -188,11 +277,19 @@ where
     let mut futures = FuturesUnordered::new();
 
     for &machine in machines {
-        let fut = f(machine);
+        // the spans of the task are children of the machine span, see `--otlp-endpoint`
+        let span = tracing::debug_span!("machine", machine = %machine);
+        let fut = span.in_scope(|| f(machine)).instrument(span);
         let sem = sem.clone();
         let fut = async move {
             let _permit = sem.acquire().await.unwrap();
-            (machine, fut.await)
+            set_state(machine, MachineState::Running);
+            let result = fut.await;
+            match result {
+                Ok(_) => set_state(machine, MachineState::Done),
+                Err(_) => set_state(machine, MachineState::Failed),
+            }
+            (machine, result)
         };
         futures.push(fut);
     }
@@ -1,50 +1,154 @@
 #![feature(exit_status_error)]
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    io::IsTerminal as _,
     net::Ipv4Addr,
     path::{Path, PathBuf},
     process::Output,
-    time::{Duration, Instant, SystemTime},
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, CommandFactory as _, FromArgMatches as _, Parser, Subcommand, ValueHint};
+use clap_complete::ArgValueCandidates;
 use eyre::Context as _;
 use eyre::Result;
 use machine::Machine;
-use serde::Deserialize;
 use tokio::{
     io::{AsyncReadExt as _, AsyncWriteExt as _},
     process::Command,
 };
 
+use oar_p2p::{
+    address_allocation_policy::{AddressAllocationPolicy, MachineWeight},
+    fingerprint,
+    latency_matrix::{self, LatencyMatrix},
+    machine,
+    netconfig::{
+        DEFAULT_NFT_CHUNK_SIZE, MachineConfig, NetPlan, machine_configuration_script,
+        machine_generate_configs, machine_nft_chunk_scripts, net_plan,
+    },
+    progress,
+    runtime::{self, ContainerRuntime},
+    timing,
+};
+
 use crate::{
-    address_allocation_policy::AddressAllocationPolicy,
     context::{Context, ExecutionNode},
-    latency_matrix::LatencyMatrix,
-    signal::{Signal, SignalSpec},
+    failure_allowance::FailureAllowance,
+    run_state::{RunPhase, RunState, RunStateFile},
+    signal::{RunSignalSpec, Signal, SignalSpec},
 };
 
-pub mod address_allocation_policy;
-pub mod context;
-pub mod latency_matrix;
-pub mod machine;
-pub mod oar;
-pub mod signal;
+mod agent;
+mod bench;
+mod churn;
+mod completions;
+mod compression;
+mod config;
+mod context;
+mod doctor;
+mod experiment;
+mod failure_allowance;
+mod foreign_state;
+mod gc;
+mod lock;
+mod manifest;
+mod mapping;
+mod measure;
+mod monitor;
+mod native_ssh;
+mod oar;
+mod otlp;
+mod pcap;
+mod peers;
+mod placement;
+mod provenance;
+mod ps;
+mod registry;
+mod retry;
+mod run_state;
+mod schedule;
+mod self_update;
+mod shell;
+mod signal;
+mod ssh;
+mod stats;
+mod timeline;
+mod watch;
 
 const CONTAINER_IMAGE_NAME: &str = "local/oar-p2p-networking";
 
+/// directory on the machines where the schedule inputs are copied to.
+const INPUTS_DIR: &str = "/tmp/oar-p2p-inputs";
+
+/// directory on the machines where the registry credentials are installed while pulling.
+const REGISTRY_CONFIG_DIR: &str = "/tmp/oar-p2p-registry";
+
+/// rough estimate of how long configuring or cleaning the network takes.
+const NET_WALLTIME_ESTIMATE: Duration = Duration::from_secs(5 * 60);
+
+/// name of the signal file that releases the start barrier, see `run --barrier`.
+const BARRIER_SIGNAL: &str = "barrier";
+
+/// rough estimate of the time `run` takes excluding the signal delays and the container execution.
+const RUN_WALLTIME_ESTIMATE: Duration = Duration::from_secs(10 * 60);
+
 #[derive(Debug, Parser)]
 #[command(version = env!("GIT_VERSION"))]
 struct Cli {
     #[clap(subcommand)]
     cmd: SubCmd,
+
+    /// how the results of a command are printed to stdout.
+    ///
+    /// with `json` every command that prints a result prints a single json document instead of
+    /// text meant for humans. logs are always written to stderr.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        env = "OAR_P2P_OUTPUT"
+    )]
+    output: OutputMode,
+
+    /// export the spans of this invocation to an otlp/http collector, like
+    /// `http://localhost:4318`.
+    ///
+    /// every phase and every machine in it gets its own span so a trace viewer shows which
+    /// machine held up an operation. the spans are sent every few seconds as they close.
+    #[clap(long, global = true, env = "OAR_P2P_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<otlp::OtlpEndpoint>,
+
+    /// profile of the configuration file whose values are used as defaults, see
+    /// `[profile.<name>]` in `~/.config/oar-p2p/config.toml`.
+    #[clap(long, global = true, env = config::PROFILE_ENV)]
+    profile: Option<String>,
 }
 
-#[derive(Debug, Args)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputMode {
+    /// text meant for humans.
+    #[default]
+    Text,
+    /// json meant for scripts.
+    Json,
+}
+
+fn print_json(value: &impl serde::Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Args)]
 struct Common {
     /// oar job id
-    #[clap(long, env = "OAR_JOB_ID")]
-    job_id: Option<u32>,
+    ///
+    /// this flag can be used more than once, or given a comma-separated list, to use the machines
+    /// of multiple jobs together.
+    #[clap(long, env = "OAR_JOB_ID", value_delimiter = ',', add = ArgValueCandidates::new(complete_job_ids))]
+    job_id: Vec<u32>,
 
     /// infer the oar job id
     ///
@@ -61,13 +165,315 @@ struct Common {
     /// cluster username, needed if running locally with differing usernames
     #[clap(long, env = "CLUSTER_USERNAME")]
     cluster_username: Option<String>,
+
+    /// base url of the oar rest api. i.e. `https://<frontend>/oarapi`.
+    ///
+    /// when set, job information is obtained from the rest api instead of running `oarstat` on
+    /// the frontend.
+    #[clap(long, env = "OAR_API_URL")]
+    oar_api_url: Option<String>,
+
+    /// token used to authenticate with the oar rest api, sent as a bearer token.
+    #[clap(long, env = "OAR_API_TOKEN", hide_env_values = true)]
+    oar_api_token: Option<String>,
+
+    /// container runtime used on the machines.
+    #[clap(long, env = "OAR_P2P_RUNTIME", value_enum, default_value_t)]
+    runtime: ContainerRuntime,
+
+    /// open a new ssh connection for every command sent to the machines.
+    ///
+    /// by default a single master connection per machine is kept open and reused by all commands.
+    #[clap(long, env = "OAR_P2P_NO_SSH_MULTIPLEXING")]
+    no_ssh_multiplexing: bool,
+
+    /// run the scripts sent to the machines through an agent.
+    ///
+    /// the oar-p2p binary is copied to every machine once and runs there in `agent` mode, it
+    /// receives all scripts over a single ssh channel and keeps the networking container running
+    /// between them. if the agent cannot be started on a machine, scripts are sent over ssh.
+    #[clap(long, env = "OAR_P2P_AGENT")]
+    agent: bool,
+
+    /// send the commands, logs and inputs to and from the machines over an embedded ssh client
+    /// instead of the ssh, scp and rsync binaries.
+    ///
+    /// a single connection per machine is opened, through the frontend when not running on it,
+    /// and every command runs on its own channel. keys are read from ssh-agent and `~/.ssh`, and
+    /// only the frontend's entry in `~/.ssh/config` is used, `--ssh-option` is ignored. if a
+    /// machine cannot be reached this way, the ssh binary is used for it.
+    #[clap(long, env = "OAR_P2P_NATIVE_SSH")]
+    native_ssh: bool,
+
+    /// number of times a command sent to a machine is retried when it fails because of the ssh
+    /// connection or the container runtime's daemon, with exponential backoff between attempts.
+    ///
+    /// scripts that fail on their own are never retried. a failure of the container runtime is
+    /// only retried when starting the networking container, since a script may have run other
+    /// commands before the runtime failed.
+    #[clap(long, env = "OAR_P2P_REMOTE_RETRIES", default_value_t = retry::DEFAULT_REMOTE_RETRIES)]
+    remote_retries: u32,
+
+    /// do not lock the job.
+    ///
+    /// commands that change the machines take a lock on the frontend for every job they use, so a
+    /// second invocation against the same job fails right away instead of interleaving its
+    /// scripts with the first.
+    #[clap(long, env = "OAR_P2P_NO_LOCK")]
+    no_lock: bool,
+
+    /// option passed to ssh, with `-o`, when connecting to the machines. i.e.
+    /// `ServerAliveInterval=30`. this flag can be used more than once. the embedded ssh client of
+    /// `--native-ssh` does not use them.
+    #[clap(long, env = "OAR_P2P_SSH_OPTION")]
+    ssh_option: Vec<String>,
 }
 
 #[derive(Debug, Subcommand)]
 enum SubCmd {
     Net(NetArgs),
-    Run(RunArgs),
+    Run(Box<RunCmdArgs>),
     Clean(CleanArgs),
+    /// remove what oar-p2p left on machines by jobs that are no longer running.
+    ///
+    /// the machines of the user's running jobs, or the ones given with `--machine`, are scanned for
+    /// oar-p2p addresses, qdiscs, nft tables, containers and logs. leftovers of a job that oar has
+    /// not ended, of any user, are kept.
+    Gc(GcArgs),
+    Oar(OarArgs),
+    Registry(RegistryArgs),
+    Experiment(ExperimentArgs),
+    Signal(SignalArgs),
+    Monitor(MonitorArgs),
+    /// print the completion script of a shell.
+    ///
+    /// subcommands and flags are completed, along with the machine hostnames for `--machine`,
+    /// the running jobs for `--job-id` and the values of flags that only accept a few. for
+    /// example, add `source <(oar-p2p completions bash)` to `~/.bashrc`.
+    Completions(CompletionsArgs),
+    /// write a man page for every subcommand to a directory.
+    Man(ManArgs),
+    /// replace this binary with the latest release, or the one given with `--tag`.
+    ///
+    /// the release tarball for this platform is downloaded from github and verified against the
+    /// checksums published with the release before the binary is replaced.
+    SelfUpdate(SelfUpdateArgs),
+    /// print the version of oar-p2p, and with `--check` whether the environment can run it.
+    ///
+    /// the checks are that ssh and scp are installed, that the frontend is reachable and has
+    /// oarstat, and the container runtime, kernel, nft and tc of a machine of the job. every
+    /// failure comes with a hint on how to fix it and the command fails if any check failed.
+    Version(VersionArgs),
+    /// serve the scripts sent by another oar-p2p over stdin, see `--agent`.
+    #[clap(hide = true)]
+    Agent(AgentArgs),
+}
+
+#[derive(Debug, Args)]
+struct CompletionsArgs {
+    shell: completions::Shell,
+}
+
+#[derive(Debug, Args)]
+struct ManArgs {
+    /// directory the pages are written to, it is created if it does not exist.
+    #[clap(value_hint = ValueHint::DirPath, default_value = "man")]
+    output_dir: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct VersionArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// also check that the environment can run oar-p2p.
+    #[clap(long)]
+    check: bool,
+
+    /// machine whose environment is checked. defaults to the first machine of the job, the
+    /// machine checks are skipped if there is no job.
+    #[clap(long, requires = "check", add = ArgValueCandidates::new(completions::machines))]
+    machine: Option<Machine>,
+}
+
+#[derive(Debug, Args)]
+struct SelfUpdateArgs {
+    /// release to install, like `v0.2.24`. defaults to the latest release.
+    #[clap(long)]
+    tag: Option<String>,
+
+    /// only print whether a newer release is available.
+    #[clap(long)]
+    check: bool,
+
+    /// replace the binary even if it is not a release, like a build from source, or is already
+    /// the requested release.
+    #[clap(long)]
+    force: bool,
+
+    /// also install the release on the frontend, at `--frontend-path`.
+    #[clap(long)]
+    frontend: bool,
+
+    /// path of the binary on the frontend, relative paths start at the home directory.
+    #[clap(long, default_value = ".local/bin/oar-p2p")]
+    frontend_path: String,
+
+    /// hostname used to access the frontend using ssh.
+    #[clap(long, env = "FRONTEND_HOSTNAME")]
+    frontend_hostname: Option<String>,
+
+    /// github api endpoint with the releases.
+    #[clap(long, env = "OAR_P2P_RELEASES_URL", default_value = self_update::RELEASES_URL, hide = true)]
+    releases_url: String,
+}
+
+#[derive(Debug, Args)]
+struct AgentArgs {
+    /// container runtime used to run the networking container.
+    #[clap(long, value_enum, default_value_t)]
+    runtime: ContainerRuntime,
+}
+
+#[derive(Debug, Args)]
+struct MonitorArgs {
+    #[clap(subcommand)]
+    cmd: MonitorSubCmd,
+}
+
+#[derive(Debug, Subcommand)]
+enum MonitorSubCmd {
+    /// deploy node-exporter and cadvisor on every machine of the job and a prometheus that scrapes
+    /// them on one machine.
+    ///
+    /// the monitoring containers are kept across runs and by `clean`.
+    Up(MonitorUpArgs),
+    /// remove the monitoring containers from every machine of the job.
+    Down(MonitorDownArgs),
+}
+
+#[derive(Debug, Args)]
+struct MonitorUpArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// machine that runs prometheus. defaults to the first machine of the job.
+    #[clap(long, add = ArgValueCandidates::new(completions::machines))]
+    machine: Option<Machine>,
+
+    /// port prometheus listens on.
+    #[clap(long, default_value = "9090")]
+    port: u16,
+
+    /// seconds between scrapes.
+    #[clap(long, default_value = "5")]
+    scrape_interval: u64,
+}
+
+#[derive(Debug, Args)]
+struct MonitorDownArgs {
+    #[clap(flatten)]
+    common: Common,
+}
+
+#[derive(Debug, Args)]
+struct SignalArgs {
+    #[clap(subcommand)]
+    cmd: SignalSubCmd,
+}
+
+#[derive(Debug, Subcommand)]
+enum SignalSubCmd {
+    /// deliver a signal to the running containers of the run on every machine of the job.
+    ///
+    /// process signals, like `SIGUSR1`, are sent with `docker kill --signal`. any other signal
+    /// becomes visible as a file under `/oar-p2p/` in every container, the same way as
+    /// `run --signal`, or is sent as an udp datagram with `--udp-port`.
+    Send(SignalSendArgs),
+    /// wait until running containers of the run emit a signal.
+    ///
+    /// a container emits a signal by creating a file named after it under `/tmp/oar-p2p-emit/`,
+    /// for example `/tmp/oar-p2p-emit/bootstrapped`.
+    Wait(SignalWaitArgs),
+}
+
+#[derive(Debug, Args)]
+struct SignalWaitArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// number of containers that must emit the signal. if not specified, every container that is
+    /// running when the command starts.
+    #[clap(long)]
+    count: Option<usize>,
+
+    /// only count containers whose name matches this glob, i.e. `node-*`. this flag can be used
+    /// more than once.
+    #[clap(long)]
+    container: Vec<String>,
+
+    /// seconds between checks.
+    #[clap(long, default_value = "2")]
+    interval: u64,
+
+    /// fail if the containers did not emit the signal after this many seconds.
+    #[clap(long)]
+    timeout: Option<u64>,
+
+    /// the signal to wait for.
+    signal: Signal,
+}
+
+#[derive(Debug, Args)]
+struct SignalSendArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// only containers on this machine. this flag can be used more than once.
+    #[clap(long, add = ArgValueCandidates::new(completions::machines))]
+    machine: Vec<Machine>,
+
+    /// only containers whose name matches this glob, i.e. `node-*`. this flag can be used more
+    /// than once. signal files are shared by all containers of a machine so this requires a
+    /// process signal or `--udp-port`.
+    #[clap(long)]
+    container: Vec<String>,
+
+    /// send the signal as an udp datagram with the content `<signal> <timestamp>` to this port of
+    /// the address of every container, instead of as a file.
+    #[clap(long, env = "OAR_P2P_SIGNAL_UDP_PORT")]
+    udp_port: Option<u16>,
+
+    /// the signal to deliver.
+    signal: Signal,
+}
+
+#[derive(Debug, Args)]
+struct ExperimentArgs {
+    #[clap(subcommand)]
+    cmd: ExperimentSubCmd,
+}
+
+#[derive(Debug, Subcommand)]
+enum ExperimentSubCmd {
+    /// configure the network, run the schedule and clean the network, as described by an
+    /// experiment file.
+    ///
+    /// the experiment file is toml with a `network` table holding the flags of `net up` and a
+    /// `run` table holding the flags of `run`, with `_` in place of `-`. relative paths are
+    /// resolved against the directory of the experiment file. the network is cleaned even if the
+    /// run fails, unless `keep_network = true`.
+    Run(ExperimentRunArgs),
+}
+
+#[derive(Debug, Args)]
+struct ExperimentRunArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// path to the experiment file.
+    #[clap(value_hint = ValueHint::FilePath)]
+    experiment: PathBuf,
 }
 
 #[derive(Debug, Args)]
@@ -82,6 +488,28 @@ enum NetSubCmd {
     Down(NetDownArgs),
     Show(NetShowArgs),
     Preview(NetPreviewArgs),
+    Bench(NetBenchArgs),
+    Measure(NetMeasureArgs),
+}
+
+#[derive(Debug, Default, Args)]
+struct ClobberArgs {
+    /// clean the network even if a machine has tc qdiscs that were not created by oar-p2p.
+    ///
+    /// by default the command stops before cleaning and lists them, they might belong to another
+    /// experiment on a shared machine.
+    #[clap(long)]
+    clobber: bool,
+}
+
+#[derive(Debug, Args)]
+struct StrictArgs {
+    /// fail if the latency matrix has values that lose precision when configured.
+    ///
+    /// latencies are applied with millisecond resolution, by default the values that have
+    /// sub-millisecond parts are only reported in a warning.
+    #[clap(long)]
+    strict: bool,
 }
 
 #[derive(Debug, Args)]
@@ -95,7 +523,8 @@ struct NetUpArgs {
     ///
     /// 1. total number of addresses: in this policy, a fixed number of addresses will be allocated
     ///    between all machines in the job. this is represented by a single number, for example,
-    ///    `64` will allocated a total of 64 addresses evenly across all machines in the job.
+    ///    `64` will allocated a total of 64 addresses across all machines in the job, split
+    ///    proportionally to the number of cpus of each machine.
     ///
     /// 2. per cpu: in this policy, a set number of addresses will be allocated per cpu. machines
     ///    with more cpus will have more addresses. this is represented by `<n>/cpu`, for example,
@@ -104,9 +533,21 @@ struct NetUpArgs {
     /// 3. per machine: in this policy, a set number of addresses will be allocated per machine.
     ///    each machine gets the same amount of addresses. this is represented by `<n>/machine`,
     ///    for example, `64/machine` will allocate 64 addresses per machine on every machine.
+    ///
+    /// any policy can be followed by `,max=<n>/machine` to never allocate more than `n`
+    /// addresses on a single machine, for example, `4/cpu,max=32/machine`. with a total, the
+    /// addresses a machine cannot take are split between the other machines.
     #[clap(long)]
     addresses: AddressAllocationPolicy,
 
+    /// scale the number of addresses of a machine, or of every machine of a class, by a weight.
+    ///
+    /// the value is `<machine>=<weight>` or `<class>=<weight>`, for example, `gengar=0.5` halves
+    /// the addresses of every gengar machine and `gengar-1=2` doubles those of gengar-1. a
+    /// machine weight takes precedence over a class weight. this flag can be used more than once.
+    #[clap(long)]
+    weight: Vec<MachineWeight>,
+
     /// path to the latency matrix.
     ///
     /// the latency matrix is a square matrix of latency values in milliseconds.
@@ -117,17 +558,87 @@ struct NetUpArgs {
     /// 78.64806 63.165894 0.0 2.4708898 93.90618 {n}
     /// 83.50032 66.74037 2.4708898 0.0 84.67561 {n}
     /// 99.91315 110.71518 93.90618 84.67561 0.0 {n}
-    #[clap(long)]
+    ///
+    /// use `-` to read the matrix from stdin.
+    #[clap(value_hint = ValueHint::FilePath, long)]
     latency_matrix: PathBuf,
 
     #[clap(long)]
     matrix_wrap: bool,
+
+    /// directory where snapshots of the nft and tc state of every machine are saved.
+    ///
+    /// the snapshots are taken before and after configuring the network and are saved as
+    /// `<machine>.<before|after>.nft` and `<machine>.<before|after>.tc`.
+    #[clap(value_hint = ValueHint::DirPath, long)]
+    snapshot_dir: Option<PathBuf>,
+
+    /// where the json file that maps every machine to its addresses and their latency matrix rows
+    /// is written.
+    #[clap(value_hint = ValueHint::FilePath, long, default_value = mapping::DEFAULT_MAPPING_PATH)]
+    mapping_out: PathBuf,
+
+    /// number of elements of the nft `mark_pairs` map loaded by each `nft` invocation.
+    ///
+    /// the map is created empty and filled in chunks of this size, one after the other, so large
+    /// maps don't have to be loaded by a single transaction.
+    #[clap(long, default_value_t = DEFAULT_NFT_CHUNK_SIZE)]
+    nft_chunk_size: std::num::NonZeroUsize,
+
+    /// merge the elements of the nft `mark_pairs` map into ranges of addresses.
+    ///
+    /// consecutive addresses of a machine with the same latency to consecutive addresses of
+    /// another machine become a single element, which shrinks the map by orders of magnitude for
+    /// matrices where regions share a latency. requires nftables 0.9.4 and linux 5.6.
+    #[clap(long)]
+    nft_intervals: bool,
+
+    /// print what would be configured on each machine of the job without connecting to them.
+    ///
+    /// the machines of the job are resolved as usual and a summary with the addresses, latency
+    /// classes, nft map size and script size of every machine is printed.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// with `--dry-run`, also print the scripts that would be executed on each machine.
+    #[clap(long, requires = "dry_run")]
+    scripts: bool,
+
+    /// configure every machine, even those that already have this configuration.
+    ///
+    /// by default the fingerprint of the configuration applied to each machine is kept on the
+    /// machine and machines whose fingerprint matches are left untouched.
+    #[clap(long)]
+    force: bool,
+
+    /// leave the machines as they are if configuring one of them fails.
+    ///
+    /// by default the network is removed from every machine of the job when configuring any of
+    /// them fails, so the job is never left with only part of the network configured.
+    #[clap(long)]
+    keep_partial: bool,
+
+    #[clap(flatten)]
+    clobber: ClobberArgs,
+
+    #[clap(flatten)]
+    strict: StrictArgs,
 }
 
 #[derive(Debug, Args)]
 struct NetDownArgs {
     #[clap(flatten)]
     common: Common,
+
+    /// directory where snapshots of the nft and tc state of every machine are saved.
+    ///
+    /// the snapshots are taken before and after cleaning the network and are saved as
+    /// `<machine>.<before|after>.nft` and `<machine>.<before|after>.tc`.
+    #[clap(value_hint = ValueHint::DirPath, long)]
+    snapshot_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    clobber: ClobberArgs,
 }
 
 #[derive(Debug, Args)]
@@ -137,560 +648,6303 @@ struct NetShowArgs {
 
     #[clap(long)]
     interleave: bool,
+
+    /// how the addresses are printed.
+    #[clap(long, value_enum, default_value_t)]
+    format: NetShowFormat,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum NetShowFormat {
+    /// a `<machine> <address>` line per address.
+    #[default]
+    Text,
+    /// a json array of `{"machine", "address"}` records.
+    Json,
+    /// csv with a `machine,address` header.
+    Csv,
+    /// an `/etc/hosts` snippet that names the addresses `oar-p2p-<index>`.
+    Hosts,
+    /// `export` lines with `OAR_P2P_ADDRESSES` and the machine and address of each index.
+    Env,
 }
 
 #[derive(Debug, Args)]
 struct NetPreviewArgs {
-    #[clap(long)]
+    #[clap(long, add = ArgValueCandidates::new(completions::machines))]
     machine: Vec<Machine>,
 
+    #[clap(flatten)]
+    strict: StrictArgs,
+
     #[clap(long)]
     addresses: AddressAllocationPolicy,
 
     #[clap(long)]
+    weight: Vec<MachineWeight>,
+
+    /// path to the latency matrix, or `-` to read it from stdin.
+    #[clap(value_hint = ValueHint::FilePath, long)]
     latency_matrix: PathBuf,
 
     #[clap(long)]
     matrix_wrap: bool,
+
+    /// number of elements of the nft `mark_pairs` map loaded by each `nft` invocation.
+    #[clap(long, default_value_t = DEFAULT_NFT_CHUNK_SIZE)]
+    nft_chunk_size: std::num::NonZeroUsize,
+
+    /// merge the elements of the nft `mark_pairs` map into ranges of addresses.
+    #[clap(long)]
+    nft_intervals: bool,
 }
 
 #[derive(Debug, Args)]
-struct RunArgs {
+struct NetBenchArgs {
     #[clap(flatten)]
     common: Common,
 
-    /// directory where all the log files will be placed.
+    /// pair of addresses to measure, like `10.16.0.1-10.17.0.1`, traffic goes from the first to
+    /// the second.
     ///
-    /// this directory will be created if it does not exist.
-    /// for each container, there will be a seperate file for the stdout and sterr.
+    /// this flag can be used more than once. without it the first address of every machine is
+    /// measured against the first address of the next machine.
     #[clap(long)]
-    output_dir: PathBuf,
+    pair: Vec<bench::BenchPair>,
 
-    /// declare a signal. this flag can be used more than once to declare multiple signals.
+    /// seconds each pair is measured for.
+    #[clap(long, default_value_t = 10)]
+    duration: u64,
+
+    /// number of parallel tcp streams of each client.
+    #[clap(long, default_value_t = 1)]
+    streams: u32,
+
+    /// measure all pairs at the same time instead of one after the other.
     ///
-    /// a signal is an empty file that will be come visible to all containers after some amount of
-    /// time under the `/oar-p2p/` directory. a sginal has the format `<signal name>:<delay>`,
-    /// where the delay is given in seconds. using the signal `start:10` as an example, this means
-    /// that after all containers are started, a 10 second timer will start and when that timer
-    /// expires the file `/oar-p2p/start` will become visible to all containers at roughtly the
-    /// same time allowing them to synchronize their start-ups to within milliseconds. to make use
-    /// of this, your program running in the container must somehow wait for this file to come into
-    /// existance and this can be as simple as having a while loop checking for the file's existing
-    /// with a short sleep, here is an example in java:{n}
-    ///```java{n}
-    ///01.  import java.nio.file.Files;{n}
-    ///02.  import java.nio.file.Path;{n}
-    ///03. {n}
-    ///04.  public static void waitForStartFile() {{n}
-    ///05.      Path startFile = Path.of("/oar-p2p/start");{n}
-    ///06.      while (!Files.exists(startFile)) {{n}
-    ///07.          try {{n}
-    ///08.              Thread.sleep(250);{n}
-    ///09.          } catch (InterruptedException e) {{n}
-    ///10.              Thread.currentThread().interrupt();{n}
-    ///11.              break;{n}
-    ///12.          }{n}
-    ///13.      }{n}
-    ///14.  }{n}
-    ///```{n}
+    /// pairs that share a machine compete for its interface, which is how to find out if the
+    /// emulated traffic of an experiment saturates it.
     #[clap(long)]
-    signal: Vec<SignalSpec>,
-
-    /// the schedule used for execution. if not specified, it will be read from stdin.
-    schedule: Option<PathBuf>,
+    parallel: bool,
 }
 
 #[derive(Debug, Args)]
-struct CleanArgs {
+struct NetMeasureArgs {
     #[clap(flatten)]
     common: Common,
-}
 
-#[derive(Debug, Clone)]
-struct MachineConfig {
-    machine: Machine,
-    addresses: Vec<Ipv4Addr>,
-    nft_script: String,
-    tc_commands: Vec<String>,
-    ip_commands: Vec<String>,
+    /// address mapping written by `net up`, it gives the index of every address.
+    #[clap(value_hint = ValueHint::FilePath, long, default_value = mapping::DEFAULT_MAPPING_PATH)]
+    mapping: PathBuf,
+
+    /// number of pings sent for every pair.
+    #[clap(long, default_value_t = 5)]
+    count: u32,
+
+    /// number of pairs pinged at the same time on each machine.
+    #[clap(long, default_value_t = 32)]
+    parallel: usize,
+
+    /// only measure the pairs between this many randomly chosen addresses.
+    ///
+    /// the matrix has a row for each chosen address, ordered by index, and the chosen indices are
+    /// logged.
+    #[clap(long)]
+    sample: Option<usize>,
+
+    /// seed of the random choice of `--sample`.
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+
+    /// latency matrix given to `net up`, the measured latencies are compared against it.
+    #[clap(value_hint = ValueHint::FilePath, long)]
+    expected: Option<PathBuf>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .with_writer(std::io::stderr)
-        .init();
-    color_eyre::install()?;
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct RunCmdArgs {
+    #[clap(subcommand)]
+    cmd: Option<RunSubCmd>,
 
-    let cli = Cli::parse();
-    match cli.cmd {
-        SubCmd::Net(args) => match args.cmd {
-            NetSubCmd::Up(args) => cmd_net_up(args).await,
-            NetSubCmd::Down(args) => cmd_net_down(args).await,
-            NetSubCmd::Show(args) => cmd_net_show(args).await,
-            NetSubCmd::Preview(args) => cmd_net_preview(args).await,
-        },
-        SubCmd::Run(args) => cmd_run(args).await,
-        SubCmd::Clean(args) => cmd_clean(args).await,
-    }
+    #[clap(flatten)]
+    run: RunArgs,
 }
 
-async fn context_from_common(common: &Common) -> Result<Context> {
-    let ctx = Context::new(
-        common.job_id,
-        common.infer_job_id,
-        common.frontend_hostname.clone(),
-        common.cluster_username.clone(),
-    )
-    .await?;
-
-    if let ExecutionNode::Machine(_) = ctx.node {
-        tracing::warn!(
-            "executing oar-p2p from a job machine is not currently supported, run from the frontend or your own machine"
-        );
-    }
-
-    Ok(ctx)
+#[derive(Debug, Subcommand)]
+enum RunSubCmd {
+    /// pull the images of a schedule on the job machines without running anything.
+    ///
+    /// images of containers with an address are pulled on the machine that owns the address,
+    /// images of containers without an address are pulled on every machine.
+    Pull(RunPullArgs),
+    /// execute a command in a container of the run, on whichever machine hosts it.
+    ///
+    /// the output is streamed to the terminal and the exit code of the command is returned.
+    Exec(RunExecArgs),
+    /// copy the inputs of a schedule to the job machines without running anything.
+    ///
+    /// inputs of containers with an address are copied to the machine that owns the address,
+    /// inputs of containers without an address are copied to every machine.
+    Push(RunPushArgs),
+    /// list the containers created by `run` on every machine of the job.
+    Ps(RunPsArgs),
+    /// print the logs of a container of the run while it is still running.
+    Logs(RunLogsArgs),
+    /// freeze the running containers of the run on every machine of the job.
+    ///
+    /// paused containers keep their state but are not scheduled until `run unpause`, which makes
+    /// it possible to inspect or snapshot an experiment at a single point in time.
+    Pause(RunPauseArgs),
+    /// resume the containers frozen by `run pause`.
+    Unpause(RunPauseArgs),
+    /// follow the containers of a run until they all exit, printing a status summary on every
+    /// poll and alerting when failures cross a threshold.
+    Watch(RunWatchArgs),
 }
 
-async fn cmd_net_up(args: NetUpArgs) -> Result<()> {
-    let context = context_from_common(&args.common).await?;
+#[derive(Debug, Args)]
+struct RunWatchArgs {
+    #[clap(flatten)]
+    common: Common,
 
-    tracing::debug!(
-        "reading latency matrix at {}",
-        args.latency_matrix.display()
-    );
-    let matrix_content = tokio::fs::read_to_string(&args.latency_matrix)
-        .await
-        .context("reading latency matrix")?;
+    /// seconds between polls.
+    #[clap(long, default_value_t = 10)]
+    interval: u64,
 
-    tracing::debug!("parsing latency matrix");
-    let matrix = LatencyMatrix::parse(&matrix_content, latency_matrix::TimeUnit::Milliseconds)
-        .context("parsing latency matrix")?;
+    /// number of failed containers plus unreachable machines that triggers the alert.
+    #[clap(long, default_value_t = 1)]
+    failure_threshold: usize,
 
-    let machines = oar::job_list_machines(&context).await?;
-    let configs = machine_generate_configs(&matrix, args.matrix_wrap, &machines, &args.addresses)?;
-    machines_containers_clean(&context, &machines).await?;
-    machines_net_container_build(&context, &machines).await?;
-    machines_clean(&context, &machines).await?;
-    machines_configure(&context, &configs).await?;
-    Ok(())
-}
+    /// bash script executed locally when the failures cross the threshold, and again whenever
+    /// they grow after that.
+    ///
+    /// the failed containers and unreachable machines are in the space separated
+    /// `OAR_P2P_FAILED_CONTAINERS` and `OAR_P2P_UNREACHABLE_MACHINES` environment variables.
+    #[clap(long)]
+    alert_command: Option<String>,
 
-async fn cmd_net_down(args: NetDownArgs) -> Result<()> {
-    let context = context_from_common(&args.common).await?;
-    let machines = oar::job_list_machines(&context).await?;
-    machines_containers_clean(&context, &machines).await?;
-    machines_net_container_build(&context, &machines).await?;
-    machines_clean(&context, &machines).await?;
-    Ok(())
+    /// directory where the logs of failed containers are saved, as `<name>.stdout` and
+    /// `<name>.stderr`, as soon as they fail.
+    #[clap(value_hint = ValueHint::DirPath, long)]
+    collect_logs: Option<PathBuf>,
 }
 
-async fn cmd_net_show(args: NetShowArgs) -> Result<()> {
-    let context = context_from_common(&args.common).await?;
-    let machines = oar::job_list_machines(&context).await?;
-    let results = machine::for_each(machines.iter(), |machine| {
-        let context = context.clone();
-        async move { machine_list_addresses(&context, machine).await }
-    })
-    .await?;
+#[derive(Debug, Args)]
+struct RunPauseArgs {
+    #[clap(flatten)]
+    common: Common,
 
-    let mut addresses = Vec::default();
-    for (machine, addrs) in results {
-        for addr in addrs {
-            addresses.push((machine, addr));
-        }
-    }
-    addresses.sort();
-    if !args.interleave {
-        for (machine, addr) in addresses {
-            println!("{machine} {addr}");
-        }
-    } else {
-        let mut addrs_per_machine: HashMap<Machine, Vec<Ipv4Addr>> = Default::default();
-        for (machine, addr) in addresses {
-            addrs_per_machine.entry(machine).or_default().push(addr);
-        }
-        while !addrs_per_machine.is_empty() {
-            for machine in &machines {
-                if let Some(addrs) = addrs_per_machine.get_mut(machine) {
-                    if let Some(addr) = addrs.pop() {
-                        println!("{machine} {addr}");
-                    } else {
-                        addrs_per_machine.remove(machine);
-                    }
-                };
-            }
-        }
-    }
-    Ok(())
+    /// only containers whose name matches this glob, i.e. `node-*`. this flag can be used more
+    /// than once.
+    #[clap(long)]
+    name: Vec<String>,
+
+    /// only containers on this machine. this flag can be used more than once.
+    #[clap(long, add = ArgValueCandidates::new(completions::machines))]
+    machine: Vec<Machine>,
 }
 
-async fn cmd_net_preview(args: NetPreviewArgs) -> Result<()> {
-    let matrix_content = tokio::fs::read_to_string(&args.latency_matrix)
-        .await
-        .context("reading latecy matrix")?;
-    let matrix = LatencyMatrix::parse(&matrix_content, latency_matrix::TimeUnit::Milliseconds)
-        .context("parsing latency matrix")?;
-    let machines = args.machine;
-    let configs = machine_generate_configs(&matrix, args.matrix_wrap, &machines, &args.addresses)?;
+#[derive(Debug, Args)]
+struct RunLogsArgs {
+    #[clap(flatten)]
+    common: Common,
 
-    for config in configs {
-        (0..20).for_each(|_| print!("-"));
-        print!(" {} ", config.machine);
-        (0..20).for_each(|_| print!("-"));
-        println!();
-        println!("{}", machine_configuration_script(&config));
-    }
-    Ok(())
+    #[clap(flatten)]
+    lookup: ContainerLookupArgs,
+
+    /// keep printing new output until interrupted or the container exits.
+    #[clap(short, long)]
+    follow: bool,
+
+    /// only print this many lines from the end of the logs.
+    #[clap(long)]
+    tail: Option<u64>,
+
+    /// name of the container.
+    container: String,
 }
 
-fn machine_from_addr(addr: Ipv4Addr) -> Result<Machine> {
-    let machine_index = usize::from(addr.octets()[1]);
-    Machine::from_index(machine_index)
-        .ok_or_else(|| eyre::eyre!("failed to resolve machine from address {addr}"))
+#[derive(Debug, Args)]
+struct RunPsArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// how the containers are printed.
+    #[clap(long, value_enum, default_value_t)]
+    format: OutputFormat,
 }
 
-#[derive(Debug, Clone)]
-struct ScheduledContainer {
-    name: String,
-    image: String,
-    machine: Machine,
-    #[allow(unused)]
-    address: Ipv4Addr,
-    variables: HashMap<String, String>,
+/// how listings are printed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// aligned columns for humans.
+    #[default]
+    Table,
+    /// a json array.
+    Json,
 }
 
-fn parse_schedule(schedule: &str) -> Result<Vec<ScheduledContainer>> {
-    #[derive(Debug, Deserialize)]
-    struct ScheduleItem {
-        name: Option<String>,
-        address: Ipv4Addr,
-        image: String,
-        env: HashMap<String, String>,
-    }
+#[derive(Debug, Args)]
+struct RunPushArgs {
+    #[clap(flatten)]
+    common: Common,
 
-    tracing::trace!("parsing schedule:\n{schedule}");
-    let items = serde_json::from_str::<Vec<ScheduleItem>>(schedule)?;
-    let mut containers = Vec::default();
-    for item in items {
-        let name = match item.name {
-            Some(name) => name,
-            None => item.address.to_string(),
-        };
-        let machine = machine_from_addr(item.address)?;
+    #[clap(flatten)]
+    transfer: LogTransferArgs,
 
-        containers.push(ScheduledContainer {
-            name,
-            image: item.image,
-            machine,
-            address: item.address,
-            variables: item.env,
-        });
-    }
-    Ok(containers)
+    /// the schedule whose inputs are copied. if not specified, it will be read from stdin.
+    #[clap(value_hint = ValueHint::FilePath)]
+    schedule: Option<PathBuf>,
 }
 
-async fn cmd_run(args: RunArgs) -> Result<()> {
-    tracing::debug!(
-        "creating output directory if it does not exist at {}",
-        args.output_dir.display()
-    );
-    tokio::fs::create_dir_all(&args.output_dir)
-        .await
-        .context("creating output directory")?;
+// how the machine of a container of a run is found.
+#[derive(Debug, Args)]
+struct ContainerLookupArgs {
+    /// output directory of the run, the container is looked up in its `run-state.json`.
+    ///
+    /// if neither this nor `--schedule` are given, every machine of the job is searched.
+    #[clap(value_hint = ValueHint::DirPath, long, conflicts_with = "schedule")]
+    output_dir: Option<PathBuf>,
 
-    let ctx = context_from_common(&args.common).await?;
-    let schedule = match args.schedule {
-        Some(path) => {
-            tracing::debug!("reading schedule from {}", path.display());
-            tokio::fs::read_to_string(&path)
-                .await
-                .with_context(|| format!("reading schedule file: {}", path.display()))?
-        }
-        None => {
-            tracing::debug!("reading schedule from stdin");
-            let mut stdin = String::default();
-            tokio::io::stdin()
-                .read_to_string(&mut stdin)
-                .await
-                .context("reading schedule from stdin")?;
-            stdin
-        }
-    };
-    let containers = parse_schedule(&schedule)?;
-    let machines = oar::job_list_machines(&ctx).await?;
+    /// schedule of the run, the container is looked up in it.
+    ///
+    /// containers without an address in the schedule can only be found with `--output-dir`.
+    #[clap(value_hint = ValueHint::FilePath, long)]
+    schedule: Option<PathBuf>,
+}
 
-    machines_containers_clean(&ctx, &machines).await?;
-    machine::for_each(&machines, |machine| {
-        let ctx = ctx.clone();
-        let containers = containers
-            .iter()
-            .filter(|c| c.machine == machine)
-            .cloned()
-            .collect::<Vec<_>>();
-        async move { machine_create_containers(&ctx, machine, &containers).await }
-    })
-    .await?;
+#[derive(Debug, Args)]
+struct RunExecArgs {
+    #[clap(flatten)]
+    common: Common,
 
-    tracing::info!("starting all containers on all machines");
-    machine::for_each(
-        machines
-            .iter()
-            .filter(|&machine| containers.iter().any(|c| c.machine == *machine)),
-        |machine| machine_start_containers(&ctx, machine),
-    )
-    .await?;
+    #[clap(flatten)]
+    lookup: ContainerLookupArgs,
 
-    let signal_start_instant = Instant::now();
-    let signal_specs = {
-        let mut specs = args.signal.clone();
-        specs.sort_by_key(|s| s.delay);
-        specs
-    };
+    /// name of the container.
+    container: String,
 
-    for spec in signal_specs {
-        tracing::info!("waiting to trigger signal {}", spec.signal);
-        let expire = signal_start_instant + spec.delay;
-        tokio::time::sleep_until(expire.into()).await;
+    /// the command to execute, after a `--`.
+    #[clap(last = true, required = true)]
+    command: Vec<String>,
+}
 
-        tracing::info!("triggering signal {}", spec.signal);
-        let signal_timestamp = unix_timestamp();
-        machine::for_each(
-            machines
-                .iter()
-                .filter(|&machine| containers.iter().any(|c| c.machine == *machine)),
-            |machine| machine_signal_containers(&ctx, machine, &spec.signal, signal_timestamp),
-        )
-        .await?;
-    }
+#[derive(Debug, Args)]
+struct RunPullArgs {
+    #[clap(flatten)]
+    common: Common,
 
-    tracing::info!("waiting for all containers to exit");
-    machine::for_each(&machines, |machine| {
-        let ctx = ctx.clone();
-        let containers = containers
-            .iter()
-            .filter(|c| c.machine == machine)
-            .cloned()
-            .collect::<Vec<_>>();
-        async move {
-            machine_containers_wait(&ctx, machine, &containers)
-                .await
-                .with_context(|| format!("waiting for containers on {machine}"))
-        }
-    })
-    .await?;
+    /// the schedule whose images are pulled. if not specified, it will be read from stdin.
+    #[clap(value_hint = ValueHint::FilePath)]
+    schedule: Option<PathBuf>,
 
-    tracing::info!("saving logs to disk on all machines");
-    machine::for_each(&machines, |machine| {
-        let ctx = ctx.clone();
-        let containers = containers
-            .iter()
-            .filter(|c| c.machine == machine)
-            .cloned()
-            .collect::<Vec<_>>();
-        async move { machine_containers_save_logs(&ctx, machine, &containers).await }
-    })
-    .await?;
+    /// docker `config.json` with the credentials used to pull private images.
+    ///
+    /// the file is copied to the machines, readable only by the cluster user, and removed once
+    /// the images are pulled.
+    #[clap(value_hint = ValueHint::FilePath, long, env = "OAR_P2P_REGISTRY_CONFIG")]
+    registry_config: Option<PathBuf>,
+}
 
-    tracing::info!("copying logs from all machines");
-    machine::for_each(
-        machines
-            .iter()
-            .filter(|&machine| containers.iter().any(|c| c.machine == *machine)),
-        |machine| machine_copy_logs_dir(&ctx, machine, &args.output_dir),
-    )
-    .await?;
+// how the logs are copied from the machines to the output directory.
+#[derive(Debug, Clone, Args)]
+struct LogTransferArgs {
+    /// copy the logs of each machine as a single compressed archive instead of file by file.
+    ///
+    /// a `logs-<machine>.tar.gz` is created on each machine and downloaded to the output
+    /// directory, which is much faster when there are many small log files.
+    #[clap(long)]
+    archive: bool,
 
-    Ok(())
-}
+    /// merge the archives of all machines into a single `results.tar.zst` in the output
+    /// directory. requires a local `tar` with zstd support.
+    #[clap(long, requires = "archive")]
+    merge_archives: bool,
 
-async fn cmd_clean(args: CleanArgs) -> Result<()> {
-    let context = context_from_common(&args.common).await?;
-    let machines = oar::job_list_machines(&context).await?;
-    machines_net_container_build(&context, &machines).await?;
-    machines_containers_clean(&context, &machines).await?;
-    machines_clean(&context, &machines).await?;
-    Ok(())
+    /// stream the logs of each machine as a compressed tar over ssh and extract them in the
+    /// output directory. container logs are mostly text and compress very well, which helps when
+    /// the link to the frontend is slow. defaults to gzip, zstd must be installed on the machines.
+    #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "gzip", conflicts_with = "archive")]
+    compress: Option<compression::Compression>,
+
+    /// number of times a failed copy is retried. the logs of a machine are copied again from the
+    /// start, archives copied with `--archive` keep the part that was already transferred.
+    #[clap(long, default_value = "3")]
+    transfer_retries: u32,
+
+    /// maximum number of machines whose logs are copied at the same time, 0 for no limit.
+    #[clap(long, default_value = "8")]
+    transfer_concurrency: usize,
 }
 
-fn machine_containers_create_script(containers: &[ScheduledContainer]) -> String {
-    let images = containers
-        .iter()
-        .map(|c| c.image.clone())
-        .collect::<HashSet<_>>();
+#[derive(Debug, Args)]
+struct RunArgs {
+    #[clap(flatten)]
+    common: Common,
 
-    let mut script = String::default();
+    /// directory where all the log files will be placed.
+    ///
+    /// this directory will be created if it does not exist.
+    /// for each container, there will be a seperate file for the stdout and sterr.
+    ///
+    /// the path can contain the placeholders `{job_id}`, `{timestamp}` (unix seconds) and
+    /// `{schedule_stem}` (the schedule's file name without its extension), for example
+    /// `results/{schedule_stem}-{timestamp}`. the run fails if the directory already exists and
+    /// is not empty, unless `--force`, `--resume` or `--retry` are given. `{timestamp}` cannot be
+    /// used with `--resume` or `--retry`, which need the directory of the previous run.
+    // optional only so that it is not required by the `run` subcommands
+    #[clap(value_hint = ValueHint::DirPath, long, required = true)]
+    output_dir: Option<PathBuf>,
+
+    /// write to the output directory even if it is not empty.
+    #[clap(long)]
+    force: bool,
+
+    /// latency matrix the network was configured with.
+    ///
+    /// only used to copy it to the output directory, together with the schedule, the machines of
+    /// the job and the version of oar-p2p, so the results describe how they were produced. unlike
+    /// `net up --latency-matrix`, `-` is not accepted.
+    #[clap(value_hint = ValueHint::FilePath, long, env = "OAR_P2P_LATENCY_MATRIX")]
+    latency_matrix: Option<PathBuf>,
+
+    /// script executed with bash before the containers are created, for example to warm caches
+    /// or fetch datasets. the run fails if the script fails.
+    ///
+    /// the script runs locally with the `OAR_P2P_OUTPUT_DIR` environment variable set, or on every
+    /// machine of the job with `--hooks-on-machines`. its stdout and stderr are saved to
+    /// `hooks/pre.<stdout|stderr>`, or `hooks/pre.<machine>.<stdout|stderr>`, in the output
+    /// directory.
+    #[clap(value_hint = ValueHint::FilePath, long)]
+    pre_hook: Option<PathBuf>,
+
+    /// script executed with bash after the logs are collected, like `--pre-hook`.
+    #[clap(value_hint = ValueHint::FilePath, long)]
+    post_hook: Option<PathBuf>,
+
+    /// run the hooks on every machine of the job instead of locally.
+    #[clap(long)]
+    hooks_on_machines: bool,
 
-    for image in images {
-        script.push_str(&format!("docker pull {} || exit 1\n", image));
+    /// declare a signal. this flag can be used more than once to declare multiple signals.
+    ///
+    /// a signal is an empty file that will be come visible to all containers after some amount of
+    /// time under the `/oar-p2p/` directory. a sginal has the format `<signal name>:<delay>`,
+    /// where the delay is given in seconds. using the signal `start:10` as an example, this means
+    /// that after all containers are started, a 10 second timer will start and when that timer
+    /// expires the file `/oar-p2p/start` will become visible to all containers at roughtly the
+    /// same time allowing them to synchronize their start-ups to within milliseconds. to make use
+    /// of this, your program running in the container must somehow wait for this file to come into
+    /// existance and this can be as simple as having a while loop checking for the file's existing
+    /// with a short sleep, here is an example in java:{n}
+    ///```java{n}
+    ///01.  import java.nio.file.Files;{n}
+    ///02.  import java.nio.file.Path;{n}
+    ///03. {n}
+    ///04.  public static void waitForStartFile() {{n}
+    ///05.      Path startFile = Path.of("/oar-p2p/start");{n}
+    ///06.      while (!Files.exists(startFile)) {{n}
+    ///07.          try {{n}
+    ///08.              Thread.sleep(250);{n}
+    ///09.          } catch (InterruptedException e) {{n}
+    ///10.              Thread.currentThread().interrupt();{n}
+    ///11.              break;{n}
+    ///12.          }{n}
+    ///13.      }{n}
+    ///14.  }{n}
+    ///```{n}
+    ///
+    /// a signal can be restricted to the containers whose name matches a glob with
+    /// `<signal name>:<delay>@<glob>`, for example `SIGTERM:600@node-*`. these signals are
+    /// delivered like the `signals` of the schedule, under the `/oar-p2p-container/` directory
+    /// or with `docker kill` for process signals.
+    #[clap(long)]
+    signal: Vec<RunSignalSpec>,
+
+    /// number of seconds before the job's walltime expires at which the containers are stopped.
+    ///
+    /// when the job is about to expire, at any phase of the run, all containers are stopped and
+    /// the logs are collected before oar kills the job.
+    #[clap(long, default_value = "300")]
+    walltime_margin: u64,
+
+    #[clap(flatten)]
+    transfer: LogTransferArgs,
+
+    /// copy a locally built image to the machines instead of pulling it from a registry.
+    ///
+    /// the image is exported with `docker save` on the local machine and loaded on every
+    /// machine with containers that use it. this flag can be used more than once.
+    #[clap(long)]
+    load_image: Vec<String>,
+
+    /// docker `config.json` with the credentials used to pull private images.
+    ///
+    /// the file is copied to the machines, readable only by the cluster user, and removed once
+    /// the images are pulled.
+    #[clap(value_hint = ValueHint::FilePath, long, env = "OAR_P2P_REGISTRY_CONFIG")]
+    registry_config: Option<PathBuf>,
+
+    /// sample `docker stats` on every machine with this interval, in seconds, while waiting for
+    /// the containers to exit.
+    ///
+    /// the cpu, memory and network usage of each container is appended to
+    /// `stats/<container>.csv` in the output directory.
+    #[clap(long)]
+    stats_interval: Option<u64>,
+
+    /// sample the cpu, memory and network usage of every machine with this interval, in seconds,
+    /// while the containers are running.
+    ///
+    /// the samples of each machine are written to `host/<machine>.csv` in the output directory.
+    #[clap(long)]
+    host_metrics_interval: Option<u64>,
+
+    /// capture packets with tcpdump while the containers are running. this flag can be used more
+    /// than once.
+    ///
+    /// the value is either a container name, to capture all traffic of its address, or two
+    /// addresses like `10.16.0.1-10.16.0.2`, to capture the traffic between them on the machines
+    /// of both. the captures are written to `pcap/` in the output directory.
+    #[clap(long)]
+    pcap: Vec<pcap::PcapTarget>,
+
+    /// size, in megabytes, at which a capture file is rotated.
+    #[clap(long, default_value = "100")]
+    pcap_rotate_size: u64,
+
+    /// maximum number of files of each capture, the oldest file is overwritten once reached.
+    #[clap(long, default_value = "10")]
+    pcap_rotate_count: u64,
+
+    /// randomly stop and restart containers while the experiment is running.
+    ///
+    /// the value is a list of options like `rate=0.1/min,seed=42,downtime=30`. `rate` is the
+    /// average number of containers stopped per second, minute or hour, `seed` makes the sequence
+    /// reproducible and `downtime` is the number of seconds a container stays stopped, 30 by
+    /// default. every stop and start is recorded in `churn.jsonl` in the output directory.
+    #[clap(long)]
+    churn: Option<churn::ChurnSpec>,
+
+    /// maximum number of seconds the experiment can run for, counted from when all containers
+    /// finished starting.
+    ///
+    /// when it expires, all containers that are still running are stopped and the logs are
+    /// collected as if they had exited. if not specified, wait for the containers forever.
+    #[clap(long)]
+    timeout: Option<u64>,
+
+    /// pin every container to this many dedicated cpus.
+    ///
+    /// cpus are handed out sequentially on each machine, so containers on the same machine never
+    /// share a cpu. containers that specify a `cpuset` in the schedule keep it and its cpus are
+    /// not given to other containers. fails if a machine does not have enough cpus for all of its
+    /// containers.
+    #[clap(long)]
+    cpus_per_container: Option<u32>,
+
+    /// number of seconds, can be fractional, between the start of consecutive containers.
+    ///
+    /// containers are started in a ramp, in the order they appear in the schedule, instead of all
+    /// at once. a `start_delay` in the schedule overrides the delay of that container.
+    #[clap(long)]
+    start_interval: Option<f64>,
+
+    /// how containers without an address in the schedule are placed on the network addresses.
+    #[clap(long, value_enum, default_value_t)]
+    placement: placement::PlacementStrategy,
+
+    /// seed for the `random` placement strategy. defaults to the current time, the seed in use is
+    /// always logged so a placement can be reproduced.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// tolerate containers that exit with a non-zero code.
+    ///
+    /// by default the run is aborted as soon as a container fails. with this flag every container
+    /// is waited for and the logs are collected, failed containers are listed in the manifest and
+    /// the run only fails if more containers failed than allowed. the value is a number of
+    /// containers or a percentage, like `10%`, and defaults to allowing any number of failures.
+    #[clap(long, num_args = 0..=1, default_missing_value = "100%")]
+    allow_failures: Option<FailureAllowance>,
+
+    /// hold every container at a start barrier until all containers on all machines are running.
+    ///
+    /// containers get the `OAR_P2P_BARRIER` environment variable with the path of a file that
+    /// only appears once every container was created, started and is running, or healthy if it
+    /// has a healthcheck. a container that exits before the barrier is released fails the run.
+    #[clap(long)]
+    barrier: bool,
+
+    /// give every container the addresses of the other containers of the run.
+    ///
+    /// with `env` the addresses are in the `OAR_P2P_PEERS` environment variable, separated by
+    /// commas. with `file` they are written one per line to a file mounted in the container, whose
+    /// path is in the `OAR_P2P_PEERS_FILE` environment variable.
+    #[clap(long, value_enum)]
+    peers: Option<peers::PeerListMode>,
+
+    /// give every container at most this many peers, picked at random.
+    ///
+    /// the pick depends on `--seed`, or on a fixed seed if it is not given, so the same schedule
+    /// always gets the same peer lists.
+    #[clap(long, requires = "peers")]
+    peers_sample: Option<usize>,
+
+    /// allow more than one container to use the same address.
+    ///
+    /// before creating anything, `run` checks that the address of every container is configured
+    /// on its machine and, unless this is set, that no two containers use the same address.
+    #[clap(long)]
+    allow_shared_addresses: bool,
+
+    /// print the scripts that would be executed on each machine without running anything.
+    ///
+    /// the machines are resolved from the addresses in the schedule, so every container must
+    /// have an address.
+    #[clap(long, conflicts_with_all = ["resume", "retry"])]
+    dry_run: bool,
+
+    /// run the schedule this many times, one after the other.
+    ///
+    /// each run is written to `run-<i>/` in the output directory, starting at 1, and the
+    /// containers are removed before every run. a run that fails does not stop the following
+    /// ones, the outcome of every run is printed at the end and written to `summary.json`.
+    #[clap(long, conflicts_with_all = ["resume", "retry", "dry_run"])]
+    repeat: Option<u32>,
+
+    /// re-run only the containers that failed or never finished in a previous run.
+    ///
+    /// takes the `manifest.json` of the previous run. the containers keep their names and
+    /// addresses, the logs are merged into the output directory and the manifest is updated.
+    #[clap(value_hint = ValueHint::FilePath, long, conflicts_with_all = ["schedule", "resume"])]
+    retry: Option<PathBuf>,
+
+    /// resume a previous run using the state saved in the output directory.
+    ///
+    /// the progress of a run is saved to `run-state.json` in the output directory. if the
+    /// orchestrator is interrupted, the run can be resumed from where it left off as long as the
+    /// containers are still on the machines. the schedule and signals are read from the state
+    /// file.
+    #[clap(long, conflicts_with_all = ["schedule", "signal", "barrier"])]
+    resume: bool,
+
+    /// the schedule used for execution. if not specified, it will be read from stdin.
+    #[clap(value_hint = ValueHint::FilePath)]
+    schedule: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct GcArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// scan these machines instead of the machines of the running jobs.
+    #[clap(long, add = ArgValueCandidates::new(completions::machines))]
+    machine: Vec<Machine>,
+
+    /// only list the leftovers found, without removing them.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// also remove the leftovers of machines without a job marker.
+    ///
+    /// by default they are only listed, since the network or containers could have been set up
+    /// by an older oar-p2p, or /tmp was cleaned, while the job is still using them.
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Debug, Args)]
+struct CleanArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    #[clap(flatten)]
+    clobber: ClobberArgs,
+}
+
+#[derive(Debug, Args)]
+struct RegistryArgs {
+    #[clap(subcommand)]
+    cmd: RegistrySubCmd,
+}
+
+#[derive(Debug, Subcommand)]
+enum RegistrySubCmd {
+    /// deploy a pull-through cache of docker hub on one machine and configure all machines of
+    /// the job to pull through it.
+    ///
+    /// configuring docker restarts the docker daemon, which stops any running container.
+    Up(RegistryUpArgs),
+    /// remove the pull-through cache and restore the configuration of all machines.
+    Down(RegistryDownArgs),
+}
+
+#[derive(Debug, Args)]
+struct RegistryUpArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// machine that runs the cache. defaults to the first machine of the job.
+    #[clap(long, add = ArgValueCandidates::new(completions::machines))]
+    machine: Option<Machine>,
+
+    /// port the cache listens on.
+    #[clap(long, default_value = "5000")]
+    port: u16,
+
+    /// registry that is cached.
+    #[clap(long, default_value = "https://registry-1.docker.io")]
+    upstream: String,
+}
+
+#[derive(Debug, Args)]
+struct RegistryDownArgs {
+    #[clap(flatten)]
+    common: Common,
+}
+
+#[derive(Debug, Args)]
+struct OarArgs {
+    #[clap(subcommand)]
+    cmd: OarSubCmd,
+}
+
+#[derive(Debug, Subcommand)]
+enum OarSubCmd {
+    Extend(OarExtendArgs),
+    Wait(OarWaitArgs),
+    Ensure(OarEnsureArgs),
+    Status(OarStatusArgs),
+}
+
+#[derive(Debug, Args)]
+struct OarExtendArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// the walltime change to request, passed as is to `oarwalltime`.
+    ///
+    /// use `+<hours>:<minutes>` to extend the walltime by some amount, for example, `+1:30` will
+    /// request an extra hour and a half.
+    walltime: String,
+}
+
+#[derive(Debug, Args)]
+struct OarWaitArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// maximum number of seconds to wait for the job to start running.
+    ///
+    /// if not specified, wait forever.
+    #[clap(long)]
+    timeout: Option<u64>,
+
+    /// number of seconds between each job state check.
+    #[clap(long, default_value = "5")]
+    interval: u64,
+}
+
+#[derive(Debug, Args)]
+struct OarEnsureArgs {
+    #[clap(flatten)]
+    common: Common,
+
+    /// number of machines the job should have.
+    #[clap(long)]
+    nodes: u32,
+
+    /// walltime of the job, passed as is to `oarsub`. for example, `2` or `2:30:00`.
+    ///
+    /// a running job is only reused if it has at least this much walltime left.
+    #[clap(long)]
+    walltime: String,
+
+    /// maximum number of seconds to wait for a submitted job to start running.
+    ///
+    /// if not specified, wait forever.
+    #[clap(long)]
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+struct OarStatusArgs {
+    #[clap(flatten)]
+    common: Common,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    use tracing::Instrument as _;
+    use tracing_subscriber::{Layer as _, layer::SubscriberExt as _, util::SubscriberInitExt as _};
+
+    // answers the completion scripts of `oar-p2p completions`, before anything is printed
+    clap_complete::CompleteEnv::with_factory(Cli::command)
+        .var(completions::ENV)
+        .complete();
+
+    // the configuration file only sets defaults, flags and environment variables take precedence
+    let config = match config::path() {
+        Some(path) => config::load(&path)?,
+        None => config::Config::default(),
+    };
+    if let Some(limit) = config.concurrency_limit {
+        machine::set_default_concurrency_limit(limit);
+    }
+    // the profile is needed before parsing, the defaults it sets change what is parsed
+    let profile = config::profile_argument(std::env::args().skip(1))
+        .or_else(|| std::env::var(config::PROFILE_ENV).ok());
+    let defaults = config.defaults(profile.as_deref())?;
+    let command = config::apply(Cli::command(), &defaults);
+    let cli = Cli::from_arg_matches(&command.get_matches()).unwrap_or_else(|err| err.exit());
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(progress::stderr)
+        .with_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        );
+    // the phase and machine spans are debug spans so they do not clutter the logs
+    let (otlp, exporter) = match cli.otlp_endpoint.clone() {
+        Some(endpoint) => {
+            let (layer, exporter) = otlp::layer(endpoint);
+            let layer = layer.with_filter(tracing_subscriber::filter::LevelFilter::DEBUG);
+            (Some(layer), Some(exporter))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry().with(fmt).with(otlp).init();
+    color_eyre::install()?;
+    if let Some(exporter) = exporter {
+        otlp::install(exporter);
+    }
+
+    let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    let span = tracing::debug_span!("oar-p2p", command);
+    let result = cli_execute(cli).instrument(span).await;
+    let timings = timing::snapshot();
+    if !timings.is_empty() {
+        eprint!("{}", timing::summary(&timings));
+    }
+    otlp::flush().await;
+    result
+}
+
+async fn cli_execute(cli: Cli) -> Result<()> {
+    let output = cli.output;
+    match cli.cmd {
+        SubCmd::Net(args) => match args.cmd {
+            NetSubCmd::Up(args) => cmd_net_up(args).await,
+            NetSubCmd::Down(args) => cmd_net_down(args).await,
+            NetSubCmd::Show(args) => cmd_net_show(args, output).await,
+            NetSubCmd::Preview(args) => cmd_net_preview(args).await,
+            NetSubCmd::Bench(args) => cmd_net_bench(args, output).await,
+            NetSubCmd::Measure(args) => cmd_net_measure(args, output).await,
+        },
+        SubCmd::Run(args) => {
+            let RunCmdArgs { cmd, run } = *args;
+            match cmd {
+                Some(RunSubCmd::Pull(args)) => cmd_run_pull(args).await,
+                Some(RunSubCmd::Exec(args)) => cmd_run_exec(args).await,
+                Some(RunSubCmd::Push(args)) => cmd_run_push(args).await,
+                Some(RunSubCmd::Ps(args)) => cmd_run_ps(args, output).await,
+                Some(RunSubCmd::Logs(args)) => cmd_run_logs(args).await,
+                Some(RunSubCmd::Pause(args)) => cmd_run_pause(args, true, output).await,
+                Some(RunSubCmd::Unpause(args)) => cmd_run_pause(args, false, output).await,
+                Some(RunSubCmd::Watch(args)) => cmd_run_watch(args, output).await,
+                None => cmd_run(run).await,
+            }
+        }
+        SubCmd::Clean(args) => cmd_clean(args).await,
+        SubCmd::Gc(args) => cmd_gc(args).await,
+        SubCmd::Oar(args) => match args.cmd {
+            OarSubCmd::Extend(args) => cmd_oar_extend(args).await,
+            OarSubCmd::Wait(args) => cmd_oar_wait(args).await,
+            OarSubCmd::Ensure(args) => cmd_oar_ensure(args, output).await,
+            OarSubCmd::Status(args) => cmd_oar_status(args, output).await,
+        },
+        SubCmd::Registry(args) => match args.cmd {
+            RegistrySubCmd::Up(args) => cmd_registry_up(args, output).await,
+            RegistrySubCmd::Down(args) => cmd_registry_down(args).await,
+        },
+        SubCmd::Experiment(args) => match args.cmd {
+            ExperimentSubCmd::Run(args) => cmd_experiment_run(args).await,
+        },
+        SubCmd::Monitor(args) => match args.cmd {
+            MonitorSubCmd::Up(args) => cmd_monitor_up(args, output).await,
+            MonitorSubCmd::Down(args) => cmd_monitor_down(args).await,
+        },
+        SubCmd::Signal(args) => match args.cmd {
+            SignalSubCmd::Send(args) => cmd_signal_send(args, output).await,
+            SignalSubCmd::Wait(args) => cmd_signal_wait(args, output).await,
+        },
+        SubCmd::Completions(args) => {
+            let completer = std::env::current_exe().context("finding the oar-p2p binary")?;
+            print!(
+                "{}",
+                completions::registration(
+                    Cli::command().get_name(),
+                    args.shell,
+                    &completer.to_string_lossy()
+                )
+            );
+            Ok(())
+        }
+        SubCmd::Man(args) => cmd_man(args).await,
+        SubCmd::SelfUpdate(args) => cmd_self_update(args).await,
+        SubCmd::Version(args) => cmd_version(args, output).await,
+        SubCmd::Agent(args) => agent::serve(args.runtime).await,
+    }
+}
+
+async fn context_from_common(common: &Common) -> Result<Context> {
+    let mut ctx = Context::new(
+        common.job_id.clone(),
+        common.infer_job_id,
+        common.frontend_hostname.clone(),
+        common.cluster_username.clone(),
+        common.oar_api_url.clone(),
+        common.oar_api_token.clone(),
+        common.runtime,
+    )
+    .await?;
+    if !common.no_ssh_multiplexing {
+        ctx.enable_ssh_multiplexing()?;
+    }
+    if common.agent {
+        ctx.enable_agents();
+    }
+    if common.native_ssh {
+        ctx.enable_native_ssh()?;
+        if !common.ssh_option.is_empty() {
+            tracing::warn!(
+                "--ssh-option does not apply to the embedded ssh client, the options are only used by machines that fall back to the ssh binary"
+            );
+        }
+    }
+    ctx.set_remote_retries(common.remote_retries);
+    ctx.set_ssh_options(common.ssh_option.clone());
+
+    if let ExecutionNode::Machine(_) = ctx.node {
+        tracing::warn!(
+            "executing oar-p2p from a job machine is not currently supported, run from the frontend or your own machine"
+        );
+    }
+
+    Ok(ctx)
+}
+
+/// lock the jobs of the context until the returned lock is dropped, see `--no-lock`.
+async fn job_lock(ctx: &Context, common: &Common) -> Result<Option<lock::JobLock>> {
+    if common.no_lock {
+        return Ok(None);
+    }
+    lock::acquire(ctx).await
+}
+
+/// warn if the job's remaining walltime is shorter than the estimated duration of an operation.
+async fn walltime_check(ctx: &Context, estimate: Duration) {
+    let (job_id, remaining) = match oar::jobs_min_remaining_walltime(ctx).await {
+        Ok(Some(remaining)) => remaining,
+        Ok(None) => return,
+        Err(err) => {
+            tracing::debug!("unable to check remaining walltime: {err}");
+            return;
+        }
+    };
+    if remaining < estimate {
+        tracing::warn!(
+            "job {job_id} has {}s of walltime remaining but this operation is estimated to take {}s, consider running `oar-p2p oar extend`",
+            remaining.as_secs(),
+            estimate.as_secs()
+        );
+    }
+}
+
+async fn cmd_net_up(args: NetUpArgs) -> Result<()> {
+    let context = context_from_common(&args.common).await?;
+    let _lock = match args.dry_run {
+        true => None,
+        false => job_lock(&context, &args.common).await?,
+    };
+    if !args.dry_run {
+        walltime_check(&context, NET_WALLTIME_ESTIMATE).await;
+    }
+
+    let matrix = read_latency_matrix(&args.latency_matrix).await?;
+    latency_matrix_precision_check(&matrix, args.strict.strict)?;
+    let matrix = Arc::new(matrix);
+
+    let job_machines = oar::job_list_machines(&context).await?;
+    let configs = machine_generate_configs(
+        &matrix,
+        args.matrix_wrap,
+        &job_machines,
+        &args.addresses,
+        &args.weight,
+        args.nft_intervals,
+    )?;
+    let mapping = mapping::AddressMapping::new(
+        matrix.dimension(),
+        args.matrix_wrap,
+        configs.iter().map(|c| {
+            (
+                c.machine,
+                c.addresses.as_slice(),
+                c.address_indices.as_slice(),
+            )
+        }),
+    );
+
+    if args.dry_run {
+        net_plan_print(&configs, args.nft_chunk_size.get(), args.scripts);
+        return Ok(());
+    }
+
+    // machines that already have this exact configuration are left untouched
+    let configs = match args.force {
+        true => configs,
+        false => {
+            let applied = machine::for_each(&job_machines, |machine| {
+                machine_network_fingerprint(&context, machine)
+            })
+            .await?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+            let (unchanged, changed) = configs
+                .into_iter()
+                .partition::<Vec<_>, _>(|c| applied[&c.machine].as_ref() == Some(&c.fingerprint));
+            if !unchanged.is_empty() {
+                tracing::info!(
+                    "skipping {} machines that already have this configuration: {:?}",
+                    unchanged.len(),
+                    unchanged.iter().map(|c| c.machine).collect::<Vec<_>>()
+                );
+            }
+            changed
+        }
+    };
+    machines_job_mark(&context, &job_machines).await?;
+    let machines = configs.iter().map(|c| c.machine).collect::<Vec<_>>();
+    if !machines.is_empty() {
+        machines_interface_check(&context, &machines).await?;
+        machines_containers_clean(&context, &machines).await?;
+        machines_net_container_build(&context, &machines).await?;
+        if !args.clobber.clobber {
+            machines_foreign_state_check(&context, &machines).await?;
+        }
+        if let Some(dir) = &args.snapshot_dir {
+            machines_state_snapshot(&context, &machines, dir, "before").await?;
+        }
+        let configured = async {
+            machines_clean(&context, &machines).await?;
+            machines_configure(&context, &configs, args.nft_chunk_size.get()).await
+        }
+        .await;
+        if let Err(err) = configured {
+            if args.keep_partial {
+                return Err(err);
+            }
+            return Err(net_up_rollback(&context, &job_machines, err).await);
+        }
+        if let Some(dir) = &args.snapshot_dir {
+            machines_state_snapshot(&context, &machines, dir, "after").await?;
+        }
+    }
+    tokio::fs::write(&args.mapping_out, serde_json::to_vec_pretty(&mapping)?)
+        .await
+        .with_context(|| format!("writing address mapping to {}", args.mapping_out.display()))?;
+    Ok(())
+}
+
+fn net_plan_print(configs: &[MachineConfig], nft_chunk_size: usize, scripts: bool) {
+    let plans = configs
+        .iter()
+        .map(|config| net_plan(config, nft_chunk_size))
+        .collect::<Vec<_>>();
+    println!(
+        "{:<16} {:>10} {:>10} {:>10} {:>12} {:>10} SCRIPT BYTES",
+        "MACHINE", "ADDRESSES", "LATENCIES", "TC CLASSES", "NFT ELEMENTS", "NFT CHUNKS"
+    );
+    for plan in &plans {
+        println!(
+            "{:<16} {:>10} {:>10} {:>10} {:>12} {:>10} {}",
+            plan.machine.to_string(),
+            plan.addresses,
+            plan.latencies,
+            plan.tc_classes,
+            plan.nft_elements,
+            plan.nft_chunks,
+            plan.script_bytes
+        );
+    }
+    let total = |f: fn(&NetPlan) -> usize| plans.iter().map(f).sum::<usize>();
+    println!(
+        "{:<16} {:>10} {:>10} {:>10} {:>12} {:>10} {}",
+        "TOTAL",
+        total(|p| p.addresses),
+        total(|p| p.latencies),
+        total(|p| p.tc_classes),
+        total(|p| p.nft_elements),
+        total(|p| p.nft_chunks),
+        total(|p| p.script_bytes)
+    );
+    if !scripts {
+        return;
+    }
+    for config in configs {
+        println!();
+        (0..20).for_each(|_| print!("-"));
+        print!(" {} ", config.machine);
+        (0..20).for_each(|_| print!("-"));
+        println!();
+        println!("{}", machine_configuration_script(config));
+        for script in machine_nft_chunk_scripts(config, nft_chunk_size) {
+            println!("{script}");
+        }
+    }
+}
+
+/// read and parse the latency matrix at `path`, or from stdin if `path` is `-`.
+async fn read_latency_matrix(path: &Path) -> Result<LatencyMatrix> {
+    let (source, content) = if path == Path::new("-") {
+        tracing::debug!("reading latency matrix from stdin");
+        let mut content = String::default();
+        tokio::io::stdin()
+            .read_to_string(&mut content)
+            .await
+            .context("reading latency matrix from stdin")?;
+        (String::from("<stdin>"), content)
+    } else {
+        tracing::debug!("reading latency matrix at {}", path.display());
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("reading latency matrix {}", path.display()))?;
+        (path.display().to_string(), content)
+    };
+    tracing::debug!("parsing latency matrix");
+    LatencyMatrix::parse(&content, latency_matrix::TimeUnit::Milliseconds)
+        .with_context(|| format!("parsing latency matrix {source}"))
+}
+
+/// warn, or fail with `strict`, if the matrix has latencies the network cannot represent since it
+/// is configured with millisecond resolution.
+fn latency_matrix_precision_check(matrix: &LatencyMatrix, strict: bool) -> Result<()> {
+    let Some(loss) = matrix.precision_loss(Duration::from_millis(1)) else {
+        return Ok(());
+    };
+    match strict {
+        true => Err(eyre::eyre!(
+            "latency matrix loses precision: {loss}, latencies are applied in whole milliseconds"
+        )),
+        false => {
+            tracing::warn!(
+                "latency matrix loses precision: {loss}, latencies are applied in whole milliseconds, pass --strict to fail instead"
+            );
+            Ok(())
+        }
+    }
+}
+
+/// remove the network from every machine of the job after configuring it failed with `err`.
+///
+/// the machines that already had the configuration are cleaned too, together with the ones that
+/// were configured before the failure.
+async fn net_up_rollback(ctx: &Context, machines: &[Machine], err: eyre::Report) -> eyre::Report {
+    tracing::error!("configuring the network failed, removing it from all machines: {err:#}");
+    match machines_clean(ctx, machines).await {
+        Ok(()) => {
+            err.wrap_err("configuring the network, the network was removed from all machines")
+        }
+        Err(clean_err) => {
+            tracing::error!("failed to remove the network: {clean_err:#}");
+            err.wrap_err("configuring the network, the machines might be partially configured")
+        }
+    }
+}
+
+async fn cmd_experiment_run(args: ExperimentRunArgs) -> Result<()> {
+    // held across the network configuration, the run and the cleanup
+    let context = context_from_common(&args.common).await?;
+    let _lock = job_lock(&context, &args.common).await?;
+    let source_name = args.experiment.display().to_string();
+    let content = tokio::fs::read_to_string(&args.experiment)
+        .await
+        .with_context(|| format!("reading experiment file {source_name}"))?;
+    let experiment = experiment::parse(&source_name, &content)?;
+    let base = args.experiment.parent().unwrap_or(Path::new("."));
+
+    // the flags are parsed the same way as on the command line so the experiment file accepts
+    // exactly the same options, the common flags are taken from this command instead
+    let mut net_up = parse_experiment_arguments::<NetUpArgs>(
+        "net up",
+        experiment.net_up_arguments(&source_name, base)?,
+    )
+    .with_context(|| format!("{source_name}: network"))?;
+    net_up.common = args.common.clone();
+    let mut run =
+        parse_experiment_arguments::<RunArgs>("run", experiment.run_arguments(&source_name, base)?)
+            .with_context(|| format!("{source_name}: run"))?;
+    run.common = args.common.clone();
+    run.latency_matrix = Some(net_up.latency_matrix.clone());
+    if run.dry_run || run.resume {
+        return Err(eyre::eyre!(
+            "{source_name}: run: dry_run and resume cannot be used in an experiment"
+        ));
+    }
+    if net_up.dry_run {
+        return Err(eyre::eyre!(
+            "{source_name}: network: dry_run cannot be used in an experiment"
+        ));
+    }
+
+    // resolved before configuring the network so a bad output directory fails early
+    let output_dir = run_output_dir(&run).await?;
+    run.output_dir = Some(output_dir.clone());
+    run.force = true;
+    tokio::fs::create_dir_all(&output_dir)
+        .await
+        .context("creating output directory")?;
+    tokio::fs::write(output_dir.join("experiment.toml"), &content)
+        .await
+        .context("copying experiment file to the output directory")?;
+    if !experiment.network.contains_key("mapping_out") {
+        net_up.mapping_out = output_dir.join(mapping::DEFAULT_MAPPING_PATH);
+    }
+
+    tracing::info!("configuring the network");
+    let result = match cmd_net_up(net_up).await {
+        Ok(()) => {
+            tracing::info!("running the schedule");
+            cmd_run(run).await
+        }
+        Err(err) => Err(err.wrap_err("configuring the network")),
+    };
+
+    if experiment.keep_network {
+        tracing::info!("keeping the network configured");
+        return result;
+    }
+    tracing::info!("cleaning the network");
+    let net_down = NetDownArgs {
+        common: args.common,
+        snapshot_dir: None,
+        clobber: ClobberArgs::default(),
+    };
+    match (result, cmd_net_down(net_down).await) {
+        (Err(err), Err(down_err)) => {
+            tracing::error!("failed to clean the network: {down_err:#}");
+            Err(err)
+        }
+        (Err(err), Ok(())) => Err(err),
+        (Ok(()), down) => down.context("cleaning the network"),
+    }
+}
+
+/// copy the inputs of a run to the output directory and write its provenance.
+async fn write_provenance(
+    output_dir: &Path,
+    schedule_source: &str,
+    schedule: &str,
+    latency_matrix: Option<&Path>,
+    machines: &[Machine],
+) -> Result<()> {
+    tokio::fs::write(output_dir.join(provenance::SCHEDULE_FILE_NAME), schedule)
+        .await
+        .context("copying schedule to the output directory")?;
+    if let Some(path) = latency_matrix {
+        tokio::fs::copy(path, output_dir.join(provenance::LATENCY_MATRIX_FILE_NAME))
+            .await
+            .with_context(|| {
+                format!(
+                    "copying latency matrix {} to the output directory",
+                    path.display()
+                )
+            })?;
+    }
+    let provenance = provenance::Provenance {
+        version: env!("GIT_VERSION").to_string(),
+        command_line: std::env::args().collect(),
+        written_at: unix_timestamp(),
+        schedule_source: schedule_source.to_string(),
+        latency_matrix_source: latency_matrix.map(|p| p.display().to_string()),
+        machines: machines.to_vec(),
+    };
+    tokio::fs::write(
+        output_dir.join(provenance::PROVENANCE_FILE_NAME),
+        serde_json::to_string_pretty(&provenance)?,
+    )
+    .await
+    .context("writing provenance")?;
+    Ok(())
+}
+
+/// parse the arguments of a subcommand given by an experiment file.
+fn parse_experiment_arguments<T: clap::Args + clap::FromArgMatches>(
+    name: &'static str,
+    arguments: Vec<String>,
+) -> Result<T> {
+    tracing::debug!("{name} arguments: {arguments:?}");
+    let command = T::augment_args(clap::Command::new(name).no_binary_name(true));
+    let matches = command.try_get_matches_from(arguments)?;
+    Ok(T::from_arg_matches(&matches)?)
+}
+
+async fn cmd_net_down(args: NetDownArgs) -> Result<()> {
+    let context = context_from_common(&args.common).await?;
+    let _lock = job_lock(&context, &args.common).await?;
+    walltime_check(&context, NET_WALLTIME_ESTIMATE).await;
+    let machines = oar::job_list_machines(&context).await?;
+    machines_containers_clean(&context, &machines).await?;
+    machines_net_container_build(&context, &machines).await?;
+    if !args.clobber.clobber {
+        machines_foreign_state_check(&context, &machines).await?;
+    }
+    if let Some(dir) = &args.snapshot_dir {
+        machines_state_snapshot(&context, &machines, dir, "before").await?;
+    }
+    machines_clean(&context, &machines).await?;
+    if let Some(dir) = &args.snapshot_dir {
+        machines_state_snapshot(&context, &machines, dir, "after").await?;
+    }
+    Ok(())
+}
+
+async fn cmd_net_show(args: NetShowArgs, output: OutputMode) -> Result<()> {
+    let context = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&context).await?;
+    let results = machine::for_each(machines.iter(), |machine| {
+        let context = context.clone();
+        async move { machine_list_addresses(&context, machine).await }
+    })
+    .await?;
+
+    let mut addresses = Vec::default();
+    for (machine, addrs) in results {
+        for addr in addrs {
+            addresses.push((machine, addr));
+        }
+    }
+    addresses.sort();
+    if args.interleave {
+        let mut addrs_per_machine: HashMap<Machine, Vec<Ipv4Addr>> = Default::default();
+        for (machine, addr) in addresses {
+            addrs_per_machine.entry(machine).or_default().push(addr);
+        }
+        addresses = Vec::default();
+        while !addrs_per_machine.is_empty() {
+            for machine in &machines {
+                if let Some(addrs) = addrs_per_machine.get_mut(machine) {
+                    if let Some(addr) = addrs.pop() {
+                        addresses.push((*machine, addr));
+                    } else {
+                        addrs_per_machine.remove(machine);
+                    }
+                };
+            }
+        }
+    }
+    let format = match output {
+        OutputMode::Json => NetShowFormat::Json,
+        OutputMode::Text => args.format,
+    };
+    print!("{}", format_net_show(&addresses, format)?);
+    Ok(())
+}
+
+fn format_net_show(addresses: &[(Machine, Ipv4Addr)], format: NetShowFormat) -> Result<String> {
+    #[derive(serde::Serialize)]
+    struct Record {
+        machine: Machine,
+        address: Ipv4Addr,
+    }
+
+    let mut output = String::default();
+    match format {
+        NetShowFormat::Text => {
+            for (machine, addr) in addresses {
+                output.push_str(&format!("{machine} {addr}\n"));
+            }
+        }
+        NetShowFormat::Json => {
+            let records = addresses
+                .iter()
+                .map(|&(machine, address)| Record { machine, address })
+                .collect::<Vec<_>>();
+            output.push_str(&serde_json::to_string_pretty(&records)?);
+            output.push('\n');
+        }
+        NetShowFormat::Csv => {
+            output.push_str("machine,address\n");
+            for (machine, addr) in addresses {
+                output.push_str(&format!("{machine},{addr}\n"));
+            }
+        }
+        NetShowFormat::Hosts => {
+            for (idx, (_, addr)) in addresses.iter().enumerate() {
+                output.push_str(&format!("{addr} oar-p2p-{idx}\n"));
+            }
+        }
+        NetShowFormat::Env => {
+            let all = addresses
+                .iter()
+                .map(|(_, addr)| addr.to_string())
+                .collect::<Vec<_>>();
+            output.push_str(&format!("export OAR_P2P_ADDRESSES=\"{}\"\n", all.join(" ")));
+            for (idx, (machine, addr)) in addresses.iter().enumerate() {
+                output.push_str(&format!("export OAR_P2P_ADDRESS_{idx}={addr}\n"));
+                output.push_str(&format!("export OAR_P2P_MACHINE_{idx}={machine}\n"));
+            }
+        }
+    }
+    Ok(output)
+}
+
+async fn cmd_net_preview(args: NetPreviewArgs) -> Result<()> {
+    let matrix = read_latency_matrix(&args.latency_matrix).await?;
+    latency_matrix_precision_check(&matrix, args.strict.strict)?;
+    let matrix = Arc::new(matrix);
+    let machines = args.machine;
+    let configs = machine_generate_configs(
+        &matrix,
+        args.matrix_wrap,
+        &machines,
+        &args.addresses,
+        &args.weight,
+        args.nft_intervals,
+    )?;
+
+    for config in configs {
+        (0..20).for_each(|_| print!("-"));
+        print!(" {} ", config.machine);
+        (0..20).for_each(|_| print!("-"));
+        println!();
+        println!("{}", machine_configuration_script(&config));
+        for script in machine_nft_chunk_scripts(&config, args.nft_chunk_size.get()) {
+            println!("{script}");
+        }
+    }
+    Ok(())
+}
+
+async fn cmd_net_bench(args: NetBenchArgs, output: OutputMode) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&ctx).await?;
+    let pairs = match args.pair.is_empty() {
+        false => args.pair.clone(),
+        true => {
+            let addresses = machine::for_each(&machines, |machine| {
+                let ctx = ctx.clone();
+                async move { machine_list_addresses(&ctx, machine).await }
+            })
+            .await?;
+            let first = addresses
+                .into_iter()
+                .filter_map(|(machine, addresses)| Some((machine, addresses.into_iter().min()?)))
+                .collect::<BTreeMap<_, _>>();
+            bench_ring_pairs(&first.into_values().collect::<Vec<_>>())?
+        }
+    };
+    for pair in &pairs {
+        machine_from_addr(pair.src)?;
+        machine_from_addr(pair.dst)?;
+    }
+
+    let involved = pairs
+        .iter()
+        .flat_map(|p| [p.src, p.dst])
+        .map(|addr| machine_from_addr(addr).unwrap())
+        .collect::<HashSet<_>>();
+    let speeds = machine::for_each(&involved, |machine| {
+        let ctx = ctx.clone();
+        async move { machine_interface_speed(&ctx, machine).await }
+    })
+    .await?
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+
+    let speeds = &speeds;
+    let measure = |(port, pair): (u16, bench::BenchPair)| {
+        let ctx = ctx.clone();
+        async move {
+            let throughput =
+                machine_bench_pair(&ctx, pair, port, args.duration, args.streams).await?;
+            let (src, dst) = (machine_from_addr(pair.src)?, machine_from_addr(pair.dst)?);
+            let interface_speeds = (src != dst).then(|| (speeds[&src], speeds[&dst]));
+            Ok::<_, eyre::Report>(bench::BenchReport::new(pair, throughput, interface_speeds))
+        }
+    };
+    let reports = if args.parallel {
+        // every pair runs at the same time so each needs its own iperf3 port
+        let ports = (0..pairs.len())
+            .map(|idx| {
+                u16::try_from(idx)
+                    .ok()
+                    .and_then(|i| bench::BASE_PORT.checked_add(i))
+                    .ok_or_else(|| {
+                        eyre::eyre!(
+                            "too many pairs to measure in parallel, ran out of ports at {idx}"
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        futures::future::try_join_all(ports.into_iter().zip(pairs.iter().copied()).map(measure))
+            .await?
+    } else {
+        let mut reports = Vec::default();
+        for &pair in pairs.iter() {
+            tracing::info!("measuring {} -> {}", pair.src, pair.dst);
+            reports.push(measure((bench::BASE_PORT, pair)).await?);
+        }
+        reports
+    };
+
+    for report in reports.iter().filter(|r| r.saturated) {
+        tracing::warn!(
+            "{} -> {} reached {:.0} Mbit/s, close to the speed of the physical interface",
+            report.src,
+            report.dst,
+            report.throughput
+        );
+    }
+    match output {
+        OutputMode::Json => print_json(&reports)?,
+        OutputMode::Text => {
+            println!(
+                "{:<16} {:<16} {:>12} {:>12} {:>12} SATURATED",
+                "SRC", "DST", "MBIT/S", "EXPECTED", "RETRANSMITS"
+            );
+            for report in reports {
+                let expected = match report.expected {
+                    Some(expected) => format!("{expected:.0}"),
+                    None => String::from("local"),
+                };
+                println!(
+                    "{:<16} {:<16} {:>12.0} {:>12} {:>12} {}",
+                    report.src.to_string(),
+                    report.dst.to_string(),
+                    report.throughput,
+                    expected,
+                    report.retransmits,
+                    if report.saturated { "yes" } else { "no" }
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn cmd_net_measure(args: NetMeasureArgs, output: OutputMode) -> Result<()> {
+    let content = tokio::fs::read(&args.mapping).await.with_context(|| {
+        format!(
+            "reading address mapping {}, it is written by net up",
+            args.mapping.display()
+        )
+    })?;
+    let mapping = serde_json::from_slice::<mapping::AddressMapping>(&content)
+        .with_context(|| format!("parsing address mapping {}", args.mapping.display()))?;
+    let expected = match &args.expected {
+        Some(path) => {
+            let content = tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("reading latency matrix {}", path.display()))?;
+            Some(
+                LatencyMatrix::parse(&content, latency_matrix::TimeUnit::Milliseconds)
+                    .with_context(|| format!("parsing latency matrix {}", path.display()))?,
+            )
+        }
+        None => None,
+    };
+
+    let mut addresses = mapping.addresses();
+    if let Some(sample) = args.sample {
+        addresses = measure_sample(addresses, sample, args.seed);
+        tracing::info!(
+            "measuring addresses with indices {:?}",
+            addresses.iter().map(|(_, a)| a.index).collect::<Vec<_>>()
+        );
+    }
+    let sources = addresses.iter().map(|(m, _)| m).collect::<HashSet<_>>();
+
+    let ctx = context_from_common(&args.common).await?;
+    let addresses = &addresses;
+    let results = machine::for_each_progress("measuring latencies", sources, |machine| {
+        let ctx = ctx.clone();
+        let pairs = addresses
+            .iter()
+            .filter(|(m, _)| *m == machine)
+            .flat_map(|(_, src)| {
+                addresses
+                    .iter()
+                    .filter(|(_, dst)| dst.address != src.address)
+                    .map(|(_, dst)| (src.address, dst.address))
+            })
+            .collect::<Vec<_>>();
+        async move {
+            let script = measure::measure_script(ctx.runtime, &pairs, args.count, args.parallel);
+            let output = machine_run_script(&ctx, machine, &script).await?;
+            let stdout = std::str::from_utf8(&output.stdout)?;
+            Ok(measure::parse_measure_output(stdout)?)
+        }
+    })
+    .await?;
+
+    let positions = addresses
+        .iter()
+        .enumerate()
+        .map(|(pos, (_, a))| (a.address, pos))
+        .collect::<HashMap<_, _>>();
+    let mut rtts = HashMap::<(usize, usize), Duration>::default();
+    let mut unreachable = Vec::default();
+    for (src, dst, rtt) in results.into_iter().flat_map(|(_, m)| m) {
+        match rtt {
+            Some(rtt) => {
+                rtts.insert((positions[&src], positions[&dst]), rtt);
+            }
+            None => unreachable.push(format!("{src} -> {dst}")),
+        }
+    }
+    if !unreachable.is_empty() {
+        return Err(eyre::eyre!(
+            "no ping replies for {} pairs: {}",
+            unreachable.len(),
+            unreachable.join(", ")
+        ));
+    }
+    // the emulated latency is applied once in each direction, the matrix holds one way
+    let measured = LatencyMatrix::from_fn(addresses.len(), |row, col| {
+        rtts.get(&(row, col))
+            .map(|rtt| *rtt / 2)
+            .unwrap_or_default()
+    });
+
+    if let Some(expected) = &expected {
+        let rows = addresses
+            .iter()
+            .map(|(_, a)| a.matrix_row)
+            .collect::<Vec<_>>();
+        if let Some(&row) = rows.iter().find(|&&row| row >= expected.dimension()) {
+            return Err(eyre::eyre!(
+                "the expected matrix has {} rows but the mapping uses row {row}",
+                expected.dimension()
+            ));
+        }
+        let comparison = measure_compare(&measured, expected, &rows);
+        tracing::info!(
+            "measured latencies differ from the expected ones by {:.3}ms on average and {:.3}ms at most, between indices {} and {}",
+            comparison.mean_error * 1000.0,
+            comparison.max_error * 1000.0,
+            addresses[comparison.worst.0].1.index,
+            addresses[comparison.worst.1].1.index,
+        );
+    }
+
+    match output {
+        OutputMode::Text => print!("{measured}"),
+        OutputMode::Json => {
+            let dimension = measured.dimension();
+            let matrix = (0..dimension)
+                .map(|row| {
+                    (0..dimension)
+                        .map(|col| measured.latency(row, col).as_secs_f64() * 1000.0)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            print_json(&serde_json::json!({
+                "addresses": addresses.iter().map(|(_, a)| a.address).collect::<Vec<_>>(),
+                "indices": addresses.iter().map(|(_, a)| a.index).collect::<Vec<_>>(),
+                "latencies_ms": matrix,
+            }))?
+        }
+    }
+    Ok(())
+}
+
+/// `count` addresses chosen at random with `seed`, ordered by index.
+fn measure_sample(
+    mut addresses: Vec<(Machine, &mapping::MappedAddress)>,
+    count: usize,
+    seed: u64,
+) -> Vec<(Machine, &mapping::MappedAddress)> {
+    let count = count.min(addresses.len());
+    let mut rng = placement::SplitMix64(seed);
+    for i in 0..count {
+        let j = i + (rng.next() % (addresses.len() - i) as u64) as usize;
+        addresses.swap(i, j);
+    }
+    addresses.truncate(count);
+    addresses.sort_by_key(|(_, a)| a.index);
+    addresses
+}
+
+struct MeasureComparison {
+    /// seconds
+    mean_error: f64,
+    /// seconds
+    max_error: f64,
+    /// positions of the pair with the largest error
+    worst: (usize, usize),
+}
+
+/// compare every off-diagonal entry of `measured` with the `expected` latency of the matrix rows
+/// of its addresses.
+fn measure_compare(
+    measured: &LatencyMatrix,
+    expected: &LatencyMatrix,
+    rows: &[usize],
+) -> MeasureComparison {
+    let mut comparison = MeasureComparison {
+        mean_error: 0.0,
+        max_error: 0.0,
+        worst: (0, 0),
+    };
+    let mut count = 0;
+    for row in 0..measured.dimension() {
+        for col in (0..measured.dimension()).filter(|&col| col != row) {
+            let error = (measured.latency(row, col).as_secs_f64()
+                - expected.latency(rows[row], rows[col]).as_secs_f64())
+            .abs();
+            comparison.mean_error += error;
+            count += 1;
+            if error > comparison.max_error {
+                comparison.max_error = error;
+                comparison.worst = (row, col);
+            }
+        }
+    }
+    if count > 0 {
+        comparison.mean_error /= count as f64;
+    }
+    comparison
+}
+
+/// pairs from each address to the next one, wrapping around, so every machine sends and
+/// receives once.
+fn bench_ring_pairs(addresses: &[Ipv4Addr]) -> Result<Vec<bench::BenchPair>> {
+    if addresses.len() < 2 {
+        return Err(eyre::eyre!(
+            "at least two machines with addresses are needed, use --pair to choose the addresses"
+        ));
+    }
+    Ok(addresses
+        .iter()
+        .zip(addresses.iter().cycle().skip(1))
+        .map(|(&src, &dst)| bench::BenchPair { src, dst })
+        .collect())
+}
+
+/// speed of the machine's interface in megabits per second, if the driver reports it.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_interface_speed(ctx: &Context, machine: Machine) -> Result<Option<u64>> {
+    let script = bench::interface_speed_script(machine.interface());
+    let output = machine_run_script(ctx, machine, &script).await?;
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    Ok(stdout.trim().parse::<u64>().ok().filter(|&speed| speed > 0))
+}
+
+/// measure the throughput of a pair with an iperf3 server on the destination machine and a client
+/// on the source machine.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_bench_pair(
+    ctx: &Context,
+    pair: bench::BenchPair,
+    port: u16,
+    duration: u64,
+    streams: u32,
+) -> Result<bench::Throughput> {
+    let (src, dst) = (machine_from_addr(pair.src)?, machine_from_addr(pair.dst)?);
+    let script = bench::server_script(ctx.runtime, pair.dst, port);
+    machine_run_script(ctx, dst, &script).await?;
+    let script = bench::client_script(ctx.runtime, pair, port, duration, streams);
+    let output = machine_run_script(ctx, src, &script).await?;
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    bench::parse_iperf_output(stdout)
+        .with_context(|| format!("measuring {} -> {}", pair.src, pair.dst))
+}
+
+fn machine_from_addr(addr: Ipv4Addr) -> Result<Machine> {
+    let machine_index = usize::from(addr.octets()[1]);
+    Machine::from_index(machine_index)
+        .ok_or_else(|| eyre::eyre!("failed to resolve machine from address {addr}"))
+}
+
+#[derive(Debug, Clone)]
+struct ScheduledContainer {
+    name: String,
+    image: String,
+    machine: Machine,
+    address: Ipv4Addr,
+    variables: HashMap<String, String>,
+    volumes: Vec<schedule::Volume>,
+    /// absolute local path and container path of every input
+    inputs: Vec<(PathBuf, String)>,
+    command: Vec<String>,
+    entrypoint: Option<String>,
+    cpuset: Option<String>,
+    memory: Option<schedule::MemorySize>,
+    cpus: Option<f64>,
+    depends_on: Vec<String>,
+    start_delay: Option<Duration>,
+    signals: Vec<SignalSpec>,
+    /// contents of the peers file, see `run --peers file`
+    peers_file: Option<String>,
+    cap_add: Vec<String>,
+    cap_drop: Vec<String>,
+    privileged: bool,
+    security_opt: Vec<String>,
+    tmpfs: Vec<String>,
+    shm_size: Option<schedule::MemorySize>,
+    restart: schedule::RestartPolicy,
+    artifacts: Vec<String>,
+}
+
+impl ScheduledContainer {
+    /// whether the container needs a directory for the signal files that only it can see.
+    fn has_file_signals(&self) -> bool {
+        self.signals.iter().any(|s| !s.signal.is_process_signal())
+    }
+}
+
+/// a signal to deliver at some point after all containers started.
+struct SignalEvent {
+    /// identifies the event in the run state's delivered signals
+    key: String,
+    /// index of the target container, all containers are signaled if missing
+    container: Option<usize>,
+    spec: SignalSpec,
+}
+
+/// convert the schedule items into containers.
+///
+/// items without an address use the address in `assigned_addresses` for their index.
+fn schedule_containers(
+    source_name: &str,
+    items: Vec<schedule::ScheduleItem>,
+    assigned_addresses: &BTreeMap<usize, Ipv4Addr>,
+) -> Result<Vec<ScheduledContainer>> {
+    let mut containers = Vec::default();
+    let total = items.len();
+    for (idx, item) in items.into_iter().enumerate() {
+        let address = item
+            .address
+            .or_else(|| assigned_addresses.get(&idx).copied())
+            .ok_or_else(|| eyre::eyre!("{source_name}: item {idx}: no address was assigned"))?;
+        let name = match item.name {
+            Some(name) => name,
+            None => address.to_string(),
+        };
+        let machine = machine_from_addr(address)
+            .with_context(|| format!("{source_name}: item {idx}: address"))?;
+        let mut variables = HashMap::with_capacity(item.env.len());
+        for (key, value) in item.env {
+            let value = schedule::expand_env_value(&value, |placeholder| match placeholder {
+                "address" => Some(address.to_string()),
+                "index" => Some(idx.to_string()),
+                "machine" => Some(machine.to_string()),
+                "name" => Some(name.clone()),
+                "total" => Some(total.to_string()),
+                _ => None,
+            })
+            .with_context(|| format!("{source_name}: item {idx}: env.{key}"))?;
+            variables.insert(key, value);
+        }
+
+        containers.push(ScheduledContainer {
+            name,
+            image: item.image,
+            machine,
+            address,
+            variables,
+            volumes: item.volumes,
+            inputs: item
+                .inputs
+                .into_iter()
+                .map(|input| {
+                    let local = std::path::absolute(&input.local)
+                        .with_context(|| format!("{source_name}: item {idx}: inputs"))?;
+                    Ok((local, input.container))
+                })
+                .collect::<Result<_>>()?,
+            command: item.command,
+            entrypoint: item.entrypoint,
+            cpuset: item.cpuset,
+            memory: item.memory,
+            cpus: item.cpus,
+            depends_on: item.depends_on,
+            start_delay: item.start_delay,
+            signals: item.signals,
+            peers_file: None,
+            cap_add: item.cap_add,
+            cap_drop: item.cap_drop,
+            privileged: item.privileged,
+            security_opt: item.security_opt,
+            tmpfs: item.tmpfs,
+            shm_size: item.shm_size,
+            restart: item.restart,
+            artifacts: item.artifacts,
+        });
+    }
+    Ok(containers)
+}
+
+/// check the addresses of the containers against the addresses `configured` on each machine of the
+/// job, listing every container with a problem.
+fn run_validate_addresses(
+    containers: &[ScheduledContainer],
+    configured: &HashMap<Machine, HashSet<Ipv4Addr>>,
+    allow_shared: bool,
+) -> Result<()> {
+    let mut errors = Vec::default();
+    let mut users = BTreeMap::<Ipv4Addr, Vec<&str>>::default();
+    for container in containers {
+        users
+            .entry(container.address)
+            .or_default()
+            .push(&container.name);
+        match configured.get(&container.machine) {
+            None => errors.push(format!(
+                "container {}: machine {} of address {} is not part of the job",
+                container.name, container.machine, container.address
+            )),
+            Some(addresses) if !addresses.contains(&container.address) => errors.push(format!(
+                "container {}: address {} is not configured on {}, was `net up` run with enough addresses?",
+                container.name, container.address, container.machine
+            )),
+            Some(_) => {}
+        }
+    }
+    if !allow_shared {
+        for (address, names) in users.iter().filter(|(_, names)| names.len() > 1) {
+            errors.push(format!(
+                "containers {} use the same address {address}, pass --allow-shared-addresses if this is intended",
+                names.join(", ")
+            ));
+        }
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(eyre::eyre!(
+        "the schedule does not match the network:\n  {}",
+        errors.join("\n  ")
+    ))
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("run was interrupted")]
+struct RunInterrupted;
+
+async fn cmd_run(args: RunArgs) -> Result<()> {
+    if args.latency_matrix.as_deref() == Some(Path::new("-")) {
+        return Err(eyre::eyre!(
+            "run --latency-matrix cannot read from stdin, the file is copied to the output directory"
+        ));
+    }
+    if args.dry_run {
+        return cmd_run_dry_run(&args).await;
+    }
+
+    let output_dir = run_output_dir(&args).await?;
+    let result = match args.repeat {
+        Some(repeat) => run_repeat(&args, &output_dir, repeat).await,
+        None => run_execute(&args, &output_dir, None).await,
+    };
+    // written even if the run failed, that is usually when the timings matter
+    let timings = timing::snapshot();
+    let timings_path = output_dir.join(timing::TIMINGS_FILE_NAME);
+    if !timings.is_empty()
+        && let Err(err) =
+            tokio::fs::write(&timings_path, serde_json::to_vec_pretty(&timings)?).await
+    {
+        tracing::warn!("failed to write {}: {err}", timings_path.display());
+    }
+    result
+}
+
+/// the output directory of a run with its placeholders expanded.
+///
+/// fails if the directory is not empty, unless the run is allowed to write to an existing one.
+async fn run_output_dir(args: &RunArgs) -> Result<PathBuf> {
+    let template = args
+        .output_dir
+        .as_deref()
+        .expect("the output directory is required");
+    let template = template.to_string_lossy();
+    check_output_dir_template(&template, args.resume || args.retry.is_some())?;
+    let job_ids = if template.contains("{job_id}") {
+        let ctx = context_from_common(&args.common).await?;
+        ctx.job_ids().await?
+    } else {
+        Vec::default()
+    };
+    let schedule_stem = args
+        .schedule
+        .as_deref()
+        .or(args.retry.as_deref())
+        .and_then(Path::file_stem)
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("stdin"));
+    let output_dir = expand_output_dir(&template, &job_ids, unix_timestamp(), &schedule_stem)?;
+    if output_dir.as_os_str() != template.as_ref() {
+        tracing::info!("using output directory {}", output_dir.display());
+    }
+
+    if !args.force && !args.resume && args.retry.is_none() && dir_has_entries(&output_dir).await? {
+        return Err(eyre::eyre!(
+            "output directory {} is not empty, use --force to write to it anyway",
+            output_dir.display()
+        ));
+    }
+    Ok(output_dir)
+}
+
+/// check that an output directory being continued with `--resume` or `--retry` resolves to the
+/// directory of the previous run.
+///
+/// `{timestamp}` would resolve to a new directory so it must be replaced by the concrete one.
+fn check_output_dir_template(template: &str, continuing: bool) -> Result<()> {
+    if continuing && template.contains("{timestamp}") {
+        return Err(eyre::eyre!(
+            "the output directory '{template}' contains {{timestamp}}, pass the directory of the previous run to --resume or --retry"
+        ));
+    }
+    Ok(())
+}
+
+/// replace the `{placeholder}`s of an output directory.
+fn expand_output_dir(
+    template: &str,
+    job_ids: &[u32],
+    timestamp: u64,
+    schedule_stem: &str,
+) -> Result<PathBuf> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut remaining = template;
+    while let Some(start) = remaining.find('{') {
+        let Some(len) = remaining[start + 1..].find('}') else {
+            break;
+        };
+        let replacement = match &remaining[start + 1..start + 1 + len] {
+            "job_id" => job_ids
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("-"),
+            "timestamp" => timestamp.to_string(),
+            "schedule_stem" => schedule_stem.to_string(),
+            placeholder => {
+                return Err(eyre::eyre!(
+                    "unknown placeholder '{{{placeholder}}}' in output directory, expected one of: job_id, timestamp or schedule_stem"
+                ));
+            }
+        };
+        expanded.push_str(&remaining[..start]);
+        expanded.push_str(&replacement);
+        remaining = &remaining[start + 1 + len + 1..];
+    }
+    expanded.push_str(remaining);
+    Ok(PathBuf::from(expanded))
+}
+
+/// whether the directory exists and has at least one entry.
+async fn dir_has_entries(path: &Path) -> Result<bool> {
+    match tokio::fs::read_dir(path).await {
+        Ok(mut entries) => Ok(entries
+            .next_entry()
+            .await
+            .with_context(|| format!("reading directory {}", path.display()))?
+            .is_some()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err).with_context(|| format!("reading directory {}", path.display())),
+    }
+}
+
+/// run the schedule `repeat` times, each run in its own directory inside `output_dir`.
+async fn run_repeat(args: &RunArgs, output_dir: &Path, repeat: u32) -> Result<()> {
+    // read once since the schedule might come from stdin
+    let schedule = read_schedule(args.schedule.as_deref()).await?;
+    let mut outcomes = Vec::default();
+    for run in 1..=repeat {
+        let run_dir = output_dir.join(format!("run-{run}"));
+        tracing::info!("starting run {run} of {repeat} in {}", run_dir.display());
+        let result = run_execute(args, &run_dir, Some(&schedule)).await;
+        if let Err(err) = &result {
+            tracing::error!("run {run} failed: {err:#}");
+        }
+        let interrupted = matches!(&result, Err(err) if err.is::<RunInterrupted>());
+        outcomes.push((run, run_dir, result));
+        if interrupted {
+            break;
+        }
+    }
+
+    let summary = outcomes
+        .iter()
+        .map(|(run, run_dir, result)| {
+            serde_json::json!({
+                "run": run,
+                "output_dir": run_dir,
+                "success": result.is_ok(),
+                "error": result.as_ref().err().map(|err| format!("{err:#}")),
+            })
+        })
+        .collect::<Vec<_>>();
+    tokio::fs::write(
+        output_dir.join("summary.json"),
+        serde_json::to_string_pretty(&summary)?,
+    )
+    .await
+    .context("writing summary")?;
+
+    println!("{:<6} {:<8} ERROR", "RUN", "OUTCOME");
+    for (run, _, result) in outcomes.iter() {
+        match result {
+            Ok(()) => println!("{run:<6} {:<8} -", "success"),
+            Err(err) => println!("{run:<6} {:<8} {err}", "failed"),
+        }
+    }
+
+    let failed = outcomes.iter().filter(|(_, _, r)| r.is_err()).count();
+    if outcomes.len() < repeat as usize {
+        return Err(RunInterrupted.into());
+    }
+    if failed > 0 {
+        return Err(eyre::eyre!("{failed} out of {repeat} runs failed"));
+    }
+    Ok(())
+}
+
+/// execute a single run writing its results to `output_dir`.
+///
+/// the schedule is read from the run flags unless given.
+async fn run_execute(
+    args: &RunArgs,
+    output_dir: &Path,
+    schedule: Option<&(String, String)>,
+) -> Result<()> {
+    tracing::debug!(
+        "creating output directory if it does not exist at {}",
+        output_dir.display()
+    );
+    tokio::fs::create_dir_all(output_dir)
+        .await
+        .context("creating output directory")?;
+
+    let ctx = context_from_common(&args.common).await?;
+    let _lock = job_lock(&ctx, &args.common).await?;
+    let state_path = output_dir.join(run_state::RUN_STATE_FILE_NAME);
+    let state = if args.resume {
+        let state = RunStateFile::load(state_path).await?;
+        tracing::info!(
+            "resuming run from {} at phase {:?}",
+            state.path().display(),
+            state.snapshot().await.phase()
+        );
+        state
+    } else {
+        let (retry, (schedule_source, schedule)) = match &args.retry {
+            Some(path) => {
+                let manifest = read_manifest(path).await?;
+                let schedule = manifest
+                    .retry_schedule()
+                    .with_context(|| format!("reading manifest {}", path.display()))?;
+                tracing::info!(
+                    "retrying {} containers from {}",
+                    manifest.retry_containers().len(),
+                    path.display()
+                );
+                (Some(manifest), (path.display().to_string(), schedule))
+            }
+            None => match schedule {
+                Some(schedule) => (None, schedule.clone()),
+                None => (None, read_schedule(args.schedule.as_deref()).await?),
+            },
+        };
+        let signals = args.signal.iter().map(ToString::to_string).collect();
+        let mut state = RunState::new(schedule_source, schedule, signals);
+        state.barrier = args.barrier;
+        state.retry = retry;
+        RunStateFile::create(state_path, state).await?
+    };
+    let snapshot = state.snapshot().await;
+    tracing::trace!("parsing schedule:\n{}", snapshot.schedule);
+    let items = schedule::parse(&snapshot.schedule_source, &snapshot.schedule)?;
+    let machines = oar::job_list_machines(&ctx).await?;
+    machines_job_mark(&ctx, &machines).await?;
+    write_provenance(
+        output_dir,
+        &snapshot.schedule_source,
+        &snapshot.schedule,
+        args.latency_matrix.as_deref(),
+        &machines,
+    )
+    .await?;
+
+    // the addresses configured on every machine, for the placement and to validate the schedule
+    let listed = machine::for_each(&machines, |machine| {
+        let ctx = ctx.clone();
+        async move { machine_list_addresses(&ctx, machine).await }
+    })
+    .await?;
+
+    // the placement is saved so that resuming a run keeps the same addresses
+    let unaddressed = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.address.is_none())
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    if !unaddressed.is_empty() && snapshot.assigned_addresses.is_empty() {
+        let seed = args.seed.unwrap_or_else(unix_timestamp);
+        tracing::info!(
+            "placing {} containers using the {:?} strategy with seed {seed}",
+            unaddressed.len(),
+            args.placement
+        );
+        let used = items
+            .iter()
+            .filter_map(|i| i.address)
+            .collect::<HashSet<_>>();
+        let available = listed
+            .iter()
+            .map(|(machine, addresses)| {
+                let free = addresses.iter().copied().filter(|a| !used.contains(a));
+                (*machine, free.collect())
+            })
+            .collect::<Vec<_>>();
+        let placed = placement::place(args.placement, seed, unaddressed.len(), &available)?;
+        state
+            .update(|state| {
+                state.assigned_addresses = unaddressed.into_iter().zip(placed).collect()
+            })
+            .await?;
+    }
+    let snapshot = state.snapshot().await;
+    let mut containers = run_prepare_containers(
+        args,
+        &snapshot.schedule_source,
+        items,
+        &snapshot.assigned_addresses,
+        snapshot.barrier,
+    )?;
+    let run_signals = snapshot
+        .signals
+        .iter()
+        .map(|s| s.parse::<RunSignalSpec>())
+        .collect::<Result<Vec<_>, _>>()?;
+    // restricted signals become container signals so their files are mounted in the containers
+    for signal in run_signals.iter() {
+        let Some(pattern) = &signal.containers else {
+            continue;
+        };
+        let mut matched = false;
+        for container in containers
+            .iter_mut()
+            .filter(|c| ps::glob_match(pattern, &c.name))
+        {
+            container.signals.push(signal.spec.clone());
+            matched = true;
+        }
+        if !matched {
+            tracing::warn!("signal {signal} does not match any container");
+        }
+    }
+    let configured = listed
+        .into_iter()
+        .map(|(machine, addresses)| (machine, addresses.into_iter().collect()))
+        .collect::<HashMap<_, _>>();
+    run_validate_addresses(&containers, &configured, args.allow_shared_addresses)?;
+    let (start_levels, start_offsets) = run_start_plan(args, &containers)?;
+    let staggered = start_offsets.iter().any(|o| !o.is_zero());
+    let max_start_offset = start_offsets.iter().copied().max().unwrap_or_default();
+    let signal_events = {
+        let mut events = Vec::default();
+        for signal in run_signals.iter().filter(|s| s.containers.is_none()) {
+            events.push(SignalEvent {
+                key: signal.to_string(),
+                container: None,
+                spec: signal.spec.clone(),
+            });
+        }
+        for (idx, container) in containers.iter().enumerate() {
+            for spec in container.signals.iter() {
+                events.push(SignalEvent {
+                    key: format!("{}/{spec}", container.name),
+                    container: Some(idx),
+                    spec: spec.clone(),
+                });
+            }
+        }
+        events.sort_by_key(|e| e.spec.delay);
+        events
+    };
+
+    let max_signal_delay = signal_events
+        .iter()
+        .map(|e| e.spec.delay)
+        .max()
+        .unwrap_or_default();
+    walltime_check(
+        &ctx,
+        RUN_WALLTIME_ESTIMATE + max_start_offset + max_signal_delay,
+    )
+    .await;
+
+    let state = &state;
+    let containers = &containers;
+
+    let execute = async {
+        // machines that did not finish creating their containers start over from a clean slate
+        let pending = state.machines_before(&machines, RunPhase::Created).await;
+        if let Some(hook) = &args.pre_hook
+            && !pending.is_empty()
+        {
+            run_hook(
+                &ctx,
+                &machines,
+                output_dir,
+                "pre",
+                hook,
+                args.hooks_on_machines,
+            )
+            .await?;
+        }
+        machines_resources_check(&ctx, &pending, containers).await?;
+        machines_containers_clean(&ctx, &pending).await?;
+        tracing::info!("pulling images on all machines");
+        machine::for_each_progress("pulling images", &pending, |machine| {
+            let ctx = ctx.clone();
+            let images = containers_images(&machine_scheduled_containers(containers, machine))
+                .into_iter()
+                .filter(|image| !args.load_image.contains(image))
+                .collect::<Vec<_>>();
+            let registry_config = args.registry_config.as_deref();
+            async move { machine_pull_images(&ctx, machine, &images, registry_config).await }
+        })
+        .await?;
+        for image in args.load_image.iter() {
+            let targets = pending
+                .iter()
+                .copied()
+                .filter(|&m| {
+                    containers
+                        .iter()
+                        .any(|c| c.machine == m && &c.image == image)
+                })
+                .collect::<Vec<_>>();
+            if !targets.is_empty() {
+                machines_load_image(&ctx, &targets, image).await?;
+            }
+        }
+        machine::for_each_progress("pushing inputs", &pending, |machine| {
+            let ctx = ctx.clone();
+            let inputs = containers_inputs(&machine_scheduled_containers(containers, machine));
+            let transfer = &args.transfer;
+            async move { machine_push_inputs(&ctx, machine, &inputs, transfer).await }
+        })
+        .await?;
+        machine::for_each_progress("creating containers", &pending, |machine| {
+            let ctx = ctx.clone();
+            let containers = machine_scheduled_containers(containers, machine);
+            async move {
+                machine_create_containers(&ctx, machine, &containers).await?;
+                state
+                    .set_machine_phase(
+                        machine,
+                        RunPhase::Created,
+                        containers.iter().map(|c| c.name.as_str()),
+                    )
+                    .await
+            }
+        })
+        .await?;
+
+        if !args.pcap.is_empty() {
+            tracing::info!("starting packet captures");
+            let captures = pcap_captures(&args.pcap, containers)?;
+            machine::for_each(captures.keys(), |machine| {
+                machine_pcap_start(
+                    &ctx,
+                    machine,
+                    &captures[&machine],
+                    args.pcap_rotate_size,
+                    args.pcap_rotate_count,
+                )
+            })
+            .await?;
+        }
+
+        if let Some(interval) = args.host_metrics_interval {
+            tracing::info!("starting host metrics sampler on all machines");
+            let interval = Duration::from_secs(interval);
+            machine::for_each(
+                machines
+                    .iter()
+                    .filter(|&machine| containers.iter().any(|c| c.machine == *machine)),
+                |machine| machine_host_metrics_start(&ctx, machine, interval),
+            )
+            .await?;
+        }
+
+        tracing::info!("starting all containers on all machines");
+        let pending = state.machines_before(&machines, RunPhase::Started).await;
+        let has_dependencies = start_levels.len() > 1;
+        if has_dependencies {
+            for (level_idx, level) in start_levels.iter().enumerate() {
+                tracing::info!(
+                    "starting {} containers at dependency level {level_idx}",
+                    level.len()
+                );
+                machine::for_each(&pending, |machine| {
+                    let ctx = ctx.clone();
+                    let names = containers
+                        .iter()
+                        .filter(|c| c.machine == machine && level.contains(&c.name))
+                        .map(|c| c.name.clone())
+                        .collect::<Vec<_>>();
+                    async move {
+                        if !names.is_empty() {
+                            machine_start_containers_and_wait(&ctx, machine, &names).await?;
+                        }
+                        Ok(())
+                    }
+                })
+                .await?;
+            }
+        }
+        // staggered startup is relative to a common instant so the ramp spans all machines
+        let start_base = SystemTime::now() + Duration::from_secs(1);
+        let start_offsets = &start_offsets;
+        machine::for_each_progress("starting containers", &pending, |machine| {
+            let ctx = ctx.clone();
+            let schedule = containers
+                .iter()
+                .zip(start_offsets.iter())
+                .filter(|(c, _)| c.machine == machine)
+                .map(|(c, &offset)| (c.name.clone(), start_base + offset))
+                .collect::<Vec<_>>();
+            let containers = machine_scheduled_containers(containers, machine);
+            async move {
+                if staggered && !schedule.is_empty() {
+                    machine_start_containers_staggered(&ctx, machine, &schedule).await?;
+                } else if !has_dependencies && !containers.is_empty() {
+                    machine_start_containers(&ctx, machine).await?;
+                }
+                state
+                    .set_machine_phase(
+                        machine,
+                        RunPhase::Started,
+                        containers.iter().map(|c| c.name.as_str()),
+                    )
+                    .await
+            }
+        })
+        .await?;
+
+        let started_at = match state.snapshot().await.started_at {
+            Some(started_at) => started_at,
+            None => {
+                let started_at = unix_timestamp();
+                state
+                    .update(|state| state.started_at = Some(started_at))
+                    .await?;
+                started_at
+            }
+        };
+        let signal_start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(started_at);
+
+        if snapshot.barrier
+            && !state
+                .snapshot()
+                .await
+                .signals_delivered
+                .iter()
+                .any(|s| s == BARRIER_SIGNAL)
+        {
+            tracing::info!("waiting for all containers to be running before releasing the barrier");
+            let barrier_machines = machines
+                .iter()
+                .copied()
+                .filter(|&machine| containers.iter().any(|c| c.machine == machine))
+                .collect::<Vec<_>>();
+            machine::for_each(&barrier_machines, |machine| {
+                let ctx = ctx.clone();
+                let names = machine_scheduled_containers(containers, machine)
+                    .into_iter()
+                    .map(|c| c.name)
+                    .collect::<Vec<_>>();
+                async move { machine_containers_wait_running(&ctx, machine, &names).await }
+            })
+            .await?;
+
+            tracing::info!("releasing the barrier");
+            let barrier = BARRIER_SIGNAL.parse::<Signal>().unwrap();
+            let timestamp = unix_timestamp();
+            machine::for_each(&barrier_machines, |machine| {
+                machine_signal_containers(&ctx, machine, &barrier, timestamp)
+            })
+            .await?;
+            timeline_append_signal(output_dir, timestamp, &barrier, None).await;
+            state
+                .update(|state| state.signals_delivered.push(BARRIER_SIGNAL.to_string()))
+                .await?;
+        }
+
+        // dropping the set aborts the churn, so it ends together with the experiment
+        let mut churn_task = tokio::task::JoinSet::new();
+        if let Some(spec) = &args.churn {
+            tracing::info!("starting container churn");
+            churn_task.spawn(run_churn(
+                ctx.clone(),
+                containers.to_vec(),
+                spec.clone(),
+                output_dir.to_path_buf(),
+            ));
+        }
+
+        for event in signal_events {
+            let spec = &event.spec;
+            if state
+                .snapshot()
+                .await
+                .signals_delivered
+                .contains(&event.key)
+            {
+                tracing::debug!("signal {} was already delivered", event.key);
+                continue;
+            }
+
+            tracing::info!("waiting to trigger signal {}", event.key);
+            let remaining = (signal_start_time + spec.delay)
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            tokio::time::sleep(remaining).await;
+
+            tracing::info!("triggering signal {}", event.key);
+            let signal_timestamp = unix_timestamp();
+            let container = event.container.map(|idx| &containers[idx]);
+            timeline_append_signal(output_dir, signal_timestamp, &spec.signal, container).await;
+            match event.container {
+                Some(idx) => {
+                    let container = &containers[idx];
+                    machine_signal_container(&ctx, container, &spec.signal, signal_timestamp)
+                        .await?
+                }
+                None => {
+                    machine::for_each(
+                        machines
+                            .iter()
+                            .filter(|&machine| containers.iter().any(|c| c.machine == *machine)),
+                        |machine| {
+                            machine_signal_containers(&ctx, machine, &spec.signal, signal_timestamp)
+                        },
+                    )
+                    .await?;
+                }
+            }
+            state
+                .update(|state| state.signals_delivered.push(event.key))
+                .await?;
+        }
+
+        tracing::info!("waiting for all containers to exit");
+        let pending = state.machines_before(&machines, RunPhase::Exited).await;
+        let wait_fut = machine::for_each_progress("waiting for containers", &pending, |machine| {
+            let ctx = ctx.clone();
+            let containers = machine_scheduled_containers(containers, machine);
+            let fail_fast = args.allow_failures.is_none();
+            let churn = args.churn.is_some();
+            async move {
+                let exit_codes =
+                    machine_containers_wait(&ctx, machine, &containers, fail_fast, churn)
+                        .await
+                        .with_context(|| format!("waiting for containers on {machine}"))?;
+                state
+                    .update(|state| state.exit_codes.extend(exit_codes))
+                    .await?;
+                state
+                    .set_machine_phase(
+                        machine,
+                        RunPhase::Exited,
+                        containers.iter().map(|c| c.name.as_str()),
+                    )
+                    .await
+            }
+        });
+        tokio::select! {
+            result = wait_fut => { result?; }
+            Some(result) = churn_task.join_next() => match result?? {},
+            _ = stats_collector(&ctx, &machines, containers, args.stats_interval.map(Duration::from_secs), output_dir) => {}
+            _ = timeout_guard(signal_start_time, args.timeout.map(Duration::from_secs)) => {
+                tracing::warn!("experiment timeout expired, stopping all containers and collecting logs");
+                churn_task.shutdown().await;
+                run_stop_containers(&ctx, state, &machines, containers).await?;
+            }
+        }
+        Ok::<_, eyre::Report>(())
+    };
+    // the job guards cover every phase of the run, from pulling the images to waiting for the
+    // containers, since a long startup or signal delay can outlive the job just as well
+    let stopped_early = tokio::select! {
+        biased;
+        _ = tokio::signal::ctrl_c() => {
+            tracing::warn!("interrupted, stopping all containers and collecting logs. interrupt again to exit immediately");
+            tokio::spawn(async {
+                let _ = tokio::signal::ctrl_c().await;
+                otlp::flush().await;
+                std::process::exit(130);
+            });
+            // only machines that finished creating their containers have logs to collect
+            let snapshot = state.snapshot().await;
+            let created = machines
+                .iter()
+                .copied()
+                .filter(|&m| snapshot.machine_phase(m) >= RunPhase::Created)
+                .collect::<Vec<_>>();
+            run_stop_containers(&ctx, state, &created, containers).await?;
+            run_collect_logs(
+                &ctx,
+                state,
+                &created,
+                containers,
+                output_dir,
+                &args.transfer,
+            )
+            .await?;
+            machines_containers_clean(&ctx, &machines).await?;
+            return Err(RunInterrupted.into());
+        }
+        result = execute => {
+            result?;
+            false
+        }
+        _ = walltime_guard(&ctx, Duration::from_secs(args.walltime_margin)) => {
+            tracing::warn!("job walltime is about to expire, stopping all containers and collecting logs");
+            true
+        }
+        _ = preemption_guard(&ctx) => {
+            tracing::warn!("best-effort job is being preempted, stopping all containers and collecting logs");
+            true
+        }
+    };
+    // dropping the run stopped the churn, only machines that created their containers are left
+    let collect_machines = if stopped_early {
+        let snapshot = state.snapshot().await;
+        let created = machines
+            .iter()
+            .copied()
+            .filter(|&m| snapshot.machine_phase(m) >= RunPhase::Created)
+            .collect::<Vec<_>>();
+        run_stop_containers(&ctx, state, &created, containers).await?;
+        created
+    } else {
+        machines.clone()
+    };
+
+    run_collect_logs(
+        &ctx,
+        state,
+        &collect_machines,
+        containers,
+        output_dir,
+        &args.transfer,
+    )
+    .await?;
+
+    if let Some(hook) = &args.post_hook {
+        run_hook(
+            &ctx,
+            &machines,
+            output_dir,
+            "post",
+            hook,
+            args.hooks_on_machines,
+        )
+        .await?;
+    }
+
+    if let Some(allowance) = args.allow_failures {
+        let failed = state.snapshot().await.failed_containers();
+        let max_failures = allowance.max_failures(containers.len());
+        if !failed.is_empty() {
+            tracing::warn!(
+                "{} out of {} containers failed: {}",
+                failed.len(),
+                containers.len(),
+                failed.join(", ")
+            );
+        }
+        if failed.len() > max_failures {
+            return Err(eyre::eyre!(
+                "{} containers failed but at most {max_failures} were allowed to fail",
+                failed.len()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// execute a hook script locally or on every machine and save its output to the `hooks`
+/// directory of the output directory.
+async fn run_hook(
+    ctx: &Context,
+    machines: &[Machine],
+    output_dir: &Path,
+    stage: &str,
+    path: &Path,
+    on_machines: bool,
+) -> Result<()> {
+    tracing::info!("running {stage} hook {}", path.display());
+    let hooks_dir = output_dir.join("hooks");
+    tokio::fs::create_dir_all(&hooks_dir)
+        .await
+        .context("creating hooks directory")?;
+    let write_output = |name: String, output: Output| {
+        let hooks_dir = hooks_dir.clone();
+        async move {
+            tokio::fs::write(hooks_dir.join(format!("{name}.stdout")), &output.stdout).await?;
+            tokio::fs::write(hooks_dir.join(format!("{name}.stderr")), &output.stderr).await?;
+            Ok::<_, std::io::Error>(output.status)
+        }
+    };
+
+    if on_machines {
+        let script = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("reading {stage} hook {}", path.display()))?;
+        let outputs =
+            machine::for_each(machines, |machine| {
+                let ctx = ctx.clone();
+                let script = script.clone();
+                async move {
+                    machine_run(&ctx, machine, &[], Some(&script), retry::Retry::Connection).await
+                }
+            })
+            .await?;
+        let mut failed = Vec::default();
+        for (machine, output) in outputs {
+            let status = write_output(format!("{stage}.{machine}"), output)
+                .await
+                .with_context(|| format!("saving the output of the {stage} hook"))?;
+            if !status.success() {
+                failed.push(machine.to_string());
+            }
+        }
+        if !failed.is_empty() {
+            return Err(eyre::eyre!(
+                "{stage} hook failed on {}, see {}",
+                failed.join(", "),
+                hooks_dir.display()
+            ));
+        }
+    } else {
+        let output = Command::new("bash")
+            .arg(path)
+            .env("OAR_P2P_OUTPUT_DIR", output_dir)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .await
+            .with_context(|| format!("running {stage} hook {}", path.display()))?;
+        let status = write_output(stage.to_string(), output)
+            .await
+            .with_context(|| format!("saving the output of the {stage} hook"))?;
+        if !status.success() {
+            return Err(eyre::eyre!(
+                "{stage} hook failed with {status}, see {}",
+                hooks_dir.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// save the container logs on every machine, copy them to the output directory and write the
+/// manifest.
+async fn run_collect_logs(
+    ctx: &Context,
+    state: &RunStateFile,
+    machines: &[Machine],
+    containers: &[ScheduledContainer],
+    output_dir: &Path,
+    transfer: &LogTransferArgs,
+) -> Result<()> {
+    if let Err(err) = run_write_timeline(ctx, machines, containers, output_dir).await {
+        tracing::warn!("failed to write the container timeline: {err:#}");
+    }
+
+    tracing::info!("saving logs to disk on all machines");
+    let pending = state.machines_before(machines, RunPhase::LogsSaved).await;
+    machine::for_each_progress("saving logs", &pending, |machine| {
+        let ctx = ctx.clone();
+        let containers = machine_scheduled_containers(containers, machine);
+        async move {
+            machine_containers_save_logs(&ctx, machine, &containers).await?;
+            state
+                .set_machine_phase(machine, RunPhase::LogsSaved, [])
+                .await
+        }
+    })
+    .await?;
+
+    tracing::info!("copying logs from all machines");
+    let pending = state.machines_before(machines, RunPhase::LogsCopied).await;
+    let copied = std::sync::atomic::AtomicUsize::new(0);
+    let copy_start = std::time::Instant::now();
+    machine::for_each_with_limit_progress(
+        "copying logs",
+        &pending,
+        transfer.transfer_concurrency,
+        |machine| {
+            let ctx = ctx.clone();
+            let has_containers = containers.iter().any(|c| c.machine == machine);
+            let output_dir = output_dir.to_path_buf();
+            let copied = &copied;
+            let total = pending.len();
+            async move {
+                if has_containers && transfer.archive {
+                    machine_copy_logs_archive(&ctx, machine, &output_dir, transfer).await?;
+                } else if has_containers {
+                    let compression = transfer.compress;
+                    machine_copy_logs_stream(&ctx, machine, &output_dir, transfer, compression)
+                        .await?;
+                }
+                let copied = copied.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+                tracing::info!(
+                    "copied logs from {machine} ({copied}/{total}) after {}",
+                    format_duration(copy_start.elapsed())
+                );
+                state
+                    .set_machine_phase(machine, RunPhase::LogsCopied, [])
+                    .await
+            }
+        },
+    )
+    .await?;
+
+    if transfer.merge_archives {
+        let machines = machines
+            .iter()
+            .copied()
+            .filter(|&machine| containers.iter().any(|c| c.machine == machine))
+            .collect::<Vec<_>>();
+        merge_logs_archives(&machines, output_dir).await?;
+    }
+
+    run_write_manifest(ctx, state, machines, containers, output_dir).await?;
+    Ok(())
+}
+
+async fn run_write_manifest(
+    ctx: &Context,
+    state: &RunStateFile,
+    machines: &[Machine],
+    containers: &[ScheduledContainer],
+    output_dir: &Path,
+) -> Result<()> {
+    tracing::info!("writing run manifest");
+    let machines = machines
+        .iter()
+        .copied()
+        .filter(|&machine| containers.iter().any(|c| c.machine == machine))
+        .collect::<Vec<_>>();
+    let mut inspected = HashMap::<String, manifest::ContainerInspect>::default();
+    let results = machine::for_each(&machines, |machine| {
+        let ctx = ctx.clone();
+        let containers = machine_scheduled_containers(containers, machine);
+        async move { machine_containers_inspect(&ctx, machine, &containers).await }
+    })
+    .await?;
+    for (_, result) in results {
+        inspected.extend(result);
+    }
+
+    let snapshot = state.snapshot().await;
+    let manifest = manifest::Manifest {
+        failed_containers: snapshot.failed_containers(),
+        schedule_source: snapshot.schedule_source,
+        schedule: serde_json::from_str(&snapshot.schedule).context("parsing schedule")?,
+        started_at: snapshot.started_at,
+        finished_at: unix_timestamp(),
+        containers: containers
+            .iter()
+            .map(|c| manifest::ManifestContainer {
+                name: c.name.clone(),
+                machine: c.machine,
+                address: c.address,
+                image: c.image.clone(),
+                inspect: inspected.remove(&c.name).unwrap_or_default(),
+            })
+            .collect(),
+    };
+    let manifest = match snapshot.retry {
+        Some(previous) => manifest.merge_retry(previous),
+        None => manifest,
+    };
+    let path = output_dir.join(manifest::MANIFEST_FILE_NAME);
+    tokio::fs::write(&path, serde_json::to_string_pretty(&manifest)?)
+        .await
+        .with_context(|| format!("writing run manifest to {}", path.display()))?;
+    Ok(())
+}
+
+async fn read_manifest(path: &Path) -> Result<manifest::Manifest> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("reading manifest {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("parsing manifest {}", path.display()))
+}
+
+async fn read_schedule(path: Option<&Path>) -> Result<(String, String)> {
+    match path {
+        Some(path) => {
+            tracing::debug!("reading schedule from {}", path.display());
+            let content = tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("reading schedule file: {}", path.display()))?;
+            Ok((path.display().to_string(), content))
+        }
+        None => {
+            tracing::debug!("reading schedule from stdin");
+            let mut stdin = String::default();
+            tokio::io::stdin()
+                .read_to_string(&mut stdin)
+                .await
+                .context("reading schedule from stdin")?;
+            Ok((String::from("<stdin>"), stdin))
+        }
+    }
+}
+
+/// build the containers of a run from the schedule items and the run flags.
+fn run_prepare_containers(
+    args: &RunArgs,
+    schedule_source: &str,
+    items: Vec<schedule::ScheduleItem>,
+    assigned_addresses: &BTreeMap<usize, Ipv4Addr>,
+    barrier: bool,
+) -> Result<Vec<ScheduledContainer>> {
+    let mut containers = schedule_containers(schedule_source, items, assigned_addresses)?;
+    if let Some(cpus) = args.cpus_per_container {
+        assign_container_cpusets(&mut containers, cpus)?;
+    }
+    if barrier {
+        for container in containers.iter_mut() {
+            container.variables.insert(
+                String::from("OAR_P2P_BARRIER"),
+                format!("/oar-p2p/{BARRIER_SIGNAL}"),
+            );
+        }
+    }
+    if let Some(mode) = args.peers {
+        let addresses = containers.iter().map(|c| c.address).collect::<Vec<_>>();
+        let seed = args.seed.unwrap_or_default();
+        for (idx, container) in containers.iter_mut().enumerate() {
+            let peers = peers::peer_list(&addresses, idx, args.peers_sample, seed);
+            match mode {
+                peers::PeerListMode::Env => {
+                    let peers = peers.iter().map(ToString::to_string).collect::<Vec<_>>();
+                    container
+                        .variables
+                        .insert(String::from(peers::PEERS_ENV_VAR), peers.join(","));
+                }
+                peers::PeerListMode::File => {
+                    let peers = peers.iter().map(|p| format!("{p}\n")).collect::<String>();
+                    container.peers_file = Some(peers);
+                    container.variables.insert(
+                        String::from(peers::PEERS_FILE_ENV_VAR),
+                        String::from(peers::PEERS_CONTAINER_PATH),
+                    );
+                }
+            }
+        }
+    }
+    Ok(containers)
+}
+
+/// the dependency levels and the start offset of each container.
+fn run_start_plan(
+    args: &RunArgs,
+    containers: &[ScheduledContainer],
+) -> Result<(Vec<Vec<String>>, Vec<Duration>)> {
+    let start_levels = container_start_levels(containers)?;
+    let start_interval = args
+        .start_interval
+        .map(Duration::try_from_secs_f64)
+        .transpose()
+        .context("invalid start interval")?;
+    let start_offsets = container_start_offsets(containers, start_interval);
+    let staggered = start_offsets.iter().any(|o| !o.is_zero());
+    if staggered && start_levels.len() > 1 {
+        return Err(eyre::eyre!(
+            "staggered startup cannot be combined with container dependencies"
+        ));
+    }
+    Ok((start_levels, start_offsets))
+}
+
+/// pull the images of the schedule on the machines that will run them.
+///
+/// items without an address have their image pulled on every machine of the job.
+async fn cmd_run_pull(args: RunPullArgs) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let (schedule_source, schedule) = read_schedule(args.schedule.as_deref()).await?;
+    let items = schedule::parse(&schedule_source, &schedule)?;
+    let machines = oar::job_list_machines(&ctx).await?;
+
+    let mut images = BTreeMap::<Machine, std::collections::BTreeSet<String>>::default();
+    for item in items.iter() {
+        match item.address {
+            Some(address) => {
+                let machine = machine_from_addr(address)?;
+                images
+                    .entry(machine)
+                    .or_default()
+                    .insert(item.image.clone());
+            }
+            None => {
+                for &machine in machines.iter() {
+                    images
+                        .entry(machine)
+                        .or_default()
+                        .insert(item.image.clone());
+                }
+            }
+        }
+    }
+    for machine in images.keys() {
+        if !machines.contains(machine) {
+            return Err(eyre::eyre!(
+                "{schedule_source}: machine {machine} is not part of the job"
+            ));
+        }
+    }
+
+    let targets = images.keys().copied().collect::<Vec<_>>();
+    machine::for_each(&targets, |machine| {
+        let ctx = ctx.clone();
+        let images = images[&machine].iter().cloned().collect::<Vec<_>>();
+        let registry_config = args.registry_config.as_deref();
+        async move { machine_pull_images(&ctx, machine, &images, registry_config).await }
+    })
+    .await?;
+    Ok(())
+}
+
+async fn cmd_run_push(args: RunPushArgs) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let (schedule_source, schedule) = read_schedule(args.schedule.as_deref()).await?;
+    let items = schedule::parse(&schedule_source, &schedule)?;
+    let machines = oar::job_list_machines(&ctx).await?;
+
+    let mut inputs = BTreeMap::<Machine, std::collections::BTreeSet<PathBuf>>::default();
+    for (idx, item) in items.iter().enumerate() {
+        let targets = match item.address {
+            Some(address) => vec![machine_from_addr(address)?],
+            None => machines.clone(),
+        };
+        for input in item.inputs.iter() {
+            let local = std::path::absolute(&input.local)
+                .with_context(|| format!("{schedule_source}: item {idx}: inputs"))?;
+            for &machine in targets.iter() {
+                inputs.entry(machine).or_default().insert(local.clone());
+            }
+        }
+    }
+    for machine in inputs.keys() {
+        if !machines.contains(machine) {
+            return Err(eyre::eyre!(
+                "{schedule_source}: machine {machine} is not part of the job"
+            ));
+        }
+    }
+
+    let targets = inputs.keys().copied().collect::<Vec<_>>();
+    machine::for_each(&targets, |machine| {
+        let ctx = ctx.clone();
+        let inputs = inputs[&machine].iter().cloned().collect::<Vec<_>>();
+        let transfer = &args.transfer;
+        async move { machine_push_inputs(&ctx, machine, &inputs, transfer).await }
+    })
+    .await?;
+    Ok(())
+}
+
+async fn cmd_run_exec(args: RunExecArgs) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machine = container_lookup_machine(&ctx, &args.lookup, &args.container).await?;
+    tracing::debug!("container {} is on {machine}", args.container);
+
+    let tty = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let mut command = vec![ctx.runtime.command().to_string(), String::from("exec")];
+    command.push(String::from(if tty { "-it" } else { "-i" }));
+    command.push(args.container.clone());
+    command.extend(args.command.iter().cloned());
+    let status = machine_run_interactive(&ctx, machine, &command, tty).await?;
+    if !status.success() {
+        otlp::flush().await;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+async fn cmd_run_logs(args: RunLogsArgs) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machine = container_lookup_machine(&ctx, &args.lookup, &args.container).await?;
+    tracing::debug!("container {} is on {machine}", args.container);
+
+    let mut command = vec![ctx.runtime.command().to_string(), String::from("logs")];
+    if args.follow {
+        command.push(String::from("--follow"));
+    }
+    if let Some(tail) = args.tail {
+        command.push(format!("--tail={tail}"));
+    }
+    command.push(args.container.clone());
+    let status = machine_run_interactive(&ctx, machine, &command, false).await?;
+    if !status.success() {
+        otlp::flush().await;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+async fn cmd_run_ps(args: RunPsArgs, output: OutputMode) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&ctx).await?;
+    let containers = machines_list_run_containers(&ctx, &machines).await?;
+
+    let format = match output {
+        OutputMode::Json => OutputFormat::Json,
+        OutputMode::Text => args.format,
+    };
+    match format {
+        OutputFormat::Table => {
+            println!(
+                "{:<24} {:<12} {:<16} {:<12} EXIT CODE",
+                "NAME", "MACHINE", "ADDRESS", "STATE"
+            );
+            for container in containers {
+                let address = container
+                    .address
+                    .map(|a| a.to_string())
+                    .unwrap_or_else(|| String::from("-"));
+                let exit_code = container
+                    .exit_code
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| String::from("-"));
+                println!(
+                    "{:<24} {:<12} {:<16} {:<12} {}",
+                    container.name, container.machine, address, container.state, exit_code
+                );
+            }
+        }
+        OutputFormat::Json => print_json(&containers)?,
+    }
+    Ok(())
+}
+
+/// list the containers created by `run` on `machines`, sorted by machine and name.
+async fn machines_list_run_containers(
+    ctx: &Context,
+    machines: &[Machine],
+) -> Result<Vec<ps::ContainerStatus>> {
+    let script = ps::ps_script(ctx.runtime);
+    let mut containers = machine::for_each(machines, |machine| {
+        let ctx = ctx.clone();
+        let script = script.clone();
+        async move {
+            let output = machine_run_script(&ctx, machine, &script).await?;
+            Ok(ps::parse_ps_output(
+                machine,
+                &String::from_utf8_lossy(&output.stdout),
+            ))
+        }
+    })
+    .await?
+    .into_iter()
+    .flat_map(|(_, containers)| containers)
+    .collect::<Vec<_>>();
+    containers.sort_by(|a, b| (a.machine, &a.name).cmp(&(b.machine, &b.name)));
+    Ok(containers)
+}
+
+async fn cmd_run_watch(args: RunWatchArgs, output: OutputMode) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&ctx).await?;
+    let script = ps::ps_script(ctx.runtime);
+    let mut alerted_failures = 0;
+    let mut collected = HashSet::<String>::default();
+    loop {
+        // unlike `machine::for_each` a machine that fails to answer does not stop the others
+        let polls = futures::future::join_all(machines.iter().map(|&machine| {
+            let ctx = ctx.clone();
+            let script = script.clone();
+            async move {
+                let result = machine_run_script(&ctx, machine, &script).await;
+                (machine, result)
+            }
+        }))
+        .await;
+        let mut containers = Vec::default();
+        let mut unreachable = Vec::default();
+        for (machine, result) in polls {
+            match result {
+                Ok(output) => containers.extend(ps::parse_ps_output(
+                    machine,
+                    &String::from_utf8_lossy(&output.stdout),
+                )),
+                Err(err) => {
+                    tracing::warn!("failed to poll {machine}: {err:#}");
+                    unreachable.push(machine);
+                }
+            }
+        }
+        containers.sort_by(|a, b| (a.machine, &a.name).cmp(&(b.machine, &b.name)));
+        let summary = watch::WatchSummary::new(unix_timestamp(), &containers, unreachable);
+        match output {
+            OutputMode::Text => println!("{}", summary.line()),
+            OutputMode::Json => println!("{}", serde_json::to_string(&summary)?),
+        }
+
+        if let Some(dir) = &args.collect_logs {
+            let failed = containers
+                .iter()
+                .filter(|c| summary.failed.contains(&c.name) && !collected.contains(&c.name))
+                .collect::<Vec<_>>();
+            for container in failed {
+                match watch_collect_logs(&ctx, container, dir).await {
+                    Ok(()) => {
+                        collected.insert(container.name.clone());
+                    }
+                    Err(err) => {
+                        tracing::warn!("failed to collect logs of {}: {err:#}", container.name)
+                    }
+                }
+            }
+        }
+
+        let failures = summary.failures();
+        if failures >= args.failure_threshold && failures > alerted_failures {
+            tracing::warn!(
+                "{failures} failures crossed the threshold of {}",
+                args.failure_threshold
+            );
+            alerted_failures = failures;
+            if let Some(command) = &args.alert_command
+                && let Err(err) = watch_alert(command, &summary).await
+            {
+                tracing::warn!("alert command failed: {err:#}");
+            }
+        }
+
+        if summary.is_finished() {
+            if !summary.failed.is_empty() {
+                return Err(eyre::eyre!(
+                    "{} containers failed: {}",
+                    summary.failed.len(),
+                    summary.failed.join(", ")
+                ));
+            }
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(args.interval)).await;
+    }
+}
+
+async fn watch_collect_logs(
+    ctx: &Context,
+    container: &ps::ContainerStatus,
+    dir: &Path,
+) -> Result<()> {
+    let name = &container.name;
+    let script = format!("{} logs {}\n", ctx.runtime, shell::quote(name));
+    let output = machine_run_script(ctx, container.machine, &script).await?;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .context("creating log directory")?;
+    tokio::fs::write(dir.join(format!("{name}.stdout")), &output.stdout).await?;
+    tokio::fs::write(dir.join(format!("{name}.stderr")), &output.stderr).await?;
+    tracing::info!("saved logs of {name} to {}", dir.display());
+    Ok(())
+}
+
+async fn watch_alert(command: &str, summary: &watch::WatchSummary) -> Result<()> {
+    let unreachable = summary
+        .unreachable
+        .iter()
+        .map(Machine::hostname)
+        .collect::<Vec<_>>();
+    let status = Command::new("bash")
+        .arg("-c")
+        .arg(command)
+        .env("OAR_P2P_FAILED_CONTAINERS", summary.failed.join(" "))
+        .env("OAR_P2P_UNREACHABLE_MACHINES", unreachable.join(" "))
+        .stdin(std::process::Stdio::null())
+        .status()
+        .await
+        .context("running alert command")?;
+    if !status.success() {
+        return Err(eyre::eyre!("alert command exited with {status}"));
+    }
+    Ok(())
+}
+
+async fn cmd_run_pause(args: RunPauseArgs, pause: bool, output: OutputMode) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&ctx)
+        .await?
+        .into_iter()
+        .filter(|m| args.machine.is_empty() || args.machine.contains(m))
+        .collect::<Vec<_>>();
+    // only containers in the opposite state are affected, the runtime fails on the others
+    let state = if pause { "running" } else { "paused" };
+    let containers = machines_list_run_containers(&ctx, &machines)
+        .await?
+        .into_iter()
+        .filter(|c| c.state == state)
+        .filter(|c| args.name.is_empty() || args.name.iter().any(|p| ps::glob_match(p, &c.name)))
+        .collect::<Vec<_>>();
+    if containers.is_empty() {
+        tracing::warn!("no {state} containers matched");
+    }
+
+    let machines = containers
+        .iter()
+        .map(|c| &c.machine)
+        .collect::<HashSet<_>>();
+    machine::for_each(machines, |machine| {
+        let ctx = ctx.clone();
+        let names = containers
+            .iter()
+            .filter(|c| c.machine == machine)
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        async move { machine_containers_pause(&ctx, machine, &names, pause).await }
+    })
+    .await?;
+    let names = containers.into_iter().map(|c| c.name).collect::<Vec<_>>();
+    match output {
+        OutputMode::Text => names.iter().for_each(|name| println!("{name}")),
+        OutputMode::Json => print_json(&serde_json::json!({ "containers": names }))?,
+    }
+    Ok(())
+}
+
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_containers_pause(
+    ctx: &Context,
+    machine: Machine,
+    names: &[&str],
+    pause: bool,
+) -> Result<()> {
+    let runtime = ctx.runtime;
+    let command = if pause { "pause" } else { "unpause" };
+    let names = names
+        .iter()
+        .map(|name| shell::quote(name))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let script = format!("{runtime} {command} {names} >/dev/null\n");
+    machine_run_script(ctx, machine, &script).await?;
+    Ok(())
+}
+
+/// find the machine that hosts the container `name`.
+///
+/// the machine is resolved from the run state or the schedule when given, otherwise every
+/// machine of the job is asked whether it has the container.
+async fn container_lookup_machine(
+    ctx: &Context,
+    lookup: &ContainerLookupArgs,
+    name: &str,
+) -> Result<Machine> {
+    let (source, schedule, assigned_addresses) = match (&lookup.output_dir, &lookup.schedule) {
+        (Some(output_dir), _) => {
+            let state = RunStateFile::load(output_dir.join(run_state::RUN_STATE_FILE_NAME))
+                .await?
+                .snapshot()
+                .await;
+            (
+                state.schedule_source,
+                state.schedule,
+                state.assigned_addresses,
+            )
+        }
+        (None, Some(path)) => {
+            let (source, schedule) = read_schedule(Some(path)).await?;
+            (source, schedule, Default::default())
+        }
+        (None, None) => return machines_find_container(ctx, name).await,
+    };
+
+    let items = schedule::parse(&source, &schedule)?;
+    for (idx, item) in items.iter().enumerate() {
+        let address = item
+            .address
+            .or_else(|| assigned_addresses.get(&idx).copied());
+        let item_name = item.name.clone().or_else(|| address.map(|a| a.to_string()));
+        if item_name.as_deref() != Some(name) {
+            continue;
+        }
+        let address = address.ok_or_else(|| {
+            eyre::eyre!(
+                "{source}: container {name} has no address, use --output-dir with the output directory of the run"
+            )
+        })?;
+        return machine_from_addr(address);
+    }
+    Err(eyre::eyre!("{source}: there is no container named {name}"))
+}
+
+/// ask every machine of the job whether it has the container `name`.
+async fn machines_find_container(ctx: &Context, name: &str) -> Result<Machine> {
+    let machines = oar::job_list_machines(ctx).await?;
+    let script = format!(
+        "{} container inspect {} >/dev/null 2>&1 && echo found || true\n",
+        ctx.runtime,
+        shell::quote(name)
+    );
+    let found = machine::for_each(&machines, |machine| {
+        let ctx = ctx.clone();
+        let script = script.clone();
+        async move {
+            let output = machine_run_script(&ctx, machine, &script).await?;
+            Ok(String::from_utf8_lossy(&output.stdout).trim() == "found")
+        }
+    })
+    .await?;
+    found
+        .into_iter()
+        .find_map(|(machine, found)| found.then_some(machine))
+        .ok_or_else(|| eyre::eyre!("no machine of the job has a container named {name}"))
+}
+
+/// print the scripts that `run` would execute on each machine.
+///
+/// machines are resolved from the schedule addresses so nothing is executed on the cluster.
+async fn cmd_run_dry_run(args: &RunArgs) -> Result<()> {
+    let (schedule_source, schedule) = read_schedule(args.schedule.as_deref()).await?;
+    let items = schedule::parse(&schedule_source, &schedule)?;
+    if let Some(idx) = items.iter().position(|item| item.address.is_none()) {
+        return Err(eyre::eyre!(
+            "{schedule_source}: item {idx}: a dry run requires every container to have an address"
+        ));
+    }
+    let containers = run_prepare_containers(
+        args,
+        &schedule_source,
+        items,
+        &Default::default(),
+        args.barrier,
+    )?;
+    let (start_levels, start_offsets) = run_start_plan(args, &containers)?;
+    let staggered = start_offsets.iter().any(|o| !o.is_zero());
+    let start_base = SystemTime::now();
+    let runtime = args.common.runtime;
+
+    let machines = containers
+        .iter()
+        .map(|c| c.machine)
+        .collect::<std::collections::BTreeSet<_>>();
+    for machine in machines {
+        let machine_containers = machine_scheduled_containers(&containers, machine);
+        (0..20).for_each(|_| print!("-"));
+        print!(" {machine} ");
+        (0..20).for_each(|_| print!("-"));
+        println!();
+        let (load, pull): (Vec<_>, Vec<_>) = containers_images(&machine_containers)
+            .into_iter()
+            .partition(|image| args.load_image.contains(image));
+        println!("# pull");
+        if args.registry_config.is_some() {
+            println!("# the registry config is first copied to {machine}:{REGISTRY_CONFIG_DIR}");
+        }
+        println!(
+            "{}",
+            machine_images_pull_script(runtime, &pull, args.registry_config.is_some())
+        );
+        for image in load {
+            println!("# the local image {image} is then loaded with `{runtime} load`");
+        }
+        for local in containers_inputs(&machine_containers) {
+            println!(
+                "# the input {} is copied with rsync to {machine}:{}",
+                local.display(),
+                input_remote_path(&local)
+            );
+        }
+        println!("# create");
+        println!(
+            "{}",
+            machine_containers_create_script(runtime, &machine_containers)
+        );
+        println!("# start");
+        if start_levels.len() > 1 {
+            for (level_idx, level) in start_levels.iter().enumerate() {
+                let names = machine_containers
+                    .iter()
+                    .filter(|c| level.contains(&c.name))
+                    .map(|c| c.name.clone())
+                    .collect::<Vec<_>>();
+                if !names.is_empty() {
+                    println!("# dependency level {level_idx}");
+                    println!(
+                        "{}",
+                        machine_containers_start_and_wait_script(runtime, &names)
+                    );
+                }
+            }
+        } else if staggered {
+            let schedule = containers
+                .iter()
+                .zip(start_offsets.iter())
+                .filter(|(c, _)| c.machine == machine)
+                .map(|(c, &offset)| (c.name.clone(), start_base + offset))
+                .collect::<Vec<_>>();
+            println!(
+                "{}",
+                machine_containers_staggered_start_script(runtime, &schedule)
+            );
+        } else {
+            println!("{}", machine_containers_start_script(runtime));
+        }
+        println!("# wait");
+        println!(
+            "{}",
+            machine_containers_wait_script(
+                runtime,
+                &machine_containers,
+                args.allow_failures.is_none(),
+                args.churn.is_some()
+            )
+        );
+        println!("# save logs");
+        println!(
+            "{}",
+            machine_containers_save_logs_script(runtime, &machine_containers)
+        );
+        if args.transfer.archive {
+            println!("# archive logs");
+            println!("{}", machine_logs_archive_script(machine));
+            println!(
+                "# the archive is then copied with rsync from {machine}:/tmp/{}",
+                logs_archive_name(machine)
+            );
+        } else {
+            println!("# stream logs");
+            println!("{}", machine_logs_stream_script(args.transfer.compress));
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// stop the containers on machines that have not yet exited, marking them as exited.
+async fn run_stop_containers(
+    ctx: &Context,
+    state: &RunStateFile,
+    machines: &[Machine],
+    containers: &[ScheduledContainer],
+) -> Result<()> {
+    let pending = state.machines_before(machines, RunPhase::Exited).await;
+    machine::for_each(&pending, |machine| {
+        let ctx = ctx.clone();
+        let containers = machine_scheduled_containers(containers, machine);
+        async move {
+            machine_containers_stop(&ctx, machine, &containers).await?;
+            state
+                .set_machine_phase(
+                    machine,
+                    RunPhase::Exited,
+                    containers.iter().map(|c| c.name.as_str()),
+                )
+                .await
+        }
+    })
+    .await?;
+    Ok(())
+}
+
+/// pin every container without an explicit cpuset to `cpus` dedicated cpus of its machine.
+///
+/// the cpus of the explicit cpusets are reserved first, so the assigned cpus never overlap them.
+fn assign_container_cpusets(containers: &mut [ScheduledContainer], cpus: u32) -> Result<()> {
+    if cpus == 0 {
+        return Err(eyre::eyre!(
+            "the number of cpus per container must be positive"
+        ));
+    }
+    let mut used = HashMap::<Machine, HashSet<u32>>::default();
+    for container in containers.iter() {
+        if let Some(cpuset) = &container.cpuset {
+            used.entry(container.machine)
+                .or_default()
+                .extend(cpuset_cpus(cpuset));
+        }
+    }
+    for container in containers.iter_mut().filter(|c| c.cpuset.is_none()) {
+        let machine = container.machine;
+        let used = used.entry(machine).or_default();
+        let free = (0..machine.cpus())
+            .filter(|cpu| !used.contains(cpu))
+            .take(cpus as usize)
+            .collect::<Vec<_>>();
+        if free.len() < cpus as usize {
+            return Err(eyre::eyre!(
+                "machine {machine} has {} cpus which is not enough to give {cpus} cpus to every container, next to the cpusets of the schedule",
+                machine.cpus()
+            ));
+        }
+        used.extend(&free);
+        container.cpuset = Some(format_cpuset(&free));
+    }
+    Ok(())
+}
+
+/// the cpus of a cpuset like `0-3,8`, which the schedule already validated.
+fn cpuset_cpus(cpuset: &str) -> Vec<u32> {
+    let mut cpus = Vec::default();
+    for part in cpuset.split(',') {
+        let (start, end) = part.split_once('-').unwrap_or((part, part));
+        if let (Ok(start), Ok(end)) = (start.trim().parse::<u32>(), end.trim().parse::<u32>()) {
+            cpus.extend(start..=end);
+        }
+    }
+    cpus
+}
+
+/// format sorted cpus as a cpuset, joining consecutive cpus into ranges.
+fn format_cpuset(cpus: &[u32]) -> String {
+    let mut ranges = Vec::<(u32, u32)>::default();
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == cpu => *end = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// check that the resource limits of the containers on each machine fit in that machine.
+async fn machines_resources_check(
+    ctx: &Context,
+    machines: &[Machine],
+    containers: &[ScheduledContainer],
+) -> Result<()> {
+    machine::for_each(machines, |machine| {
+        let ctx = ctx.clone();
+        let containers = machine_scheduled_containers(containers, machine);
+        async move {
+            let memory_total = match containers.iter().any(|c| c.memory.is_some()) {
+                true => Some(machine_memory_total(&ctx, machine).await?),
+                false => None,
+            };
+            machine_resources_check(machine, &containers, memory_total)
+        }
+    })
+    .await?;
+    Ok(())
+}
+
+fn machine_resources_check(
+    machine: Machine,
+    containers: &[ScheduledContainer],
+    memory_total: Option<schedule::MemorySize>,
+) -> Result<()> {
+    let cpus = containers.iter().filter_map(|c| c.cpus).sum::<f64>();
+    if cpus > f64::from(machine.cpus()) {
+        return Err(eyre::eyre!(
+            "containers on {machine} require {cpus} cpus but the machine only has {}",
+            machine.cpus()
+        ));
+    }
+    if let Some(memory_total) = memory_total {
+        let memory = containers
+            .iter()
+            .filter_map(|c| c.memory)
+            .map(|m| m.0)
+            .sum::<u64>();
+        if memory > memory_total.0 {
+            return Err(eyre::eyre!(
+                "containers on {machine} require {memory} bytes of memory but the machine only has {}",
+                memory_total.0
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// group the containers into levels such that every container only depends on containers of
+/// previous levels. containers in the same level can be started concurrently.
+fn container_start_levels(containers: &[ScheduledContainer]) -> Result<Vec<Vec<String>>> {
+    let by_name = containers
+        .iter()
+        .map(|c| (c.name.as_str(), c))
+        .collect::<HashMap<_, _>>();
+    for container in containers {
+        for dependency in container.depends_on.iter() {
+            if !by_name.contains_key(dependency.as_str()) {
+                return Err(eyre::eyre!(
+                    "container {} depends on unknown container {dependency}",
+                    container.name
+                ));
+            }
+        }
+    }
+
+    let mut level_of = HashMap::<&str, usize>::default();
+    let mut levels = Vec::<Vec<String>>::default();
+    while level_of.len() < containers.len() {
+        let mut progress = false;
+        for container in containers {
+            if level_of.contains_key(container.name.as_str()) {
+                continue;
+            }
+            let dependency_levels = container
+                .depends_on
+                .iter()
+                .map(|d| level_of.get(d.as_str()).copied())
+                .collect::<Option<Vec<_>>>();
+            if let Some(dependency_levels) = dependency_levels {
+                let level = dependency_levels
+                    .into_iter()
+                    .map(|l| l + 1)
+                    .max()
+                    .unwrap_or(0);
+                if levels.len() <= level {
+                    levels.resize_with(level + 1, Default::default);
+                }
+                levels[level].push(container.name.clone());
+                level_of.insert(&container.name, level);
+                progress = true;
+            }
+        }
+        if !progress {
+            let mut cycle = containers
+                .iter()
+                .filter(|c| !level_of.contains_key(c.name.as_str()))
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>();
+            cycle.sort();
+            return Err(eyre::eyre!(
+                "dependency cycle between containers: {}",
+                cycle.join(", ")
+            ));
+        }
+    }
+    Ok(levels)
+}
+
+/// the delay, after the start of the run, at which each container is started.
+fn container_start_offsets(
+    containers: &[ScheduledContainer],
+    start_interval: Option<Duration>,
+) -> Vec<Duration> {
+    containers
+        .iter()
+        .enumerate()
+        .map(|(idx, container)| {
+            container
+                .start_delay
+                .unwrap_or_else(|| start_interval.unwrap_or_default() * u32::try_from(idx).unwrap())
+        })
+        .collect()
+}
+
+fn machine_scheduled_containers(
+    containers: &[ScheduledContainer],
+    machine: Machine,
+) -> Vec<ScheduledContainer> {
+    containers
+        .iter()
+        .filter(|c| c.machine == machine)
+        .cloned()
+        .collect()
+}
+
+/// sample the resource usage of the containers every `interval` and append it to a csv file per
+/// container under the `stats` directory of the output directory. never resolves.
+async fn stats_collector(
+    ctx: &Context,
+    machines: &[Machine],
+    containers: &[ScheduledContainer],
+    interval: Option<Duration>,
+    output_dir: &Path,
+) {
+    let Some(interval) = interval else {
+        return std::future::pending().await;
+    };
+    let machines = machines
+        .iter()
+        .copied()
+        .filter(|&machine| containers.iter().any(|c| c.machine == machine))
+        .collect::<Vec<_>>();
+    let stats_dir = output_dir.join("stats");
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let timestamp = unix_timestamp();
+        let results = machine::for_each(&machines, |machine| {
+            let ctx = ctx.clone();
+            async move { machine_containers_stats(&ctx, machine).await }
+        })
+        .await;
+        let samples = match results {
+            Ok(results) => results.into_iter().flat_map(|(_, s)| s),
+            Err(err) => {
+                tracing::warn!("failed to collect container stats: {err}");
+                continue;
+            }
+        };
+        for sample in samples.filter(|s| containers.iter().any(|c| c.name == s.name)) {
+            if let Err(err) = stats_append(&stats_dir, &sample, timestamp).await {
+                tracing::warn!("failed to write stats of container {}: {err}", sample.name);
+            }
+        }
+    }
+}
+
+async fn stats_append(
+    stats_dir: &Path,
+    sample: &stats::ContainerStats,
+    timestamp: u64,
+) -> Result<()> {
+    tokio::fs::create_dir_all(stats_dir).await?;
+    let path = stats_dir.join(format!("{}.csv", sample.name));
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+    let mut content = String::default();
+    if file.metadata().await?.len() == 0 {
+        content.push_str(stats::CSV_HEADER);
+        content.push('\n');
+    }
+    content.push_str(&sample.csv_row(timestamp));
+    content.push('\n');
+    file.write_all(content.as_bytes()).await?;
+    Ok(())
+}
+
+#[tracing::instrument(err, skip(ctx))]
+async fn machine_containers_stats(
+    ctx: &Context,
+    machine: Machine,
+) -> Result<Vec<stats::ContainerStats>> {
+    let output = machine_run_script(ctx, machine, &stats::stats_script(ctx.runtime)).await?;
+    Ok(stats::parse_stats_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// stop and restart random containers according to `spec` and append every event to the churn
+/// events file. only returns if restarting a container fails, since the wait would otherwise
+/// never finish.
+async fn run_churn(
+    ctx: Context,
+    containers: Vec<ScheduledContainer>,
+    spec: churn::ChurnSpec,
+    output_dir: PathBuf,
+) -> Result<std::convert::Infallible> {
+    let path = output_dir.join(churn::CHURN_EVENTS_FILE_NAME);
+    let mut process = churn::ChurnProcess::new(&spec);
+    // containers that are currently stopped and when they are restarted
+    let mut stopped = Vec::<(tokio::time::Instant, usize)>::default();
+    let mut next_stop = tokio::time::Instant::now() + process.next_interval();
+    loop {
+        let next_start = stopped.iter().copied().min_by_key(|&(at, _)| at);
+        let (action, idx) = match next_start {
+            Some((at, idx)) if at <= next_stop => {
+                tokio::time::sleep_until(at).await;
+                stopped.retain(|&(_, i)| i != idx);
+                let container = &containers[idx];
+                tracing::info!("churn: restarting container {}", container.name);
+                let script = churn::start_script(ctx.runtime, &container.name);
+                machine_run_script(&ctx, container.machine, &script)
+                    .await
+                    .with_context(|| format!("restarting container {}", container.name))?;
+                (churn::ChurnAction::Start, idx)
+            }
+            _ => {
+                tokio::time::sleep_until(next_stop).await;
+                next_stop += process.next_interval();
+                let candidates = (0..containers.len())
+                    .filter(|&i| stopped.iter().all(|&(_, s)| s != i))
+                    .collect::<Vec<_>>();
+                if candidates.is_empty() {
+                    continue;
+                }
+                let idx = candidates[process.choose(candidates.len())];
+                let container = &containers[idx];
+                tracing::info!("churn: stopping container {}", container.name);
+                let script = churn::stop_script(ctx.runtime, &container.name);
+                match machine_run_script(&ctx, container.machine, &script).await {
+                    Ok(output) if String::from_utf8_lossy(&output.stdout).contains("stopped") => {}
+                    Ok(_) => {
+                        tracing::debug!("churn: container {} already exited", container.name);
+                        continue;
+                    }
+                    Err(err) => {
+                        tracing::warn!("churn: failed to stop container {}: {err}", container.name);
+                        continue;
+                    }
+                }
+                stopped.push((tokio::time::Instant::now() + spec.downtime, idx));
+                (churn::ChurnAction::Stop, idx)
+            }
+        };
+        let event = churn::ChurnEvent {
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            container: containers[idx].name.clone(),
+            action,
+        };
+        let container = &containers[idx];
+        let timeline_event = timeline::TimelineEvent {
+            container: Some(container.name.clone()),
+            machine: Some(container.machine),
+            ..timeline::TimelineEvent::new(
+                event.timestamp,
+                match action {
+                    churn::ChurnAction::Stop => timeline::TimelineEventKind::ChurnStop,
+                    churn::ChurnAction::Start => timeline::TimelineEventKind::ChurnStart,
+                },
+            )
+        };
+        let timeline_path = output_dir.join(timeline::TIMELINE_FILE_NAME);
+        if let Err(err) = jsonl_append(&path, &event).await {
+            tracing::warn!("failed to record churn event: {err}");
+        }
+        if let Err(err) = jsonl_append(&timeline_path, &timeline_event).await {
+            tracing::warn!("failed to record churn event in the timeline: {err}");
+        }
+    }
+}
+
+/// record that `signal` was delivered to `container`, or every container, in the timeline.
+/// failing to record it only logs a warning.
+async fn timeline_append_signal(
+    output_dir: &Path,
+    timestamp: u64,
+    signal: &Signal,
+    container: Option<&ScheduledContainer>,
+) {
+    let event = timeline::TimelineEvent {
+        container: container.map(|c| c.name.clone()),
+        machine: container.map(|c| c.machine),
+        signal: Some(signal.to_string()),
+        ..timeline::TimelineEvent::new(timestamp * 1000, timeline::TimelineEventKind::Signal)
+    };
+    let path = output_dir.join(timeline::TIMELINE_FILE_NAME);
+    if let Err(err) = jsonl_append(&path, &event).await {
+        tracing::warn!("failed to record signal in the timeline: {err}");
+    }
+}
+
+/// add the created, started and exited events of every container to the timeline, it is only
+/// complete once all containers exited.
+async fn run_write_timeline(
+    ctx: &Context,
+    machines: &[Machine],
+    containers: &[ScheduledContainer],
+    output_dir: &Path,
+) -> Result<()> {
+    let events = machine::for_each(
+        machines
+            .iter()
+            .filter(|&machine| containers.iter().any(|c| c.machine == *machine)),
+        |machine| {
+            let ctx = ctx.clone();
+            let names = containers
+                .iter()
+                .filter(|c| c.machine == machine)
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>();
+            let script = timeline::inspect_script(ctx.runtime, &names);
+            async move {
+                let output = machine_run_script(&ctx, machine, &script).await?;
+                let stdout = std::str::from_utf8(&output.stdout)?;
+                Ok(timeline::parse_inspect_output(stdout, machine))
+            }
+        },
+    )
+    .await?
+    .into_iter()
+    .flat_map(|(_, events)| events)
+    .collect();
+    let path = output_dir.join(timeline::TIMELINE_FILE_NAME);
+    let existing = match tokio::fs::read_to_string(&path).await {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::default(),
+        Err(err) => return Err(err).context("reading timeline"),
+    };
+    let content = timeline::merge(&existing, events).context("parsing timeline")?;
+    tokio::fs::write(&path, content)
+        .await
+        .context("writing timeline")?;
+    Ok(())
+}
+
+async fn jsonl_append(path: &Path, event: &impl serde::Serialize) -> Result<()> {
+    let mut line = serde_json::to_string(event)?;
+    line.push('\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+/// resolves once `timeout` has elapsed since `start`, never resolves if there is no timeout.
+async fn timeout_guard(start: SystemTime, timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => {
+            let remaining = (start + timeout)
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            tokio::time::sleep(remaining).await
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// resolves once the job's remaining walltime drops below `margin`.
+///
+/// the remaining walltime is queried periodically so that walltime extensions requested during
+/// the run are taken into account. if the walltime cannot be obtained this never resolves.
+async fn walltime_guard(ctx: &Context, margin: Duration) {
+    const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+    loop {
+        let remaining = match oar::jobs_min_remaining_walltime(ctx).await {
+            Ok(remaining) => remaining.map(|(_, remaining)| remaining),
+            Err(err) => {
+                tracing::debug!("unable to obtain job walltime: {err}");
+                None
+            }
+        };
+        let remaining = match remaining {
+            Some(remaining) => remaining,
+            None => return futures::future::pending().await,
+        };
+        if remaining <= margin {
+            return;
+        }
+        tokio::time::sleep((remaining - margin).min(MAX_CHECK_INTERVAL)).await;
+    }
+}
+
+/// resolves once oar announces that one of the context's best-effort jobs is about to be killed.
+///
+/// oar can kill best-effort jobs at any time to make room for other jobs. waiting for the job to
+/// leave the running state is too late, oar is already killing it by then, so the job's events
+/// are polled for the kill decision or the checkpoint signal, which come first. submitting the
+/// job with `oarsub --checkpoint <seconds>` makes oar wait that long between the two. the job
+/// state is still checked for the oar rest api, which has no events. if none of the jobs is
+/// best-effort this never resolves.
+async fn preemption_guard(ctx: &Context) {
+    const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+    let job_ids = match ctx.job_ids().await {
+        Ok(job_ids) => job_ids,
+        Err(_) => return futures::future::pending().await,
+    };
+    let mut besteffort = Vec::default();
+    for job_id in job_ids {
+        match oar::job_info(ctx, job_id).await {
+            Ok(info) if info.is_besteffort() => besteffort.push(job_id),
+            Ok(_) => {}
+            Err(err) => tracing::debug!("unable to obtain job {job_id} info: {err}"),
+        }
+    }
+    if besteffort.is_empty() {
+        return futures::future::pending().await;
+    }
+
+    // events from before the run, like an earlier checkpoint, are not a preemption
+    let mut seen = HashSet::<oar::JobEvent>::default();
+    for &job_id in &besteffort {
+        if let Ok(events) = oar::job_events(ctx, job_id).await {
+            seen.extend(events);
+        }
+    }
+
+    tracing::info!("watching best-effort jobs {besteffort:?} for preemption");
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        for &job_id in &besteffort {
+            match oar::job_events(ctx, job_id).await {
+                Ok(events) => {
+                    if let Some(event) = events
+                        .into_iter()
+                        .find(|e| e.is_preemption() && !seen.contains(e))
+                    {
+                        tracing::warn!(
+                            "best-effort job {job_id} is about to be killed: {} {}",
+                            event.kind,
+                            event.description
+                        );
+                        return;
+                    }
+                }
+                Err(err) => tracing::debug!("unable to obtain job {job_id} events: {err}"),
+            }
+            match oar::job_info(ctx, job_id).await {
+                Ok(info) if info.state != "Running" => {
+                    tracing::warn!("best-effort job {job_id} is now in state {}", info.state);
+                    return;
+                }
+                Ok(_) => {}
+                Err(err) => tracing::debug!("unable to obtain job {job_id} info: {err}"),
+            }
+        }
+    }
+}
+
+async fn cmd_clean(args: CleanArgs) -> Result<()> {
+    let context = context_from_common(&args.common).await?;
+    let _lock = job_lock(&context, &args.common).await?;
+    let machines = oar::job_list_machines(&context).await?;
+    machines_net_container_build(&context, &machines).await?;
+    if !args.clobber.clobber {
+        machines_foreign_state_check(&context, &machines).await?;
+    }
+    machines_containers_clean(&context, &machines).await?;
+    machines_clean(&context, &machines).await?;
+    Ok(())
+}
+
+async fn cmd_man(args: ManArgs) -> Result<()> {
+    tokio::fs::create_dir_all(&args.output_dir)
+        .await
+        .with_context(|| format!("creating directory {}", args.output_dir.display()))?;
+    clap_mangen::generate_to(Cli::command(), &args.output_dir)
+        .with_context(|| format!("writing the man pages to {}", args.output_dir.display()))?;
+    tracing::info!("wrote the man pages to {}", args.output_dir.display());
+    Ok(())
+}
+
+async fn cmd_version(args: VersionArgs, output: OutputMode) -> Result<()> {
+    use doctor::{Check, Status};
+
+    let version = env!("GIT_VERSION");
+    if !args.check {
+        match output {
+            OutputMode::Text => println!("oar-p2p {version}"),
+            OutputMode::Json => print_json(&serde_json::json!({ "version": version }))?,
+        }
+        return Ok(());
+    }
+
+    let ctx = context_from_common(&args.common).await?;
+    let mut checks = vec![
+        doctor_local_tool("ssh", &["-V"]).await,
+        doctor_local_tool("scp", &[]).await,
+    ];
+    let frontend_reachable = doctor_frontend(&ctx, &mut checks).await;
+
+    let machine = match args.machine {
+        Some(machine) => Some(machine),
+        None if !frontend_reachable => {
+            checks.push(Check::new(
+                "machine",
+                Status::Skip,
+                "the frontend is unreachable",
+            ));
+            None
+        }
+        None => match oar::job_list_machines(&ctx).await {
+            Ok(machines) => machines.first().copied(),
+            Err(err) => {
+                checks.push(
+                    Check::new("machine", Status::Skip, format!("no job to check: {err}"))
+                        .with_hint("pass --job-id, or --machine, to check a machine"),
+                );
+                None
+            }
+        },
+    };
+    if let Some(machine) = machine {
+        let script = doctor::machine_script(ctx.runtime, CONTAINER_IMAGE_NAME);
+        match machine_run(&ctx, machine, &[], Some(&script), retry::Retry::Connection).await {
+            Ok(output) if output.status.success() => checks.extend(doctor::parse_machine(
+                machine,
+                ctx.runtime,
+                &String::from_utf8_lossy(&output.stdout),
+            )),
+            Ok(output) => checks.push(
+                Check::new(
+                    machine.to_string(),
+                    Status::Fail,
+                    doctor_stderr_reason(&output.stderr),
+                )
+                .with_hint(format!(
+                    "check that the job owning {machine} is running and that the frontend can ssh to it"
+                )),
+            ),
+            Err(err) => checks.push(Check::new(
+                machine.to_string(),
+                Status::Fail,
+                format!("{err:#}"),
+            )),
+        }
+    }
+
+    match output {
+        OutputMode::Text => {
+            println!("oar-p2p {version}\n");
+            print!("{}", doctor::format(&checks));
+        }
+        OutputMode::Json => print_json(&serde_json::json!({
+            "version": version,
+            "checks": checks,
+        }))?,
+    }
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failed > 0 {
+        return Err(eyre::eyre!("{failed} of {} checks failed", checks.len()));
+    }
+    Ok(())
+}
+
+/// check that `tool` is installed locally, its version is the first line it prints with `args`.
+async fn doctor_local_tool(tool: &str, args: &[&str]) -> doctor::Check {
+    use doctor::{Check, Status};
+
+    match Command::new(tool).args(args).output().await {
+        Ok(output) => {
+            // ssh prints its version to stderr and scp its usage without arguments
+            let text = [output.stdout, output.stderr].concat();
+            let detail = match args.is_empty() {
+                true => String::from("installed"),
+                false => String::from_utf8_lossy(&text)
+                    .lines()
+                    .next()
+                    .unwrap_or("installed")
+                    .trim()
+                    .to_string(),
+            };
+            Check::new(tool, Status::Ok, detail)
+        }
+        Err(err) => Check::new(tool, Status::Fail, err.to_string()).with_hint(format!(
+            "install the openssh client, oar-p2p runs `{tool}` to reach the cluster"
+        )),
+    }
+}
+
+/// check that the frontend is reachable and has oarstat, returns whether it is reachable.
+async fn doctor_frontend(ctx: &Context, checks: &mut Vec<doctor::Check>) -> bool {
+    use doctor::{Check, Status};
+
+    let script = "command -v oarstat";
+    let output = match ctx.node {
+        ExecutionNode::Frontend => {
+            checks.push(Check::new(
+                "frontend",
+                Status::Ok,
+                "running on the frontend",
+            ));
+            Command::new("sh").args(["-c", script]).output().await
+        }
+        ExecutionNode::Machine(machine) => {
+            checks.push(
+                Check::new("frontend", Status::Warn, format!("running on {machine}"))
+                    .with_hint("oar-p2p is meant to run from the frontend or your own machine"),
+            );
+            return true;
+        }
+        ExecutionNode::Unknown => {
+            let frontend = match ctx.frontend_hostname() {
+                Ok(frontend) => frontend,
+                Err(err) => {
+                    checks.push(
+                        Check::new("frontend", Status::Fail, err.to_string()).with_hint(
+                            "pass --frontend-hostname, set FRONTEND_HOSTNAME or set frontend_hostname in ~/.config/oar-p2p/config.toml",
+                        ),
+                    );
+                    checks.push(Check::new("oarstat", Status::Skip, "no frontend"));
+                    return false;
+                }
+            };
+            let output = Command::new("ssh")
+                .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=10"])
+                .args([frontend, script])
+                .output()
+                .await;
+            match &output {
+                Ok(output) if output.status.code() == Some(255) => {
+                    checks.push(
+                        Check::new(
+                            "frontend",
+                            Status::Fail,
+                            doctor_stderr_reason(&output.stderr),
+                        )
+                        .with_hint(format!(
+                            "check that `ssh {frontend}` works without asking for a password"
+                        )),
+                    );
+                    checks.push(Check::new(
+                        "oarstat",
+                        Status::Skip,
+                        "the frontend is unreachable",
+                    ));
+                    return false;
+                }
+                Ok(_) => checks.push(Check::new("frontend", Status::Ok, frontend)),
+                Err(_) => {
+                    checks.push(Check::new("frontend", Status::Skip, "ssh is not installed"));
+                    checks.push(Check::new("oarstat", Status::Skip, "ssh is not installed"));
+                    return false;
+                }
+            }
+            output
+        }
+    };
+    let check = match output {
+        Ok(output) if output.status.success() => Check::new(
+            "oarstat",
+            Status::Ok,
+            String::from_utf8_lossy(&output.stdout).trim(),
+        ),
+        _ if ctx.oar_api_url().is_some() => Check::new(
+            "oarstat",
+            Status::Warn,
+            "not found on the frontend, the oar rest api is used instead",
+        ),
+        _ => Check::new("oarstat", Status::Fail, "not found on the frontend")
+            .with_hint("is the frontend hostname the one of the oar frontend? pass --oar-api-url to use the oar rest api instead"),
+    };
+    checks.push(check);
+    true
+}
+
+/// the last line of stderr that is not ssh's verbose output, which explains why a command failed.
+fn doctor_stderr_reason(stderr: &[u8]) -> String {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .rfind(|line| !line.starts_with("debug") && !line.trim().is_empty())
+        .unwrap_or("unknown error")
+        .trim()
+        .to_string()
+}
+
+async fn cmd_self_update(args: SelfUpdateArgs) -> Result<()> {
+    let current = env!("GIT_VERSION");
+    let release = self_update::fetch_release(&args.releases_url, args.tag.as_deref()).await?;
+    let tag = release.tag_name.as_str();
+    if args.check {
+        if current == tag {
+            println!("oar-p2p {current} is up to date");
+        } else {
+            println!("oar-p2p {tag} is available, this is {current}");
+        }
+        return Ok(());
+    }
+
+    let update_local = if args.force {
+        true
+    } else if current == tag {
+        tracing::info!("oar-p2p {current} is up to date");
+        false
+    } else if !self_update::is_release_version(current) {
+        return Err(eyre::eyre!(
+            "this is a development build ({current}), pass --force to replace it with {tag}"
+        ));
+    } else {
+        true
+    };
+    if !update_local && !args.frontend {
+        return Ok(());
+    }
+
+    let dir = std::env::temp_dir().join(format!("oar-p2p-update-{}", std::process::id()));
+    let result = async {
+        let platform = self_update::platform(std::env::consts::OS, std::env::consts::ARCH);
+        let mut local_binary = None;
+        if update_local {
+            let platform = platform.ok_or_else(|| {
+                eyre::eyre!(
+                    "there are no releases for {}-{}",
+                    std::env::consts::OS,
+                    std::env::consts::ARCH
+                )
+            })?;
+            let binary = self_update::download_binary(&release, platform, &dir).await?;
+            let target = std::env::current_exe().context("finding the current executable")?;
+            self_update::replace_executable(&binary, &target).await?;
+            tracing::info!("updated {} from {current} to {tag}", target.display());
+            local_binary = Some(binary);
+        }
+        if args.frontend {
+            let frontend = args
+                .frontend_hostname
+                .as_deref()
+                .ok_or_else(|| eyre::eyre!("missing frontend hostname"))?;
+            let binary = match local_binary {
+                Some(binary) if platform == Some(self_update::CLUSTER_PLATFORM) => binary,
+                _ => {
+                    self_update::download_binary(&release, self_update::CLUSTER_PLATFORM, &dir)
+                        .await?
+                }
+            };
+            frontend_install_binary(frontend, &binary, &args.frontend_path).await?;
+            tracing::info!("installed {tag} on {frontend} at {}", args.frontend_path);
+        }
+        Ok::<_, eyre::Report>(())
+    }
+    .await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+/// copy `binary` to `path` on the frontend, replacing the binary there only once it was fully
+/// copied.
+async fn frontend_install_binary(frontend: &str, binary: &Path, path: &str) -> Result<()> {
+    let staging = format!("{path}.new");
+    let ssh = |script: String| {
+        let mut command = Command::new("ssh");
+        command.arg(frontend).arg(script);
+        command
+    };
+    let steps = [
+        (
+            "creating the directory",
+            ssh(format!("mkdir -p \"$(dirname {})\"", shell::quote(path))),
+        ),
+        ("copying the binary", {
+            let mut command = Command::new("scp");
+            command
+                .arg("-q")
+                .arg(binary)
+                .arg(format!("{frontend}:{staging}"));
+            command
+        }),
+        (
+            "replacing the binary",
+            ssh(format!(
+                "chmod 755 {staging} && mv -f {staging} {path}",
+                staging = shell::quote(&staging),
+                path = shell::quote(path)
+            )),
+        ),
+    ];
+    for (step, mut command) in steps {
+        let output = command
+            .output()
+            .await
+            .with_context(|| format!("{step} on {frontend}"))?;
+        output
+            .status
+            .exit_ok()
+            .map_err(|_| eyre::eyre!("{}", String::from_utf8_lossy(&output.stderr).trim()))
+            .with_context(|| format!("{step} on {frontend}"))?;
+    }
+    Ok(())
+}
+
+/// the ids of the user's jobs, what `--job-id` is completed with.
+///
+/// the frontend is found like any other command would, from the environment and the
+/// configuration file. nothing is completed if the jobs cannot be listed.
+fn complete_job_ids() -> Vec<clap_complete::CompletionCandidate> {
+    #[derive(Debug, Parser)]
+    struct CompleteCli {
+        #[clap(flatten)]
+        common: Common,
+    }
+
+    let job_ids = || -> Result<Vec<u32>> {
+        let config = match config::path() {
+            Some(path) => config::load(&path)?,
+            None => config::Config::default(),
+        };
+        let profile = std::env::var(config::PROFILE_ENV).ok();
+        let defaults = config.defaults(profile.as_deref())?;
+        let command = config::apply(CompleteCli::command(), &defaults);
+        let cli = CompleteCli::from_arg_matches(&command.try_get_matches_from(["oar-p2p"])?)?;
+        // completers are synchronous but run inside the runtime of `main`
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async {
+                let ctx = context_from_common(&cli.common).await?;
+                oar::list_user_job_ids(&ctx).await
+            })
+        })
+    };
+    job_ids()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|job_id| clap_complete::CompletionCandidate::new(job_id.to_string()))
+        .collect()
+}
+
+async fn cmd_gc(args: GcArgs) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machines = match args.machine.is_empty() {
+        true => {
+            // the machines of the user's running jobs
+            let mut machines = oar::list_user_jobs(&ctx)
+                .await?
+                .into_iter()
+                .filter(|job| job.state == "Running")
+                .flat_map(|job| job.machines)
+                .collect::<Vec<_>>();
+            machines.sort();
+            machines.dedup();
+            machines
+        }
+        false => args.machine.clone(),
+    };
+    if machines.is_empty() {
+        tracing::info!("no running jobs, nothing to scan");
+        return Ok(());
+    }
+
+    machines_net_container_build(&ctx, &machines).await?;
+    let scanned = machine::for_each_progress("scanning machines", &machines, |machine| {
+        machine_gc_scan(&ctx, machine)
+    })
+    .await?;
+    // the markers can name jobs of other users or jobs that are not running yet, so each one is
+    // looked up instead of relying on the user's running jobs
+    let marked = scanned
+        .iter()
+        .flat_map(|(_, leftovers)| leftovers.job_ids.iter().flatten().copied())
+        .collect::<std::collections::BTreeSet<_>>();
+    let mut live = HashSet::<u32>::default();
+    for job_id in marked {
+        match oar::job_info(&ctx, job_id).await {
+            Ok(info) if info.has_ended() => {}
+            Ok(_) => {
+                live.insert(job_id);
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "failed to get the state of job {job_id}, assuming it is live: {err:#}"
+                );
+                live.insert(job_id);
+            }
+        }
+    }
+
+    let mut orphaned = Vec::default();
+    for (machine, leftovers) in scanned {
+        if leftovers.is_empty() {
+            continue;
+        }
+        let jobs = leftovers
+            .job_ids
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|id| live.contains(id))
+            .collect::<Vec<_>>();
+        if !leftovers.removable(&jobs, args.force) {
+            match leftovers.owner(&jobs) {
+                gc::Owner::Unknown => tracing::warn!(
+                    "{machine}: keeping {leftovers}, there is no job marker to tell whether it is in use, pass --force to remove it"
+                ),
+                _ => tracing::info!("{machine}: keeping {leftovers}, in use by job {jobs:?}"),
+            }
+            continue;
+        }
+        tracing::info!("{machine}: found {leftovers}");
+        if leftovers.network() && !leftovers.foreign.is_empty() {
+            let foreign = leftovers
+                .foreign
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            tracing::warn!(
+                "{machine}: not removing the network, it has qdiscs not created by oar-p2p: {foreign}"
+            );
+        }
+        orphaned.push((machine, leftovers));
+    }
+    if orphaned.is_empty() {
+        tracing::info!("no leftovers found");
+        return Ok(());
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+    let orphaned = &orphaned;
+    machine::for_each_progress(
+        "removing leftovers",
+        orphaned.iter().map(|(machine, _)| machine),
+        |machine| {
+            let ctx = ctx.clone();
+            async move {
+                let (_, leftovers) = orphaned.iter().find(|(m, _)| *m == machine).unwrap();
+                machine_gc_remove(&ctx, machine, leftovers).await
+            }
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_gc_scan(ctx: &Context, machine: Machine) -> Result<gc::Leftovers> {
+    let host = machine_run_script(ctx, machine, &gc::host_scan_script(ctx.runtime)).await?;
+    let net = gc::net_scan_script(machine.interface());
+    let net = machine_net_container_run_script(ctx, machine, &net).await?;
+    Ok(gc::Leftovers::parse(
+        &String::from_utf8_lossy(&host.stdout),
+        &String::from_utf8_lossy(&net.stdout),
+    ))
+}
+
+#[tracing::instrument(ret, err, skip(ctx, leftovers))]
+async fn machine_gc_remove(
+    ctx: &Context,
+    machine: Machine,
+    leftovers: &gc::Leftovers,
+) -> Result<()> {
+    if leftovers.network() && leftovers.foreign.is_empty() {
+        machine_clean(ctx, machine).await?;
+    }
+    machine_run_script(
+        ctx,
+        machine,
+        &gc::host_remove_script(ctx.runtime, leftovers),
+    )
+    .await?;
+    Ok(())
+}
+
+/// record the jobs of the context on the machines, see [`gc::JOB_MARKER_PATH`].
+#[tracing::instrument(ret, err, skip_all)]
+async fn machines_job_mark(ctx: &Context, machines: &[Machine]) -> Result<()> {
+    let script = gc::job_marker_script(&ctx.job_ids().await?);
+    machine::for_each(machines, |machine| {
+        let script = &script;
+        async move { machine_run_script(ctx, machine, script).await }
+    })
+    .await?;
+    Ok(())
+}
+
+async fn cmd_registry_up(args: RegistryUpArgs, output: OutputMode) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&ctx).await?;
+    let mirror = match args.machine {
+        Some(machine) if !machines.contains(&machine) => {
+            return Err(eyre::eyre!("machine {machine} is not part of the job"));
+        }
+        Some(machine) => machine,
+        None => *machines
+            .first()
+            .ok_or_else(|| eyre::eyre!("the job has no machines"))?,
+    };
+    let url = registry::mirror_url(mirror, args.port);
+
+    machines_net_container_build(&ctx, &machines).await?;
+    tracing::info!("configuring all machines to use the mirror at {url}");
+    let script = registry::configure_script(ctx.runtime, &url);
+    machine::for_each(&machines, |machine| {
+        machine_registry_configure(&ctx, machine, &script)
+    })
+    .await?;
+
+    tracing::info!("starting the mirror on {mirror}");
+    let script = registry::mirror_up_script(ctx.runtime, args.port, &args.upstream);
+    machine_run_script(&ctx, mirror, &script).await?;
+    match output {
+        OutputMode::Text => println!("{url}"),
+        OutputMode::Json => print_json(&serde_json::json!({ "url": url, "machine": mirror }))?,
+    }
+    Ok(())
+}
+
+async fn cmd_registry_down(args: RegistryDownArgs) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&ctx).await?;
+    machines_net_container_build(&ctx, &machines).await?;
+    let ctx = &ctx;
+    machine::for_each(&machines, |machine| async move {
+        machine_run_script(ctx, machine, &registry::mirror_down_script(ctx.runtime)).await?;
+        let script = registry::unconfigure_script(ctx.runtime);
+        machine_registry_configure(ctx, machine, &script).await
+    })
+    .await?;
+    Ok(())
+}
+
+async fn cmd_monitor_up(args: MonitorUpArgs, output: OutputMode) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&ctx).await?;
+    let prometheus = match args.machine {
+        Some(machine) if !machines.contains(&machine) => {
+            return Err(eyre::eyre!("machine {machine} is not part of the job"));
+        }
+        Some(machine) => machine,
+        None => *machines
+            .first()
+            .ok_or_else(|| eyre::eyre!("the job has no machines"))?,
+    };
+
+    tracing::info!("starting the exporters on all machines");
+    let script = monitor::exporters_up_script(ctx.runtime);
+    machine::for_each(&machines, |machine| {
+        let ctx = ctx.clone();
+        let script = script.clone();
+        async move { machine_run_script(&ctx, machine, &script).await }
+    })
+    .await?;
+
+    tracing::info!("starting prometheus on {prometheus}");
+    let config = monitor::prometheus_config(&machines, args.scrape_interval);
+    let script = monitor::prometheus_up_script(ctx.runtime, &config, args.port);
+    machine_run_script(&ctx, prometheus, &script).await?;
+    let url = monitor::prometheus_url(prometheus, args.port);
+    match output {
+        OutputMode::Text => println!("{url}"),
+        OutputMode::Json => print_json(&serde_json::json!({ "url": url, "machine": prometheus }))?,
+    }
+    Ok(())
+}
+
+async fn cmd_monitor_down(args: MonitorDownArgs) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&ctx).await?;
+    let script = monitor::down_script(ctx.runtime);
+    machine::for_each(&machines, |machine| {
+        let ctx = ctx.clone();
+        let script = script.clone();
+        async move { machine_run_script(&ctx, machine, &script).await }
+    })
+    .await?;
+    Ok(())
+}
+
+/// run a script that changes the registry configuration of the machine's runtime and wait for
+/// the runtime to be ready again.
+#[tracing::instrument(ret, err, skip(ctx, script))]
+async fn machine_registry_configure(ctx: &Context, machine: Machine, script: &str) -> Result<()> {
+    let mounts = registry::HOST_CONFIG_DIRS
+        .iter()
+        .map(|dir| format!("{dir}:{dir}"))
+        .collect::<Vec<_>>();
+    let mut extra_args = vec!["--pid=host"];
+    for mount in mounts.iter() {
+        extra_args.extend(["-v", mount]);
+    }
+    machine_net_container_run_script_with(ctx, machine, &extra_args, script).await?;
+    machine_run_script(ctx, machine, &registry::wait_ready_script(ctx.runtime)).await?;
+    Ok(())
+}
+
+async fn cmd_oar_extend(args: OarExtendArgs) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    for job_id in ctx.job_ids().await? {
+        let output = oar::job_extend_walltime(&ctx, job_id, &args.walltime).await?;
+        if !output.is_empty() {
+            println!("{output}");
+        }
+        if let Ok(info) = oar::job_info(&ctx, job_id).await
+            && let Some(remaining) = info.remaining_walltime()
+        {
+            tracing::info!(
+                "job {job_id} has {}s of walltime remaining",
+                remaining.as_secs()
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn cmd_oar_wait(args: OarWaitArgs) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    for job_id in ctx.job_ids().await? {
+        let info = oar::job_wait_running(
+            &ctx,
+            job_id,
+            args.timeout.map(Duration::from_secs),
+            Duration::from_secs(args.interval.max(1)),
+        )
+        .await?;
+        tracing::info!("job {} is running on {:?}", info.job_id, info.machines);
+    }
+    Ok(())
+}
+
+async fn cmd_oar_ensure(args: OarEnsureArgs, output: OutputMode) -> Result<()> {
+    let print_job = |job_id: u32, submitted: bool| match output {
+        OutputMode::Text => {
+            println!("{job_id}");
+            Ok(())
+        }
+        OutputMode::Json => {
+            print_json(&serde_json::json!({ "job_id": job_id, "submitted": submitted }))
+        }
+    };
+    let walltime = oar::parse_walltime(&args.walltime).ok_or_else(|| {
+        eyre::eyre!(
+            "invalid walltime '{}', expected <hours>[:<minutes>[:<seconds>]]",
+            args.walltime
+        )
+    })?;
+    let ctx = context_from_common(&args.common).await?;
+    for job_id in oar::list_user_job_ids(&ctx).await? {
+        let info = oar::job_info(&ctx, job_id).await?;
+        if info.machines.len() != args.nodes as usize {
+            continue;
+        }
+        let remaining = info.remaining_walltime();
+        if remaining.is_none_or(|remaining| remaining < walltime) {
+            tracing::info!(
+                "not reusing job {job_id}, its remaining walltime {:?} is less than {:?}",
+                remaining,
+                walltime
+            );
+            continue;
+        }
+        tracing::info!(
+            "reusing running job {job_id} with machines {:?}",
+            info.machines
+        );
+        return print_job(job_id, false);
+    }
+
+    tracing::info!(
+        "no running job with {} machines and {} of walltime left found, submitting a new one",
+        args.nodes,
+        args.walltime
+    );
+    let job_id = oar::job_submit(&ctx, args.nodes, &args.walltime).await?;
+    tracing::info!("submitted job {job_id}, waiting for it to start running");
+    let info = oar::job_wait_running(
+        &ctx,
+        job_id,
+        args.timeout.map(Duration::from_secs),
+        Duration::from_secs(5),
+    )
+    .await?;
+    tracing::info!("job {job_id} is running on {:?}", info.machines);
+    print_job(job_id, true)
+}
+
+/// the status of a job as printed by `oar status`.
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct JobStatus {
+    job_id: u32,
+    state: String,
+    /// unix timestamp, in seconds
+    started_at: Option<u64>,
+    /// seconds left before the walltime ends, only for running jobs
+    remaining_walltime: Option<u64>,
+    /// number of machines with the network configured, only for running jobs
+    network_deployed: Option<usize>,
+    machines: Vec<Machine>,
+    /// machines whose network could not be checked, only for running jobs
+    unreachable_machines: Vec<Machine>,
+}
+
+impl JobStatus {
+    /// `deployed` is `None` for the machines that could not be reached.
+    fn new(job: oar::JobInfo, deployed: &HashMap<Machine, Option<bool>>) -> Self {
+        let running = job.state == "Running";
+        Self {
+            job_id: job.job_id,
+            started_at: job
+                .start_time
+                .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|t| t.as_secs()),
+            remaining_walltime: job
+                .remaining_walltime()
+                .filter(|_| running)
+                .map(|r| r.as_secs()),
+            network_deployed: running.then(|| {
+                job.machines
+                    .iter()
+                    .filter(|m| deployed.get(m).copied().flatten().unwrap_or(false))
+                    .count()
+            }),
+            unreachable_machines: job
+                .machines
+                .iter()
+                .filter(|m| running && deployed.get(m).is_some_and(Option::is_none))
+                .copied()
+                .collect(),
+            state: job.state,
+            machines: job.machines,
+        }
+    }
+
+    fn network(&self) -> String {
+        let network = match self.network_deployed {
+            _ if self.machines.is_empty() => String::from("-"),
+            None => String::from("-"),
+            Some(count) if count == self.machines.len() => String::from("deployed"),
+            Some(0) if self.unreachable_machines.is_empty() => String::from("absent"),
+            Some(count) => format!("partial({count}/{})", self.machines.len()),
+        };
+        match self.unreachable_machines.len() {
+            0 => network,
+            n => format!("{network},{n}-unreachable"),
+        }
+    }
+}
+
+async fn cmd_oar_status(args: OarStatusArgs, output: OutputMode) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let jobs = oar::list_user_jobs(&ctx).await?;
+    let machines = jobs
+        .iter()
+        .filter(|j| j.state == "Running")
+        .flat_map(|j| j.machines.iter())
+        .collect::<HashSet<_>>();
+    let deployed = machine::for_each(machines, |machine| {
+        let ctx = ctx.clone();
+        async move {
+            // an unreachable machine is reported in its job instead of failing the command
+            match machine_network_deployed(&ctx, machine).await {
+                Ok(deployed) => Ok(Some(deployed)),
+                Err(err) => {
+                    tracing::warn!("failed to check the network of {machine}: {err}");
+                    Ok(None)
+                }
+            }
+        }
+    })
+    .await?
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+    let jobs = jobs
+        .into_iter()
+        .map(|job| JobStatus::new(job, &deployed))
+        .collect::<Vec<_>>();
+
+    if output == OutputMode::Json {
+        return print_json(&jobs);
+    }
+    println!(
+        "{:<8} {:<12} {:<14} {:<12} {:<10} MACHINES",
+        "JOB", "STATE", "STARTED", "REMAINING", "NETWORK"
+    );
+    let now = unix_timestamp();
+    for job in jobs {
+        let started = match job.started_at {
+            Some(started_at) => format!(
+                "{} ago",
+                format_duration(Duration::from_secs(now.saturating_sub(started_at)))
+            ),
+            None => String::from("-"),
+        };
+        let remaining = match job.remaining_walltime {
+            Some(remaining) => format_duration(Duration::from_secs(remaining)),
+            None => String::from("-"),
+        };
+        let machines = job
+            .machines
+            .iter()
+            .map(|m| {
+                if job.unreachable_machines.contains(m) {
+                    format!("{}(unreachable)", m.hostname())
+                } else {
+                    m.hostname().to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        println!(
+            "{:<8} {:<12} {:<14} {:<12} {:<10} {}",
+            job.job_id,
+            job.state,
+            started,
+            remaining,
+            job.network(),
+            machines
+        );
+    }
+    Ok(())
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds / 60) % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}h{minutes:02}m")
+    } else if minutes > 0 {
+        format!("{minutes}m{seconds:02}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+fn machine_containers_create_script(
+    runtime: ContainerRuntime,
+    containers: &[ScheduledContainer],
+) -> String {
+    let mut script = String::default();
+
+    // check that all volume host paths exist before creating anything
+    let volume_host_paths = containers
+        .iter()
+        .flat_map(|c| c.volumes.iter().map(|v| v.host.as_str()))
+        .collect::<HashSet<_>>();
+    for path in volume_host_paths {
+        let path = shell::quote(path);
+        script.push_str(&format!(
+            "[ -e {path} ] || {{ echo Volume host path {path} does not exist ; exit 1 ; }}\n"
+        ));
+    }
+
+    for (idx, container) in containers.iter().enumerate() {
+        // remove the start signal file if it exists
+        script.push_str("mkdir -p /tmp/oar-p2p-signal\n");
+        script.push_str("rm /tmp/oar-p2p-signal/start 2>/dev/null || true\n");
+        script.push_str(&format!(
+            "rm /tmp/oar-p2p-signal/{BARRIER_SIGNAL} 2>/dev/null || true\n"
+        ));
+
+        if container.has_file_signals() {
+            let dir = format!("/tmp/oar-p2p-container-signal/{}", container.name);
+            script.push_str(&format!("rm -rf {dir} && mkdir -p {dir} || exit 1\n"));
+        }
+
+        if let Some(peers) = &container.peers_file {
+            let dir = peers::PEERS_HOST_DIR;
+            script.push_str(&format!("mkdir -p {dir} || exit 1\n"));
+            script.push_str(&format!(
+                "printf '%s' {} > {dir}/{} || exit 1\n",
+                shell::quote(peers),
+                container.name
+            ));
+        }
+
+        script.push_str(&format!("{runtime} create \\\n"));
+        script.push_str("\t--pull=never \\\n");
+        script.push_str("\t--network=host \\\n");
+        script.push_str(&format!("\t--restart={} \\\n", container.restart));
+        script.push_str("\t--volume /tmp/oar-p2p-signal:/oar-p2p\\\n");
+        if container.has_file_signals() {
+            script.push_str(&format!(
+                "\t--volume /tmp/oar-p2p-container-signal/{}:/oar-p2p-container \\\n",
+                container.name
+            ));
+        }
+        if container.peers_file.is_some() {
+            script.push_str(&format!(
+                "\t--volume {}/{}:{}:ro \\\n",
+                peers::PEERS_HOST_DIR,
+                container.name,
+                peers::PEERS_CONTAINER_PATH
+            ));
+        }
+        script.push_str(&format!("\t--name {} \\\n", container.name));
+        script.push_str(&format!(
+            "\t--label {}={} \\\n",
+            ps::ADDRESS_LABEL,
+            container.address
+        ));
+        for volume in container.volumes.iter() {
+            script.push_str(&format!("\t-v {} \\\n", shell::quote(&volume.to_string())));
+        }
+        for (local, path) in container.inputs.iter() {
+            let mount = format!("{}:{path}:ro", input_remote_path(local));
+            script.push_str(&format!("\t-v {} \\\n", shell::quote(&mount)));
+        }
+        for mount in container.tmpfs.iter() {
+            script.push_str(&format!("\t--tmpfs {} \\\n", shell::quote(mount)));
+        }
+        for (key, val) in container.variables.iter() {
+            script.push_str("\t-e ");
+            script.push_str(key);
+            script.push('=');
+            script.push('\'');
+            script.push_str(val);
+            script.push('\'');
+            script.push_str(" \\\n");
+        }
+        if let Some(cpuset) = &container.cpuset {
+            script.push_str(&format!("\t--cpuset-cpus {cpuset} \\\n"));
+        }
+        if let Some(memory) = container.memory {
+            script.push_str(&format!("\t--memory {memory} \\\n"));
+        }
+        if let Some(cpus) = container.cpus {
+            script.push_str(&format!("\t--cpus {cpus} \\\n"));
+        }
+        if let Some(shm_size) = container.shm_size {
+            script.push_str(&format!("\t--shm-size {shm_size} \\\n"));
+        }
+        for capability in container.cap_add.iter() {
+            script.push_str(&format!("\t--cap-add {capability} \\\n"));
+        }
+        for capability in container.cap_drop.iter() {
+            script.push_str(&format!("\t--cap-drop {capability} \\\n"));
+        }
+        if container.privileged {
+            script.push_str("\t--privileged \\\n");
+        }
+        for option in container.security_opt.iter() {
+            script.push_str(&format!("\t--security-opt {} \\\n", shell::quote(option)));
+        }
+        if let Some(entrypoint) = &container.entrypoint {
+            script.push_str(&format!("\t--entrypoint {} \\\n", shell::quote(entrypoint)));
+        }
+        script.push('\t');
+        script.push_str(&container.image);
+        for arg in container.command.iter() {
+            script.push(' ');
+            script.push_str(&shell::quote(arg));
+        }
+        script.push_str(" &\n");
+        script.push_str(&format!("pid_{idx}=$!\n\n"));
+    }
+
+    for (idx, container) in containers.iter().enumerate() {
+        let name = &container.name;
+        script.push_str(&format!(
+            "wait $pid_{idx} || {{ echo Failed to create container {name} ; exit 1 ; }}\n"
+        ));
+    }
+
+    script
+}
+
+/// script that pulls all images in parallel.
+///
+/// with `authenticated` the registry credentials installed by [`machine_install_registry_config`]
+/// are used and removed once the script exits.
+fn machine_images_pull_script(
+    runtime: ContainerRuntime,
+    images: &[String],
+    authenticated: bool,
+) -> String {
+    let mut script = String::default();
+    let mut env = String::default();
+    if authenticated {
+        script.push_str(&format!("trap 'rm -rf {REGISTRY_CONFIG_DIR}' EXIT\n"));
+        env = format!("DOCKER_CONFIG={REGISTRY_CONFIG_DIR} ");
+    }
+    for (idx, image) in images.iter().enumerate() {
+        script.push_str(&format!("{env}{runtime} pull {} &\n", shell::quote(image)));
+        script.push_str(&format!("pid_{idx}=$!\n"));
+    }
+    for (idx, image) in images.iter().enumerate() {
+        script.push_str(&format!(
+            "wait $pid_{idx} || {{ echo Failed to pull image {} ; exit 1 ; }}\n",
+            shell::quote(image)
+        ));
+    }
+    script
+}
+
+/// pull the images, using the registry credentials in `registry_config` if given.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_pull_images(
+    ctx: &Context,
+    machine: Machine,
+    images: &[String],
+    registry_config: Option<&Path>,
+) -> Result<()> {
+    if images.is_empty() {
+        return Ok(());
+    }
+    if let Some(registry_config) = registry_config {
+        machine_install_registry_config(ctx, machine, registry_config).await?;
+    }
+    tracing::info!("pulling {} images", images.len());
+    let script = machine_images_pull_script(ctx.runtime, images, registry_config.is_some());
+    machine_run_script(ctx, machine, &script).await?;
+    tracing::info!("images pulled");
+    Ok(())
+}
+
+/// copy the registry credentials to the machine. the file is streamed through stdin so that the
+/// credentials never show up in the logged scripts.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_install_registry_config(
+    ctx: &Context,
+    machine: Machine,
+    registry_config: &Path,
+) -> Result<()> {
+    let file = std::fs::File::open(registry_config)
+        .with_context(|| format!("opening registry config {}", registry_config.display()))?;
+    let command = format!(
+        "umask 077 && mkdir -p {REGISTRY_CONFIG_DIR} && cat > {REGISTRY_CONFIG_DIR}/config.json"
+    );
+    let mut arguments = machine_ssh_arguments(ctx, machine)?;
+    let quoted = format!("bash -c {}", shell::quote(&command));
+    if arguments.is_empty() {
+        arguments.extend(["bash", "-c", &command]);
+    } else {
+        arguments.push(&quoted);
+    }
+    let output = Command::new(arguments[0])
+        .args(&arguments[1..])
+        .process_group(0)
+        .kill_on_drop(true)
+        .stdin(file)
+        .output()
+        .await
+        .context("spawning process")?;
+    if !output.status.success() {
+        let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf-8>");
+        tracing::error!("stderr:\n{stderr}");
+    }
+    output.exit_ok()?;
+    Ok(())
+}
+
+/// export a local image and load it on the machines.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machines_load_image(ctx: &Context, machines: &[Machine], image: &str) -> Result<()> {
+    tracing::info!("saving local image {image}");
+    let archive = std::env::temp_dir().join(format!(
+        "oar-p2p-image-{}-{}.tar",
+        std::process::id(),
+        image.replace(['/', ':', '@'], "_")
+    ));
+    let mut command = Command::new("docker");
+    command.arg("save").arg("-o").arg(&archive).arg(image);
+    command_run(command)
+        .await
+        .with_context(|| format!("saving local image {image}"))?;
+
+    let result = machine::for_each(machines, |machine| {
+        let ctx = ctx.clone();
+        let archive = archive.clone();
+        async move { machine_load_image(&ctx, machine, &archive).await }
+    })
+    .await;
+    tokio::fs::remove_file(&archive).await?;
+    result.with_context(|| format!("loading image {image}"))?;
+    tracing::info!("image {image} loaded on all machines");
+    Ok(())
+}
+
+/// stream an image archive to the machine and load it.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_load_image(ctx: &Context, machine: Machine, archive: &Path) -> Result<()> {
+    let mut arguments = machine_ssh_arguments(ctx, machine)?;
+    arguments.push(ctx.runtime.command());
+    arguments.push("load");
+    let archive = std::fs::File::open(archive)
+        .with_context(|| format!("opening image archive {}", archive.display()))?;
+    let output = Command::new(arguments[0])
+        .args(&arguments[1..])
+        .process_group(0)
+        .kill_on_drop(true)
+        .stdin(archive)
+        .output()
+        .await
+        .context("spawning process")?;
+    if !output.status.success() {
+        let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf-8>");
+        tracing::error!("stderr:\n{stderr}");
+    }
+    output.exit_ok()?;
+    Ok(())
+}
+
+/// the images used by the containers, without duplicates and in a stable order.
+fn containers_images(containers: &[ScheduledContainer]) -> Vec<String> {
+    containers
+        .iter()
+        .map(|c| c.image.clone())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+#[tracing::instrument(ret, err, skip(ctx, containers))]
+async fn machine_create_containers(
+    ctx: &Context,
+    machine: Machine,
+    containers: &[ScheduledContainer],
+) -> Result<()> {
+    tracing::info!("creating {} containers", containers.len());
+    let script = machine_containers_create_script(ctx.runtime, containers);
+    machine_run_script(ctx, machine, &script).await?;
+    tracing::info!("containers created");
+    Ok(())
+}
+
+fn machine_containers_start_script(runtime: ContainerRuntime) -> String {
+    format!("{runtime} container ls -aq | xargs {runtime} container start\n")
+}
+
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_start_containers(ctx: &Context, machine: Machine) -> Result<()> {
+    tracing::info!("starting all containers");
+    machine_run_script(ctx, machine, &machine_containers_start_script(ctx.runtime)).await?;
+    tracing::info!("all containers started");
+    Ok(())
+}
+
+fn machine_containers_start_and_wait_script(runtime: ContainerRuntime, names: &[String]) -> String {
+    let quoted = names
+        .iter()
+        .map(|n| shell::quote(n))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut script = String::default();
+    script.push_str(&format!(
+        "{runtime} container start {quoted} >/dev/null || exit 1\n"
+    ));
+    script.push_str(&machine_containers_wait_running_script(runtime, names));
+    script
+}
+
+/// script that waits until the containers are running, or healthy if they have a healthcheck,
+/// and fails if any of them stops. a container that already exited with code 0 is a one-shot
+/// dependency, like a setup task, and counts as ready.
+fn machine_containers_wait_running_script(runtime: ContainerRuntime, names: &[String]) -> String {
+    let names = names
+        .iter()
+        .map(|n| shell::quote(n))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut script = String::default();
+    script.push_str(&format!("for name in {names} ; do\n"));
+    script.push_str("\twhile true ; do\n");
+    script.push_str(&format!("\t\tstatus=$({runtime} inspect -f '{{{{if .State.Health}}}}{{{{.State.Health.Status}}}}{{{{else}}}}{{{{.State.Status}}}}{{{{end}}}} {{{{.State.ExitCode}}}}' \"$name\") || exit 1\n"));
+    script.push_str("\t\tcase \"$status\" in\n");
+    script.push_str("\t\t\t\"running \"*|\"healthy \"*|\"exited 0\") break ;;\n");
+    script.push_str("\t\t\t\"created \"*|\"starting \"*|\"restarting \"*) sleep 1 ;;\n");
+    script.push_str("\t\t\t*) echo \"Container $name is $status\" ; exit 1 ;;\n");
+    script.push_str("\t\tesac\n");
+    script.push_str("\tdone\n");
+    script.push_str("done\n");
+    script
+}
+
+fn machine_containers_staggered_start_script(
+    runtime: ContainerRuntime,
+    schedule: &[(String, SystemTime)],
+) -> String {
+    let mut schedule = schedule.to_vec();
+    schedule.sort_by_key(|(_, at)| *at);
+    let mut script = String::default();
+    script.push_str("wait_until() {\n");
+    script.push_str("\tlocal remaining=$(( $1 - $(date +%s%3N) ))\n");
+    script.push_str("\tif [ $remaining -gt 0 ] ; then sleep $(printf '%d.%03d' $(( remaining / 1000 )) $(( remaining % 1000 ))) ; fi\n");
+    script.push_str("}\n");
+    for (name, at) in schedule {
+        let millis = at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let name = shell::quote(&name);
+        script.push_str(&format!(
+            "wait_until {millis} ; {runtime} container start {name} >/dev/null || {{ echo Failed to start container {name} ; exit 1 ; }}\n"
+        ));
+    }
+    script
+}
+
+/// start each container at its given instant.
+#[tracing::instrument(ret, err, skip(ctx, schedule))]
+async fn machine_start_containers_staggered(
+    ctx: &Context,
+    machine: Machine,
+    schedule: &[(String, SystemTime)],
+) -> Result<()> {
+    tracing::info!("starting {} containers in a ramp", schedule.len());
+    let script = machine_containers_staggered_start_script(ctx.runtime, schedule);
+    machine_run_script(ctx, machine, &script).await?;
+    tracing::info!("all containers started");
+    Ok(())
+}
+
+/// wait until the given containers are running, or healthy if they have a healthcheck.
+#[tracing::instrument(ret, err, skip(ctx, names))]
+async fn machine_containers_wait_running(
+    ctx: &Context,
+    machine: Machine,
+    names: &[String],
+) -> Result<()> {
+    tracing::info!("waiting for {} containers to be running", names.len());
+    let script = machine_containers_wait_running_script(ctx.runtime, names);
+    machine_run_script(ctx, machine, &script).await?;
+    tracing::info!("containers are running");
+    Ok(())
+}
+
+/// start the given containers and wait until they are running, or healthy if they have a
+/// healthcheck.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_start_containers_and_wait(
+    ctx: &Context,
+    machine: Machine,
+    names: &[String],
+) -> Result<()> {
+    tracing::info!("starting {} containers", names.len());
+    let script = machine_containers_start_and_wait_script(ctx.runtime, names);
+    machine_run_script(ctx, machine, &script).await?;
+    tracing::info!("containers are running");
+    Ok(())
+}
+
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_signal_containers(
+    ctx: &Context,
+    machine: Machine,
+    signal: &Signal,
+    timestamp: u64,
+) -> Result<()> {
+    tracing::info!("signaling containers");
+    machine_run_script(
+        ctx,
+        machine,
+        &format!("echo -n {timestamp} > /tmp/oar-p2p-signal/{signal}.tmp ; mv /tmp/oar-p2p-signal/{signal}.tmp /tmp/oar-p2p-signal/{signal}"),
+    )
+    .await?;
+    tracing::info!("containers signaled");
+    Ok(())
+}
+
+async fn cmd_signal_send(args: SignalSendArgs, output: OutputMode) -> Result<()> {
+    let file_signal = !args.signal.is_process_signal() && args.udp_port.is_none();
+    if file_signal && !args.container.is_empty() {
+        return Err(eyre::eyre!(
+            "signal files are shared by all containers of a machine, --container requires a process signal or --udp-port"
+        ));
+    }
+
+    let ctx = context_from_common(&args.common).await?;
+    let machines = oar::job_list_machines(&ctx)
+        .await?
+        .into_iter()
+        .filter(|m| args.machine.is_empty() || args.machine.contains(m))
+        .collect::<Vec<_>>();
+    let containers = machines_list_run_containers(&ctx, &machines)
+        .await?
+        .into_iter()
+        .filter(|c| c.state == "running")
+        .filter(|c| {
+            args.container.is_empty() || args.container.iter().any(|p| ps::glob_match(p, &c.name))
+        })
+        .collect::<Vec<_>>();
+    if containers.is_empty() {
+        tracing::warn!("no running containers matched");
+    }
+
+    let timestamp = unix_timestamp();
+    let machines = containers
+        .iter()
+        .map(|c| &c.machine)
+        .collect::<HashSet<_>>();
+    let signal = &args.signal;
+    machine::for_each(machines, |machine| {
+        let ctx = ctx.clone();
+        let containers = containers
+            .iter()
+            .filter(|c| c.machine == machine)
+            .collect::<Vec<_>>();
+        async move {
+            if file_signal {
+                return machine_signal_containers(&ctx, machine, signal, timestamp).await;
+            }
+            let script = match args.udp_port {
+                Some(port) => signal_udp_script(&containers, signal, timestamp, port),
+                None => signal_kill_script(ctx.runtime, &containers, signal),
+            };
+            machine_run_script(&ctx, machine, &script).await?;
+            Ok(())
+        }
+    })
+    .await?;
+    match output {
+        OutputMode::Text => println!("delivered {signal} to {} containers", containers.len()),
+        OutputMode::Json => print_json(&serde_json::json!({
+            "signal": signal.as_str(),
+            "timestamp": timestamp,
+            "containers": containers.iter().map(|c| &c.name).collect::<Vec<_>>(),
+        }))?,
+    }
+    Ok(())
+}
+
+async fn cmd_signal_wait(args: SignalWaitArgs, output: OutputMode) -> Result<()> {
+    let ctx = context_from_common(&args.common).await?;
+    let matches = |name: &str| {
+        args.container.is_empty() || args.container.iter().any(|p| ps::glob_match(p, name))
+    };
+    let machines = oar::job_list_machines(&ctx).await?;
+    let count = match args.count {
+        Some(count) => count,
+        None => machines_list_run_containers(&ctx, &machines)
+            .await?
+            .into_iter()
+            .filter(|c| c.state == "running" && matches(&c.name))
+            .count(),
+    };
+    let signal = &args.signal;
+    tracing::info!("waiting for {count} containers to emit {signal}");
+
+    let start = SystemTime::now();
+    let script = signal::emitted_script(ctx.runtime, signal);
+    let mut emitted = 0;
+    loop {
+        let results = machine::for_each(&machines, |machine| {
+            let ctx = ctx.clone();
+            let script = script.clone();
+            async move { machine_run_script(&ctx, machine, &script).await }
+        })
+        .await?;
+        let current = results
+            .iter()
+            .flat_map(|(_, output)| {
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|name| matches(name))
+            .count();
+        if current != emitted {
+            tracing::info!("{current} out of {count} containers emitted {signal}");
+            emitted = current;
+        }
+        if emitted >= count {
+            break;
+        }
+        if let Some(timeout) = args.timeout
+            && start.elapsed().unwrap_or_default() >= Duration::from_secs(timeout)
+        {
+            return Err(eyre::eyre!(
+                "only {emitted} out of {count} containers emitted {signal} after {timeout} seconds"
+            ));
+        }
+        tokio::time::sleep(Duration::from_secs(args.interval)).await;
+    }
+    match output {
+        OutputMode::Text => println!("{emitted} containers emitted {signal}"),
+        OutputMode::Json => print_json(&serde_json::json!({
+            "signal": signal.as_str(),
+            "emitted": emitted,
+        }))?,
+    }
+    Ok(())
+}
+
+/// script that sends a process signal to every container, containers that exit in the meantime
+/// are not an error.
+fn signal_kill_script(
+    runtime: ContainerRuntime,
+    containers: &[&ps::ContainerStatus],
+    signal: &Signal,
+) -> String {
+    let mut script = String::default();
+    for container in containers {
+        let name = &container.name;
+        script.push_str(&format!(
+            "{runtime} kill --signal {signal} {name} >/dev/null || echo Container {name} is not running\n"
+        ));
+    }
+    script
+}
+
+/// script that sends `<signal> <timestamp>` as an udp datagram to `port` of the address of every
+/// container. containers without an address are skipped.
+fn signal_udp_script(
+    containers: &[&ps::ContainerStatus],
+    signal: &Signal,
+    timestamp: u64,
+    port: u16,
+) -> String {
+    let mut script = String::default();
+    for address in containers.iter().filter_map(|c| c.address) {
+        script.push_str(&format!(
+            "echo -n '{signal} {timestamp}' > /dev/udp/{address}/{port}\n"
+        ));
+    }
+    script
+}
+
+/// deliver a signal to a single container.
+///
+/// process signals are sent with `docker kill`, other signals become visible as a file under the
+/// container's `/oar-p2p-container/` directory.
+#[tracing::instrument(ret, err, skip(ctx, container), fields(container = container.name))]
+async fn machine_signal_container(
+    ctx: &Context,
+    container: &ScheduledContainer,
+    signal: &Signal,
+    timestamp: u64,
+) -> Result<()> {
+    let name = &container.name;
+    let runtime = ctx.runtime;
+    let script = if signal.is_process_signal() {
+        // the container may have already exited, which is not an error
+        format!(
+            "{runtime} kill --signal {signal} {name} >/dev/null || echo Container {name} is not running\n"
+        )
+    } else {
+        let dir = format!("/tmp/oar-p2p-container-signal/{name}");
+        format!("echo -n {timestamp} > {dir}/{signal}.tmp ; mv {dir}/{signal}.tmp {dir}/{signal}\n")
+    };
+    machine_run_script(ctx, container.machine, &script).await?;
+    tracing::info!("container signaled");
+    Ok(())
+}
+
+/// script that waits for every container to exit and prints a line in the format
+/// `exited <name> <exit code>` for each of them. with `fail_fast` the script fails as soon as a
+/// container exits with a non-zero code. with `churn` the script keeps waiting for containers
+/// that were stopped by the churn and will be restarted.
+///
+/// containers with a restart policy are waited on until their final exit, `docker wait` returns
+/// on every exit so it is repeated while the container is restarting or running again.
+fn machine_containers_wait_script(
+    runtime: ContainerRuntime,
+    containers: &[ScheduledContainer],
+    fail_fast: bool,
+    churn: bool,
+) -> String {
+    let mut script = String::default();
+    for container in containers {
+        let name = &container.name;
+        if container.restart == schedule::RestartPolicy::No && !churn {
+            script.push_str(&format!("code=$({runtime} wait {name}) || exit 1\n"));
+        } else {
+            script.push_str("while true ; do\n");
+            script.push_str(&format!("\tcode=$({runtime} wait {name}) || exit 1\n"));
+            script.push_str(&format!(
+                "\tstatus=$({runtime} inspect -f '{{{{.State.Status}}}}' {name}) || exit 1\n"
+            ));
+            script.push_str("\tcase \"$status\" in\n");
+            script.push_str("\t\trestarting|running) sleep 1 ;;\n");
+            if churn {
+                script.push_str(&format!(
+                    "\t\t*) [ -e {}/{name} ] || break ; sleep 1 ;;\n",
+                    churn::CHURN_MARKER_DIR
+                ));
+            } else {
+                script.push_str("\t\t*) break ;;\n");
+            }
+            script.push_str("\tesac\n");
+            script.push_str("done\n");
+        }
+        script.push_str(&format!("echo exited {name} $code\n"));
+        if fail_fast {
+            script.push_str("if [ \"$code\" -ne \"0\" ] ; then\n");
+            script.push_str(&format!("\techo Container {name} failed\n"));
+            script.push_str(&format!("\t{runtime} logs {name} 2>&1 | tail -n 500\n"));
+            script.push_str("\texit 1\n");
+            script.push_str("fi\n");
+        }
+        script.push('\n');
+    }
+    script.push_str("exit 0\n");
+    script
+}
+
+/// parse the exit codes out of the output of [`machine_containers_wait_script`].
+fn parse_containers_wait_output(output: &str) -> Vec<(String, i64)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("exited"), Some(name), Some(code)) => {
+                    Some((name.to_string(), code.parse().ok()?))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// wait for all containers to exit and return their exit codes.
+#[tracing::instrument(ret, err, skip(ctx, containers))]
+async fn machine_containers_wait(
+    ctx: &Context,
+    machine: Machine,
+    containers: &[ScheduledContainer],
+    fail_fast: bool,
+    churn: bool,
+) -> Result<Vec<(String, i64)>> {
+    tracing::info!("waiting for {} containers to exit", containers.len());
+    let script = machine_containers_wait_script(ctx.runtime, containers, fail_fast, churn);
+    let wait_timeout = Duration::from_secs(60);
+    let retry_seconds = 5;
+    let mut retries = 10;
+    let output = loop {
+        let fut = tokio::time::timeout(wait_timeout, machine_run_script(ctx, machine, &script));
+        match fut.await {
+            Ok(Ok(output)) => break output,
+            Ok(Err(err)) => {
+                tracing::debug!("failed to run script: {err}, {retries} left");
+                if retries == 0 {
+                    return Err(err);
+                }
+                retries -= 1;
+                tracing::debug!("waiting {retry_seconds} before retrying...");
+                tokio::time::sleep(Duration::from_secs(retry_seconds)).await;
+                tracing::debug!("retrying");
+            }
+            Err(_) => {
+                tracing::debug!("wait timeout, retrying...");
+                tokio::time::sleep(Duration::from_secs(retry_seconds)).await;
+            }
+        }
+    };
+    tracing::info!("all containers exited");
+    Ok(parse_containers_wait_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+#[tracing::instrument(err, skip(ctx, containers))]
+async fn machine_containers_inspect(
+    ctx: &Context,
+    machine: Machine,
+    containers: &[ScheduledContainer],
+) -> Result<HashMap<String, manifest::ContainerInspect>> {
+    let images = containers
+        .iter()
+        .map(|c| c.image.as_str())
+        .collect::<HashSet<_>>();
+    let script = manifest::inspect_script(
+        ctx.runtime,
+        containers.iter().map(|c| c.name.as_str()),
+        images,
+    );
+    let output = machine_run_script(ctx, machine, &script).await?;
+    Ok(manifest::parse_inspect_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+fn machine_containers_stop_script(
+    runtime: ContainerRuntime,
+    containers: &[ScheduledContainer],
+) -> String {
+    let mut script = String::default();
+    script.push_str(&format!("{runtime} stop -t 10"));
+    for container in containers {
+        script.push(' ');
+        script.push_str(&container.name);
+    }
+    script.push_str(" || true\n");
+    script
+}
+
+#[tracing::instrument(ret, err, skip(ctx, containers))]
+async fn machine_containers_stop(
+    ctx: &Context,
+    machine: Machine,
+    containers: &[ScheduledContainer],
+) -> Result<()> {
+    if containers.is_empty() {
+        return Ok(());
+    }
+    tracing::info!("stopping {} containers", containers.len());
+    let script = machine_containers_stop_script(ctx.runtime, containers);
+    machine_run_script(ctx, machine, &script).await?;
+    tracing::info!("containers stopped");
+    Ok(())
+}
+
+fn machine_containers_save_logs_script(
+    runtime: ContainerRuntime,
+    containers: &[ScheduledContainer],
+) -> String {
+    let mut script = String::default();
+    script.push_str("set -e\n");
+    script.push_str(&stats::host_metrics_stop_script(runtime));
+    script.push_str(&pcap::capture_stop_script(runtime));
+    script.push_str("mkdir -p /tmp/oar-p2p-logs\n");
+    script.push_str("find /tmp/oar-p2p-logs -maxdepth 1 -type f -delete\n");
+    script.push_str("rm -rf /tmp/oar-p2p-logs/artifacts\n");
+    for container in containers {
+        let name = &container.name;
+        script.push_str(&format!("{runtime} logs {name} 1> /tmp/oar-p2p-logs/{name}.stdout 2> /tmp/oar-p2p-logs/{name}.stderr\n"));
+        for artifact in container.artifacts.iter() {
+            script.push_str(&artifact_copy_script(runtime, name, artifact));
+        }
+    }
+    script.push_str("exit 0\n");
+    script
+}
+
+/// script that copies an artifact out of a container to `/tmp/oar-p2p-logs/artifacts/<name>/`,
+/// keeping its path inside the container. a missing artifact only prints a warning.
+///
+/// `docker cp` does not expand globs, so for a pattern the directory above the first component
+/// with a glob is copied and the pattern is matched against that copy.
+fn artifact_copy_script(runtime: ContainerRuntime, name: &str, artifact: &str) -> String {
+    let dest = format!("/tmp/oar-p2p-logs/artifacts/{name}");
+    let components = artifact
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>();
+    let mut script = String::default();
+    match components.iter().position(|c| c.contains(['*', '?', '['])) {
+        None => {
+            let parent = components[..components.len().saturating_sub(1)].join("/");
+            let path = components.join("/");
+            script.push_str(&format!(
+                "mkdir -p {dest}/{parent} && {runtime} cp {name}:/{path} {dest}/{path} || echo Artifact /{path} not found in {name} >&2\n"
+            ));
+        }
+        Some(idx) => {
+            let dir = components[..idx].join("/");
+            let pattern = components[idx..].join("/");
+            script.push_str("tmp=$(mktemp -d)\n");
+            script.push_str(&format!("if {runtime} cp {name}:/{dir}/. $tmp/ ; then\n"));
+            script.push_str(&format!("\tmkdir -p {dest}/{dir}\n"));
+            script.push_str(&format!(
+                "\t(cd $tmp && shopt -s nullglob && for f in {pattern} ; do cp -r --parents \"$f\" {dest}/{dir}/ ; done) || true\n"
+            ));
+            script.push_str("else\n");
+            script.push_str(&format!(
+                "\techo Artifact directory /{dir} not found in {name} >&2\n"
+            ));
+            script.push_str("fi\n");
+            script.push_str("rm -rf $tmp\n");
+        }
+    }
+    script
+}
+
+/// the captures that each machine has to run for the `--pcap` targets.
+fn pcap_captures(
+    targets: &[pcap::PcapTarget],
+    containers: &[ScheduledContainer],
+) -> Result<BTreeMap<Machine, Vec<pcap::Capture>>> {
+    let mut captures = BTreeMap::<Machine, Vec<pcap::Capture>>::default();
+    for target in targets {
+        match target {
+            pcap::PcapTarget::Container(name) => {
+                let container = containers.iter().find(|c| &c.name == name).ok_or_else(|| {
+                    eyre::eyre!("cannot capture packets of unknown container {name}")
+                })?;
+                captures
+                    .entry(container.machine)
+                    .or_default()
+                    .push(pcap::Capture::host(name.clone(), container.address));
+            }
+            pcap::PcapTarget::Pair(a, b) => {
+                let mut machines = vec![machine_from_addr(*a)?, machine_from_addr(*b)?];
+                machines.dedup();
+                for machine in machines {
+                    captures
+                        .entry(machine)
+                        .or_default()
+                        .push(pcap::Capture::pair(*a, *b));
+                }
+            }
+        }
+    }
+    // captures of the same traffic would write to the same files
+    captures.values_mut().for_each(|c| c.dedup());
+    Ok(captures)
+}
+
+#[tracing::instrument(ret, err, skip(ctx, captures))]
+async fn machine_pcap_start(
+    ctx: &Context,
+    machine: Machine,
+    captures: &[pcap::Capture],
+    rotate_size_mb: u64,
+    rotate_count: u64,
+) -> Result<()> {
+    let script = pcap::capture_start_script(
+        ctx.runtime,
+        CONTAINER_IMAGE_NAME,
+        captures,
+        rotate_size_mb,
+        rotate_count,
+    );
+    machine_run_script(ctx, machine, &script).await?;
+    Ok(())
+}
+
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_host_metrics_start(
+    ctx: &Context,
+    machine: Machine,
+    interval: Duration,
+) -> Result<()> {
+    let script =
+        stats::host_metrics_start_script(ctx.runtime, CONTAINER_IMAGE_NAME, machine, interval);
+    machine_run_script(ctx, machine, &script).await?;
+    Ok(())
+}
+
+#[tracing::instrument(ret, err, skip(ctx, containers))]
+async fn machine_containers_save_logs(
+    ctx: &Context,
+    machine: Machine,
+    containers: &[ScheduledContainer],
+) -> Result<()> {
+    tracing::info!("saving logs from {} containers", containers.len());
+    let script = machine_containers_save_logs_script(ctx.runtime, containers);
+    machine_run_script(ctx, machine, &script).await?;
+    tracing::info!("logs saved");
+    Ok(())
+}
+
+fn logs_archive_name(machine: Machine) -> String {
+    format!("logs-{machine}.tar.gz")
+}
+
+fn machine_logs_archive_script(machine: Machine) -> String {
+    let archive = logs_archive_name(machine);
+    format!("set -e\ntar -czf /tmp/{archive} -C /tmp/oar-p2p-logs .\n")
+}
+
+/// archive the logs directory on the machine and download the archive to the output directory.
+#[tracing::instrument(ret, err, skip(ctx, transfer))]
+async fn machine_copy_logs_archive(
+    ctx: &Context,
+    machine: Machine,
+    output_dir: &Path,
+    transfer: &LogTransferArgs,
+) -> Result<()> {
+    tracing::info!("archiving container logs on machine");
+    machine_run_script(ctx, machine, &machine_logs_archive_script(machine)).await?;
+    tracing::info!("copying logs archive from machine");
+    let archive = format!("/tmp/{}", logs_archive_name(machine));
+    machine_download(ctx, machine, &archive, output_dir, transfer).await?;
+    tracing::info!("logs archive finished copying");
+    Ok(())
+}
+
+fn machine_logs_stream_script(compression: Option<compression::Compression>) -> String {
+    match compression {
+        Some(compression) => format!(
+            "set -o pipefail\ntar -C /tmp/oar-p2p-logs -cf - . | {}\n",
+            compression.compress_command()
+        ),
+        None => String::from("tar -C /tmp/oar-p2p-logs -cf - .\n"),
+    }
+}
+
+/// stream the logs directory of the machine as a tar, compressed unless `compression` is `None`,
+/// over the ssh connection and extract it into the output directory.
+///
+/// the whole directory is a single stream, so many small files don't cost a round trip each
+/// through the frontend.
+#[tracing::instrument(ret, err, skip(ctx, transfer))]
+async fn machine_copy_logs_stream(
+    ctx: &Context,
+    machine: Machine,
+    output_dir: &Path,
+    transfer: &LogTransferArgs,
+    compression: Option<compression::Compression>,
+) -> Result<()> {
+    tracing::info!("streaming container logs from machine");
+    let script = machine_logs_stream_script(compression);
+    let mut attempt = 0;
+    loop {
+        let result = machine_stream_extract(ctx, machine, &script, compression, output_dir).await;
+        match result {
+            Ok(()) => break,
+            Err(err) if attempt < transfer.transfer_retries => {
+                attempt += 1;
+                tracing::warn!(
+                    "copy from {machine} failed, retrying ({attempt}/{}): {err}",
+                    transfer.transfer_retries
+                );
+                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt.min(5)))).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    tracing::info!("logs finished copying");
+    Ok(())
+}
+
+/// run `script` on the machine and extract its output, a tar compressed with `compression`, into
+/// `output_dir`.
+async fn machine_stream_extract(
+    ctx: &Context,
+    machine: Machine,
+    script: &str,
+    compression: Option<compression::Compression>,
+    output_dir: &Path,
+) -> Result<()> {
+    if let Some(session) = machine_native_session(ctx, machine).await {
+        match machine_native_stream_extract(&session, script, compression, output_dir).await {
+            Ok(()) => return Ok(()),
+            Err(err) => machine_native_fallback(ctx, machine, err)?,
+        }
     }
+    machine_ssh_master(ctx, machine).await;
+    let mut arguments = machine_ssh_arguments(ctx, machine)?;
+    arguments.push("bash");
+    let mut remote = Command::new(arguments[0])
+        .args(&arguments[1..])
+        .process_group(0)
+        .kill_on_drop(true)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .context("spawning process")?;
+    let remote_stdout: std::process::Stdio =
+        remote.stdout.take().expect("stdout is piped").try_into()?;
+    let local = tar_extract_command(compression, output_dir)
+        .stdin(remote_stdout)
+        .spawn()
+        .context("spawning tar")?;
 
-    for (idx, container) in containers.iter().enumerate() {
-        // remove the start signal file if it exists
-        script.push_str("mkdir -p /tmp/oar-p2p-signal\n");
-        script.push_str("rm /tmp/oar-p2p-signal/start 2>/dev/null || true\n");
+    let mut remote_stdin = remote.stdin.take().expect("stdin is piped");
+    remote_stdin
+        .write_all(script.as_bytes())
+        .await
+        .context("writing stdin")?;
+    drop(remote_stdin);
 
-        script.push_str("docker create \\\n");
-        script.push_str("\t--pull=never \\\n");
-        script.push_str("\t--network=host \\\n");
-        script.push_str("\t--restart=no \\\n");
-        script.push_str("\t--volume /tmp/oar-p2p-signal:/oar-p2p\\\n");
-        script.push_str(&format!("\t--name {} \\\n", container.name));
-        for (key, val) in container.variables.iter() {
-            script.push_str("\t-e ");
-            script.push_str(key);
-            script.push('=');
-            script.push('\'');
-            script.push_str(val);
-            script.push('\'');
-            script.push_str(" \\\n");
+    let (remote, local) = tokio::try_join!(remote.wait_with_output(), local.wait_with_output())?;
+    for (name, output) in [("remote", &remote), ("tar", &local)] {
+        if !output.status.success() {
+            let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf-8>");
+            tracing::error!("{name} stderr:\n{stderr}");
         }
-        script.push('\t');
-        script.push_str(&container.image);
-        script.push_str(" &\n");
-        script.push_str(&format!("pid_{idx}=$!\n\n"));
     }
+    remote.exit_ok()?;
+    local.exit_ok()?;
+    Ok(())
+}
 
-    for (idx, container) in containers.iter().enumerate() {
-        let name = &container.name;
-        script.push_str(&format!(
-            "wait $pid_{idx} || {{ echo Failed to create container {name} ; exit 1 ; }}\n"
-        ));
+/// like [`machine_stream_extract`] but over the embedded ssh client, the output is written to
+/// the local tar as it arrives.
+async fn machine_native_stream_extract(
+    session: &native_ssh::Session,
+    script: &str,
+    compression: Option<compression::Compression>,
+    output_dir: &Path,
+) -> Result<()> {
+    let mut local = tar_extract_command(compression, output_dir)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .context("spawning tar")?;
+    let mut local_stdin = local.stdin.take().expect("stdin is piped");
+    let mut remote_stderr = Vec::default();
+    let remote = session
+        .exec(
+            "bash",
+            script.as_bytes(),
+            &mut local_stdin,
+            &mut remote_stderr,
+        )
+        .await;
+    drop(local_stdin);
+    let local = local.wait_with_output().await.context("waiting for tar")?;
+    let remote = remote?;
+    if !remote.success() {
+        let stderr = std::str::from_utf8(&remote_stderr).unwrap_or("<invalid utf-8>");
+        tracing::error!("remote stderr:\n{stderr}");
     }
+    if !local.status.success() {
+        let stderr = std::str::from_utf8(&local.stderr).unwrap_or("<invalid utf-8>");
+        tracing::error!("tar stderr:\n{stderr}");
+    }
+    remote.exit_ok()?;
+    local.exit_ok()?;
+    Ok(())
+}
 
-    script
+/// the local tar that extracts a tar compressed with `compression`, read from its stdin, into
+/// `output_dir`.
+fn tar_extract_command(
+    compression: Option<compression::Compression>,
+    output_dir: &Path,
+) -> Command {
+    let mut command = Command::new("tar");
+    command
+        .process_group(0)
+        .kill_on_drop(true)
+        .args(compression.map(|c| c.tar_flag()))
+        .arg("-xf")
+        .arg("-")
+        .arg("-C")
+        .arg(output_dir)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+    command
 }
 
-#[tracing::instrument(ret, err, skip(ctx, containers))]
-async fn machine_create_containers(
-    ctx: &Context,
-    machine: Machine,
-    containers: &[ScheduledContainer],
-) -> Result<()> {
-    tracing::info!("creating {} containers", containers.len());
-    let script = machine_containers_create_script(containers);
-    machine_run_script(ctx, machine, &script).await?;
-    tracing::info!("containers created");
+/// extract the logs archive of every machine and pack them into a single `results.tar.zst`,
+/// removing the per machine archives.
+#[tracing::instrument(ret, err, skip(output_dir))]
+async fn merge_logs_archives(machines: &[Machine], output_dir: &Path) -> Result<()> {
+    tracing::info!("merging logs archives");
+    let staging = output_dir.join(".results");
+    if tokio::fs::try_exists(&staging).await? {
+        tokio::fs::remove_dir_all(&staging).await?;
+    }
+    tokio::fs::create_dir_all(&staging).await?;
+    for &machine in machines {
+        let archive = output_dir.join(logs_archive_name(machine));
+        let mut command = Command::new("tar");
+        command.arg("-xzf").arg(&archive).arg("-C").arg(&staging);
+        command_run(command)
+            .await
+            .with_context(|| format!("extracting {}", archive.display()))?;
+    }
+    let mut command = Command::new("tar");
+    command
+        .arg("--zstd")
+        .arg("-cf")
+        .arg(output_dir.join("results.tar.zst"))
+        .arg("-C")
+        .arg(&staging)
+        .arg(".");
+    command_run(command)
+        .await
+        .context("creating results.tar.zst")?;
+    tokio::fs::remove_dir_all(&staging).await?;
+    for &machine in machines {
+        tokio::fs::remove_file(output_dir.join(logs_archive_name(machine))).await?;
+    }
+    tracing::info!("logs archives merged");
     Ok(())
 }
 
-#[tracing::instrument(ret, err, skip(ctx))]
-async fn machine_start_containers(ctx: &Context, machine: Machine) -> Result<()> {
-    tracing::info!("starting all containers");
-    machine_run_script(
-        ctx,
-        machine,
-        "docker container ls -aq | xargs docker container start",
-    )
-    .await?;
-    tracing::info!("all containers started");
+/// run a local command, logging its output if it fails.
+async fn command_run(mut command: Command) -> Result<()> {
+    let output = command.process_group(0).output().await?;
+    if !output.status.success() {
+        let stdout = std::str::from_utf8(&output.stdout).unwrap_or("<invalid utf-8>");
+        let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf-8>");
+        tracing::error!("stdout:\n{stdout}");
+        tracing::error!("stderr:\n{stderr}");
+    }
+    output.exit_ok()?;
     Ok(())
 }
 
-#[tracing::instrument(ret, err, skip(ctx))]
-async fn machine_signal_containers(
+/// copy `remote_path` from the machine into `local_path` using rsync, retrying failed copies.
+async fn machine_download(
     ctx: &Context,
     machine: Machine,
-    signal: &Signal,
-    timestamp: u64,
+    remote_path: &str,
+    local_path: &Path,
+    transfer: &LogTransferArgs,
 ) -> Result<()> {
-    tracing::info!("signaling containers");
-    machine_run_script(
+    if let Some(session) = machine_native_session(ctx, machine).await {
+        let what = format!("copy of {remote_path} from {machine}");
+        let result = native_transfer_with_retries(transfer, &what, || {
+            machine_native_download(&session, remote_path, local_path)
+        })
+        .await;
+        match result {
+            Ok(()) => return Ok(()),
+            Err(err) => machine_native_fallback(ctx, machine, err)
+                .with_context(|| format!("copying {remote_path} from {machine}"))?,
+        }
+    }
+    rsync(
         ctx,
-        machine,
-        &format!("echo -n {timestamp} > /tmp/oar-p2p-signal/{signal}.tmp ; mv /tmp/oar-p2p-signal/{signal}.tmp /tmp/oar-p2p-signal/{signal}"),
+        &["-avz", "--partial"],
+        &format!("{}:{remote_path}", machine.hostname()),
+        &local_path.display().to_string(),
+        transfer,
     )
-    .await?;
-    tracing::info!("containers signaled");
-    Ok(())
+    .await
+    .with_context(|| format!("copying {remote_path} from {machine}"))
 }
 
-fn machine_containers_wait_script(containers: &[ScheduledContainer]) -> String {
-    let mut script = String::default();
-    for container in containers {
-        let name = &container.name;
-        script.push_str(&format!(
-            "if [ \"$(docker wait {name})\" -ne \"0\" ] ; then\n"
-        ));
-        script.push_str(&format!("\techo Container {name} failed\n"));
-        script.push_str(&format!("\tdocker logs {name} 2>&1 | tail -n 500\n"));
-        script.push_str("\texit 1\n");
-        script.push_str("fi\n\n");
-    }
-    script.push_str("exit 0\n");
-    script
+/// like [`machine_download`] but over the embedded ssh client, the file is copied in full into
+/// `local_dir` as it arrives.
+async fn machine_native_download(
+    session: &native_ssh::Session,
+    remote_path: &str,
+    local_dir: &Path,
+) -> Result<()> {
+    let name = Path::new(remote_path)
+        .file_name()
+        .ok_or_else(|| eyre::eyre!("{remote_path} is not a file"))?;
+    let local_path = local_dir.join(name);
+    let mut file = tokio::fs::File::create(&local_path)
+        .await
+        .with_context(|| format!("creating {}", local_path.display()))?;
+    let mut stderr = Vec::default();
+    let status = session
+        .exec(
+            &format!("cat {}", shell::quote(remote_path)),
+            tokio::io::empty(),
+            &mut file,
+            &mut stderr,
+        )
+        .await?;
+    status
+        .exit_ok()
+        .map_err(|_| eyre::eyre!("{}", String::from_utf8_lossy(&stderr).trim()))
 }
 
-#[tracing::instrument(ret, err, skip(ctx, containers))]
-async fn machine_containers_wait(
-    ctx: &Context,
-    machine: Machine,
-    containers: &[ScheduledContainer],
-) -> Result<()> {
-    tracing::info!("waiting for {} containers to exit", containers.len());
-    let script = machine_containers_wait_script(containers);
-    let wait_timeout = Duration::from_secs(60);
-    let retry_seconds = 5;
-    let mut retries = 10;
+/// run a copy over the embedded ssh client, retrying it if it fails. a connection lost before the
+/// copy started is not retried, the copy is then made with the ssh binary.
+async fn native_transfer_with_retries<F, Fut>(
+    transfer: &LogTransferArgs,
+    what: &str,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    let mut attempt = 0;
     loop {
-        let fut = tokio::time::timeout(wait_timeout, machine_run_script(ctx, machine, &script));
-        match fut.await {
-            Ok(Ok(_)) => break,
-            Ok(Err(err)) => {
-                tracing::debug!("failed to run script: {err}, {retries} left");
-                if retries == 0 {
-                    return Err(err);
-                }
-                retries -= 1;
-                tracing::debug!("waiting {retry_seconds} before retrying...");
-                tokio::time::sleep(Duration::from_secs(retry_seconds)).await;
-                tracing::debug!("retrying");
-            }
-            Err(_) => {
-                tracing::debug!("wait timeout, retrying...");
-                tokio::time::sleep(Duration::from_secs(retry_seconds)).await;
+        match f().await {
+            Ok(()) => return Ok(()),
+            Err(err) if err.is::<native_ssh::ConnectionLost>() => return Err(err),
+            Err(err) if attempt < transfer.transfer_retries => {
+                attempt += 1;
+                tracing::warn!(
+                    "{what} failed, retrying ({attempt}/{}): {err:#}",
+                    transfer.transfer_retries
+                );
+                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt.min(5)))).await;
             }
+            Err(err) => return Err(err),
         }
     }
-    tracing::info!("all containers exited");
-    Ok(())
 }
 
-fn machine_containers_save_logs_script(containers: &[ScheduledContainer]) -> String {
-    let mut script = String::default();
-    script.push_str("set -e\n");
-    script.push_str("mkdir -p /tmp/oar-p2p-logs\n");
-    script.push_str("find /tmp/oar-p2p-logs -maxdepth 1 -type f -delete\n");
-    for container in containers {
-        let name = &container.name;
-        script.push_str(&format!("docker logs {name} 1> /tmp/oar-p2p-logs/{name}.stdout 2> /tmp/oar-p2p-logs/{name}.stderr\n"));
-    }
-    script.push_str("exit 0\n");
-    script
+/// the unique local inputs of the containers, sorted.
+fn containers_inputs(containers: &[ScheduledContainer]) -> Vec<PathBuf> {
+    let mut inputs = containers
+        .iter()
+        .flat_map(|c| c.inputs.iter().map(|(local, _)| local.clone()))
+        .collect::<Vec<_>>();
+    inputs.sort();
+    inputs.dedup();
+    inputs
 }
 
-#[tracing::instrument(ret, err, skip(ctx, containers))]
-async fn machine_containers_save_logs(
+/// the directory on the machines that an input, given by its absolute local path, is copied
+/// into. the input ends up at [`input_remote_path`].
+fn input_remote_dir(local: &Path) -> String {
+    let id = local
+        .display()
+        .to_string()
+        .trim_start_matches('/')
+        .replace('/', "_");
+    format!("{INPUTS_DIR}/{id}")
+}
+
+/// the path on the machines of an input, given by its absolute local path.
+fn input_remote_path(local: &Path) -> String {
+    let name = local
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!("{}/{name}", input_remote_dir(local))
+}
+
+/// copy the inputs to the machine. files that are already there with the same checksum are not
+/// copied again.
+#[tracing::instrument(ret, err, skip(ctx, inputs, transfer))]
+async fn machine_push_inputs(
     ctx: &Context,
     machine: Machine,
-    containers: &[ScheduledContainer],
+    inputs: &[PathBuf],
+    transfer: &LogTransferArgs,
 ) -> Result<()> {
-    tracing::info!("saving logs from {} containers", containers.len());
-    let script = machine_containers_save_logs_script(containers);
-    machine_run_script(ctx, machine, &script).await?;
-    tracing::info!("logs saved");
+    if inputs.is_empty() {
+        return Ok(());
+    }
+    tracing::info!("copying {} inputs", inputs.len());
+    let dirs = inputs
+        .iter()
+        .map(|local| shell::quote(&input_remote_dir(local)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    machine_run_script(ctx, machine, &format!("mkdir -p {dirs}\n")).await?;
+    for local in inputs {
+        if !local.exists() {
+            return Err(eyre::eyre!("input {} does not exist", local.display()));
+        }
+        if let Some(session) = machine_native_session(ctx, machine).await {
+            let what = format!("copy of input {} to {machine}", local.display());
+            let result = native_transfer_with_retries(transfer, &what, || {
+                machine_native_push_input(&session, local)
+            })
+            .await;
+            match result {
+                Ok(()) => continue,
+                Err(err) => machine_native_fallback(ctx, machine, err)
+                    .with_context(|| format!("copying input {} to {machine}", local.display()))?,
+            }
+        }
+        rsync(
+            ctx,
+            &["-az", "--partial", "--checksum", "--delete"],
+            &local.display().to_string(),
+            &format!("{}:{}/", machine.hostname(), input_remote_dir(local)),
+            transfer,
+        )
+        .await
+        .with_context(|| format!("copying input {} to {machine}", local.display()))?;
+    }
+    tracing::info!("inputs copied");
     Ok(())
 }
 
-#[tracing::instrument(ret, err, skip(ctx))]
-async fn machine_copy_logs_dir(ctx: &Context, machine: Machine, output_dir: &Path) -> Result<()> {
-    tracing::info!("copying container logs from machine");
+/// like the rsync of [`machine_push_inputs`] but over the embedded ssh client. the input is
+/// streamed as a tar and always copied in full, replacing the previous copy on the machine.
+async fn machine_native_push_input(session: &native_ssh::Session, local: &Path) -> Result<()> {
+    let (Some(parent), Some(name)) = (local.parent(), local.file_name()) else {
+        return Err(eyre::eyre!("input {} has no file name", local.display()));
+    };
+    let mut tar = Command::new("tar")
+        .process_group(0)
+        .kill_on_drop(true)
+        .arg("-cf")
+        .arg("-")
+        .arg("-C")
+        .arg(parent)
+        .arg(name)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .context("spawning tar")?;
+    let tar_stdout = tar.stdout.take().expect("stdout is piped");
+    let dir = shell::quote(&input_remote_dir(local));
+    let command = format!(
+        "rm -rf {path} && mkdir -p {dir} && tar -xf - -C {dir}",
+        path = shell::quote(&input_remote_path(local)),
+    );
+    let mut stderr = Vec::default();
+    let remote = session
+        .exec(&command, tar_stdout, &mut tokio::io::sink(), &mut stderr)
+        .await;
+    let tar = tar.wait_with_output().await.context("waiting for tar")?;
+    let remote = remote?;
+    tar.status
+        .exit_ok()
+        .map_err(|_| eyre::eyre!("tar: {}", String::from_utf8_lossy(&tar.stderr).trim()))?;
+    remote
+        .exit_ok()
+        .map_err(|_| eyre::eyre!("{}", String::from_utf8_lossy(&stderr).trim()))
+}
 
+/// run rsync between this machine and a job machine, retrying failed copies.
+async fn rsync(
+    ctx: &Context,
+    flags: &[&str],
+    source: &str,
+    destination: &str,
+    transfer: &LogTransferArgs,
+) -> Result<()> {
     let mut rsync_rsh =
         "ssh -o ConnectionAttempts=3 -o StrictHostKeyChecking=no -o UserKnownHostsFile=/dev/null"
             .to_string();
@@ -698,31 +6952,53 @@ async fn machine_copy_logs_dir(ctx: &Context, machine: Machine, output_dir: &Pat
         rsync_rsh += &format!(" -J {}", ctx.frontend_hostname()?);
     }
 
-    let output = Command::new("rsync")
-        .env("RSYNC_RSH", rsync_rsh)
-        .arg("-avz")
-        .arg(format!("{}:/tmp/oar-p2p-logs/", machine.hostname()))
-        .arg(output_dir.display().to_string())
-        .output()
-        .await?;
-    let stdout = std::str::from_utf8(&output.stdout).unwrap_or("<invalid utf-8>");
-    let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf-8>");
-    if output.status.success() {
-        tracing::trace!("rsync stdout:\n{stdout}");
-        tracing::trace!("rsync stderr:\n{stderr}");
-    } else {
+    let mut attempt = 0;
+    loop {
+        let output = Command::new("rsync")
+            .process_group(0)
+            .env("RSYNC_RSH", &rsync_rsh)
+            .args(flags)
+            .arg(source)
+            .arg(destination)
+            .output()
+            .await?;
+        let stdout = std::str::from_utf8(&output.stdout).unwrap_or("<invalid utf-8>");
+        let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf-8>");
+        if output.status.success() {
+            tracing::trace!("rsync stdout:\n{stdout}");
+            tracing::trace!("rsync stderr:\n{stderr}");
+            return Ok(());
+        }
         tracing::error!("rsync stdout:\n{stdout}");
         tracing::error!("rsync stderr:\n{stderr}");
+        if attempt >= transfer.transfer_retries {
+            output.exit_ok()?;
+        }
+        attempt += 1;
+        tracing::warn!(
+            "copy from {source} to {destination} failed, retrying ({attempt}/{})",
+            transfer.transfer_retries
+        );
+        tokio::time::sleep(Duration::from_secs(2u64.pow(attempt.min(5)))).await;
     }
-    output.exit_ok()?;
-    tracing::info!("logs finished copying");
-    Ok(())
 }
 
 #[tracing::instrument(ret, err, skip(ctx))]
 async fn machine_containers_clean(ctx: &Context, machine: Machine) -> Result<()> {
     tracing::info!("removing all containers...");
-    machine_run_script(ctx, machine, "docker ps -aq | xargs -r docker rm -f").await?;
+    let runtime = ctx.runtime;
+    // the registry mirror and the monitoring outlive runs and are only removed by their own
+    // `down` commands
+    let kept = [registry::REGISTRY_CONTAINER_NAME, agent::CONTAINER_NAME]
+        .into_iter()
+        .chain(monitor::CONTAINER_NAMES.iter().copied())
+        .collect::<Vec<_>>()
+        .join("|");
+    let script = format!(
+        "{runtime} ps -a --format '{{{{.ID}}}} {{{{.Names}}}}' | grep -vE ' ({kept})$' | cut -d' ' -f1 | xargs -r {runtime} rm -f\nrm -rf {}",
+        churn::CHURN_MARKER_DIR
+    );
+    machine_run_script(ctx, machine, &script).await?;
     tracing::info!("all containers removed");
     Ok(())
 }
@@ -730,7 +7006,7 @@ async fn machine_containers_clean(ctx: &Context, machine: Machine) -> Result<()>
 #[tracing::instrument(ret, err, skip_all)]
 async fn machines_clean(ctx: &Context, machines: &[Machine]) -> Result<()> {
     tracing::info!("cleaning machines: {machines:?}");
-    machine::for_each(machines, |machine| {
+    machine::for_each_progress("cleaning machines", machines, |machine| {
         let ctx = ctx.clone();
         async move { machine_clean(&ctx, machine).await }
     })
@@ -740,14 +7016,17 @@ async fn machines_clean(ctx: &Context, machines: &[Machine]) -> Result<()> {
 
 #[tracing::instrument(ret, err, skip_all)]
 async fn machines_containers_clean(ctx: &Context, machines: &[Machine]) -> Result<()> {
-    machine::for_each(machines, |machine| machine_containers_clean(ctx, machine)).await?;
+    machine::for_each_progress("removing containers", machines, |machine| {
+        machine_containers_clean(ctx, machine)
+    })
+    .await?;
     Ok(())
 }
 
 #[tracing::instrument(ret, err, skip_all)]
 async fn machines_net_container_build(ctx: &Context, machines: &[Machine]) -> Result<()> {
     tracing::info!("building networking container for machines: {machines:?}");
-    machine::for_each(machines, |machine| {
+    machine::for_each_progress("building networking container", machines, |machine| {
         let ctx = ctx.clone();
         async move { machine_net_container_build(&ctx, machine).await }
     })
@@ -756,18 +7035,98 @@ async fn machines_net_container_build(ctx: &Context, machines: &[Machine]) -> Re
 }
 
 #[tracing::instrument(ret, err, skip_all)]
-async fn machines_configure(ctx: &Context, configs: &[MachineConfig]) -> Result<()> {
+async fn machines_interface_check(ctx: &Context, machines: &[Machine]) -> Result<()> {
+    tracing::info!("checking network interfaces");
+    machine::for_each_progress("checking network interfaces", machines, |machine| {
+        machine_interface_check(ctx, machine)
+    })
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(ret, err, skip_all)]
+async fn machines_configure(
+    ctx: &Context,
+    configs: &[MachineConfig],
+    nft_chunk_size: usize,
+) -> Result<()> {
     tracing::info!("configuring machines");
     let machines = configs.iter().map(|c| &c.machine);
-    machine::for_each(machines, |machine| {
+    machine::for_each_progress("configuring machines", machines, |machine| {
         let ctx = ctx.clone();
         let config = configs.iter().find(|c| c.machine == machine).unwrap();
-        async move { machine_configure(&ctx, config).await }
+        async move { machine_configure(&ctx, config, nft_chunk_size).await }
+    })
+    .await?;
+    Ok(())
+}
+
+/// fail, listing them, if any machine has tc qdiscs that cleaning the network would delete but
+/// that were not created by oar-p2p.
+#[tracing::instrument(ret, err, skip_all)]
+async fn machines_foreign_state_check(ctx: &Context, machines: &[Machine]) -> Result<()> {
+    tracing::info!("checking for network state not created by oar-p2p");
+    let foreign = machine::for_each(machines, |machine| async move {
+        let script = foreign_state::detect_script(machine.interface());
+        let output = machine_net_container_run_script(ctx, machine, &script).await?;
+        Ok(foreign_state::parse(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    })
+    .await?;
+    let mut report = String::default();
+    for (machine, qdiscs) in foreign {
+        for qdisc in qdiscs {
+            report.push_str(&format!("\n  {machine} {qdisc}"));
+        }
+    }
+    if report.is_empty() {
+        return Ok(());
+    }
+    Err(eyre::eyre!(
+        "found tc qdiscs that were not created by oar-p2p, they might belong to another experiment. pass --clobber to remove them anyway:{report}"
+    ))
+}
+
+const STATE_SNAPSHOT_SEPARATOR: &str = "----- oar-p2p tc -----";
+
+#[tracing::instrument(ret, err, skip(ctx, dir))]
+async fn machines_state_snapshot(
+    ctx: &Context,
+    machines: &[Machine],
+    dir: &Path,
+    phase: &str,
+) -> Result<()> {
+    tracing::info!("saving {phase} state snapshots to {}", dir.display());
+    tokio::fs::create_dir_all(dir)
+        .await
+        .context("creating snapshot directory")?;
+    machine::for_each(machines, |machine| async move {
+        let (nft, tc) = machine_state_snapshot(ctx, machine).await?;
+        tokio::fs::write(dir.join(format!("{machine}.{phase}.nft")), nft)
+            .await
+            .context("writing nft snapshot")?;
+        tokio::fs::write(dir.join(format!("{machine}.{phase}.tc")), tc)
+            .await
+            .context("writing tc snapshot")?;
+        Ok(())
     })
     .await?;
     Ok(())
 }
 
+/// capture the output of `nft list ruleset` and `tc -s qdisc show` on the machine.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_state_snapshot(ctx: &Context, machine: Machine) -> Result<(String, String)> {
+    let script = format!("nft list ruleset\necho '{STATE_SNAPSHOT_SEPARATOR}'\ntc -s qdisc show\n");
+    let output = machine_net_container_run_script(ctx, machine, &script).await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (nft, tc) = stdout
+        .split_once(&format!("{STATE_SNAPSHOT_SEPARATOR}\n"))
+        .ok_or_else(|| eyre::eyre!("invalid state snapshot output from {machine}"))?;
+    Ok((nft.to_string(), tc.to_string()))
+}
+
 #[tracing::instrument(err, skip(ctx))]
 async fn machine_list_addresses(ctx: &Context, machine: Machine) -> Result<Vec<Ipv4Addr>> {
     tracing::info!("listing machine addresses");
@@ -785,14 +7144,47 @@ async fn machine_list_addresses(ctx: &Context, machine: Machine) -> Result<Vec<I
     Ok(addresses)
 }
 
-#[tracing::instrument(ret, err, level = tracing::Level::TRACE)]
-async fn machine_run(
-    ctx: &Context,
-    machine: Machine,
-    args: &[&str],
-    stdin: Option<&str>,
-) -> Result<Output> {
-    let ssh_common = &[
+/// total memory of the machine, as reported by `/proc/meminfo`.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_memory_total(ctx: &Context, machine: Machine) -> Result<schedule::MemorySize> {
+    let output = machine_run_script(
+        ctx,
+        machine,
+        "awk '/^MemTotal:/ { print $2 }' /proc/meminfo",
+    )
+    .await?;
+    let kibibytes = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u64>()
+        .with_context(|| format!("parsing total memory of {machine}"))?;
+    Ok(schedule::MemorySize(kibibytes * 1024))
+}
+
+/// the fingerprint of the configuration applied to the machine, if the network is configured.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_network_fingerprint(ctx: &Context, machine: Machine) -> Result<Option<String>> {
+    let script = fingerprint::read_script(ctx.runtime);
+    let output = machine_run_script(ctx, machine, &script).await?;
+    Ok(fingerprint::parse_read_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// check if the oar-p2p nft table exists on the machine.
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_network_deployed(ctx: &Context, machine: Machine) -> Result<bool> {
+    // if the networking image does not exist then the network was never configured
+    let runtime = ctx.runtime;
+    let script = format!(
+        "if {runtime} image inspect {CONTAINER_IMAGE_NAME} >/dev/null 2>&1 && {runtime} run --rm --net=host --privileged {CONTAINER_IMAGE_NAME} nft list table ip oar-p2p >/dev/null 2>&1 ; then echo deployed ; else echo absent ; fi\n"
+    );
+    let output = machine_run_script(ctx, machine, &script).await?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "deployed")
+}
+
+/// the command used to run a command on the machine, empty if it is the local machine.
+fn machine_ssh_arguments(ctx: &Context, machine: Machine) -> Result<Vec<&str>> {
+    let mut ssh_common = vec![
         "-vvv",
         "-o",
         "ConnectionAttempts=10",
@@ -801,12 +7193,20 @@ async fn machine_run(
         "-o",
         "UserKnownHostsFile=/dev/null",
     ];
+    // commands go through the master connection when its socket exists, and connect on their own
+    // otherwise
+    if let Some(control) = ctx.ssh_control() {
+        ssh_common.extend(["-o", control.control_path_option()]);
+    }
+    for option in ctx.ssh_options() {
+        ssh_common.extend(["-o", option]);
+    }
 
-    let mut arguments = match ctx.node {
+    let arguments = match ctx.node {
         ExecutionNode::Frontend => {
             let mut arguments = Vec::default();
             arguments.push("ssh");
-            arguments.extend(ssh_common);
+            arguments.extend(&ssh_common);
             arguments.push(machine.hostname());
             arguments
         }
@@ -816,7 +7216,7 @@ async fn machine_run(
             } else {
                 let mut arguments = Vec::default();
                 arguments.push("ssh");
-                arguments.extend(ssh_common);
+                arguments.extend(&ssh_common);
                 arguments.push(machine.hostname());
                 arguments
             }
@@ -825,7 +7225,7 @@ async fn machine_run(
             let frontend = ctx.frontend_hostname()?;
             let mut arguments = Vec::default();
             arguments.push("ssh");
-            arguments.extend(ssh_common);
+            arguments.extend(&ssh_common);
             arguments.push("-J");
             arguments.push(frontend);
             if ctx.cluster_username().is_ok() {
@@ -836,14 +7236,115 @@ async fn machine_run(
             arguments
         }
     };
+    Ok(arguments)
+}
+
+/// arguments that start a master connection to `machine` in the background, `None` if commands
+/// run locally or multiplexing is disabled.
+fn machine_ssh_master_arguments(ctx: &Context, machine: Machine) -> Result<Option<Vec<String>>> {
+    if ctx.ssh_control().is_none() {
+        return Ok(None);
+    }
+    let mut arguments = machine_ssh_arguments(ctx, machine)?;
+    let Some(hostname) = arguments.pop() else {
+        return Ok(None);
+    };
+    // the verbose output would keep the master attached to our stderr
+    arguments.retain(|arg| *arg != "-vvv");
+    let persist = format!("ControlPersist={}", ssh::CONTROL_PERSIST);
+    let mut arguments = arguments.into_iter().map(String::from).collect::<Vec<_>>();
+    arguments.extend(
+        [
+            "-o",
+            "ControlMaster=yes",
+            "-o",
+            &persist,
+            "-N",
+            "-f",
+            hostname,
+        ]
+        .map(String::from),
+    );
+    Ok(Some(arguments))
+}
+
+/// start the master connection to `machine` if this is the first command sent to it.
+///
+/// failing to start it is not an error, the commands then open their own connections.
+async fn machine_ssh_master(ctx: &Context, machine: Machine) {
+    let Some(control) = ctx.ssh_control() else {
+        return;
+    };
+    let arguments = match machine_ssh_master_arguments(ctx, machine) {
+        Ok(Some(arguments)) => arguments,
+        _ => return,
+    };
+    control
+        .ensure_master(machine, async {
+            tracing::trace!("starting ssh master: {arguments:?}");
+            let status = Command::new(&arguments[0])
+                .args(&arguments[1..])
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .await;
+            match status {
+                Ok(status) if status.success() => true,
+                Ok(status) => {
+                    tracing::warn!("failed to start ssh master connection to {machine}: {status}");
+                    false
+                }
+                Err(err) => {
+                    tracing::warn!("failed to start ssh master connection to {machine}: {err}");
+                    false
+                }
+            }
+        })
+        .await;
+}
+
+/// run a command on the machine, retrying it if it fails with a transient error of the kind
+/// `retry` allows.
+#[tracing::instrument(ret, err, level = tracing::Level::TRACE)]
+async fn machine_run(
+    ctx: &Context,
+    machine: Machine,
+    args: &[&str],
+    stdin: Option<&str>,
+    retry: retry::Retry,
+) -> Result<Output> {
+    let what = format!("command on {machine}");
+    retry::with_backoff(ctx.remote_retries(), &what, retry, || {
+        machine_run_once(ctx, machine, args, stdin)
+    })
+    .await
+}
+
+async fn machine_run_once(
+    ctx: &Context,
+    machine: Machine,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> Result<Output> {
+    // boxed, the russh futures are deep enough to overflow the layout of the commands' futures
+    if let Some(output) = Box::pin(machine_native_run(ctx, machine, args, stdin)).await? {
+        return Ok(output);
+    }
+    machine_ssh_master(ctx, machine).await;
+    let mut arguments = machine_ssh_arguments(ctx, machine)?;
     if args.is_empty() {
         arguments.push("bash");
     }
     arguments.extend(args);
 
     tracing::trace!("running command: {arguments:?}");
+    // a separate process group keeps a ctrl+c in the terminal from killing the remote commands
+    // before `run` gets the chance to clean up, dropping the future still kills the process.
     let mut proc = Command::new(arguments[0])
         .args(&arguments[1..])
+        .process_group(0)
+        .kill_on_drop(true)
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .stdin(std::process::Stdio::piped())
@@ -866,9 +7367,237 @@ async fn machine_run(
     Ok(output)
 }
 
+/// the embedded ssh connection to `machine`. `None` if `--native-ssh` is disabled or the machine
+/// could not be connected to, the ssh binary is used then.
+async fn machine_native_session(
+    ctx: &Context,
+    machine: Machine,
+) -> Option<Arc<native_ssh::Session>> {
+    ctx.native_ssh()?.session(machine).await
+}
+
+/// handle an error of the embedded ssh client. if the connection to the machine was lost before
+/// the command started, the machine is switched to the ssh binary and `Ok` is returned so the
+/// caller runs the command with it.
+fn machine_native_fallback(ctx: &Context, machine: Machine, err: eyre::Report) -> Result<()> {
+    if !err.is::<native_ssh::ConnectionLost>() {
+        return Err(err);
+    }
+    tracing::warn!("{err}, using ssh");
+    if let Some(native) = ctx.native_ssh() {
+        native.disable(machine);
+    }
+    Ok(())
+}
+
+/// like [`machine_run_once`] but over the embedded ssh client. `None` if it is disabled, the
+/// machine could not be connected to or its connection dropped, the ssh binary is used then.
+async fn machine_native_run(
+    ctx: &Context,
+    machine: Machine,
+    args: &[&str],
+    stdin: Option<&str>,
+) -> Result<Option<Output>> {
+    let Some(session) = machine_native_session(ctx, machine).await else {
+        return Ok(None);
+    };
+    let command = match args {
+        [] => String::from("bash"),
+        args => args.join(" "),
+    };
+    tracing::trace!("running command over the embedded ssh client on {machine}: {command}");
+    match session.run(&command, stdin.map(str::as_bytes)).await {
+        Ok(output) => Ok(Some(output)),
+        Err(err) => machine_native_fallback(ctx, machine, err).map(|()| None),
+    }
+}
+
+/// the oar-p2p binary and its fingerprint, read once and copied to every machine by the agent.
+static AGENT_BINARY: tokio::sync::OnceCell<(Vec<u8>, String)> = tokio::sync::OnceCell::const_new();
+
+/// the agent of `machine`, started the first time a script is sent to it. `None` if agents are
+/// disabled, the machine is the local machine or the agent could not be started.
+async fn machine_agent(ctx: &Context, machine: Machine) -> Option<Arc<agent::AgentClient>> {
+    let agents = ctx.agents()?;
+    agents
+        .get_or_start(machine, async {
+            match machine_agent_start(ctx, machine).await {
+                Ok(client) => client,
+                Err(err) => {
+                    tracing::warn!("failed to start agent on {machine}, using ssh: {err:#}");
+                    None
+                }
+            }
+        })
+        .await
+}
+
+async fn machine_agent_start(
+    ctx: &Context,
+    machine: Machine,
+) -> Result<Option<agent::AgentClient>> {
+    let mut arguments = machine_ssh_arguments(ctx, machine)?;
+    if arguments.is_empty() {
+        return Ok(None);
+    }
+    // the agent's stderr is not read, the verbose output would only fill the pipe
+    arguments.retain(|arg| *arg != "-vvv");
+
+    let (binary, fingerprint) = AGENT_BINARY
+        .get_or_try_init(|| async {
+            let path = std::env::current_exe().context("locating the oar-p2p binary")?;
+            let binary = tokio::fs::read(&path)
+                .await
+                .with_context(|| format!("reading {}", path.display()))?;
+            let mut fingerprint = fingerprint::Fingerprint::default();
+            fingerprint.write(&binary);
+            Ok::<_, eyre::Report>((binary, fingerprint.hex()))
+        })
+        .await?;
+    let path = agent::remote_path(fingerprint);
+
+    machine_ssh_master(ctx, machine).await;
+    let installed = machine_run(
+        ctx,
+        machine,
+        &["test", "-x", &path],
+        None,
+        retry::Retry::Connection,
+    )
+    .await?;
+    if !installed.status.success() {
+        tracing::info!("copying agent to {machine}");
+        let install = format!("cat > {path}.$$ && chmod +x {path}.$$ && mv {path}.$$ {path}");
+        let mut proc = Command::new(arguments[0])
+            .args(&arguments[1..])
+            .arg(&install)
+            .process_group(0)
+            .kill_on_drop(true)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .context("spawning process")?;
+        let mut stdin = proc.stdin.take().unwrap();
+        stdin.write_all(binary).await.context("copying agent")?;
+        drop(stdin);
+        let output = proc
+            .wait_with_output()
+            .await
+            .context("waiting for process to exit")?;
+        output.status.exit_ok().with_context(|| {
+            format!(
+                "copying agent: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        })?;
+    }
+
+    let runtime = ctx.runtime.to_string();
+    let child = Command::new(arguments[0])
+        .args(&arguments[1..])
+        .args([path.as_str(), "agent", "--runtime", &runtime])
+        .process_group(0)
+        .kill_on_drop(true)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .context("spawning agent")?;
+    let client = agent::AgentClient::new(child);
+    tokio::time::timeout(Duration::from_secs(30), client.run("exit 0", false))
+        .await
+        .context("timed out waiting for the agent")?
+        .context("waiting for the agent")?
+        .status
+        .exit_ok()
+        .context("waiting for the agent")?;
+    tracing::debug!("agent running on {machine}");
+    Ok(Some(client))
+}
+
+/// like [`machine_run`] but through the machine's agent. `None` if the agent exited, it is then
+/// disabled and the script must be executed over ssh.
+async fn machine_agent_run(
+    ctx: &Context,
+    machine: Machine,
+    agent: &agent::AgentClient,
+    script: &str,
+    net: bool,
+) -> Result<Option<Output>> {
+    let what = format!("agent script on {machine}");
+    // a network script is a single `exec` in the agent's container
+    let retry = match net {
+        true => retry::Retry::Runtime,
+        false => retry::Retry::Connection,
+    };
+    match retry::with_backoff(ctx.remote_retries(), &what, retry, || {
+        agent.run(script, net)
+    })
+    .await
+    {
+        Ok(output) => Ok(Some(output)),
+        Err(err) if err.is::<agent::AgentExited>() => {
+            tracing::warn!("{err} on {machine}, using ssh");
+            if let Some(agents) = ctx.agents() {
+                agents.disable(machine);
+            }
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// run `command` on the machine with the local terminal attached to it.
+///
+/// with `tty` a terminal is allocated on the machine. the verbose ssh logs are disabled since
+/// they would be mixed with the command's output.
+async fn machine_run_interactive(
+    ctx: &Context,
+    machine: Machine,
+    command: &[String],
+    tty: bool,
+) -> Result<std::process::ExitStatus> {
+    machine_ssh_master(ctx, machine).await;
+    let mut arguments = machine_ssh_arguments(ctx, machine)?;
+    let remote = command
+        .iter()
+        .map(|arg| shell::quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    match arguments.pop() {
+        // the hostname is the last argument and options must come before it
+        Some(hostname) => {
+            arguments.push("-q");
+            if tty {
+                arguments.push("-t");
+            }
+            arguments.push(hostname);
+            // ssh joins the arguments and the remote shell splits them again
+            arguments.push(&remote);
+        }
+        None => arguments.extend(command.iter().map(String::as_str)),
+    }
+
+    tracing::trace!("running command: {arguments:?}");
+    let status = Command::new(arguments[0])
+        .args(&arguments[1..])
+        .status()
+        .await
+        .context("running command")?;
+    Ok(status)
+}
+
 async fn machine_run_script(ctx: &Context, machine: Machine, script: &str) -> Result<Output> {
     tracing::debug!("script body:\n{script}");
-    let output = machine_run(ctx, machine, &[], Some(script)).await?;
+    let output = match machine_agent(ctx, machine).await {
+        Some(agent) => machine_agent_run(ctx, machine, &agent, script, false).await?,
+        None => None,
+    };
+    let output = match output {
+        Some(output) => output,
+        None => machine_run(ctx, machine, &[], Some(script), retry::Retry::Connection).await?,
+    };
     let stdout = std::str::from_utf8(&output.stdout).unwrap_or("<invalid utf-8>");
     let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf-8>");
     if output.status.success() {
@@ -885,23 +7614,42 @@ async fn machine_net_container_run_script(
     ctx: &Context,
     machine: Machine,
     script: &str,
+) -> Result<Output> {
+    machine_net_container_run_script_with(ctx, machine, &[], script).await
+}
+
+/// like [`machine_net_container_run_script`] but with extra arguments to the container's `run`.
+async fn machine_net_container_run_script_with(
+    ctx: &Context,
+    machine: Machine,
+    extra_args: &[&str],
+    script: &str,
 ) -> Result<Output> {
     tracing::debug!("network container script body:\n{script}");
-    let output = machine_run(
-        ctx,
-        machine,
-        &[
-            "docker",
-            "run",
-            "--rm",
-            "-i",
-            "--net=host",
-            "--privileged",
-            CONTAINER_IMAGE_NAME,
-        ],
-        Some(script),
-    )
-    .await?;
+    let mut args = vec![
+        ctx.runtime.command(),
+        "run",
+        "--rm",
+        "-i",
+        "--net=host",
+        "--privileged",
+    ];
+    args.extend(extra_args);
+    args.push(CONTAINER_IMAGE_NAME);
+    // the agent's container is started without extra arguments
+    let agent = match extra_args.is_empty() {
+        true => machine_agent(ctx, machine).await,
+        false => None,
+    };
+    let output = match agent {
+        Some(agent) => machine_agent_run(ctx, machine, &agent, script, true).await?,
+        None => None,
+    };
+    let output = match output {
+        Some(output) => output,
+        // the script is only read by the container, a runtime failure happens before it starts
+        None => machine_run(ctx, machine, &args, Some(script), retry::Retry::Runtime).await?,
+    };
 
     let stdout = std::str::from_utf8(&output.stdout).unwrap_or("<invalid utf-8>");
     let stderr = std::str::from_utf8(&output.stderr).unwrap_or("<invalid utf-8>");
@@ -916,32 +7664,106 @@ async fn machine_net_container_run_script(
     Ok(output.exit_ok()?)
 }
 
-#[tracing::instrument(ret, err, skip(ctx))]
-async fn machine_net_container_build(ctx: &Context, machine: Machine) -> Result<()> {
-    tracing::info!("building network container...");
-    let script = r#"
-set -e
-cat << EOF > /tmp/oar-p2p.containerfile
-FROM alpine:latest
+/// containerfile of the networking image.
+const NET_CONTAINERFILE: &str = r#"FROM alpine:latest
 RUN apk update && \
-    apk add --no-cache bash grep iproute2 iproute2-tc nftables && \
+    apk add --no-cache bash grep iproute2 iproute2-tc jq iperf3 nftables tcpdump util-linux-misc && \
     rm -rf /var/cache/apk/*
 
 WORKDIR /work
-EOF
+"#;
+
+/// label of the networking image with the fingerprint of the containerfile it was built from.
+const NET_CONTAINERFILE_LABEL: &str = "oar-p2p.containerfile";
+
+/// script that builds the networking image, unless the image on the machine was already built
+/// from the same containerfile.
+fn machine_net_container_build_script(runtime: ContainerRuntime) -> String {
+    let mut fingerprint = fingerprint::Fingerprint::default();
+    fingerprint.write_str(NET_CONTAINERFILE);
+    let fingerprint = fingerprint.hex();
+    // the agent's container uses the old image and is started again on its next script
+    let agent_container = agent::CONTAINER_NAME;
+    format!(
+        r#"
+set -e
+built=$({runtime} image inspect -f '{{{{ index .Config.Labels "{NET_CONTAINERFILE_LABEL}" }}}}' {CONTAINER_IMAGE_NAME}:latest 2>/dev/null || true)
+if [ "$built" = "{fingerprint}" ] ; then
+	echo "network container is up to date"
+	exit 0
+fi
+{runtime} rm -f {agent_container} >/dev/null 2>&1 || true
+cat << 'EOF' > /tmp/oar-p2p.containerfile
+{NET_CONTAINERFILE}EOF
 
 rm -rf /tmp/oar-p2p || true
 mkdir -p /tmp/oar-p2p
-docker build -t local/oar-p2p-networking:latest -f /tmp/oar-p2p.containerfile /tmp/oar-p2p
-"#;
-    machine_run_script(ctx, machine, script).await?;
+{runtime} build --label {NET_CONTAINERFILE_LABEL}={fingerprint} -t {CONTAINER_IMAGE_NAME}:latest -f /tmp/oar-p2p.containerfile /tmp/oar-p2p
+"#
+    )
+}
+
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_net_container_build(ctx: &Context, machine: Machine) -> Result<()> {
+    tracing::info!("building network container...");
+    let script = machine_net_container_build_script(ctx.runtime);
+    machine_run_script(ctx, machine, &script).await?;
     tracing::info!("network container built");
     Ok(())
 }
 
+fn machine_interface_check_script(interface: &str) -> String {
+    format!(
+        r#"
+iface={interface}
+if [ ! -d /sys/class/net/$iface ] ; then
+	echo "interface $iface does not exist"
+	exit 1
+fi
+state=$(cat /sys/class/net/$iface/operstate)
+if [ "$state" != "up" ] ; then
+	echo "interface $iface is not up, operstate is $state"
+	exit 1
+fi
+if [ "$(cat /sys/class/net/$iface/carrier 2>/dev/null)" != "1" ] ; then
+	echo "interface $iface has no carrier"
+	exit 1
+fi
+if [ -d /sys/class/net/$iface/bonding ] ; then
+	active=0
+	for slave in $(cat /sys/class/net/$iface/bonding/slaves) ; do
+		if [ "$(cat /sys/class/net/$slave/operstate)" = "up" ] && [ "$(cat /sys/class/net/$slave/carrier 2>/dev/null)" = "1" ] ; then
+			active=$((active + 1))
+		fi
+	done
+	if [ "$active" -eq 0 ] ; then
+		echo "bond $iface has no active slaves (slaves: $(cat /sys/class/net/$iface/bonding/slaves))"
+		exit 1
+	fi
+fi
+exit 0
+"#
+    )
+}
+
+#[tracing::instrument(ret, err, skip(ctx))]
+async fn machine_interface_check(ctx: &Context, machine: Machine) -> Result<()> {
+    let script = machine_interface_check_script(machine.interface());
+    let output = machine_run(ctx, machine, &[], Some(&script), retry::Retry::Connection).await?;
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        return Err(eyre::eyre!(
+            "interface check failed on {machine}: {}",
+            stdout.trim()
+        ));
+    }
+    Ok(())
+}
+
 #[tracing::instrument(ret, err, skip(ctx))]
 async fn machine_clean(ctx: &Context, machine: Machine) -> Result<()> {
     tracing::info!("cleaning network interfaces");
+    machine_run_script(ctx, machine, &fingerprint::remove_script()).await?;
     let interface = machine.interface();
     let mut script = String::default();
     script.push_str(&format!(
@@ -962,247 +7784,504 @@ async fn machine_clean(ctx: &Context, machine: Machine) -> Result<()> {
     Ok(())
 }
 
-fn machine_configuration_script(config: &MachineConfig) -> String {
-    let mut script = String::default();
-    // arp cache limit increase
-    script.push_str("echo 8192 > /proc/sys/net/ipv4/neigh/default/gc_thresh1\n");
-    script.push_str("echo 16384 > /proc/sys/net/ipv4/neigh/default/gc_thresh2\n");
-    script.push_str("echo 32768 > /proc/sys/net/ipv4/neigh/default/gc_thresh3\n");
-
-    // tcp max orphan limit
-    script.push_str("echo 524288 > /proc/sys/net/ipv4/tcp_max_orphans\n");
-
-    // exit docker swarm and remove all networks
-    script.push_str("docker swarm leave --force || true\n");
-    script.push_str("docker network ls -q | xargs docker network rm -f || true\n");
-
-    // ip configuration
-    script.push_str("cat << EOF | ip -b -\n");
-    for command in config.ip_commands.iter() {
-        script.push_str(command);
-        script.push('\n');
-    }
-    script.push_str("\nEOF\n");
-
-    // tc configuration
-    script.push_str("cat << EOF | tc -b -\n");
-    for command in config.tc_commands.iter() {
-        script.push_str(command);
-        script.push('\n');
-    }
-    script.push_str("\nEOF\n");
-
-    // nft configuration
-    script.push_str("cat << EOF | nft -f -\n");
-    script.push_str(&config.nft_script);
-    script.push_str("\nEOF\n");
-    script
-}
-
 #[tracing::instrument(ret, err, skip_all, fields(machine = ?config.machine))]
-async fn machine_configure(ctx: &Context, config: &MachineConfig) -> Result<()> {
+async fn machine_configure(
+    ctx: &Context,
+    config: &MachineConfig,
+    nft_chunk_size: usize,
+) -> Result<()> {
+    let elements = config.nft_map_elements.element_count();
     tracing::info!(
-        "configuring machine with {} addresses",
-        config.addresses.len()
+        "configuring machine with {} addresses and {elements} nft map elements",
+        config.addresses.len(),
     );
     let script = machine_configuration_script(config);
     machine_net_container_run_script(ctx, config.machine, &script).await?;
+
+    let chunks = elements.div_ceil(nft_chunk_size);
+    for (idx, script) in machine_nft_chunk_scripts(config, nft_chunk_size).enumerate() {
+        machine_net_container_run_script(ctx, config.machine, &script)
+            .await
+            .with_context(|| format!("loading nft map chunk {}/{chunks}", idx + 1))?;
+        tracing::info!("loaded nft map chunk {}/{chunks}", idx + 1);
+    }
+    machine_run_script(
+        ctx,
+        config.machine,
+        &fingerprint::write_script(&config.fingerprint),
+    )
+    .await?;
     tracing::info!("machine configured");
     Ok(())
 }
 
-fn machine_address_for_idx(machine: Machine, idx: u32) -> Ipv4Addr {
-    let c = u8::try_from(idx / 254).unwrap();
-    let d = u8::try_from(idx % 254 + 1).unwrap();
-    Ipv4Addr::new(10, machine.index().try_into().unwrap(), c, d)
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
 }
 
-fn machine_generate_configs(
-    matrix: &LatencyMatrix,
-    matrix_wrap: bool,
-    machines: &[Machine],
-    addr_policy: &AddressAllocationPolicy,
-) -> Result<Vec<MachineConfig>> {
-    if machines.is_empty() {
-        return Err(eyre::eyre!("cannot generate config for zero machines"));
+#[cfg(test)]
+mod tests {
+    use oar_p2p::netconfig::machine_address_for_idx;
+
+    use super::*;
+
+    /// a container with only a name, an image and an address, built from a schedule item so new
+    /// schedule fields take their defaults without changing this fixture.
+    fn test_container(name: &str, machine: Machine) -> ScheduledContainer {
+        let item = serde_json::from_value::<schedule::ScheduleItem>(serde_json::json!({
+            "name": name,
+            "image": "alpine",
+            "address": machine_address_for_idx(machine, 0),
+        }))
+        .unwrap();
+        schedule_containers("test", vec![item], &BTreeMap::default())
+            .unwrap()
+            .remove(0)
     }
 
-    let mut configs = Vec::default();
-    let mut addresses = Vec::default();
-    let mut address_to_index = HashMap::<Ipv4Addr, usize>::default();
-    let mut addresses_per_machine = HashMap::<Machine, Vec<Ipv4Addr>>::default();
-    machines.iter().for_each(|&m| {
-        addresses_per_machine.insert(m, Default::default());
-    });
+    #[test]
+    fn test_container_start_levels() {
+        let mut containers = vec![
+            test_container("a", Machine::Gengar1),
+            test_container("b", Machine::Gengar2),
+            test_container("c", Machine::Gengar1),
+            test_container("d", Machine::Gengar2),
+        ];
+        containers[0].depends_on = vec![String::from("b")];
+        containers[2].depends_on = vec![String::from("a"), String::from("d")];
+        let levels = container_start_levels(&containers).unwrap();
+        assert_eq!(levels, vec![vec!["b", "d"], vec!["a"], vec!["c"]]);
 
-    // gather all addresses across all machines
-    match addr_policy {
-        AddressAllocationPolicy::PerCpu(n) => {
-            for &machine in machines {
-                for i in 0..(n * machine.cpus()) {
-                    let address = machine_address_for_idx(machine, i);
-                    addresses.push(address);
-                }
-            }
-        }
-        AddressAllocationPolicy::PerMachine(n) => {
-            for &machine in machines {
-                for i in 0..*n {
-                    let address = machine_address_for_idx(machine, i);
-                    addresses.push(address);
-                }
-            }
-        }
-        AddressAllocationPolicy::Total(n) => {
-            let mut counter = 0;
-            while counter < *n {
-                let machine = machines[(counter as usize) % machines.len()]; // TODO: proper error
-                // message for panic here
-                let address = machine_address_for_idx(machine, counter / (machines.len() as u32));
-                addresses.push(address);
-                counter += 1;
-            }
-        }
+        containers[1].depends_on = vec![String::from("c")];
+        let err = container_start_levels(&containers).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("dependency cycle between containers: a, b, c"),
+            "{err}"
+        );
+
+        containers[1].depends_on = vec![String::from("e")];
+        assert!(container_start_levels(&containers).is_err());
     }
-    for (idx, &address) in addresses.iter().enumerate() {
-        let machine = machine_from_addr(address).expect("we should only generate valid addresses");
-        address_to_index.insert(address, idx);
-        addresses_per_machine
-            .entry(machine)
-            .or_default()
-            .push(address);
+
+    #[test]
+    fn test_container_start_offsets() {
+        let mut containers = vec![
+            test_container("a", Machine::Gengar1),
+            test_container("b", Machine::Gengar2),
+            test_container("c", Machine::Gengar1),
+        ];
+        assert!(
+            container_start_offsets(&containers, None)
+                .iter()
+                .all(Duration::is_zero)
+        );
+
+        containers[1].start_delay = Some(Duration::from_secs(10));
+        let offsets = container_start_offsets(&containers, Some(Duration::from_millis(500)));
+        assert_eq!(
+            offsets,
+            vec![
+                Duration::ZERO,
+                Duration::from_secs(10),
+                Duration::from_secs(1)
+            ]
+        );
     }
 
-    if !matrix_wrap && addresses.len() > matrix.dimension() {
-        return Err(eyre::eyre!(
-            "latency matrix is too small, size is {} but {} was required",
-            matrix.dimension(),
-            addresses.len()
-        ));
+    #[test]
+    fn test_machine_images_pull_script() {
+        let containers = [
+            test_container("a", Machine::Gengar1),
+            test_container("b", Machine::Gengar1),
+        ];
+        let images = containers_images(&containers);
+        assert_eq!(images.len(), 1);
+        let script = machine_images_pull_script(ContainerRuntime::Docker, &images, false);
+        assert_eq!(script.matches("docker pull").count(), 1);
+        assert!(script.contains("wait $pid_0"));
+        let script = machine_images_pull_script(ContainerRuntime::Docker, &images, true);
+        assert!(script.contains(&format!("DOCKER_CONFIG={REGISTRY_CONFIG_DIR} docker pull")));
+        assert!(script.starts_with("trap"));
     }
 
-    for &machine in machines {
-        let machine_addresses = &addresses_per_machine[&machine];
-        let mut machine_ip_commands = Vec::default();
-        let mut machine_tc_commands = Vec::default();
-        let mut machine_nft_script = String::default();
-
-        machine_ip_commands.push(format!("route add 10.0.0.0/8 dev {}", machine.interface()));
-        for address in machine_addresses.iter() {
-            machine_ip_commands.push(format!("addr add {address}/32 dev {}", machine.interface()));
-        }
-
-        let mut latencies_set = HashSet::<u32>::default();
-        let mut latencies_buckets = Vec::<u32>::default();
-        let mut latencies_addr_pairs = HashMap::<u32, Vec<(Ipv4Addr, Ipv4Addr)>>::default();
-        for &addr in machine_addresses {
-            let addr_idx = address_to_index[&addr];
-            for other_idx in (0..addresses.len()).filter(|i| *i != addr_idx) {
-                let other = addresses[other_idx];
-                let latency = match matrix_wrap {
-                    true => matrix.latency(
-                        addr_idx % matrix.dimension(),
-                        other_idx % matrix.dimension(),
-                    ),
-                    false => matrix.latency(addr_idx, other_idx),
-                };
-                let latency_millis = u32::try_from(latency.as_millis()).unwrap();
-                if !latencies_set.contains(&latency_millis) {
-                    latencies_set.insert(latency_millis);
-                    latencies_buckets.push(latency_millis);
-                }
-                latencies_addr_pairs
-                    .entry(latency_millis)
-                    .or_default()
-                    .push((addr, other));
-            }
-        }
+    #[test]
+    fn test_machine_containers_wait_running_script() {
+        let names = [String::from("setup"), String::from("node 1")];
+        let script = machine_containers_wait_running_script(ContainerRuntime::Docker, &names);
+        assert!(script.starts_with("for name in 'setup' 'node 1' ; do\n"));
+        assert!(script.contains("{{.State.ExitCode}}' \"$name\")"));
+        // one-shot dependencies that finished successfully are ready
+        assert!(script.contains("\"running \"*|\"healthy \"*|\"exited 0\") break ;;"));
+    }
 
-        for iface in &["lo", machine.interface()] {
-            machine_tc_commands.push(format!(
-                "qdisc add dev {iface} root handle 1: htb default 9999 r2q 100000"
-            ));
-            machine_tc_commands.push(format!(
-                "class add dev {iface} parent 1: classid 1:9999 htb rate 10gbit"
-            ));
-            for (idx, &latency_millis) in latencies_buckets.iter().enumerate() {
-                // tc class for latency at idx X is X + 1
-                let latency_class_id = idx + 1;
-                // mark for latency at idx X is X + 1
-                let latency_mark = idx + 1;
-
-                machine_tc_commands.push(format!(
-                    "class add dev {iface} parent 1: classid 1:{latency_class_id} htb rate 10gbit"
-                ));
-                // why idx + 2 here? I dont remember anymore and forgot to comment
-                machine_tc_commands.push(format!(
-                    "qdisc add dev {iface} parent 1:{} handle {}: netem delay {latency_millis}ms",
-                    latency_class_id,
-                    idx + 2
-                ));
-                // TODO: is the order of these things correct?
-                machine_tc_commands.push(format!(
-                    "filter add dev {iface} parent 1:0 prio 1 handle {latency_mark} fw flowid 1:{latency_class_id}",
-                ));
-            }
-        }
+    #[test]
+    fn test_signal_scripts() {
+        let containers = [
+            ps::ContainerStatus {
+                name: String::from("a"),
+                machine: Machine::Gengar1,
+                address: Some(Ipv4Addr::new(10, 16, 0, 1)),
+                state: String::from("running"),
+                exit_code: None,
+            },
+            ps::ContainerStatus {
+                name: String::from("b"),
+                machine: Machine::Gengar1,
+                address: None,
+                state: String::from("running"),
+                exit_code: None,
+            },
+        ];
+        let containers = containers.iter().collect::<Vec<_>>();
+        let signal = "SIGUSR1".parse::<Signal>().unwrap();
+        let script = signal_kill_script(ContainerRuntime::Docker, &containers, &signal);
+        assert!(script.contains("docker kill --signal SIGUSR1 a >/dev/null"));
+        assert!(script.contains("docker kill --signal SIGUSR1 b >/dev/null"));
+
+        let signal = "phase-2".parse::<Signal>().unwrap();
+        let script = signal_udp_script(&containers, &signal, 100, 9000);
+        assert_eq!(script, "echo -n 'phase-2 100' > /dev/udp/10.16.0.1/9000\n");
+    }
+
+    #[test]
+    fn test_format_net_show() {
+        let addresses = vec![
+            (Machine::Gengar1, Ipv4Addr::new(10, 16, 0, 1)),
+            (Machine::Gengar2, Ipv4Addr::new(10, 17, 0, 1)),
+        ];
+        assert_eq!(
+            format_net_show(&addresses, NetShowFormat::Text).unwrap(),
+            "gengar-1 10.16.0.1\ngengar-2 10.17.0.1\n"
+        );
+        assert_eq!(
+            format_net_show(&addresses, NetShowFormat::Csv).unwrap(),
+            "machine,address\ngengar-1,10.16.0.1\ngengar-2,10.17.0.1\n"
+        );
+        assert_eq!(
+            format_net_show(&addresses, NetShowFormat::Hosts).unwrap(),
+            "10.16.0.1 oar-p2p-0\n10.17.0.1 oar-p2p-1\n"
+        );
+        let env = format_net_show(&addresses, NetShowFormat::Env).unwrap();
+        assert!(env.starts_with("export OAR_P2P_ADDRESSES=\"10.16.0.1 10.17.0.1\"\n"));
+        assert!(env.contains("export OAR_P2P_MACHINE_1=gengar-2\n"));
+        let json = format_net_show(&addresses, NetShowFormat::Json).unwrap();
+        let records = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(records[1]["machine"], "gengar-2");
+        assert_eq!(records[1]["address"], "10.17.0.1");
+    }
 
-        machine_nft_script.push_str("table ip oar-p2p {\n");
-        machine_nft_script.push_str(
-            r#"
-    chain prerouting {
-        type filter hook prerouting priority raw;
-        ip saddr 10.0.0.0/8 notrack
-        ip daddr 10.0.0.0/8 notrack
+    #[test]
+    fn test_measure_sample_and_compare() {
+        let mapped = (0..4)
+            .map(|index| mapping::MappedAddress {
+                address: Ipv4Addr::new(10, 16, 0, index as u8 + 1),
+                index,
+                matrix_row: index % 2,
+            })
+            .collect::<Vec<_>>();
+        let addresses = mapped
+            .iter()
+            .map(|a| (Machine::Gengar1, a))
+            .collect::<Vec<_>>();
+        let sample = measure_sample(addresses.clone(), 3, 7);
+        assert_eq!(sample, measure_sample(addresses.clone(), 3, 7));
+        assert_eq!(sample.len(), 3);
+        assert!(sample.windows(2).all(|w| w[0].1.index < w[1].1.index));
+        assert_eq!(measure_sample(addresses, 10, 7).len(), 4);
+
+        let expected = "0 10\n10 0\n".parse::<LatencyMatrix>().unwrap();
+        let measured = LatencyMatrix::from_fn(3, |row, col| match (row, col) {
+            (0, 1) => Duration::from_millis(12),
+            (row, col) if row == col => Duration::ZERO,
+            _ => Duration::from_millis(10),
+        });
+        // rows 0 and 2 map to the same matrix row, so 0 is expected between them
+        let comparison = measure_compare(&measured, &expected, &[0, 1, 0]);
+        assert_eq!(comparison.worst, (0, 2));
+        assert!((comparison.max_error - 0.010).abs() < 1e-9);
+        assert!((comparison.mean_error - 0.022 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_bench_ring_pairs() {
+        let addresses = [
+            Ipv4Addr::new(10, 16, 0, 1),
+            Ipv4Addr::new(10, 17, 0, 1),
+            Ipv4Addr::new(10, 18, 0, 1),
+        ];
+        let pairs = bench_ring_pairs(&addresses).unwrap();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!((pairs[0].src, pairs[0].dst), (addresses[0], addresses[1]));
+        assert_eq!((pairs[2].src, pairs[2].dst), (addresses[2], addresses[0]));
+        assert!(bench_ring_pairs(&addresses[..1]).is_err());
+    }
+
+    #[test]
+    fn test_job_status() {
+        let job = |state: &str| oar::JobInfo {
+            job_id: 42,
+            state: state.to_string(),
+            machines: vec![Machine::Gengar1, Machine::Gengar2],
+            start_time: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1000)),
+            walltime: Some(Duration::from_secs(3600)),
+            queue: String::from("default"),
+            types: Vec::default(),
+        };
+        let deployed = HashMap::from([
+            (Machine::Gengar1, Some(true)),
+            (Machine::Gengar2, Some(false)),
+        ]);
+
+        let status = JobStatus::new(job("Running"), &deployed);
+        assert_eq!(status.started_at, Some(1000));
+        assert_eq!(status.remaining_walltime, Some(0));
+        assert_eq!(status.network_deployed, Some(1));
+        assert_eq!(status.network(), "partial(1/2)");
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["job_id"], 42);
+        assert_eq!(json["machines"][1], "gengar-2");
+
+        let status = JobStatus::new(job("Waiting"), &deployed);
+        assert_eq!(status.remaining_walltime, None);
+        assert_eq!(status.network_deployed, None);
+        assert_eq!(status.network(), "-");
+
+        // an unreachable machine is reported without hiding the rest of the job
+        let deployed = HashMap::from([(Machine::Gengar1, Some(true)), (Machine::Gengar2, None)]);
+        let status = JobStatus::new(job("Running"), &deployed);
+        assert_eq!(status.unreachable_machines, vec![Machine::Gengar2]);
+        assert_eq!(status.network(), "partial(1/2),1-unreachable");
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["unreachable_machines"][0], "gengar-2");
     }
-    chain output {
-        type filter hook output priority raw;
-        ip saddr 10.0.0.0/8 notrack
-        ip daddr 10.0.0.0/8 notrack
+
+    #[test]
+    fn test_machine_containers_wait_script_restart() {
+        let mut containers = vec![
+            test_container("a", Machine::Gengar1),
+            test_container("b", Machine::Gengar1),
+        ];
+        containers[1].restart = schedule::RestartPolicy::OnFailure(Some(3));
+        let script =
+            machine_containers_wait_script(ContainerRuntime::Docker, &containers, false, false);
+        assert!(script.contains("code=$(docker wait a) || exit 1\necho exited a $code\n"));
+        assert!(script.contains("\tcode=$(docker wait b) || exit 1\n"));
+        assert!(script.contains("restarting|running) sleep 1 ;;"));
+
+        let script =
+            machine_containers_wait_script(ContainerRuntime::Docker, &containers, false, true);
+        assert!(script.contains("\tcode=$(docker wait a) || exit 1\n"));
+        assert!(script.contains("[ -e /tmp/oar-p2p-churn/a ] || break ; sleep 1 ;;"));
     }
-"#,
+
+    #[test]
+    fn test_run_validate_addresses() {
+        let mut containers = vec![
+            test_container("a", Machine::Gengar1),
+            test_container("b", Machine::Gengar2),
+        ];
+        let address = |machine| machine_address_for_idx(machine, 0);
+        let configured = [
+            (Machine::Gengar1, HashSet::from([address(Machine::Gengar1)])),
+            (Machine::Gengar2, HashSet::from([address(Machine::Gengar2)])),
+        ]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+        run_validate_addresses(&containers, &configured, false).unwrap();
+
+        containers[1].machine = Machine::Gengar1;
+        containers[1].address = address(Machine::Gengar1);
+        let err = run_validate_addresses(&containers, &configured, false).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("containers a, b use the same address")
         );
+        run_validate_addresses(&containers, &configured, true).unwrap();
 
-        machine_nft_script.push_str("\tmap mark_pairs {\n");
-        machine_nft_script.push_str("\t\ttype ipv4_addr . ipv4_addr : mark\n");
-        machine_nft_script.push_str("\t\telements = {\n");
-        for (latency_idx, &latency_millis) in latencies_buckets.iter().enumerate() {
-            let latency_mark = latency_idx + 1;
-            let pairs = match latencies_addr_pairs.get(&latency_millis) {
-                Some(pairs) => pairs,
-                None => continue,
-            };
+        containers[0].address = machine_address_for_idx(Machine::Gengar1, 1);
+        containers[1].machine = Machine::Gengar3;
+        let err = run_validate_addresses(&containers, &configured, true)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("container a: address"));
+        assert!(err.contains("container b: machine gengar-3"));
+    }
 
-            for (src, dst) in pairs {
-                assert_ne!(src, dst);
-                machine_nft_script.push_str(&format!("\t\t\t{src} . {dst} : {latency_mark},\n"));
-            }
-        }
-        machine_nft_script.push_str("\t\t}\n");
-        machine_nft_script.push_str("\t}\n");
-        machine_nft_script.push('\n');
-        machine_nft_script.push_str("\tchain postrouting {\n");
-        machine_nft_script.push_str("\t\ttype filter hook postrouting priority mangle -1\n");
-        machine_nft_script.push_str("\t\tpolicy accept\n");
-        machine_nft_script
-            .push_str("\t\tmeta mark set ip saddr . ip daddr map @mark_pairs counter\n");
-        machine_nft_script.push_str("\t}\n");
-        machine_nft_script.push_str("}\n");
+    #[test]
+    fn test_expand_output_dir() {
+        assert_eq!(
+            expand_output_dir(
+                "results/{schedule_stem}-{job_id}-{timestamp}",
+                &[1, 2],
+                100,
+                "s"
+            )
+            .unwrap(),
+            PathBuf::from("results/s-1-2-100")
+        );
+        assert_eq!(
+            expand_output_dir("results/{timestamp", &[], 100, "s").unwrap(),
+            PathBuf::from("results/{timestamp")
+        );
+        assert!(expand_output_dir("results/{date}", &[], 100, "s").is_err());
+    }
 
-        configs.push(MachineConfig {
-            machine,
-            addresses: machine_addresses.clone(),
-            nft_script: machine_nft_script,
-            tc_commands: machine_tc_commands,
-            ip_commands: machine_ip_commands,
-        });
+    #[test]
+    fn test_check_output_dir_template_resume() {
+        let cli = Cli::try_parse_from([
+            "oar-p2p",
+            "run",
+            "--resume",
+            "--output-dir",
+            "results/{schedule_stem}-{timestamp}",
+        ])
+        .unwrap();
+        let SubCmd::Run(run) = cli.cmd else {
+            panic!("expected the run subcommand");
+        };
+        let args = run.run;
+        let template = args.output_dir.as_deref().unwrap().to_string_lossy();
+        assert!(check_output_dir_template(&template, args.resume).is_err());
+        assert!(check_output_dir_template(&template, false).is_ok());
+        assert!(check_output_dir_template("results/{schedule_stem}-100", true).is_ok());
     }
-    Ok(configs)
-}
 
-fn unix_timestamp() -> u64 {
-    SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+    #[test]
+    fn test_artifact_copy_script() {
+        let script = artifact_copy_script(ContainerRuntime::Docker, "c0", "/data/metrics.db");
+        assert_eq!(
+            script,
+            "mkdir -p /tmp/oar-p2p-logs/artifacts/c0/data && docker cp c0:/data/metrics.db /tmp/oar-p2p-logs/artifacts/c0/data/metrics.db || echo Artifact /data/metrics.db not found in c0 >&2\n"
+        );
+        let script = artifact_copy_script(ContainerRuntime::Docker, "c0", "/data/*/out.csv");
+        assert!(script.contains("docker cp c0:/data/. $tmp/"));
+        assert!(script.contains(
+            "for f in */out.csv ; do cp -r --parents \"$f\" /tmp/oar-p2p-logs/artifacts/c0/data/ ; done"
+        ));
+    }
+
+    #[test]
+    fn test_input_remote_path() {
+        let local = Path::new("/home/user/datasets/a.csv");
+        assert_eq!(
+            input_remote_dir(local),
+            "/tmp/oar-p2p-inputs/home_user_datasets_a.csv"
+        );
+        assert_eq!(
+            input_remote_path(local),
+            "/tmp/oar-p2p-inputs/home_user_datasets_a.csv/a.csv"
+        );
+    }
+
+    #[test]
+    fn test_parse_containers_wait_output() {
+        let output = "exited c0 0\nexited c1 137\nsome noise\nexited c2\n";
+        assert_eq!(
+            parse_containers_wait_output(output),
+            vec![(String::from("c0"), 0), (String::from("c1"), 137)]
+        );
+    }
+
+    #[test]
+    fn test_machine_resources_check() {
+        let mut containers = vec![
+            test_container("a", Machine::Gengar1),
+            test_container("b", Machine::Gengar1),
+        ];
+        containers[0].cpus = Some(4.0);
+        containers[1].cpus = Some(4.0);
+        containers[0].memory = Some(schedule::MemorySize(1 << 30));
+        containers[1].memory = Some(schedule::MemorySize(1 << 30));
+        let memory_total = Some(schedule::MemorySize(2 << 30));
+        assert!(machine_resources_check(Machine::Gengar1, &containers, memory_total).is_ok());
+
+        containers[1].cpus = Some(4.5);
+        assert!(machine_resources_check(Machine::Gengar1, &containers, memory_total).is_err());
+
+        containers[1].cpus = None;
+        containers[1].memory = Some(schedule::MemorySize((1 << 30) + 1));
+        assert!(machine_resources_check(Machine::Gengar1, &containers, memory_total).is_err());
+    }
+
+    #[test]
+    fn test_assign_container_cpusets() {
+        let mut containers = vec![
+            test_container("a", Machine::Gengar1),
+            test_container("b", Machine::Gengar1),
+            test_container("c", Machine::Gengar2),
+            test_container("d", Machine::Gengar1),
+        ];
+        containers[1].cpuset = Some(String::from("7"));
+        assign_container_cpusets(&mut containers, 2).unwrap();
+        assert_eq!(containers[0].cpuset.as_deref(), Some("0-1"));
+        assert_eq!(containers[1].cpuset.as_deref(), Some("7"));
+        assert_eq!(containers[2].cpuset.as_deref(), Some("0-1"));
+        assert_eq!(containers[3].cpuset.as_deref(), Some("2-3"));
+    }
+
+    #[test]
+    fn test_assign_container_cpusets_mixed() {
+        // the explicit cpusets are reserved before assigning the others
+        let mut containers = vec![
+            test_container("a", Machine::Gengar1),
+            test_container("b", Machine::Gengar1),
+            test_container("c", Machine::Gengar1),
+            test_container("d", Machine::Gengar1),
+        ];
+        containers[2].cpuset = Some(String::from("0-2"));
+        containers[3].cpuset = Some(String::from("4"));
+        assign_container_cpusets(&mut containers, 2).unwrap();
+        assert_eq!(containers[0].cpuset.as_deref(), Some("3,5"));
+        assert_eq!(containers[1].cpuset.as_deref(), Some("6-7"));
+
+        // gengar machines have 8 cpus, 4 of which are taken
+        let mut containers = (0..3)
+            .map(|i| test_container(&i.to_string(), Machine::Gengar1))
+            .collect::<Vec<_>>();
+        containers[0].cpuset = Some(String::from("0-3"));
+        assert!(assign_container_cpusets(&mut containers, 3).is_err());
+        assert_eq!(cpuset_cpus("0-2,5"), vec![0, 1, 2, 5]);
+    }
+
+    #[test]
+    fn test_assign_container_cpusets_not_enough_cpus() {
+        // gengar machines have 8 cpus
+        let mut containers = (0..5)
+            .map(|i| test_container(&i.to_string(), Machine::Gengar1))
+            .collect::<Vec<_>>();
+        assert!(assign_container_cpusets(&mut containers, 2).is_err());
+        assert!(assign_container_cpusets(&mut containers[..4], 2).is_ok());
+    }
+
+    #[test]
+    fn test_net_container_build_script() {
+        let script = machine_net_container_build_script(ContainerRuntime::Docker);
+        let label = script
+            .lines()
+            .find(|l| l.starts_with("if [ \"$built\" = "))
+            .unwrap();
+        let fingerprint = label.split('"').nth(3).unwrap();
+        assert_eq!(fingerprint.len(), 16, "{label}");
+        assert!(script.contains(&format!(
+            "docker build --label oar-p2p.containerfile={fingerprint} -t local/oar-p2p-networking:latest"
+        )));
+        assert!(script.contains("'{{ index .Config.Labels \"oar-p2p.containerfile\" }}'"));
+        assert!(script.contains("WORKDIR /work\nEOF\n"));
+    }
+
+    #[test]
+    fn test_machine_logs_stream_script() {
+        assert_eq!(
+            machine_logs_stream_script(None),
+            "tar -C /tmp/oar-p2p-logs -cf - .\n"
+        );
+        assert_eq!(
+            machine_logs_stream_script(Some(compression::Compression::Zstd)),
+            "set -o pipefail\ntar -C /tmp/oar-p2p-logs -cf - . | zstd -c -q\n"
+        );
+    }
 }
@@ -0,0 +1,303 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::Ipv4Addr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{machine::Machine, runtime::ContainerRuntime};
+
+/// name of the file, inside the output directory, where the run manifest is written.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// metadata about a finished run, written so analysis scripts do not have to reconstruct it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// where the schedule was read from
+    pub schedule_source: String,
+    /// the schedule as it was given, addresses assigned by placement are only in `containers`
+    pub schedule: serde_json::Value,
+    /// unix timestamp, in seconds, of when all containers finished starting
+    pub started_at: Option<u64>,
+    /// unix timestamp, in seconds, of when the manifest was written
+    pub finished_at: u64,
+    /// containers that exited with a non-zero code
+    pub failed_containers: Vec<String>,
+    pub containers: Vec<ManifestContainer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestContainer {
+    pub name: String,
+    pub machine: Machine,
+    pub address: Ipv4Addr,
+    pub image: String,
+    #[serde(flatten)]
+    pub inspect: ContainerInspect,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid manifest: {0}")]
+pub struct InvalidManifest(String);
+
+impl Manifest {
+    /// the names of the containers that failed or never finished.
+    pub fn retry_containers(&self) -> Vec<&str> {
+        self.containers
+            .iter()
+            .filter(|c| self.failed_containers.contains(&c.name) || c.inspect.exit_code != Some(0))
+            .map(|c| c.name.as_str())
+            .collect()
+    }
+
+    /// a schedule with only the containers that need to be retried.
+    ///
+    /// every item keeps the name and address it had in the previous run and dependencies on
+    /// containers that are not retried are dropped since those are already done.
+    pub fn retry_schedule(&self) -> Result<String, InvalidManifest> {
+        let items = self
+            .schedule
+            .as_array()
+            .ok_or_else(|| InvalidManifest(String::from("schedule is not an array")))?;
+        if items.len() != self.containers.len() {
+            return Err(InvalidManifest(format!(
+                "schedule has {} items but there are {} containers",
+                items.len(),
+                self.containers.len()
+            )));
+        }
+
+        let retry = self.retry_containers().into_iter().collect::<HashSet<_>>();
+        let mut schedule = Vec::default();
+        for (item, container) in items.iter().zip(self.containers.iter()) {
+            if !retry.contains(container.name.as_str()) {
+                continue;
+            }
+            let mut item = item.clone();
+            let object = item
+                .as_object_mut()
+                .ok_or_else(|| InvalidManifest(String::from("schedule item is not an object")))?;
+            object.insert(String::from("name"), container.name.clone().into());
+            object.insert(
+                String::from("address"),
+                container.address.to_string().into(),
+            );
+            if let Some(depends_on) = object
+                .get_mut("depends_on")
+                .and_then(serde_json::Value::as_array_mut)
+            {
+                depends_on.retain(|d| d.as_str().is_some_and(|d| retry.contains(d)));
+            }
+            schedule.push(item);
+        }
+        Ok(serde_json::to_string_pretty(&schedule).expect("json values are serializable"))
+    }
+
+    /// merge the manifest of a retry run into the manifest of the run that was retried.
+    pub fn merge_retry(self, previous: Manifest) -> Manifest {
+        let mut retried = self
+            .containers
+            .into_iter()
+            .map(|c| (c.name.clone(), c))
+            .collect::<HashMap<_, _>>();
+        let mut failed_containers = previous
+            .failed_containers
+            .into_iter()
+            .filter(|name| !retried.contains_key(name))
+            .collect::<Vec<_>>();
+        failed_containers.extend(self.failed_containers);
+        failed_containers.sort();
+        Manifest {
+            schedule_source: previous.schedule_source,
+            schedule: previous.schedule,
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            failed_containers,
+            containers: previous
+                .containers
+                .into_iter()
+                .map(|c| retried.remove(&c.name).unwrap_or(c))
+                .collect(),
+        }
+    }
+}
+
+/// the state of a container as reported by docker. all fields are empty if the container could
+/// not be inspected.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerInspect {
+    pub image_id: Option<String>,
+    pub image_digests: Vec<String>,
+    /// rfc3339 timestamp
+    pub container_started_at: Option<String>,
+    /// rfc3339 timestamp
+    pub container_finished_at: Option<String>,
+    pub exit_code: Option<i64>,
+}
+
+/// script that prints one `container` line per container followed by one `image` line per image.
+/// parsed by [`parse_inspect_output`].
+pub fn inspect_script<'a>(
+    runtime: ContainerRuntime,
+    names: impl IntoIterator<Item = &'a str>,
+    images: impl IntoIterator<Item = &'a str>,
+) -> String {
+    let names = names
+        .into_iter()
+        .map(crate::shell::quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let images = images
+        .into_iter()
+        .map(crate::shell::quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut script = String::default();
+    script.push_str(&format!("{runtime} container inspect --format 'container {{{{.Name}}}} {{{{.Image}}}} {{{{.State.ExitCode}}}} {{{{.State.StartedAt}}}} {{{{.State.FinishedAt}}}}' {names} || true\n"));
+    script.push_str(&format!("{runtime} image inspect --format 'image {{{{.Id}}}}{{{{range .RepoDigests}}}} {{{{.}}}}{{{{end}}}}' {images} || true\n"));
+    script
+}
+
+/// parse the output of [`inspect_script`] into the state of each container, by name.
+pub fn parse_inspect_output(output: &str) -> HashMap<String, ContainerInspect> {
+    let mut image_digests = HashMap::<&str, Vec<String>>::default();
+    for line in output.lines() {
+        let mut fields = line.split_whitespace();
+        if fields.next() == Some("image")
+            && let Some(id) = fields.next()
+        {
+            image_digests.insert(id, fields.map(ToString::to_string).collect());
+        }
+    }
+
+    let mut containers = HashMap::default();
+    for line in output.lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if let [
+            "container",
+            name,
+            image_id,
+            exit_code,
+            started_at,
+            finished_at,
+        ] = fields[..]
+        {
+            // docker reports timestamps of events that did not happen as the zero time
+            let timestamp = |t: &str| (!t.starts_with("0001-")).then(|| t.to_string());
+            containers.insert(
+                name.trim_start_matches('/').to_string(),
+                ContainerInspect {
+                    image_id: Some(image_id.to_string()),
+                    image_digests: image_digests.get(image_id).cloned().unwrap_or_default(),
+                    container_started_at: timestamp(started_at),
+                    container_finished_at: timestamp(finished_at),
+                    exit_code: exit_code.parse().ok(),
+                },
+            );
+        }
+    }
+    containers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_container(name: &str, last_octet: u8, exit_code: Option<i64>) -> ManifestContainer {
+        ManifestContainer {
+            name: name.to_string(),
+            machine: Machine::Gengar1,
+            address: Ipv4Addr::new(10, 16, 0, last_octet),
+            image: String::from("alpine"),
+            inspect: ContainerInspect {
+                exit_code,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn test_manifest() -> Manifest {
+        Manifest {
+            schedule_source: String::from("schedule.json"),
+            schedule: serde_json::json!([
+                { "image": "alpine" },
+                { "image": "alpine", "depends_on": ["a"] },
+                { "image": "alpine", "depends_on": ["a", "b"] },
+            ]),
+            started_at: Some(10),
+            finished_at: 20,
+            failed_containers: vec![String::from("b")],
+            containers: vec![
+                test_container("a", 1, Some(0)),
+                test_container("b", 2, Some(1)),
+                test_container("c", 3, None),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_retry_schedule() {
+        let manifest = test_manifest();
+        assert_eq!(manifest.retry_containers(), vec!["b", "c"]);
+        let schedule =
+            crate::schedule::parse("retry", &manifest.retry_schedule().unwrap()).unwrap();
+        assert_eq!(schedule.len(), 2);
+        assert_eq!(schedule[0].name.as_deref(), Some("b"));
+        assert_eq!(schedule[0].address, Some(Ipv4Addr::new(10, 16, 0, 2)));
+        assert!(schedule[0].depends_on.is_empty());
+        assert_eq!(schedule[1].depends_on, vec!["b"]);
+    }
+
+    #[test]
+    fn test_merge_retry() {
+        let retry = Manifest {
+            schedule_source: String::from("retry"),
+            schedule: serde_json::json!([]),
+            started_at: Some(30),
+            finished_at: 40,
+            failed_containers: vec![String::from("c")],
+            containers: vec![
+                test_container("b", 2, Some(0)),
+                test_container("c", 3, Some(2)),
+            ],
+        };
+        let merged = retry.merge_retry(test_manifest());
+        assert_eq!(merged.schedule_source, "schedule.json");
+        assert_eq!(merged.finished_at, 40);
+        assert_eq!(merged.failed_containers, vec!["c"]);
+        assert_eq!(merged.containers[1].inspect.exit_code, Some(0));
+        assert_eq!(merged.containers[2].inspect.exit_code, Some(2));
+    }
+
+    #[test]
+    fn test_parse_inspect_output() {
+        let output = "\
+container /c0 sha256:aaa 0 2025-01-01T10:00:00.1Z 2025-01-01T10:05:00.2Z
+container /c1 sha256:aaa 137 2025-01-01T10:00:00.3Z 2025-01-01T10:01:00.4Z
+container /c2 sha256:bbb 0 0001-01-01T00:00:00Z 0001-01-01T00:00:00Z
+image sha256:aaa ghcr.io/diogo464/oar-p2p/demo@sha256:ccc
+image sha256:bbb
+";
+        let containers = parse_inspect_output(output);
+        assert_eq!(containers.len(), 3);
+        assert_eq!(containers["c0"].exit_code, Some(0));
+        assert_eq!(
+            containers["c0"].image_digests,
+            vec!["ghcr.io/diogo464/oar-p2p/demo@sha256:ccc"]
+        );
+        assert_eq!(containers["c1"].exit_code, Some(137));
+        assert_eq!(
+            containers["c1"].container_finished_at.as_deref(),
+            Some("2025-01-01T10:01:00.4Z")
+        );
+        assert!(containers["c2"].image_digests.is_empty());
+        assert_eq!(containers["c2"].container_started_at, None);
+    }
+
+    #[test]
+    fn test_inspect_script() {
+        let script = inspect_script(ContainerRuntime::Docker, ["c0", "it's"], ["demo:latest"]);
+        assert!(script.contains(r"{{.State.FinishedAt}}' 'c0' 'it'\''s' || true"));
+        assert!(script.contains("{{end}}' 'demo:latest' || true"));
+    }
+}
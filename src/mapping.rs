@@ -0,0 +1,112 @@
+use std::{collections::BTreeMap, net::Ipv4Addr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::machine::Machine;
+
+/// default path of the mapping file written by `net up`.
+pub const DEFAULT_MAPPING_PATH: &str = "address-mapping.json";
+
+/// which latency matrix row every address created by `net up` uses.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddressMapping {
+    /// number of rows of the latency matrix
+    pub matrix_dimension: usize,
+    /// whether indices past the end of the matrix wrap around
+    pub matrix_wrap: bool,
+    pub machines: BTreeMap<Machine, Vec<MappedAddress>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MappedAddress {
+    pub address: Ipv4Addr,
+    /// position of the address among all addresses of the network
+    pub index: usize,
+    /// row of the latency matrix used by the address, `index` modulo the dimension with wrapping
+    pub matrix_row: usize,
+}
+
+impl AddressMapping {
+    /// `addresses` has, for every machine, its addresses and their indices.
+    pub fn new<'a>(
+        matrix_dimension: usize,
+        matrix_wrap: bool,
+        addresses: impl IntoIterator<Item = (Machine, &'a [Ipv4Addr], &'a [usize])>,
+    ) -> Self {
+        let machines = addresses
+            .into_iter()
+            .map(|(machine, addresses, indices)| {
+                let mapped = addresses
+                    .iter()
+                    .zip(indices)
+                    .map(|(&address, &index)| MappedAddress {
+                        address,
+                        index,
+                        matrix_row: match matrix_wrap {
+                            true => index % matrix_dimension,
+                            false => index,
+                        },
+                    })
+                    .collect();
+                (machine, mapped)
+            })
+            .collect();
+        Self {
+            matrix_dimension,
+            matrix_wrap,
+            machines,
+        }
+    }
+
+    /// every address with its machine, ordered by index.
+    pub fn addresses(&self) -> Vec<(Machine, &MappedAddress)> {
+        let mut addresses = self
+            .machines
+            .iter()
+            .flat_map(|(&machine, addresses)| addresses.iter().map(move |a| (machine, a)))
+            .collect::<Vec<_>>();
+        addresses.sort_by_key(|(_, a)| a.index);
+        addresses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapping() {
+        let a = [Ipv4Addr::new(10, 16, 0, 1), Ipv4Addr::new(10, 16, 0, 2)];
+        let b = [Ipv4Addr::new(10, 17, 0, 1)];
+        let mapping = AddressMapping::new(
+            2,
+            true,
+            [
+                (Machine::Gengar1, &a[..], &[0, 1][..]),
+                (Machine::Gengar2, &b[..], &[2][..]),
+            ],
+        );
+        let gengar2 = &mapping.machines[&Machine::Gengar2];
+        assert_eq!(gengar2[0].index, 2);
+        assert_eq!(gengar2[0].matrix_row, 0);
+        let json = serde_json::to_value(&mapping).unwrap();
+        assert_eq!(json["machines"]["gengar-1"][1]["address"], "10.16.0.2");
+        assert_eq!(json["machines"]["gengar-1"][1]["matrix_row"], 1);
+
+        let parsed = serde_json::from_value::<AddressMapping>(json).unwrap();
+        assert_eq!(parsed, mapping);
+        let indices = parsed
+            .addresses()
+            .iter()
+            .map(|(m, a)| (*m, a.index))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            indices,
+            [
+                (Machine::Gengar1, 0),
+                (Machine::Gengar1, 1),
+                (Machine::Gengar2, 2)
+            ]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use crate::runtime::ContainerRuntime;
+
+const IMAGE: &str = "local/oar-p2p-networking:latest";
+
+/// script that pings every `(src, dst)` pair from the networking container and prints a
+/// `<src> <dst> <average rtt in ms>` line per pair, without the rtt when no reply arrived.
+pub fn measure_script(
+    runtime: ContainerRuntime,
+    pairs: &[(Ipv4Addr, Ipv4Addr)],
+    count: u32,
+    parallel: usize,
+) -> String {
+    let mut inner = String::default();
+    inner.push_str("measure() {\n");
+    // both iputils and busybox print a `min/avg/max... = a/b/c...` summary line
+    inner.push_str(&format!(
+        "\tsummary=$(ping -q -c {count} -i 0.2 -I \"$1\" \"$2\" 2>/dev/null | grep min/avg)\n"
+    ));
+    inner.push_str("\techo \"$1 $2 $(echo \"${summary#*= }\" | cut -d/ -f2)\"\n");
+    inner.push_str("}\n");
+    inner.push_str("export -f measure\n");
+    inner.push_str(&format!(
+        "xargs -P {parallel} -L 1 bash -c 'measure $0 $1' << 'OAR_P2P_PAIRS'\n"
+    ));
+    for (src, dst) in pairs {
+        inner.push_str(&format!("{src} {dst}\n"));
+    }
+    inner.push_str("OAR_P2P_PAIRS\n");
+
+    let mut script = String::default();
+    script.push_str(&format!(
+        "{runtime} run --rm -i --net=host {IMAGE} bash -s << 'OAR_P2P_MEASURE'\n"
+    ));
+    script.push_str(&inner);
+    script.push_str("OAR_P2P_MEASURE\n");
+    script
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid measurement line '{0}'")]
+pub struct InvalidMeasurement(String);
+
+/// a measured pair with its round trip time, if any reply arrived.
+pub type Measurement = (Ipv4Addr, Ipv4Addr, Option<Duration>);
+
+/// parse the output of [`measure_script`].
+pub fn parse_measure_output(output: &str) -> Result<Vec<Measurement>, InvalidMeasurement> {
+    let mut measurements = Vec::default();
+    for line in output.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let err_fn = || InvalidMeasurement(line.to_string());
+        let mut parts = line.split_whitespace();
+        let src = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(err_fn)?;
+        let dst = parts
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(err_fn)?;
+        let rtt = match parts.next() {
+            Some(rtt) => {
+                let millis = rtt.parse::<f64>().map_err(|_| err_fn())?;
+                if !millis.is_finite() || millis < 0.0 {
+                    return Err(err_fn());
+                }
+                Some(Duration::from_secs_f64(millis / 1000.0))
+            }
+            None => None,
+        };
+        measurements.push((src, dst, rtt));
+    }
+    Ok(measurements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure_script() {
+        let a = Ipv4Addr::new(10, 16, 0, 1);
+        let b = Ipv4Addr::new(10, 17, 0, 1);
+        let script = measure_script(ContainerRuntime::Docker, &[(a, b)], 5, 16);
+        assert!(script.starts_with(
+            "docker run --rm -i --net=host local/oar-p2p-networking:latest bash -s << 'OAR_P2P_MEASURE'\n"
+        ));
+        assert!(script.contains("ping -q -c 5 -i 0.2 -I \"$1\" \"$2\""));
+        assert!(script.contains("xargs -P 16 -L 1"));
+        assert!(script.contains("\n10.16.0.1 10.17.0.1\nOAR_P2P_PAIRS\nOAR_P2P_MEASURE\n"));
+    }
+
+    #[test]
+    fn test_parse_measure_output() {
+        let output = "10.16.0.1 10.17.0.1 166.300\n10.16.0.1 10.17.0.2 \n";
+        let measurements = parse_measure_output(output).unwrap();
+        assert_eq!(measurements.len(), 2);
+        assert_eq!(measurements[0].2, Some(Duration::from_micros(166_300)));
+        assert_eq!(measurements[1].1, Ipv4Addr::new(10, 17, 0, 2));
+        assert_eq!(measurements[1].2, None);
+        assert!(parse_measure_output("10.16.0.1 166.3\n").is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{machine::Machine, runtime::ContainerRuntime};
+
+/// name of the container that exports the metrics of a machine.
+pub const NODE_EXPORTER_CONTAINER_NAME: &str = "oar-p2p-node-exporter";
+
+/// name of the container that exports the metrics of the containers of a machine.
+pub const CADVISOR_CONTAINER_NAME: &str = "oar-p2p-cadvisor";
+
+/// name of the container that scrapes the exporters of every machine.
+pub const PROMETHEUS_CONTAINER_NAME: &str = "oar-p2p-prometheus";
+
+/// names of all monitoring containers, they outlive runs and are only removed by `monitor down`.
+pub const CONTAINER_NAMES: &[&str] = &[
+    NODE_EXPORTER_CONTAINER_NAME,
+    CADVISOR_CONTAINER_NAME,
+    PROMETHEUS_CONTAINER_NAME,
+];
+
+pub const NODE_EXPORTER_IMAGE: &str = "quay.io/prometheus/node-exporter:v1.8.2";
+pub const CADVISOR_IMAGE: &str = "gcr.io/cadvisor/cadvisor:v0.49.1";
+pub const PROMETHEUS_IMAGE: &str = "prom/prometheus:v2.53.0";
+
+pub const NODE_EXPORTER_PORT: u16 = 9100;
+pub const CADVISOR_PORT: u16 = 9101;
+
+/// directory on the prometheus machine with its configuration.
+pub const PROMETHEUS_CONFIG_DIR: &str = "/tmp/oar-p2p-monitor";
+
+/// the url of the prometheus web interface.
+pub fn prometheus_url(machine: Machine, port: u16) -> String {
+    format!("http://{}:{port}", machine.hostname())
+}
+
+/// script that (re)starts node-exporter and cadvisor on a machine.
+pub fn exporters_up_script(runtime: ContainerRuntime) -> String {
+    let mut script = String::default();
+    script.push_str("set -e\n");
+    script.push_str(&format!(
+        "{runtime} rm -f {NODE_EXPORTER_CONTAINER_NAME} {CADVISOR_CONTAINER_NAME} >/dev/null 2>&1 || true\n"
+    ));
+    script.push_str(&format!(
+        "{runtime} run -d --name {NODE_EXPORTER_CONTAINER_NAME} --restart=always --net=host --pid=host -v /:/host:ro,rslave {NODE_EXPORTER_IMAGE} --path.rootfs=/host --web.listen-address=:{NODE_EXPORTER_PORT} >/dev/null\n"
+    ));
+    script.push_str(&format!(
+        "{runtime} run -d --name {CADVISOR_CONTAINER_NAME} --restart=always --net=host --privileged --device=/dev/kmsg -v /:/rootfs:ro -v /var/run:/var/run:ro -v /sys:/sys:ro -v /var/lib/docker/:/var/lib/docker:ro -v /dev/disk/:/dev/disk:ro {CADVISOR_IMAGE} --port={CADVISOR_PORT} >/dev/null\n"
+    ));
+    script
+}
+
+/// prometheus configuration that scrapes the exporters of `machines`.
+pub fn prometheus_config(machines: &[Machine], scrape_interval: u64) -> String {
+    let targets = |port: u16| {
+        machines
+            .iter()
+            .map(|m| format!("'{}:{port}'", m.hostname()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut config = String::default();
+    config.push_str("global:\n");
+    config.push_str(&format!("  scrape_interval: {scrape_interval}s\n"));
+    config.push_str("scrape_configs:\n");
+    for (job, port) in [("node", NODE_EXPORTER_PORT), ("cadvisor", CADVISOR_PORT)] {
+        config.push_str(&format!("  - job_name: {job}\n"));
+        config.push_str("    static_configs:\n");
+        config.push_str(&format!("      - targets: [{}]\n", targets(port)));
+    }
+    config
+}
+
+/// script that (re)starts prometheus with `config` on the machine it runs on.
+pub fn prometheus_up_script(runtime: ContainerRuntime, config: &str, port: u16) -> String {
+    let mut script = String::default();
+    script.push_str("set -e\n");
+    script.push_str(&format!(
+        "{runtime} rm -f {PROMETHEUS_CONTAINER_NAME} >/dev/null 2>&1 || true\n"
+    ));
+    script.push_str(&format!("mkdir -p {PROMETHEUS_CONFIG_DIR}\n"));
+    script.push_str(&format!(
+        "cat << 'EOF' > {PROMETHEUS_CONFIG_DIR}/prometheus.yml\n{config}EOF\n"
+    ));
+    script.push_str(&format!(
+        "{runtime} run -d --name {PROMETHEUS_CONTAINER_NAME} --restart=always --net=host -v {PROMETHEUS_CONFIG_DIR}/prometheus.yml:/etc/prometheus/prometheus.yml:ro {PROMETHEUS_IMAGE} --config.file=/etc/prometheus/prometheus.yml --web.listen-address=:{port} >/dev/null\n"
+    ));
+    script
+}
+
+/// script that removes every monitoring container from a machine.
+pub fn down_script(runtime: ContainerRuntime) -> String {
+    let mut script = format!(
+        "{runtime} rm -f {} >/dev/null 2>&1 || true\n",
+        CONTAINER_NAMES.join(" ")
+    );
+    script.push_str(&format!("rm -rf {PROMETHEUS_CONFIG_DIR}\n"));
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_config() {
+        let config = prometheus_config(&[Machine::Gengar1, Machine::Gengar2], 5);
+        assert!(config.contains("  scrape_interval: 5s\n"));
+        assert!(config.contains("      - targets: ['gengar-1:9100', 'gengar-2:9100']\n"));
+        assert!(config.contains("      - targets: ['gengar-1:9101', 'gengar-2:9101']\n"));
+
+        let script = prometheus_up_script(ContainerRuntime::Docker, &config, 9090);
+        assert!(script.contains(&format!(
+            "cat << 'EOF' > {PROMETHEUS_CONFIG_DIR}/prometheus.yml\n{config}EOF\n"
+        )));
+        assert!(script.contains("--web.listen-address=:9090"));
+    }
+}
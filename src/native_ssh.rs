@@ -0,0 +1,479 @@
+use std::{
+    collections::HashMap,
+    os::unix::process::ExitStatusExt as _,
+    path::PathBuf,
+    process::{ExitStatus, Output},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use eyre::{Context as _, Result};
+use russh::{
+    ChannelMsg,
+    client::{self, Handle},
+    keys::{
+        PrivateKeyWithHashAlg, PublicKeyOrCertificate,
+        agent::{AgentIdentity, client::AgentClient},
+    },
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt as _},
+    sync::OnceCell,
+};
+
+use crate::machine::Machine;
+
+/// port of the ssh server on the machines.
+const SSH_PORT: u16 = 22;
+
+/// time to open a connection, from the tcp connection to the authentication.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// interval between keepalives, a connection is closed after 3 keepalives without an answer.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// private keys in `~/.ssh` tried, in order, after the keys of ssh-agent and the identity files
+/// of the frontend's entry in `~/.ssh/config`.
+const DEFAULT_KEY_FILES: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// exit code of ssh when it failed itself, or the command was killed by a signal.
+const SSH_FAILURE_CODE: i32 = 255;
+
+/// how the machines are reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// the machines are connected to directly, from the frontend.
+    Direct,
+    /// the machines are connected to through the frontend, like `ssh -J <frontend>`.
+    Jump(String),
+}
+
+/// the machine's connection once opened, `None` if it could not be opened.
+type SessionCell = OnceCell<Option<Arc<Session>>>;
+
+/// the embedded ssh client of a context, one connection is opened per machine the first time a
+/// command is sent to it and all commands are multiplexed over it.
+pub struct NativeSsh {
+    route: Route,
+    /// user on the machines.
+    user: String,
+    config: Arc<client::Config>,
+    /// the connection to the frontend, when jumping through it, or why it could not be opened.
+    frontend: OnceCell<Result<Arc<Handle<Client>>, String>>,
+    sessions: Mutex<HashMap<Machine, Arc<SessionCell>>>,
+}
+
+impl std::fmt::Debug for NativeSsh {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeSsh")
+            .field("route", &self.route)
+            .field("user", &self.user)
+            .finish_non_exhaustive()
+    }
+}
+
+impl NativeSsh {
+    pub fn new(route: Route, user: String) -> Self {
+        Self {
+            route,
+            user,
+            config: Arc::new(client::Config {
+                inactivity_timeout: None,
+                keepalive_interval: Some(KEEPALIVE_INTERVAL),
+                keepalive_max: 3,
+                ..Default::default()
+            }),
+            frontend: OnceCell::default(),
+            sessions: Mutex::default(),
+        }
+    }
+
+    /// the connection to `machine`, opened if this is the first request for it. `None` if it could
+    /// not be opened, the commands are then sent with the ssh binary.
+    pub async fn session(&self, machine: Machine) -> Option<Arc<Session>> {
+        let cell = self
+            .sessions
+            .lock()
+            .unwrap()
+            .entry(machine)
+            .or_default()
+            .clone();
+        cell.get_or_init(|| async {
+            match tokio::time::timeout(CONNECT_TIMEOUT, self.connect(machine)).await {
+                Ok(Ok(session)) => Some(Arc::new(session)),
+                Ok(Err(err)) => {
+                    tracing::warn!("failed to connect to {machine} with the embedded ssh client, using ssh: {err:#}");
+                    None
+                }
+                Err(_) => {
+                    tracing::warn!("timed out connecting to {machine} with the embedded ssh client, using ssh");
+                    None
+                }
+            }
+        })
+        .await
+        .clone()
+    }
+
+    /// stop using the connection to `machine`, after it dropped, so its commands are sent with the
+    /// ssh binary.
+    pub fn disable(&self, machine: Machine) {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(machine, Arc::new(OnceCell::new_with(Some(None))));
+    }
+
+    async fn connect(&self, machine: Machine) -> Result<Session> {
+        let mut handle = match &self.route {
+            Route::Direct => client::connect(
+                self.config.clone(),
+                (machine.hostname(), SSH_PORT),
+                Client::Any,
+            )
+            .await
+            .context("connecting")?,
+            Route::Jump(frontend) => {
+                let frontend = self
+                    .frontend
+                    .get_or_init(|| async {
+                        tokio::time::timeout(
+                            CONNECT_TIMEOUT,
+                            connect_frontend(&self.config, frontend),
+                        )
+                        .await
+                        .unwrap_or_else(|_| Err(eyre::eyre!("timed out")))
+                        .map(Arc::new)
+                        .map_err(|err| format!("connecting to the frontend {frontend}: {err:#}"))
+                    })
+                    .await
+                    .clone()
+                    .map_err(|err| eyre::eyre!(err))?;
+                let channel = frontend
+                    .channel_open_direct_tcpip(machine.hostname(), SSH_PORT.into(), "127.0.0.1", 0)
+                    .await
+                    .context("forwarding through the frontend")?;
+                client::connect_stream(self.config.clone(), channel.into_stream(), Client::Any)
+                    .await
+                    .context("connecting through the frontend")?
+            }
+        };
+        authenticate(&mut handle, &self.user, &[]).await?;
+        tracing::debug!("connected to {machine} with the embedded ssh client");
+        Ok(Session { machine, handle })
+    }
+}
+
+/// the channel for a command could not be opened, the connection is no longer usable and the
+/// command was not started.
+#[derive(Debug, Error)]
+#[error("connection to {machine} lost: {reason}")]
+pub struct ConnectionLost {
+    pub machine: Machine,
+    pub reason: String,
+}
+
+/// the connection to a machine, commands can be run concurrently.
+pub struct Session {
+    machine: Machine,
+    handle: Handle<Client>,
+}
+
+impl Session {
+    /// run `command` on the machine with `stdin`, like `ssh <machine> <command>`, and collect its
+    /// output.
+    ///
+    /// fails with [`ConnectionLost`] if the command could not be started. a connection that drops
+    /// while the command runs is reported like ssh does, with exit code 255.
+    pub async fn run(&self, command: &str, stdin: Option<&[u8]>) -> Result<Output> {
+        let mut stdout = Vec::default();
+        let mut stderr = Vec::default();
+        let status = self
+            .exec(command, stdin.unwrap_or_default(), &mut stdout, &mut stderr)
+            .await?;
+        Ok(Output {
+            status,
+            stdout,
+            stderr,
+        })
+    }
+
+    /// like [`Session::run`] but `stdin` is copied to the command while its stdout and stderr are
+    /// written to `stdout` and `stderr` as they arrive, so large transfers are never held in
+    /// memory.
+    pub async fn exec<I, O, E>(
+        &self,
+        command: &str,
+        stdin: I,
+        stdout: &mut O,
+        stderr: &mut E,
+    ) -> Result<ExitStatus>
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+        E: AsyncWrite + Unpin,
+    {
+        let lost = |err: russh::Error| ConnectionLost {
+            machine: self.machine,
+            reason: err.to_string(),
+        };
+        let channel = self.handle.channel_open_session().await.map_err(lost)?;
+        channel.exec(true, command).await.map_err(lost)?;
+        let (mut reader, writer) = channel.split();
+
+        let exit = {
+            let send = async {
+                // the command may exit without reading its stdin, its output is still collected
+                if writer.data(stdin).await.is_ok() {
+                    let _ = writer.eof().await;
+                }
+            };
+            let receive = async {
+                let mut exit = None;
+                while let Some(msg) = reader.wait().await {
+                    match msg {
+                        ChannelMsg::Data { data } => stdout.write_all(&data).await?,
+                        ChannelMsg::ExtendedData { data, ext: 1 } => {
+                            stderr.write_all(&data).await?
+                        }
+                        ChannelMsg::ExitStatus { exit_status } => {
+                            exit = Some(Exit::Code(exit_status))
+                        }
+                        ChannelMsg::ExitSignal { signal_name, .. } => {
+                            exit = Some(Exit::Signal(format!("{signal_name:?}")))
+                        }
+                        _ => {}
+                    }
+                }
+                Ok::<_, std::io::Error>(exit)
+            };
+            tokio::pin!(receive);
+            tokio::select! {
+                exit = &mut receive => exit,
+                () = send => receive.await,
+            }
+        }
+        .context("writing the output of the command")?;
+
+        let (status, message) = exit_status(self.machine, exit);
+        if let Some(message) = message {
+            stderr
+                .write_all(message.as_bytes())
+                .await
+                .context("writing the output of the command")?;
+        }
+        stdout
+            .flush()
+            .await
+            .context("writing the output of the command")?;
+        stderr
+            .flush()
+            .await
+            .context("writing the output of the command")?;
+        Ok(status)
+    }
+}
+
+/// how a command run over a channel exited, `None` if the channel closed before it exited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Exit {
+    Code(u32),
+    Signal(String),
+}
+
+/// the status ssh would have exited with, and the message it would have printed, for a command
+/// that exited with `exit`.
+fn exit_status(machine: Machine, exit: Option<Exit>) -> (ExitStatus, Option<String>) {
+    let (code, message) = match exit {
+        Some(Exit::Code(code)) => (code as i32, None),
+        Some(Exit::Signal(signal)) => (
+            SSH_FAILURE_CODE,
+            Some(format!(
+                "ssh: command on {machine} killed by signal {signal}\n"
+            )),
+        ),
+        None => (
+            SSH_FAILURE_CODE,
+            Some(format!("connection to {machine} closed by remote host.\n")),
+        ),
+    };
+    (ExitStatus::from_raw(code << 8), message)
+}
+
+/// the host key check of a connection.
+pub enum Client {
+    /// the key must be in `~/.ssh/known_hosts`, for the frontend.
+    Known { host: String, port: u16 },
+    /// any key is accepted, like the ssh binary does for the machines with
+    /// `StrictHostKeyChecking=no`, their keys change when they are redeployed.
+    Any,
+}
+
+impl client::Handler for Client {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        key: &PublicKeyOrCertificate,
+    ) -> Result<bool, Self::Error> {
+        match (self, key) {
+            (Client::Any, _) => Ok(true),
+            (Client::Known { host, port }, PublicKeyOrCertificate::PublicKey { key, .. }) => {
+                Ok(russh::keys::check_known_hosts(host, *port, key)?)
+            }
+            (Client::Known { .. }, PublicKeyOrCertificate::Certificate(_)) => Ok(false),
+        }
+    }
+}
+
+/// connect to the frontend with its entry in `~/.ssh/config`, the hostname, port, user and identity
+/// files are honored. entries that proxy the connection are not supported.
+async fn connect_frontend(config: &Arc<client::Config>, frontend: &str) -> Result<Handle<Client>> {
+    let host_config = russh_config::parse_home(frontend)
+        .unwrap_or_else(|_| russh_config::Config::default(frontend));
+    if host_config.host_config.proxy_jump.is_some()
+        || host_config.host_config.proxy_command.is_some()
+    {
+        return Err(eyre::eyre!(
+            "the ssh config of {frontend} proxies the connection, which the embedded client does not support"
+        ));
+    }
+    let host = host_config.host().to_string();
+    let port = host_config.port();
+    let mut handle = client::connect(
+        config.clone(),
+        (host.as_str(), port),
+        Client::Known {
+            host: host.clone(),
+            port,
+        },
+    )
+    .await
+    .map_err(|err| match err {
+        russh::Error::UnknownKey => eyre::eyre!(
+            "the host key of {host} is not in ~/.ssh/known_hosts, connect to it with ssh once to add it"
+        ),
+        err => eyre::Report::new(err).wrap_err("connecting"),
+    })?;
+    let identity_files = host_config
+        .host_config
+        .identity_file
+        .clone()
+        .unwrap_or_default();
+    authenticate(&mut handle, &host_config.user(), &identity_files).await?;
+    Ok(handle)
+}
+
+/// authenticate as `user` with the keys of ssh-agent, then with the unencrypted keys in
+/// `identity_files` and `~/.ssh`.
+async fn authenticate(
+    handle: &mut Handle<Client>,
+    user: &str,
+    identity_files: &[PathBuf],
+) -> Result<()> {
+    let rsa_hash = handle
+        .best_supported_rsa_hash()
+        .await
+        .context("negotiating the signature algorithm")?
+        .flatten();
+
+    if let Ok(mut agent) = AgentClient::connect_env().await {
+        let identities = match agent.request_identities().await {
+            Ok(identities) => identities,
+            Err(err) => {
+                tracing::debug!("listing the keys of ssh-agent: {err}");
+                Vec::default()
+            }
+        };
+        for identity in identities {
+            let AgentIdentity::PublicKey { key, .. } = identity else {
+                continue;
+            };
+            let hash_alg = if key.algorithm().is_rsa() {
+                rsa_hash
+            } else {
+                None
+            };
+            match handle
+                .authenticate_publickey_with(user, key, hash_alg, &mut agent)
+                .await
+            {
+                Ok(result) if result.success() => return Ok(()),
+                Ok(_) => {}
+                Err(err) => tracing::debug!("authenticating with ssh-agent: {err}"),
+            }
+        }
+    }
+
+    let default_key_files = std::env::var_os("HOME")
+        .map(|home| {
+            DEFAULT_KEY_FILES
+                .iter()
+                .map(|name| PathBuf::from(&home).join(".ssh").join(name))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    for path in identity_files.iter().chain(&default_key_files) {
+        let key = match russh::keys::load_secret_key(path, None) {
+            Ok(key) => key,
+            Err(err) => {
+                tracing::trace!("skipping key {}: {err}", path.display());
+                continue;
+            }
+        };
+        let key = PrivateKeyWithHashAlg::new(Arc::new(key), rsa_hash);
+        if handle
+            .authenticate_publickey(user, key)
+            .await
+            .context("authenticating")?
+            .success()
+        {
+            return Ok(());
+        }
+    }
+
+    Err(eyre::eyre!(
+        "no key was accepted for {user}, the keys of ssh-agent and the unencrypted keys in ~/.ssh were tried"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_status() {
+        let (status, message) = exit_status(Machine::Gengar1, Some(Exit::Code(2)));
+        assert_eq!(status.code(), Some(2));
+        assert_eq!(message, None);
+
+        let (status, message) =
+            exit_status(Machine::Gengar1, Some(Exit::Signal(String::from("KILL"))));
+        assert_eq!(status.code(), Some(SSH_FAILURE_CODE));
+        assert!(message.unwrap().contains("KILL"));
+    }
+
+    #[test]
+    fn test_exit_status_closed_is_not_transient() {
+        // the command may have run, it is not safe to run it again
+        let (status, message) = exit_status(Machine::Gengar1, None);
+        let output = Output {
+            status,
+            stdout: Vec::default(),
+            stderr: message.unwrap().into_bytes(),
+        };
+        assert_eq!(output.status.code(), Some(SSH_FAILURE_CODE));
+        assert!(!crate::retry::is_transient(
+            &output,
+            crate::retry::Retry::Connection
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_disable() {
+        let native = NativeSsh::new(Route::Direct, String::from("user"));
+        native.disable(Machine::Gengar1);
+        assert!(native.session(Machine::Gengar1).await.is_none());
+    }
+}
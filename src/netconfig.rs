@@ -0,0 +1,834 @@
+use std::{collections::BTreeMap, net::Ipv4Addr, sync::Arc};
+
+use eyre::Result;
+
+use crate::{
+    address_allocation_policy::{AddressAllocationPolicy, MachineWeight},
+    fingerprint,
+    latency_matrix::LatencyMatrix,
+    machine::Machine,
+};
+
+/// default number of nft map elements loaded by each `nft` invocation.
+pub const DEFAULT_NFT_CHUNK_SIZE: std::num::NonZeroUsize =
+    std::num::NonZeroUsize::new(50_000).unwrap();
+
+#[derive(Debug, Clone)]
+pub struct MachineConfig {
+    pub machine: Machine,
+    pub addresses: Vec<Ipv4Addr>,
+    /// index of each address among all addresses, which selects its latency matrix row
+    pub address_indices: Vec<usize>,
+    /// the nft table, with an empty `mark_pairs` map
+    pub nft_script: String,
+    /// elements of the nft `mark_pairs` map
+    pub nft_map_elements: MarkPairs,
+    pub tc_commands: Vec<String>,
+    pub ip_commands: Vec<String>,
+    /// hash of everything applied to the machine, see [`machine_config_fingerprint`]
+    pub fingerprint: String,
+}
+
+/// the elements of the nft `mark_pairs` map of a machine, the source and destination addresses and
+/// the mark, generated as they are iterated so only the addresses are kept in memory.
+#[derive(Debug, Clone)]
+pub struct MarkPairs {
+    pub matrix: Arc<LatencyMatrix>,
+    pub matrix_wrap: bool,
+    /// every address, shared by the configurations of all machines
+    pub addresses: Arc<[Ipv4Addr]>,
+    /// the indices of the machine's own addresses
+    pub range: std::ops::Range<usize>,
+    /// the latencies, in milliseconds, and their marks sorted by latency. a sorted vector is
+    /// looked up much faster than a hash map in the loops over every pair
+    pub marks: Vec<(u32, usize)>,
+    /// whether the map has `flags interval` and its elements are [`MarkPairs::intervals`]
+    pub intervals: bool,
+}
+
+/// an element of a map with `flags interval`, the inclusive ranges of the indices of the source
+/// and destination addresses and the mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkInterval {
+    pub src: (usize, usize),
+    pub dst: (usize, usize),
+    pub mark: usize,
+}
+
+impl MarkPairs {
+    fn latency_millis(&self, src_idx: usize, dst_idx: usize) -> u32 {
+        let latency = match self.matrix_wrap {
+            true => self.matrix.latency(
+                src_idx % self.matrix.dimension(),
+                dst_idx % self.matrix.dimension(),
+            ),
+            false => self.matrix.latency(src_idx, dst_idx),
+        };
+        u32::try_from(latency.as_millis()).unwrap()
+    }
+
+    /// the indices of every pair with a local source address.
+    fn pairs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.range.clone().flat_map(move |src_idx| {
+            (0..self.addresses.len())
+                .filter(move |&dst_idx| dst_idx != src_idx)
+                .map(move |dst_idx| (src_idx, dst_idx))
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.range.len() * self.addresses.len().saturating_sub(1)
+    }
+
+    fn mark(&self, latency_millis: u32) -> usize {
+        let idx = self
+            .marks
+            .binary_search_by_key(&latency_millis, |&(latency, _)| latency)
+            .expect("every latency has a mark");
+        self.marks[idx].1
+    }
+
+    /// every element, with the indices of the source and destination addresses and the mark.
+    fn iter(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        self.pairs().map(|(src_idx, dst_idx)| {
+            let mark = self.mark(self.latency_millis(src_idx, dst_idx));
+            (src_idx, dst_idx, mark)
+        })
+    }
+
+    /// the destinations of `src_idx` as runs of consecutive addresses of the same machine with the
+    /// same mark, `(first, last, mark)`.
+    fn runs(&self, src_idx: usize) -> Vec<(usize, usize, usize)> {
+        let mut runs = Vec::<(usize, usize, usize)>::default();
+        for dst_idx in (0..self.addresses.len()).filter(|&i| i != src_idx) {
+            let mark = self.mark(self.latency_millis(src_idx, dst_idx));
+            match runs.last_mut() {
+                // the addresses of a machine are consecutive and share the second octet
+                Some((_, last, run_mark))
+                    if *last + 1 == dst_idx
+                        && *run_mark == mark
+                        && self.addresses[*last].octets()[1]
+                            == self.addresses[dst_idx].octets()[1] =>
+                {
+                    *last = dst_idx
+                }
+                _ => runs.push((dst_idx, dst_idx, mark)),
+            }
+        }
+        runs
+    }
+
+    /// the elements as rectangles of addresses, a destination run shared by consecutive source
+    /// addresses is a single element.
+    ///
+    /// only the runs that are still growing are kept in memory, the intervals never overlap since
+    /// the runs of a source partition its destinations.
+    fn intervals(&self) -> impl Iterator<Item = MarkInterval> + '_ {
+        let close = |((first, last, mark), src_first), src_last| MarkInterval {
+            src: (src_first, src_last),
+            dst: (first, last),
+            mark,
+        };
+        let mut open = BTreeMap::<(usize, usize, usize), usize>::default();
+        let mut sources = self.range.clone();
+        let mut closed = std::collections::VecDeque::default();
+        let mut finished = false;
+        std::iter::from_fn(move || {
+            loop {
+                if let Some(interval) = closed.pop_front() {
+                    return Some(interval);
+                }
+                if finished {
+                    return None;
+                }
+                match sources.next() {
+                    Some(src_idx) => {
+                        let mut next = BTreeMap::default();
+                        for run in self.runs(src_idx) {
+                            let src_first = open.remove(&run).unwrap_or(src_idx);
+                            next.insert(run, src_first);
+                        }
+                        let ended = std::mem::replace(&mut open, next);
+                        closed.extend(ended.into_iter().map(|run| close(run, src_idx - 1)));
+                    }
+                    None => {
+                        finished = true;
+                        let ended = std::mem::take(&mut open);
+                        closed.extend(ended.into_iter().map(|run| close(run, self.range.end - 1)));
+                    }
+                }
+            }
+        })
+    }
+
+    /// the elements loaded into the map, one per pair unless `intervals` is set.
+    fn elements(&self) -> Box<dyn Iterator<Item = MarkInterval> + '_> {
+        match self.intervals {
+            true => Box::new(self.intervals()),
+            false => Box::new(self.iter().map(|(src_idx, dst_idx, mark)| MarkInterval {
+                src: (src_idx, src_idx),
+                dst: (dst_idx, dst_idx),
+                mark,
+            })),
+        }
+    }
+
+    /// number of elements loaded into the map.
+    pub fn element_count(&self) -> usize {
+        match self.intervals {
+            true => self.intervals().count(),
+            false => self.len(),
+        }
+    }
+}
+
+pub fn machine_configuration_script(config: &MachineConfig) -> String {
+    let mut script = String::default();
+    // arp cache limit increase
+    script.push_str("echo 8192 > /proc/sys/net/ipv4/neigh/default/gc_thresh1\n");
+    script.push_str("echo 16384 > /proc/sys/net/ipv4/neigh/default/gc_thresh2\n");
+    script.push_str("echo 32768 > /proc/sys/net/ipv4/neigh/default/gc_thresh3\n");
+
+    // tcp max orphan limit
+    script.push_str("echo 524288 > /proc/sys/net/ipv4/tcp_max_orphans\n");
+
+    // exit docker swarm and remove all networks
+    script.push_str("docker swarm leave --force || true\n");
+    script.push_str("docker network ls -q | xargs docker network rm -f || true\n");
+
+    // ip configuration
+    script.push_str("cat << EOF | ip -b -\n");
+    for command in config.ip_commands.iter() {
+        script.push_str(command);
+        script.push('\n');
+    }
+    script.push_str("\nEOF\n");
+
+    // tc configuration
+    script.push_str("cat << EOF | tc -b -\n");
+    for command in config.tc_commands.iter() {
+        script.push_str(command);
+        script.push('\n');
+    }
+    script.push_str("\nEOF\n");
+
+    // nft configuration
+    script.push_str("cat << EOF | nft -f -\n");
+    script.push_str(&config.nft_script);
+    script.push_str("\nEOF\n");
+    script
+}
+
+/// scripts that add the elements of the `mark_pairs` map, `chunk_size` elements per script.
+///
+/// the scripts are generated as they are consumed, so only a chunk is in memory at a time.
+pub fn machine_nft_chunk_scripts(
+    config: &MachineConfig,
+    chunk_size: usize,
+) -> impl Iterator<Item = String> + '_ {
+    // every address and mark is formatted once instead of once per element
+    let pairs = &config.nft_map_elements;
+    let addresses = pairs
+        .addresses
+        .iter()
+        .map(Ipv4Addr::to_string)
+        .collect::<Vec<_>>();
+    let marks = (0..=pairs.marks.len())
+        .map(|mark| mark.to_string())
+        .collect::<Vec<_>>();
+    let push_range = move |script: &mut String, (first, last): (usize, usize)| {
+        script.push_str(&addresses[first]);
+        if first != last {
+            script.push('-');
+            script.push_str(&addresses[last]);
+        }
+    };
+    // `\t255.255.255.255 . 255.255.255.255 : 65535,\n`
+    let capacity = chunk_size.min(pairs.len()) * 46 + 64;
+    let mut elements = pairs.elements().peekable();
+    std::iter::from_fn(move || {
+        elements.peek()?;
+        let mut script = String::with_capacity(capacity);
+        script.push_str("cat << EOF | nft -f -\n");
+        script.push_str("add element ip oar-p2p mark_pairs {\n");
+        for element in elements.by_ref().take(chunk_size) {
+            script.push('\t');
+            push_range(&mut script, element.src);
+            script.push_str(" . ");
+            push_range(&mut script, element.dst);
+            script.push_str(" : ");
+            script.push_str(&marks[element.mark]);
+            script.push_str(",\n");
+        }
+        script.push_str("}\n");
+        script.push_str("EOF\n");
+        Some(script)
+    })
+}
+
+pub fn machine_address_for_idx(machine: Machine, idx: u32) -> Ipv4Addr {
+    let c = u8::try_from(idx / 254).unwrap();
+    let d = u8::try_from(idx % 254 + 1).unwrap();
+    Ipv4Addr::new(10, machine.index().try_into().unwrap(), c, d)
+}
+
+/// generate the network configuration for every machine.
+///
+/// latency is applied by the sender, so the `mark_pairs` map of a machine only contains the pairs
+/// whose source address is one of its own addresses. a packet leaving a machine always has one of
+/// that machine's addresses as the source, so pairs sourced at other machines could never match.
+/// with `L` local addresses and `T` total addresses a machine's map has `L * (T - 1)` elements and
+/// the sum over all machines is `T * (T - 1)`.
+///
+/// symmetric pairs cannot be deduplicated even when the matrix is symmetric: `a -> b` is in the
+/// map of `a`'s machine and `b -> a` in the map of `b`'s machine, and each sender has to find the
+/// latency of its own packets in its own map.
+///
+/// the configuration of each machine is generated on its own thread. the map elements are not
+/// kept in memory, see [`MarkPairs`].
+pub fn machine_generate_configs(
+    matrix: &Arc<LatencyMatrix>,
+    matrix_wrap: bool,
+    machines: &[Machine],
+    addr_policy: &AddressAllocationPolicy,
+    weights: &[MachineWeight],
+    nft_intervals: bool,
+) -> Result<Vec<MachineConfig>> {
+    if machines.is_empty() {
+        return Err(eyre::eyre!("cannot generate config for zero machines"));
+    }
+
+    // gather all addresses across all machines, the addresses of a machine are contiguous
+    let cpus = machines.iter().map(|m| m.cpus()).collect::<Vec<_>>();
+    let weights = machines
+        .iter()
+        .map(|m| MachineWeight::resolve(weights, m.hostname()))
+        .collect::<Vec<_>>();
+    let counts = addr_policy.addresses_per_machine_weighted(&cpus, &weights);
+    if let AddressAllocationPolicy::Capped(policy, max) = addr_policy
+        && let AddressAllocationPolicy::Total(n) = **policy
+        && counts.iter().sum::<u32>() < n
+    {
+        return Err(eyre::eyre!(
+            "cannot allocate {n} addresses with at most {max} per machine on {} machines",
+            machines.len()
+        ));
+    }
+    let mut addresses = Vec::default();
+    let mut machine_ranges = Vec::default();
+    for (&machine, count) in machines.iter().zip(counts) {
+        let first = addresses.len();
+        for i in 0..count {
+            addresses.push(machine_address_for_idx(machine, i));
+        }
+        machine_ranges.push((machine, first..addresses.len()));
+    }
+
+    // with wrapping any dimension works, otherwise every address needs its own row and a bigger
+    // matrix means the policy allocates fewer addresses than the matrix was made for
+    if !matrix_wrap && addresses.len() != matrix.dimension() {
+        return Err(eyre::eyre!(
+            "{}",
+            matrix_dimension_mismatch(matrix.dimension(), &machine_ranges)
+        ));
+    }
+
+    let addresses = Arc::<[Ipv4Addr]>::from(addresses);
+    let configs = std::thread::scope(|scope| {
+        let handles = machine_ranges
+            .into_iter()
+            .map(|(machine, range)| {
+                let addresses = addresses.clone();
+                scope.spawn(move || {
+                    machine_generate_config(
+                        matrix,
+                        matrix_wrap,
+                        machine,
+                        addresses,
+                        range,
+                        nft_intervals,
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("config generation panicked"))
+            .collect::<Vec<_>>()
+    });
+    Ok(configs)
+}
+
+/// error message for a latency matrix whose dimension differs from the number of addresses, with
+/// the number of addresses of every machine.
+fn matrix_dimension_mismatch(
+    dimension: usize,
+    machine_ranges: &[(Machine, std::ops::Range<usize>)],
+) -> String {
+    let total = machine_ranges.iter().map(|(_, r)| r.len()).sum::<usize>();
+    let mut message = format!(
+        "latency matrix has dimension {dimension} but {total} addresses would be allocated, adjust --addresses or pass --matrix-wrap to reuse the matrix"
+    );
+    for (machine, range) in machine_ranges {
+        message.push_str(&format!("\n  {machine}: {} addresses", range.len()));
+    }
+    message
+}
+
+/// generate the network configuration of a machine whose addresses are `addresses[range]`.
+fn machine_generate_config(
+    matrix: &Arc<LatencyMatrix>,
+    matrix_wrap: bool,
+    machine: Machine,
+    addresses: Arc<[Ipv4Addr]>,
+    range: std::ops::Range<usize>,
+    nft_intervals: bool,
+) -> MachineConfig {
+    let machine_addresses = &addresses[range.clone()];
+    let mut machine_ip_commands = Vec::default();
+    let mut machine_tc_commands = Vec::default();
+    let mut machine_nft_script = String::default();
+
+    machine_ip_commands.push(format!("route add 10.0.0.0/8 dev {}", machine.interface()));
+    for address in machine_addresses.iter() {
+        machine_ip_commands.push(format!("addr add {address}/32 dev {}", machine.interface()));
+    }
+
+    // only pairs with a local source address, see the documentation of
+    // `machine_generate_configs`. marks are given to latencies in the order they are first seen.
+    let mut nft_map_elements = MarkPairs {
+        matrix: matrix.clone(),
+        matrix_wrap,
+        addresses: addresses.clone(),
+        range: range.clone(),
+        marks: Vec::default(),
+        intervals: nft_intervals,
+    };
+    let mut latencies_buckets = Vec::<u32>::default();
+    let mut marks = Vec::<(u32, usize)>::default();
+    let mut latencies = fingerprint::Fingerprint::default();
+    for (src_idx, dst_idx) in nft_map_elements.pairs() {
+        let latency_millis = nft_map_elements.latency_millis(src_idx, dst_idx);
+        latencies.write(&latency_millis.to_le_bytes());
+        if let Err(idx) = marks.binary_search_by_key(&latency_millis, |&(latency, _)| latency) {
+            latencies_buckets.push(latency_millis);
+            marks.insert(idx, (latency_millis, latencies_buckets.len()));
+        }
+    }
+    nft_map_elements.marks = marks;
+
+    for iface in &["lo", machine.interface()] {
+        machine_tc_commands.push(format!(
+            "qdisc add dev {iface} root handle 1: htb default 9999 r2q 100000"
+        ));
+        machine_tc_commands.push(format!(
+            "class add dev {iface} parent 1: classid 1:9999 htb rate 10gbit"
+        ));
+        for (idx, &latency_millis) in latencies_buckets.iter().enumerate() {
+            // tc class for latency at idx X is X + 1
+            let latency_class_id = idx + 1;
+            // mark for latency at idx X is X + 1
+            let latency_mark = idx + 1;
+
+            machine_tc_commands.push(format!(
+                "class add dev {iface} parent 1: classid 1:{latency_class_id} htb rate 10gbit"
+            ));
+            // why idx + 2 here? I dont remember anymore and forgot to comment
+            machine_tc_commands.push(format!(
+                "qdisc add dev {iface} parent 1:{} handle {}: netem delay {latency_millis}ms",
+                latency_class_id,
+                idx + 2
+            ));
+            // TODO: is the order of these things correct?
+            machine_tc_commands.push(format!(
+                "filter add dev {iface} parent 1:0 prio 1 handle {latency_mark} fw flowid 1:{latency_class_id}",
+            ));
+        }
+    }
+
+    machine_nft_script.push_str("table ip oar-p2p {\n");
+    machine_nft_script.push_str(
+        r#"
+    chain prerouting {
+        type filter hook prerouting priority raw;
+        ip saddr 10.0.0.0/8 notrack
+        ip daddr 10.0.0.0/8 notrack
+    }
+    chain output {
+        type filter hook output priority raw;
+        ip saddr 10.0.0.0/8 notrack
+        ip daddr 10.0.0.0/8 notrack
+    }
+"#,
+    );
+
+    machine_nft_script.push_str("\tmap mark_pairs {\n");
+    machine_nft_script.push_str("\t\ttype ipv4_addr . ipv4_addr : mark\n");
+    if nft_intervals {
+        machine_nft_script.push_str("\t\tflags interval\n");
+    }
+    machine_nft_script.push_str("\t}\n");
+    machine_nft_script.push('\n');
+    machine_nft_script.push_str("\tchain postrouting {\n");
+    machine_nft_script.push_str("\t\ttype filter hook postrouting priority mangle -1\n");
+    machine_nft_script.push_str("\t\tpolicy accept\n");
+    machine_nft_script.push_str("\t\tmeta mark set ip saddr . ip daddr map @mark_pairs counter\n");
+    machine_nft_script.push_str("\t}\n");
+    machine_nft_script.push_str("}\n");
+
+    let mut config = MachineConfig {
+        machine,
+        addresses: machine_addresses.to_vec(),
+        address_indices: range.collect(),
+        nft_script: machine_nft_script,
+        nft_map_elements,
+        tc_commands: machine_tc_commands,
+        ip_commands: machine_ip_commands,
+        fingerprint: String::default(),
+    };
+    config.fingerprint = machine_config_fingerprint(&config, latencies);
+    config
+}
+
+/// hash of the scripts that configure the machine and of every element of its nft map.
+///
+/// the elements are given by the addresses and by `latencies`, the hash of the latency of every
+/// pair in the order of [`MarkPairs::pairs`], which decide the marks.
+fn machine_config_fingerprint(
+    config: &MachineConfig,
+    latencies: fingerprint::Fingerprint,
+) -> String {
+    let mut fingerprint = fingerprint::Fingerprint::default();
+    fingerprint.write_str(&machine_configuration_script(config));
+    for address in config.nft_map_elements.addresses.iter() {
+        fingerprint.write(&address.octets());
+    }
+    fingerprint.write_str(&latencies.hex());
+    fingerprint.hex()
+}
+
+/// what `net up` would configure on a machine, see `net up --dry-run`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NetPlan {
+    pub machine: Machine,
+    pub addresses: usize,
+    /// distinct latencies from the machine's addresses, each gets a mark and a tc class
+    pub latencies: usize,
+    /// tc classes on both `lo` and the interface, including the default class
+    pub tc_classes: usize,
+    pub nft_elements: usize,
+    pub nft_chunks: usize,
+    /// size of all scripts executed in the networking container
+    pub script_bytes: usize,
+}
+
+pub fn net_plan(config: &MachineConfig, nft_chunk_size: usize) -> NetPlan {
+    let latencies = config.nft_map_elements.marks.len();
+    let nft_elements = config.nft_map_elements.element_count();
+    let script_bytes = machine_configuration_script(config).len()
+        + machine_nft_chunk_scripts(config, nft_chunk_size)
+            .map(|s| s.len())
+            .sum::<usize>();
+    NetPlan {
+        machine: config.machine,
+        addresses: config.addresses.len(),
+        latencies,
+        tc_classes: 2 * (latencies + 1),
+        nft_elements,
+        nft_chunks: nft_elements.div_ceil(nft_chunk_size),
+        script_bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::latency_matrix;
+
+    fn test_matrix(dimension: usize) -> Arc<LatencyMatrix> {
+        let mut content = String::default();
+        for row in 0..dimension {
+            for col in 0..dimension {
+                content.push_str(&format!("{} ", row.abs_diff(col) * 10));
+            }
+            content.push('\n');
+        }
+        Arc::new(LatencyMatrix::parse(&content, latency_matrix::TimeUnit::Milliseconds).unwrap())
+    }
+
+    fn map_pairs(config: &MachineConfig) -> Vec<(Ipv4Addr, Ipv4Addr)> {
+        let scripts = machine_nft_chunk_scripts(config, 4).collect::<Vec<_>>();
+        scripts
+            .iter()
+            .flat_map(|script| script.lines())
+            .filter_map(|line| line.trim().strip_suffix(','))
+            .filter_map(|line| {
+                let (pair, _mark) = line.split_once(" : ")?;
+                let (src, dst) = pair.split_once(" . ")?;
+                Some((src.parse().ok()?, dst.parse().ok()?))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nft_chunk_scripts() {
+        let machines = [Machine::Gengar1, Machine::Gengar2];
+        let configs = machine_generate_configs(
+            &test_matrix(6),
+            false,
+            &machines,
+            &AddressAllocationPolicy::PerMachine(3),
+            &[],
+            false,
+        )
+        .unwrap();
+        let config = &configs[0];
+        // 3 local addresses with 5 destinations each
+        assert_eq!(config.nft_map_elements.len(), 15);
+        assert!(!config.nft_script.contains("elements"));
+
+        let scripts = machine_nft_chunk_scripts(config, 4).collect::<Vec<_>>();
+        assert_eq!(scripts.len(), 4);
+        assert!(
+            scripts[0].starts_with("cat << EOF | nft -f -\nadd element ip oar-p2p mark_pairs {\n")
+        );
+        assert!(scripts[0].ends_with("}\nEOF\n"));
+        assert_eq!(scripts[3].matches(" : ").count(), 3);
+        assert_eq!(machine_nft_chunk_scripts(config, 15).count(), 1);
+
+        // the matrix has latencies 10, 20, ... 50ms and the first address sees all of them first
+        let elements = config.nft_map_elements.iter().collect::<Vec<_>>();
+        assert_eq!(elements.len(), config.nft_map_elements.len());
+        let marks = elements[..5].iter().map(|e| e.2).collect::<Vec<_>>();
+        assert_eq!(marks, [1, 2, 3, 4, 5]);
+        assert!(config.tc_commands[3].ends_with("netem delay 10ms"));
+    }
+
+    #[test]
+    fn test_generate_configs_sender_only_pairs() {
+        let machines = [Machine::Gengar1, Machine::Gengar2, Machine::Gengar3];
+        let configs = machine_generate_configs(
+            &test_matrix(9),
+            false,
+            &machines,
+            &AddressAllocationPolicy::PerMachine(3),
+            &[],
+            false,
+        )
+        .unwrap();
+
+        for config in &configs {
+            let pairs = map_pairs(config);
+            assert_eq!(pairs.len(), config.nft_map_elements.len());
+            for (src, dst) in pairs {
+                assert!(config.addresses.contains(&src));
+                assert_ne!(src, dst);
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_configs_element_count() {
+        let machines = [Machine::Gengar1, Machine::Gengar2, Machine::Bulbasaur1];
+        let total = 7;
+        let configs = machine_generate_configs(
+            &test_matrix(total),
+            false,
+            &machines,
+            &AddressAllocationPolicy::Total(total as u32),
+            &[],
+            false,
+        )
+        .unwrap();
+
+        let mut sum = 0;
+        for config in &configs {
+            assert_eq!(
+                config.nft_map_elements.len(),
+                config.addresses.len() * (total - 1)
+            );
+            sum += config.nft_map_elements.len();
+        }
+        assert_eq!(sum, total * (total - 1));
+    }
+
+    #[test]
+    fn test_generate_configs_machine_order() {
+        let machines = [Machine::Gengar3, Machine::Gengar1, Machine::Gengar2];
+        let configs = machine_generate_configs(
+            &test_matrix(6),
+            false,
+            &machines,
+            &AddressAllocationPolicy::PerMachine(2),
+            &[],
+            false,
+        )
+        .unwrap();
+        let order = configs.iter().map(|c| c.machine).collect::<Vec<_>>();
+        assert_eq!(order, machines);
+        let indices = configs
+            .iter()
+            .flat_map(|c| c.address_indices.iter().copied())
+            .collect::<Vec<_>>();
+        assert_eq!(indices, (0..6).collect::<Vec<_>>());
+        assert_eq!(
+            configs[0].addresses[0],
+            machine_address_for_idx(Machine::Gengar3, 0)
+        );
+    }
+
+    #[test]
+    fn test_net_plan() {
+        let configs = machine_generate_configs(
+            &test_matrix(4),
+            false,
+            &[Machine::Gengar1, Machine::Gengar2],
+            &AddressAllocationPolicy::PerMachine(2),
+            &[],
+            false,
+        )
+        .unwrap();
+        let plan = net_plan(&configs[0], 4);
+        // latencies 10, 20 and 30ms from the addresses with index 0 and 1
+        assert_eq!(
+            plan,
+            NetPlan {
+                machine: Machine::Gengar1,
+                addresses: 2,
+                latencies: 3,
+                tc_classes: 8,
+                nft_elements: 6,
+                nft_chunks: 2,
+                script_bytes: plan.script_bytes,
+            }
+        );
+        assert!(plan.script_bytes > machine_configuration_script(&configs[0]).len());
+    }
+
+    #[test]
+    fn test_generate_configs_dimension_mismatch() {
+        let machines = [Machine::Gengar1, Machine::Gengar2];
+        let generate = |dimension, wrap| {
+            machine_generate_configs(
+                &test_matrix(dimension),
+                wrap,
+                &machines,
+                &AddressAllocationPolicy::PerMachine(2),
+                &[],
+                false,
+            )
+        };
+        assert!(generate(4, false).is_ok());
+        for dimension in [3, 5] {
+            let err = generate(dimension, false).unwrap_err().to_string();
+            assert!(err.contains(&format!("dimension {dimension} but 4 addresses")));
+            assert!(err.contains("gengar-1: 2 addresses"));
+            assert!(err.contains("gengar-2: 2 addresses"));
+        }
+        assert!(generate(3, true).is_ok());
+        assert!(generate(5, true).is_ok());
+    }
+
+    #[test]
+    fn test_config_fingerprint() {
+        let machines = [Machine::Gengar1, Machine::Gengar2];
+        let generate = |matrix| {
+            machine_generate_configs(
+                &matrix,
+                false,
+                &machines,
+                &AddressAllocationPolicy::PerMachine(2),
+                &[],
+                false,
+            )
+            .unwrap()
+        };
+        let configs = generate(test_matrix(4));
+        assert_eq!(configs[0].fingerprint.len(), 16);
+        assert_ne!(configs[0].fingerprint, configs[1].fingerprint);
+        assert_eq!(
+            configs[0].fingerprint,
+            generate(test_matrix(4))[0].fingerprint
+        );
+
+        // a single latency of the second machine changes only its own map
+        let mut content = String::default();
+        for row in 0..4usize {
+            for col in 0..4usize {
+                let latency = match (row, col) {
+                    (3, 0) => 15,
+                    _ => row.abs_diff(col) * 10,
+                };
+                content.push_str(&format!("{latency} "));
+            }
+            content.push('\n');
+        }
+        let matrix =
+            LatencyMatrix::parse(&content, latency_matrix::TimeUnit::Milliseconds).unwrap();
+        let changed = generate(Arc::new(matrix));
+        assert_eq!(configs[0].fingerprint, changed[0].fingerprint);
+        assert_ne!(configs[1].fingerprint, changed[1].fingerprint);
+    }
+
+    #[test]
+    fn test_nft_intervals() {
+        // two regions, 10ms within a machine and 50ms between machines
+        let matrix = LatencyMatrix::from_fn(6, |row, col| match (row / 3 == col / 3, row == col) {
+            (_, true) => Duration::ZERO,
+            (true, false) => Duration::from_millis(10),
+            (false, false) => Duration::from_millis(50),
+        });
+        let machines = [Machine::Gengar1, Machine::Gengar2];
+        let generate = |intervals| {
+            machine_generate_configs(
+                &Arc::new(matrix.clone()),
+                false,
+                &machines,
+                &AddressAllocationPolicy::PerMachine(3),
+                &[],
+                intervals,
+            )
+            .unwrap()
+        };
+        let config = &generate(true)[0];
+        assert!(config.nft_script.contains("flags interval"));
+        let pairs = &config.nft_map_elements;
+        let intervals = pairs.intervals().collect::<Vec<_>>();
+        assert_eq!(intervals.len(), 5);
+        assert!(intervals.contains(&MarkInterval {
+            src: (0, 2),
+            dst: (3, 5),
+            mark: pairs.mark(50),
+        }));
+
+        // the intervals cover every pair exactly once
+        let mut covered = intervals
+            .iter()
+            .flat_map(|i| {
+                (i.src.0..=i.src.1)
+                    .flat_map(move |src| (i.dst.0..=i.dst.1).map(move |dst| (src, dst, i.mark)))
+            })
+            .collect::<Vec<_>>();
+        covered.sort();
+        let mut expected = pairs.iter().collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(covered, expected);
+
+        let scripts = machine_nft_chunk_scripts(config, 100).collect::<Vec<_>>();
+        assert!(scripts[0].contains("\t10.16.0.1-10.16.0.3 . 10.17.0.1-10.17.0.3 : "));
+        assert_ne!(config.fingerprint, generate(false)[0].fingerprint);
+    }
+
+    #[test]
+    fn test_generate_configs_capped_total() {
+        let machines = [Machine::Gengar1, Machine::Gengar2];
+        let policy = "5,max=2/machine"
+            .parse::<AddressAllocationPolicy>()
+            .unwrap();
+        let err = machine_generate_configs(&test_matrix(5), false, &machines, &policy, &[], false)
+            .unwrap_err();
+        assert!(err.to_string().contains("at most 2 per machine"), "{err}");
+    }
+}
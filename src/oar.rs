@@ -1,96 +1,297 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    process::Output,
+    time::{Duration, SystemTime},
+};
 
 use eyre::{Context as _, Result};
-use serde::Deserialize;
+use serde::{Deserialize, de::DeserializeOwned};
 use tokio::process::Command;
 
 use crate::{
     context::{Context, ExecutionNode},
     machine::Machine,
+    shell,
 };
 
+/// list the machines of all the context's jobs.
+///
+/// when multiple jobs are used, the machines are the union of the machines of every job.
 pub async fn job_list_machines(ctx: &Context) -> Result<Vec<Machine>> {
-    match ctx.node {
-        ExecutionNode::Frontend => {
-            let job_id = ctx.job_id().await?;
-            let output = Command::new("oarstat")
-                .arg("-j")
-                .arg(job_id.to_string())
-                .arg("-J")
-                .output()
-                .await?;
-
-            if !output.status.success() {
-                tracing::error!(
-                    "stdout: {}",
-                    std::str::from_utf8(&output.stdout).unwrap_or("stderr contains invalid uft-8")
-                );
-                tracing::error!(
-                    "stderr: {}",
-                    std::str::from_utf8(&output.stderr).unwrap_or("stderr contains invalid uft-8")
-                );
-                return Err(eyre::eyre!("failed to run oarstat"));
-            }
+    if let ExecutionNode::Machine(_) = ctx.node {
+        // the nodefile already describes the job we are running in
+        return nodefile_list_machines().await;
+    }
 
-            let stdout = std::str::from_utf8(&output.stdout)?;
-            extract_machines_from_oar_stat_json(stdout, job_id)
+    let mut machines = Vec::default();
+    for job_id in ctx.job_ids().await? {
+        let job_machines = match ctx.oar_api_url() {
+            Some(_) => api_job_info(ctx, job_id).await?.machines,
+            None => job_list_machines_single(ctx, job_id).await?,
+        };
+        for machine in job_machines {
+            if !machines.contains(&machine) {
+                machines.push(machine);
+            }
         }
-        ExecutionNode::Unknown => {
-            let job_id = ctx.job_id().await?;
-            let frontend_hostname = ctx.frontend_hostname()?;
-
-            let output = Command::new("ssh")
-                .arg(frontend_hostname)
-                .arg("oarstat")
-                .arg("-j")
-                .arg(job_id.to_string())
-                .arg("-J")
-                .output()
-                .await?;
+    }
+    Ok(machines)
+}
 
-            if !output.status.success() {
-                return Err(eyre::eyre!("failed to run oarstat"));
-            }
+async fn job_list_machines_single(ctx: &Context, job_id: u32) -> Result<Vec<Machine>> {
+    if let ExecutionNode::Machine(_) = ctx.node {
+        return nodefile_list_machines().await;
+    }
+    let job_id_str = job_id.to_string();
+    let output = frontend_run(ctx, &["oarstat", "-j", &job_id_str, "-J"]).await?;
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    extract_machines_from_oar_stat_json(stdout, job_id)
+}
 
-            let stdout = std::str::from_utf8(&output.stdout)?;
-            extract_machines_from_oar_stat_json(stdout, job_id)
-        }
-        ExecutionNode::Machine(_) => {
-            let nodefile = std::env::var("OAR_NODEFILE").context("reading OAR_NODEFILE env var")?;
-            let content = tokio::fs::read_to_string(&nodefile).await?;
-            let unique_lines = content
-                .lines()
-                .map(|l| l.trim())
-                .filter(|l| !l.is_empty())
-                .collect::<HashSet<_>>();
-            let mut machines = Vec::default();
-            for hostname in unique_lines {
-                let machine = match Machine::from_hostname(hostname) {
-                    Some(machine) => machine,
-                    None => return Err(eyre::eyre!("unknown machine: {hostname}")),
-                };
-                machines.push(machine);
+async fn nodefile_list_machines() -> Result<Vec<Machine>> {
+    let nodefile = std::env::var("OAR_NODEFILE").context("reading OAR_NODEFILE env var")?;
+    let content = tokio::fs::read_to_string(&nodefile).await?;
+    let unique_lines = content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .collect::<HashSet<_>>();
+    let mut machines = Vec::default();
+    for hostname in unique_lines {
+        let machine = match Machine::from_hostname(hostname) {
+            Some(machine) => machine,
+            None => return Err(eyre::eyre!("unknown machine: {hostname}")),
+        };
+        machines.push(machine);
+    }
+    Ok(machines)
+}
+
+pub async fn list_user_job_ids(ctx: &Context) -> Result<Vec<u32>> {
+    if ctx.oar_api_url().is_some() {
+        let jobs = api_list_user_jobs(ctx).await?;
+        return Ok(jobs
+            .into_iter()
+            .filter(|j| j.state == "Running")
+            .map(|j| j.job_id)
+            .collect());
+    }
+    let output = oarstat_user_jobs(ctx).await?;
+    extract_job_ids_from_oarstat_output(&output)
+}
+
+/// states of the jobs listed by [`list_user_jobs`], every state of a job that has not ended.
+const LISTED_STATES: &[&str] = &[
+    "Waiting",
+    "Hold",
+    "toLaunch",
+    "toError",
+    "toAckReservation",
+    "Launching",
+    "Running",
+    "Suspended",
+    "Resuming",
+    "Finishing",
+];
+
+/// list the user's jobs that have not ended, in any of [`LISTED_STATES`], sorted by job id.
+pub async fn list_user_jobs(ctx: &Context) -> Result<Vec<JobInfo>> {
+    if ctx.oar_api_url().is_some() {
+        return api_list_user_jobs(ctx).await;
+    }
+    let output = oarstat_user_jobs(ctx).await?;
+    let mut jobs = extract_jobs_from_oarstat_output(&output)?;
+    // the api is asked for these states only, filter oarstat's output the same way
+    jobs.retain(|job| LISTED_STATES.contains(&job.state.as_str()));
+    Ok(jobs)
+}
+
+async fn oarstat_user_jobs(ctx: &Context) -> Result<String> {
+    let output = frontend_run(ctx, &["oarstat", "-u", "-J"]).await?;
+    let stdout = String::from_utf8(output.stdout)?;
+    // for some reason, running oarstat with the -J flag (for json output) when you have no jobs
+    // running results in this error message instead of an empty object, so we will just assume it
+    // meant an empty object
+    let json_string = if stdout
+        == "hash- or arrayref expected (not a simple scalar, use allow_nonref to allow this) at /usr/lib/oar/oarstat line 285."
+    {
+        String::from("{}")
+    } else {
+        stdout
+    };
+    Ok(json_string)
+}
+
+#[derive(Debug, Clone)]
+pub struct JobInfo {
+    pub job_id: u32,
+    pub state: String,
+    pub machines: Vec<Machine>,
+    pub start_time: Option<SystemTime>,
+    pub walltime: Option<Duration>,
+    pub queue: String,
+    pub types: Vec<String>,
+}
+
+impl JobInfo {
+    /// best-effort jobs can be killed at any time to make room for other jobs.
+    pub fn is_besteffort(&self) -> bool {
+        self.queue == "besteffort" || self.types.iter().any(|t| t == "besteffort")
+    }
+
+    /// whether oar is done with the job, it will not run again.
+    pub fn has_ended(&self) -> bool {
+        matches!(self.state.as_str(), "Terminated" | "Error")
+    }
+
+    /// remaining walltime of the job, if the job has started and its walltime is known.
+    pub fn remaining_walltime(&self) -> Option<Duration> {
+        let end = self.start_time? + self.walltime?;
+        Some(
+            end.duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+}
+
+pub async fn job_info(ctx: &Context, job_id: u32) -> Result<JobInfo> {
+    if ctx.oar_api_url().is_some() {
+        return api_job_info(ctx, job_id).await;
+    }
+    let job_id_str = job_id.to_string();
+    let output = frontend_run(ctx, &["oarstat", "-j", &job_id_str, "-J"]).await?;
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    extract_job_info_from_oar_stat_json(stdout, job_id)
+}
+
+/// poll the job's state until it is running.
+///
+/// fails if the job reaches a final state (`Terminated` or `Error`) or if the timeout expires
+/// before the job starts running.
+pub async fn job_wait_running(
+    ctx: &Context,
+    job_id: u32,
+    timeout: Option<Duration>,
+    interval: Duration,
+) -> Result<JobInfo> {
+    let start = std::time::Instant::now();
+    loop {
+        let info = job_info(ctx, job_id).await?;
+        tracing::debug!("job {} is in state {}", info.job_id, info.state);
+        match info.state.as_str() {
+            "Running" => return Ok(info),
+            _ if info.has_ended() => {
+                return Err(eyre::eyre!(
+                    "job {} will not run, it is in state {}",
+                    info.job_id,
+                    info.state
+                ));
             }
-            Ok(machines)
+            _ => {}
         }
+        if let Some(timeout) = timeout
+            && start.elapsed() + interval > timeout
+        {
+            return Err(eyre::eyre!(
+                "timed out waiting for job {} to start running, last state was {}",
+                info.job_id,
+                info.state
+            ));
+        }
+        tokio::time::sleep(interval).await;
     }
 }
 
-pub async fn list_user_job_ids(ctx: &Context) -> Result<Vec<u32>> {
+/// the smallest remaining walltime across all of the context's jobs.
+///
+/// returns the job id along with its remaining walltime or `None` if it could not be determined.
+pub async fn jobs_min_remaining_walltime(ctx: &Context) -> Result<Option<(u32, Duration)>> {
+    let mut min: Option<(u32, Duration)> = None;
+    for job_id in ctx.job_ids().await? {
+        let info = job_info(ctx, job_id).await?;
+        if let Some(remaining) = info.remaining_walltime()
+            && min.is_none_or(|(_, m)| remaining < m)
+        {
+            min = Some((job_id, remaining));
+        }
+    }
+    Ok(min)
+}
+
+/// request a walltime change for the current job using `oarwalltime`.
+///
+/// the walltime is passed as is to `oarwalltime`, so values like `+1:30` or `4:00:00` are valid.
+pub async fn job_extend_walltime(ctx: &Context, job_id: u32, walltime: &str) -> Result<String> {
+    let job_id_str = job_id.to_string();
+    let output = frontend_run(ctx, &["oarwalltime", &job_id_str, walltime]).await?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// oar events announcing that a job is about to be killed: the scheduler decided to kill a
+/// best-effort job to make room for another one, the job is being deleted, or its checkpoint
+/// signal was sent.
+const PREEMPTION_EVENTS: &[&str] = &[
+    "BESTEFFORT_KILL",
+    "FRAG_JOB_REQUEST",
+    "CHECKPOINT",
+    "CHECKPOINT_SUCCESSFULL",
+];
+
+/// an event of a job, as listed by `oarstat --events`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JobEvent {
+    pub event_id: Option<u64>,
+    pub kind: String,
+    pub description: String,
+}
+
+impl JobEvent {
+    /// whether the event announces that the job is about to be killed.
+    pub fn is_preemption(&self) -> bool {
+        PREEMPTION_EVENTS.contains(&self.kind.as_str())
+    }
+}
+
+/// the events oar recorded for the job.
+///
+/// events are only available through `oarstat`, not through the oar rest api.
+pub async fn job_events(ctx: &Context, job_id: u32) -> Result<Vec<JobEvent>> {
+    if ctx.oar_api_url().is_some() {
+        return Err(eyre::eyre!(
+            "job events are not available through the oar rest api"
+        ));
+    }
+    let job_id_str = job_id.to_string();
+    let output = frontend_run(ctx, &["oarstat", "-j", &job_id_str, "--events", "-J"]).await?;
+    extract_job_events_from_oarstat_output(std::str::from_utf8(&output.stdout)?)
+}
+
+/// submit a new passive job that holds `nodes` machines for `walltime`.
+///
+/// the walltime is passed as is to `oarsub`, so both `2` and `2:30:00` are valid.
+pub async fn job_submit(ctx: &Context, nodes: u32, walltime: &str) -> Result<u32> {
+    let resources = format!("nodes={nodes},walltime={walltime}");
+    let output = frontend_run(ctx, &["oarsub", "-l", &resources, "sleep 365d"]).await?;
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    extract_job_id_from_oarsub_output(stdout)
+}
+
+/// run a command on the frontend, using ssh if we are not already there.
+async fn frontend_run(ctx: &Context, args: &[&str]) -> Result<Output> {
     let output = match ctx.node {
-        ExecutionNode::Frontend => Command::new("oarstat").arg("-u").arg("-J").output().await?,
+        ExecutionNode::Frontend => Command::new(args[0]).args(&args[1..]).output().await?,
         ExecutionNode::Unknown => {
+            // ssh joins all arguments and passes them to the remote shell
             Command::new("ssh")
                 .arg(ctx.frontend_hostname()?)
-                .arg("oarstat")
-                .arg("-u")
-                .arg("-J")
+                .args(args.iter().map(|arg| shell::quote(arg)))
                 .output()
                 .await?
         }
         ExecutionNode::Machine(_) => {
             return Err(eyre::eyre!(
-                "cannot run oarstat from inside a cluster machine"
+                "cannot run {} from inside a cluster machine",
+                args[0]
             ));
         }
     };
@@ -98,27 +299,287 @@ pub async fn list_user_job_ids(ctx: &Context) -> Result<Vec<u32>> {
     if !output.status.success() {
         tracing::error!(
             "stdout: {}",
-            std::str::from_utf8(&output.stdout).unwrap_or("stderr contains invalid uft-8")
+            std::str::from_utf8(&output.stdout).unwrap_or("stdout contains invalid uft-8")
         );
         tracing::error!(
             "stderr: {}",
             std::str::from_utf8(&output.stderr).unwrap_or("stderr contains invalid uft-8")
         );
-        return Err(eyre::eyre!("failed to run oarstat"));
+        return Err(eyre::eyre!("failed to run {}", args[0]));
     }
 
-    let stdout = String::from_utf8(output.stdout)?;
-    // for some reason, running oarstat with the -J flag (for json output) when you have no jobs
-    // running results in this error message instead of an empty object, so we will just assume it
-    // meant an empty object
-    let json_string = if stdout
-        == "hash- or arrayref expected (not a simple scalar, use allow_nonref to allow this) at /usr/lib/oar/oarstat line 285."
-    {
-        String::from("{}")
-    } else {
-        stdout
+    Ok(output)
+}
+
+async fn api_job_info(ctx: &Context, job_id: u32) -> Result<JobInfo> {
+    let job = api_get(ctx, &format!("jobs/{job_id}/details"), &[]).await?;
+    extract_job_info_from_api_json(&job)
+}
+
+async fn api_list_user_jobs(ctx: &Context) -> Result<Vec<JobInfo>> {
+    let user = match ctx.cluster_username() {
+        Ok(username) => username.to_string(),
+        Err(_) => std::env::var("USER").context("reading USER env var")?,
     };
-    extract_job_ids_from_oarstat_output(&json_string)
+    // the details listing has the nodes of every job, so one request covers all of them
+    let states = LISTED_STATES.join(",");
+    let listing = api_get(
+        ctx,
+        "jobs/details",
+        &[("user", user.as_str()), ("state", states.as_str())],
+    )
+    .await?;
+    let mut jobs = extract_jobs_from_api_json(&listing)?;
+    jobs.sort_by_key(|j| j.job_id);
+    Ok(jobs)
+}
+
+/// timeout of a request to the oar rest api.
+const API_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// perform a GET request to the oar rest api, with `query` encoded as the query string.
+async fn api_get(ctx: &Context, path: &str, query: &[(&str, &str)]) -> Result<String> {
+    static CLIENT: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+
+    let base = ctx
+        .oar_api_url()
+        .ok_or_else(|| eyre::eyre!("missing oar api url"))?;
+    let url = format!("{}/{path}", base.trim_end_matches('/'));
+    let mut url =
+        reqwest::Url::parse(&url).with_context(|| format!("invalid oar api url {url}"))?;
+    if !query.is_empty() {
+        url.query_pairs_mut().extend_pairs(query);
+    }
+    tracing::debug!("oar api request: GET {url}");
+
+    let client = CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(API_TIMEOUT)
+            .build()
+            .expect("building http client")
+    });
+    let mut request = client
+        .get(url.clone())
+        .header(reqwest::header::ACCEPT, "application/json");
+    if let Some(token) = ctx.oar_api_token() {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .with_context(|| format!("oar api request to {url} failed"))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .with_context(|| format!("reading the response of {url}"))?;
+    if !status.is_success() {
+        return Err(eyre::eyre!(
+            "oar api request to {url} failed with {status}: {}",
+            body.trim()
+        ));
+    }
+    Ok(body)
+}
+
+/// a job of the oar rest api with its details, as returned by `jobs/<id>/details`.
+#[derive(Debug, Deserialize)]
+struct ApiJobSchema {
+    id: u32,
+    state: String,
+    #[serde(default)]
+    start_time: Option<u64>,
+    #[serde(default)]
+    walltime: Option<u64>,
+    #[serde(default)]
+    queue: String,
+    #[serde(default)]
+    types: Vec<String>,
+    #[serde(default)]
+    nodes: Vec<ApiNodeSchema>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiNodeSchema {
+    network_address: String,
+}
+
+impl ApiJobSchema {
+    fn into_job_info(self) -> Result<JobInfo> {
+        let mut machines = Vec::default();
+        for node in self.nodes {
+            let machine = Machine::from_hostname(&node.network_address)
+                .ok_or_else(|| eyre::eyre!("unknown machine: '{}'", node.network_address))?;
+            if !machines.contains(&machine) {
+                machines.push(machine);
+            }
+        }
+        Ok(JobInfo {
+            job_id: self.id,
+            state: self.state,
+            machines,
+            start_time: self
+                .start_time
+                .filter(|&t| t != 0)
+                .map(|t| SystemTime::UNIX_EPOCH + Duration::from_secs(t)),
+            walltime: self.walltime.map(Duration::from_secs),
+            queue: self.queue,
+            types: self.types,
+        })
+    }
+}
+
+fn extract_job_info_from_api_json(job: &str) -> Result<JobInfo> {
+    parse_oar_json::<ApiJobSchema>(job)?.into_job_info()
+}
+
+fn extract_jobs_from_api_json(listing: &str) -> Result<Vec<JobInfo>> {
+    #[derive(Debug, Deserialize)]
+    struct ListingSchema {
+        items: Vec<ApiJobSchema>,
+    }
+    let listing = parse_oar_json::<ListingSchema>(listing)?;
+    listing
+        .items
+        .into_iter()
+        .map(ApiJobSchema::into_job_info)
+        .collect()
+}
+
+/// the events in the output of `oarstat --events -J`. depending on the oar version the events
+/// are listed by job id or inside the job, so every object with a `type` is an event.
+fn extract_job_events_from_oarstat_output(output: &str) -> Result<Vec<JobEvent>> {
+    fn collect(value: &serde_json::Value, events: &mut Vec<JobEvent>) {
+        match value {
+            serde_json::Value::Object(object) => {
+                if let Some(kind) = object.get("type").and_then(|t| t.as_str()) {
+                    events.push(JobEvent {
+                        event_id: object.get("event_id").and_then(|id| id.as_u64()),
+                        kind: kind.to_string(),
+                        description: object
+                            .get("description")
+                            .and_then(|d| d.as_str())
+                            .unwrap_or_default()
+                            .to_string(),
+                    });
+                } else {
+                    object.values().for_each(|value| collect(value, events));
+                }
+            }
+            serde_json::Value::Array(values) => {
+                values.iter().for_each(|value| collect(value, events))
+            }
+            _ => {}
+        }
+    }
+    let value = parse_oar_json::<serde_json::Value>(output)?;
+    let mut events = Vec::default();
+    collect(&value, &mut events);
+    Ok(events)
+}
+
+fn extract_job_id_from_oarsub_output(output: &str) -> Result<u32> {
+    let job_id = output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("OAR_JOB_ID="))
+        .ok_or_else(|| eyre::eyre!("oarsub output did not contain a job id"))?;
+    job_id
+        .parse()
+        .with_context(|| format!("parsing job id '{job_id}'"))
+}
+
+fn parse_oar_json<T: DeserializeOwned>(output: &str) -> Result<T> {
+    let deserializer = &mut serde_json::Deserializer::from_str(output);
+    serde_path_to_error::deserialize(deserializer).context("parsing oar output")
+}
+
+#[derive(Debug, Deserialize)]
+struct JobInfoSchema {
+    state: String,
+    #[serde(default)]
+    assigned_network_address: Vec<String>,
+    #[serde(rename = "startTime", default)]
+    start_time: Option<u64>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    queue: String,
+    #[serde(default)]
+    types: Vec<String>,
+}
+
+impl JobInfoSchema {
+    fn into_job_info(self, job_id: u32) -> Result<JobInfo> {
+        let mut machines = Vec::default();
+        for hostname in self.assigned_network_address.iter() {
+            match Machine::from_hostname(hostname) {
+                Some(machine) => machines.push(machine),
+                None => return Err(eyre::eyre!("unknown machine: '{hostname}'")),
+            }
+        }
+        Ok(JobInfo {
+            job_id,
+            state: self.state,
+            machines,
+            // oar uses a start time of 0 for jobs that have not started yet
+            start_time: self
+                .start_time
+                .filter(|&t| t != 0)
+                .map(|t| SystemTime::UNIX_EPOCH + Duration::from_secs(t)),
+            walltime: self
+                .message
+                .as_deref()
+                .and_then(extract_walltime_from_oar_message),
+            queue: self.queue,
+            types: self.types,
+        })
+    }
+}
+
+fn extract_job_info_from_oar_stat_json(output: &str, job_id: u32) -> Result<JobInfo> {
+    let mut map = parse_oar_json::<HashMap<String, JobInfoSchema>>(output)?;
+    let data = map
+        .remove(&job_id.to_string())
+        .ok_or_else(|| eyre::eyre!("missing job key"))?;
+    data.into_job_info(job_id)
+}
+
+fn extract_jobs_from_oarstat_output(output: &str) -> Result<Vec<JobInfo>> {
+    let map = parse_oar_json::<HashMap<String, JobInfoSchema>>(output)?;
+    let mut jobs = Vec::default();
+    for (key, data) in map {
+        let job_id = key
+            .parse()
+            .with_context(|| format!("parsing job id '{key}'"))?;
+        jobs.push(data.into_job_info(job_id)?);
+    }
+    jobs.sort_by_key(|j| j.job_id);
+    Ok(jobs)
+}
+
+/// parse a walltime as given to `oarsub`: `<hours>[:<minutes>[:<seconds>]]`.
+pub fn parse_walltime(walltime: &str) -> Option<Duration> {
+    let components = walltime.trim().split(':').collect::<Vec<_>>();
+    if components.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0;
+    for (index, component) in components.iter().enumerate() {
+        let value = component.parse::<u64>().ok()?;
+        seconds += value * 60u64.pow(2 - index as u32);
+    }
+    Some(Duration::from_secs(seconds))
+}
+
+/// extract the walltime from the job message.
+///
+/// the message looks like `R=16,W=12:0:0,J=B (Karma=0.087,quota_ok)`, where `W` is the walltime
+/// in the format `<hours>:<minutes>:<seconds>`.
+fn extract_walltime_from_oar_message(message: &str) -> Option<Duration> {
+    let walltime = message
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .find_map(|component| component.strip_prefix("W="))?;
+    parse_walltime(walltime)
 }
 
 fn extract_machines_from_oar_stat_json(output: &str, job_id: u32) -> Result<Vec<Machine>> {
@@ -126,7 +587,7 @@ fn extract_machines_from_oar_stat_json(output: &str, job_id: u32) -> Result<Vec<
     struct JobSchema {
         assigned_network_address: Vec<String>,
     }
-    let map = serde_json::from_str::<HashMap<String, JobSchema>>(output)?;
+    let map = parse_oar_json::<HashMap<String, JobSchema>>(output)?;
     let key = job_id.to_string();
     let data = map
         .get(&key)
@@ -149,7 +610,7 @@ fn extract_machines_from_oar_stat_json(output: &str, job_id: u32) -> Result<Vec<
 }
 
 fn extract_job_ids_from_oarstat_output(output: &str) -> Result<Vec<u32>> {
-    let value = serde_json::from_str::<serde_json::Value>(output)?;
+    let value = parse_oar_json::<serde_json::Value>(output)?;
     let object = match value {
         serde_json::Value::Object(map) => map,
         _ => {
@@ -243,7 +704,151 @@ mod test {
         assert_eq!(machines[1], Machine::Gengar2);
     }
 
-    const OAR_STAT_ALL_USER_JOBS_OUTPUT: &'static str = r#"
+    #[test]
+    fn test_extract_job_info_from_oar_stat_json() {
+        let info = extract_job_info_from_oar_stat_json(OAR_STAT_JSON_OUTPUT, OAR_STAT_JSON_JOB_ID)
+            .unwrap();
+        assert_eq!(info.job_id, OAR_STAT_JSON_JOB_ID);
+        assert_eq!(info.state, "Running");
+        assert_eq!(info.machines, vec![Machine::Gengar1, Machine::Gengar2]);
+        assert_eq!(
+            info.start_time,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1751979909))
+        );
+        assert_eq!(info.walltime, Some(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(info.queue, "default");
+        assert!(!info.is_besteffort());
+        assert!(!info.has_ended());
+        for (state, ended) in [
+            ("Launching", false),
+            ("Finishing", false),
+            ("Terminated", true),
+            ("Error", true),
+        ] {
+            let info = JobInfo {
+                state: String::from(state),
+                ..info.clone()
+            };
+            assert_eq!(info.has_ended(), ended, "{state}");
+        }
+    }
+
+    #[test]
+    fn test_extract_jobs_from_oarstat_output() {
+        let jobs = extract_jobs_from_oarstat_output(OAR_STAT_ALL_USER_JOBS_OUTPUT).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].job_id, 37029);
+        assert_eq!(jobs[0].machines, vec![Machine::Moltres01]);
+        assert_eq!(jobs[1].job_id, 37030);
+        assert_eq!(jobs[1].machines, vec![Machine::Moltres02]);
+        assert_eq!(jobs[1].walltime, Some(Duration::from_secs(60 * 60)));
+    }
+
+    #[test]
+    fn test_extract_job_id_from_oarsub_output() {
+        let output = "[ADMISSION RULE] Set default walltime to 3600.\n[ADMISSION RULE] Modify resource description with type constraints\nOAR_JOB_ID=37031\n";
+        assert_eq!(extract_job_id_from_oarsub_output(output).unwrap(), 37031);
+        assert!(extract_job_id_from_oarsub_output("# Error: walltime too big").is_err());
+    }
+
+    const OAR_API_JOB_OUTPUT: &str = r#"
+{
+   "id" : 37030,
+   "name" : null,
+   "owner" : "diogo464",
+   "state" : "Running",
+   "queue" : "besteffort",
+   "start_time" : 1752824506,
+   "submission_time" : 1752824505,
+   "walltime" : 3600,
+   "types" : [ "besteffort" ],
+   "nodes" : [
+      { "network_address" : "moltres-02", "status" : "assigned" },
+      { "network_address" : "moltres-02", "status" : "assigned" }
+   ],
+   "links" : [
+      { "rel" : "self", "href" : "/oarapi/jobs/37030" }
+   ]
+}
+"#;
+
+    #[test]
+    fn test_extract_job_info_from_api_json() {
+        let info = extract_job_info_from_api_json(OAR_API_JOB_OUTPUT).unwrap();
+        assert_eq!(info.job_id, 37030);
+        assert_eq!(info.state, "Running");
+        assert_eq!(info.machines, vec![Machine::Moltres02]);
+        assert_eq!(info.walltime, Some(Duration::from_secs(3600)));
+        assert!(info.is_besteffort());
+        assert_eq!(
+            info.start_time,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1752824506))
+        );
+    }
+
+    #[test]
+    fn test_extract_jobs_from_api_json() {
+        let listing = format!(
+            r#"{{ "items" : [ {OAR_API_JOB_OUTPUT}, {{ "id" : 37031, "state" : "Waiting", "start_time" : 0 }} ], "total" : 2 }}"#
+        );
+        let jobs = extract_jobs_from_api_json(&listing).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].machines, vec![Machine::Moltres02]);
+        assert_eq!(jobs[1].job_id, 37031);
+        assert!(jobs[1].machines.is_empty());
+        assert_eq!(jobs[1].start_time, None);
+    }
+
+    #[test]
+    fn test_extract_job_events_from_oarstat_output() {
+        let output = r#"{
+   "37030" : [
+      { "event_id" : 1, "type" : "SWITCH_INTO_TERMINATE_STATE", "description" : "", "to_check" : "NO" },
+      { "event_id" : 2, "type" : "BESTEFFORT_KILL", "description" : "kill the besteffort job 37030", "to_check" : "NO" }
+   ]
+}"#;
+        let events = extract_job_events_from_oarstat_output(output).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(!events[0].is_preemption());
+        assert!(events[1].is_preemption());
+        assert_eq!(events[1].event_id, Some(2));
+
+        let output = r#"{ "37031" : { "state" : "Running", "events" : [ { "type" : "FRAG_JOB_REQUEST", "date" : 1 } ] } }"#;
+        let events = extract_job_events_from_oarstat_output(output).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].is_preemption());
+    }
+
+    #[test]
+    fn test_parse_walltime() {
+        assert_eq!(parse_walltime("2"), Some(Duration::from_secs(2 * 3600)));
+        assert_eq!(
+            parse_walltime("2:30"),
+            Some(Duration::from_secs(2 * 3600 + 30 * 60))
+        );
+        assert_eq!(
+            parse_walltime("0:10:05"),
+            Some(Duration::from_secs(10 * 60 + 5))
+        );
+        assert_eq!(parse_walltime("1:0:0:0"), None);
+        assert_eq!(parse_walltime("2h"), None);
+    }
+
+    #[test]
+    fn test_extract_walltime_from_oar_message() {
+        assert_eq!(
+            extract_walltime_from_oar_message("R=64,W=1:0:0,J=B (Karma=0.106,quota_ok)"),
+            Some(Duration::from_secs(60 * 60))
+        );
+        assert_eq!(
+            extract_walltime_from_oar_message("R=16,W=2:30:15,J=B"),
+            Some(Duration::from_secs(2 * 60 * 60 + 30 * 60 + 15))
+        );
+        assert_eq!(extract_walltime_from_oar_message("R=16,J=B"), None);
+        assert_eq!(extract_walltime_from_oar_message("W=abc"), None);
+    }
+
+    const OAR_STAT_ALL_USER_JOBS_OUTPUT: &str = r#"
 {
    "37030" : {
       "dependencies" : [],
@@ -0,0 +1,494 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use eyre::{Context as _, Result};
+use serde_json::{Value, json};
+use tracing::{
+    Subscriber,
+    field::{Field, Visit},
+    span,
+};
+use tracing_subscriber::{Layer, layer::Context, registry::LookupSpan};
+
+use crate::placement::SplitMix64;
+
+const DEFAULT_PATH: &str = "/v1/traces";
+
+/// closed spans waiting to be exported, once reached new spans are dropped.
+const MAX_BUFFERED_SPANS: usize = 4096;
+
+/// how often the closed spans are sent to the collector.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// the collector gets this long to accept a batch before it is dropped.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// the exporter of this process, see [`install`].
+static EXPORTER: OnceLock<OtlpExporter> = OnceLock::new();
+
+/// an otlp/http collector, like `http://localhost:4318`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtlpEndpoint {
+    pub host: String,
+    pub port: u16,
+    /// defaults to `/v1/traces` when the url has no path
+    pub path: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid otlp endpoint '{0}', expected an url like 'http://localhost:4318'")]
+pub struct InvalidOtlpEndpoint(String);
+
+impl FromStr for OtlpEndpoint {
+    type Err = InvalidOtlpEndpoint;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = || InvalidOtlpEndpoint(s.to_string());
+        // only plain http is supported, collectors usually run next to the user
+        let rest = s.strip_prefix("http://").ok_or_else(err_fn)?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| err_fn())?),
+            None => (authority, 4318),
+        };
+        if host.is_empty() {
+            return Err(err_fn());
+        }
+        let path = match path.trim_end_matches('/') {
+            "" => DEFAULT_PATH.to_string(),
+            path => path.to_string(),
+        };
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+/// a span that has been closed and is waiting to be exported.
+#[derive(Debug, Clone)]
+struct SpanRecord {
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    name: String,
+    target: String,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(String, Value)>,
+    error: Option<String>,
+}
+
+/// closed spans that have not been exported yet.
+#[derive(Debug, Default)]
+struct SpanBuffer {
+    spans: Vec<SpanRecord>,
+    /// spans dropped because the buffer was full
+    dropped: usize,
+}
+
+impl SpanBuffer {
+    fn push(&mut self, span: SpanRecord) {
+        if self.spans.len() < MAX_BUFFERED_SPANS {
+            self.spans.push(span);
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+/// tracing layer that records every span to export them to an otlp collector.
+///
+/// all spans of an invocation belong to the same trace.
+pub struct OtlpLayer {
+    rng: Mutex<SplitMix64>,
+    spans: Arc<Mutex<SpanBuffer>>,
+}
+
+/// sends the spans recorded by the [`OtlpLayer`] it was created with.
+#[derive(Clone)]
+pub struct OtlpExporter {
+    endpoint: OtlpEndpoint,
+    trace_id: u128,
+    spans: Arc<Mutex<SpanBuffer>>,
+    client: reqwest::Client,
+}
+
+pub fn layer(endpoint: OtlpEndpoint) -> (OtlpLayer, OtlpExporter) {
+    let seed = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+        ^ u64::from(std::process::id());
+    let mut rng = SplitMix64(seed);
+    let trace_id = (u128::from(rng.next()) << 64) | u128::from(rng.next());
+    let spans = Arc::default();
+    let layer = OtlpLayer {
+        rng: Mutex::new(rng),
+        spans: Arc::clone(&spans),
+    };
+    let client = reqwest::Client::builder()
+        .connect_timeout(EXPORT_TIMEOUT)
+        .timeout(EXPORT_TIMEOUT)
+        .build()
+        .expect("the otlp http client has a valid configuration");
+    let exporter = OtlpExporter {
+        endpoint,
+        trace_id,
+        spans,
+        client,
+    };
+    (layer, exporter)
+}
+
+#[derive(Default)]
+struct Attributes(Vec<(String, Value)>);
+
+impl Attributes {
+    fn push(&mut self, field: &Field, value: Value) {
+        self.0.retain(|(key, _)| key != field.name());
+        self.0.push((field.name().to_string(), value));
+    }
+}
+
+// the otlp json encoding carries 64 bit integers as strings
+impl Visit for Attributes {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, json!({ "stringValue": value }));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, json!({ "intValue": value.to_string() }));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, json!({ "boolValue": value }));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, json!({ "doubleValue": value }));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.push(field, json!({ "stringValue": format!("{value:?}") }));
+    }
+}
+
+/// state of an open span, kept in its extensions.
+struct OpenSpan {
+    span_id: u64,
+    start: SystemTime,
+    attributes: Attributes,
+    error: Option<String>,
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut attributes = Attributes::default();
+        attrs.record(&mut attributes);
+        let span_id = loop {
+            // zero is not a valid span id
+            let id = self.rng.lock().unwrap().next();
+            if id != 0 {
+                break id;
+            }
+        };
+        span.extensions_mut().insert(OpenSpan {
+            span_id,
+            start: SystemTime::now(),
+            attributes,
+            error: None,
+        });
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        if let Some(open) = span.extensions_mut().get_mut::<OpenSpan>() {
+            values.record(&mut open.attributes);
+        }
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != tracing::Level::ERROR {
+            return;
+        }
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+        let mut fields = Attributes::default();
+        event.record(&mut fields);
+        let message = fields
+            .0
+            .iter()
+            .map(|(key, value)| {
+                let value = value["stringValue"]
+                    .as_str()
+                    .map_or(value.to_string(), str::to_string);
+                match key.as_str() {
+                    "message" => value,
+                    _ => format!("{key}={value}"),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Some(open) = span.extensions_mut().get_mut::<OpenSpan>() {
+            open.error = Some(message);
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let Some(open) = span.extensions_mut().remove::<OpenSpan>() else {
+            return;
+        };
+        let parent_span_id = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<OpenSpan>().map(|p| p.span_id));
+        self.spans.lock().unwrap().push(SpanRecord {
+            span_id: open.span_id,
+            parent_span_id,
+            name: span.name().to_string(),
+            target: span.metadata().target().to_string(),
+            start: open.start,
+            end: SystemTime::now(),
+            attributes: open.attributes.0,
+            error: open.error,
+        });
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+/// the otlp/json `ExportTraceServiceRequest` with `spans`.
+fn export_request(trace_id: u128, spans: &[SpanRecord]) -> Value {
+    let spans = spans
+        .iter()
+        .map(|span| {
+            let mut attributes = span
+                .attributes
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": value }))
+                .collect::<Vec<_>>();
+            attributes.push(json!({
+                "key": "code.namespace",
+                "value": { "stringValue": span.target },
+            }));
+            let status = match &span.error {
+                // STATUS_CODE_ERROR
+                Some(message) => json!({ "code": 2, "message": message }),
+                None => json!({}),
+            };
+            json!({
+                "traceId": format!("{trace_id:032x}"),
+                "spanId": format!("{:016x}", span.span_id),
+                "parentSpanId": span.parent_span_id.map(|id| format!("{id:016x}")).unwrap_or_default(),
+                "name": span.name,
+                // SPAN_KIND_INTERNAL
+                "kind": 1,
+                "startTimeUnixNano": unix_nanos(span.start),
+                "endTimeUnixNano": unix_nanos(span.end),
+                "attributes": attributes,
+                "status": status,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": "oar-p2p" } },
+                    { "key": "service.version", "value": { "stringValue": env!("GIT_VERSION") } },
+                ],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "oar-p2p" },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+impl OtlpExporter {
+    /// send every span closed so far to the collector.
+    pub async fn export(&self) -> Result<()> {
+        let (spans, dropped) = {
+            let mut buffer = self.spans.lock().unwrap();
+            let spans = std::mem::take(&mut buffer.spans);
+            (spans, std::mem::take(&mut buffer.dropped))
+        };
+        if dropped > 0 {
+            tracing::warn!(
+                "dropped {dropped} spans, more than {MAX_BUFFERED_SPANS} were waiting to be exported"
+            );
+        }
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let OtlpEndpoint { host, port, path } = &self.endpoint;
+        let response = self
+            .client
+            .post(format!("http://{host}:{port}{path}"))
+            .json(&export_request(self.trace_id, &spans))
+            .send()
+            .await
+            .with_context(|| format!("sending {} spans to {host}:{port}", spans.len()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(eyre::eyre!(
+                "otlp collector rejected {} spans: {status}",
+                spans.len()
+            ));
+        }
+        tracing::debug!("exported {} spans to the otlp collector", spans.len());
+        Ok(())
+    }
+}
+
+/// export the spans of `exporter` every few seconds, until the process exits.
+///
+/// the exporter is kept so [`flush`] can send the spans closed since the last export.
+pub fn install(exporter: OtlpExporter) {
+    if EXPORTER.set(exporter.clone()).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPORT_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(err) = exporter.export().await {
+                tracing::warn!("failed to export traces: {err:#}");
+            }
+        }
+    });
+}
+
+/// send the spans closed since the last export, if an exporter is installed.
+pub async fn flush() {
+    if let Some(exporter) = EXPORTER.get()
+        && let Err(err) = exporter.export().await
+    {
+        tracing::warn!("failed to export traces: {err:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoint() {
+        let endpoint = "http://collector:4318".parse::<OtlpEndpoint>().unwrap();
+        assert_eq!(endpoint.host, "collector");
+        assert_eq!(endpoint.port, 4318);
+        assert_eq!(endpoint.path, "/v1/traces");
+
+        let endpoint = "http://localhost/otlp/v1/traces/"
+            .parse::<OtlpEndpoint>()
+            .unwrap();
+        assert_eq!(endpoint.port, 4318);
+        assert_eq!(endpoint.path, "/otlp/v1/traces");
+
+        assert!("https://collector:4318".parse::<OtlpEndpoint>().is_err());
+        assert!("http://:4318".parse::<OtlpEndpoint>().is_err());
+        assert!("http://collector:port".parse::<OtlpEndpoint>().is_err());
+    }
+
+    #[test]
+    fn test_layer_records_spans() {
+        let (layer, exporter) = layer("http://localhost:4318".parse().unwrap());
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let phase = tracing::info_span!("phase", phase = "configuring machines");
+            let _phase = phase.enter();
+            let span = tracing::info_span!("machine", machine = "gengar-1", addresses = 3u64);
+            let _span = span.enter();
+            tracing::error!("nft failed");
+        });
+
+        let spans = exporter.spans.lock().unwrap().spans.clone();
+        assert_eq!(spans.len(), 2);
+        let request = export_request(exporter.trace_id, &spans);
+        let spans = &request["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        let (machine, phase) = (&spans[0], &spans[1]);
+        assert_eq!(machine["name"], "machine");
+        assert_eq!(machine["parentSpanId"], phase["spanId"]);
+        assert_eq!(machine["traceId"], phase["traceId"]);
+        assert_eq!(machine["attributes"][0]["key"], "machine");
+        assert_eq!(machine["attributes"][0]["value"]["stringValue"], "gengar-1");
+        assert_eq!(machine["attributes"][1]["value"]["intValue"], "3");
+        assert_eq!(machine["status"]["code"], 2);
+        assert_eq!(machine["status"]["message"], "nft failed");
+        assert_eq!(phase["parentSpanId"], "");
+    }
+
+    #[test]
+    fn test_buffer_is_bounded() {
+        let (layer, exporter) = layer("http://localhost:4318".parse().unwrap());
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..MAX_BUFFERED_SPANS + 10 {
+                let _span = tracing::info_span!("machine").entered();
+            }
+        });
+        let buffer = exporter.spans.lock().unwrap();
+        assert_eq!(buffer.spans.len(), MAX_BUFFERED_SPANS);
+        assert_eq!(buffer.dropped, 10);
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let collector = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::default();
+            let mut buf = [0; 4096];
+            // the spans are in the body, after the headers
+            while !String::from_utf8_lossy(&request).contains("\"spanId\"") {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let (layer, exporter) = layer(format!("http://127.0.0.1:{port}").parse().unwrap());
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("phase").entered();
+        });
+        exporter.export().await.unwrap();
+        let request = collector.await.unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+        assert!(exporter.spans.lock().unwrap().spans.is_empty());
+        // nothing left to send
+        exporter.export().await.unwrap();
+    }
+}
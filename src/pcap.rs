@@ -0,0 +1,148 @@
+use std::{net::Ipv4Addr, str::FromStr};
+
+use crate::runtime::ContainerRuntime;
+
+/// name of the container that runs the packet captures on each machine.
+pub const PCAP_CONTAINER_NAME: &str = "oar-p2p-pcap";
+
+/// what traffic is captured, see `run --pcap`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PcapTarget {
+    /// all traffic to and from the address of this container
+    Container(String),
+    /// traffic between these two addresses
+    Pair(Ipv4Addr, Ipv4Addr),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "invalid capture '{0}', expected a container name or two addresses like '10.0.0.1-10.0.0.2'"
+)]
+pub struct InvalidPcapTarget(String);
+
+impl FromStr for PcapTarget {
+    type Err = InvalidPcapTarget;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(InvalidPcapTarget(s.to_string()));
+        }
+        if let Some((a, b)) = s.split_once('-')
+            && let (Ok(a), Ok(b)) = (a.parse(), b.parse())
+        {
+            return Ok(Self::Pair(a, b));
+        }
+        Ok(Self::Container(s.to_string()))
+    }
+}
+
+/// a single tcpdump process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    /// name of the capture files, without the extension
+    pub label: String,
+    /// bpf filter of the captured packets
+    pub filter: String,
+}
+
+impl Capture {
+    /// capture all traffic to and from `address`.
+    pub fn host(label: String, address: Ipv4Addr) -> Self {
+        Self {
+            label,
+            filter: format!("host {address}"),
+        }
+    }
+
+    /// capture the traffic between `a` and `b`.
+    pub fn pair(a: Ipv4Addr, b: Ipv4Addr) -> Self {
+        Self {
+            label: format!("{a}-{b}"),
+            filter: format!("host {a} and host {b}"),
+        }
+    }
+}
+
+/// script that starts the packet captures on a machine, unless they are already running.
+///
+/// tcpdump runs in the networking container, `image`, and writes the packets to
+/// `/tmp/oar-p2p-logs/pcap/<label>.pcap` so they are copied together with the container logs.
+/// each capture is rotated every `rotate_size_mb` megabytes keeping at most `rotate_count`
+/// files. packets are written as they arrive so removing the container loses nothing.
+pub fn capture_start_script(
+    runtime: ContainerRuntime,
+    image: &str,
+    captures: &[Capture],
+    rotate_size_mb: u64,
+    rotate_count: u64,
+) -> String {
+    let mut command = String::default();
+    for capture in captures {
+        command.push_str(&format!(
+            "tcpdump -i any -n -U -C {rotate_size_mb} -W {rotate_count} -w /out/{}.pcap {} & ",
+            capture.label,
+            crate::shell::quote(&capture.filter)
+        ));
+    }
+    command.push_str("wait");
+
+    let mut script = String::default();
+    script.push_str("mkdir -p /tmp/oar-p2p-logs/pcap\n");
+    script.push_str(&format!(
+        "{runtime} container inspect {PCAP_CONTAINER_NAME} >/dev/null 2>&1 && exit 0\n"
+    ));
+    // captures from a previous run would otherwise be collected with this one
+    script.push_str("rm -f /tmp/oar-p2p-logs/pcap/*\n");
+    script.push_str(&format!(
+        "{runtime} run -d --name {PCAP_CONTAINER_NAME} --net=host --privileged -v /tmp/oar-p2p-logs/pcap:/out {image} bash -c {} >/dev/null\n",
+        crate::shell::quote(&command)
+    ));
+    script
+}
+
+/// script that stops the packet captures, if they are running.
+pub fn capture_stop_script(runtime: ContainerRuntime) -> String {
+    format!("{runtime} rm -f {PCAP_CONTAINER_NAME} >/dev/null 2>&1 || true\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            "bootstrap-0".parse::<PcapTarget>().unwrap(),
+            PcapTarget::Container(String::from("bootstrap-0"))
+        );
+        assert_eq!(
+            "10.16.0.1-10.16.0.2".parse::<PcapTarget>().unwrap(),
+            PcapTarget::Pair(Ipv4Addr::new(10, 16, 0, 1), Ipv4Addr::new(10, 16, 0, 2))
+        );
+        assert_eq!(
+            "node-1".parse::<PcapTarget>().unwrap(),
+            PcapTarget::Container(String::from("node-1"))
+        );
+        assert!("".parse::<PcapTarget>().is_err());
+    }
+
+    #[test]
+    fn test_capture_start_script() {
+        let captures = vec![
+            Capture::host(String::from("c0"), Ipv4Addr::new(10, 16, 0, 1)),
+            Capture::pair(Ipv4Addr::new(10, 16, 0, 1), Ipv4Addr::new(10, 16, 0, 2)),
+        ];
+        let script = capture_start_script(
+            ContainerRuntime::Docker,
+            "local/oar-p2p-networking",
+            &captures,
+            100,
+            5,
+        );
+        assert!(script.contains(&format!("docker container inspect {PCAP_CONTAINER_NAME}")));
+        assert!(script.contains("-C 100 -W 5 -w /out/c0.pcap"));
+        assert!(script.contains("/out/10.16.0.1-10.16.0.2.pcap"));
+        assert!(script.contains("host 10.16.0.1 and host 10.16.0.2"));
+    }
+}
@@ -0,0 +1,84 @@
+use std::net::Ipv4Addr;
+
+use crate::placement::SplitMix64;
+
+/// environment variable with the comma separated peer addresses, see [`PeerListMode::Env`].
+pub const PEERS_ENV_VAR: &str = "OAR_P2P_PEERS";
+
+/// environment variable with the path of the peers file, see [`PeerListMode::File`].
+pub const PEERS_FILE_ENV_VAR: &str = "OAR_P2P_PEERS_FILE";
+
+/// directory on the machines with the peers file of each container.
+pub const PEERS_HOST_DIR: &str = "/tmp/oar-p2p-peers";
+
+/// path of the peers file inside the container.
+pub const PEERS_CONTAINER_PATH: &str = "/oar-p2p-peers";
+
+/// how the peer list is given to each container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PeerListMode {
+    /// comma separated addresses in the `OAR_P2P_PEERS` environment variable.
+    Env,
+    /// one address per line in a file whose path is in the `OAR_P2P_PEERS_FILE` environment
+    /// variable. prefer this for large runs since environment variables are size limited.
+    File,
+}
+
+/// the peers of the container at index `own` out of `addresses`, the container itself is never
+/// part of its own list.
+///
+/// with `sample`, at most that many peers are picked at random. the pick only depends on `seed`
+/// and on `own` so the same schedule always produces the same lists.
+pub fn peer_list(
+    addresses: &[Ipv4Addr],
+    own: usize,
+    sample: Option<usize>,
+    seed: u64,
+) -> Vec<Ipv4Addr> {
+    let mut peers = addresses
+        .iter()
+        .enumerate()
+        .filter(|&(idx, address)| idx != own && *address != addresses[own])
+        .map(|(_, address)| *address)
+        .collect::<Vec<_>>();
+    if let Some(sample) = sample
+        && sample < peers.len()
+    {
+        let mut rng = SplitMix64(seed ^ (own as u64).wrapping_mul(0x9e3779b97f4a7c15));
+        // fisher-yates, only the first `sample` positions are needed
+        for i in 0..sample {
+            let j = i + (rng.next() % (peers.len() - i) as u64) as usize;
+            peers.swap(i, j);
+        }
+        peers.truncate(sample);
+    }
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses() -> Vec<Ipv4Addr> {
+        (1..=10).map(|i| Ipv4Addr::new(10, 16, 0, i)).collect()
+    }
+
+    #[test]
+    fn test_peer_list() {
+        let addresses = addresses();
+        let peers = peer_list(&addresses, 2, None, 0);
+        assert_eq!(peers.len(), 9);
+        assert!(!peers.contains(&addresses[2]));
+
+        let sampled = peer_list(&addresses, 2, Some(4), 7);
+        assert_eq!(sampled.len(), 4);
+        assert!(!sampled.contains(&addresses[2]));
+        assert_eq!(sampled, peer_list(&addresses, 2, Some(4), 7));
+        let mut unique = sampled.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 4);
+
+        assert_eq!(peer_list(&addresses, 0, Some(20), 7).len(), 9);
+    }
+}
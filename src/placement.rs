@@ -0,0 +1,174 @@
+use std::net::Ipv4Addr;
+
+use crate::machine::Machine;
+
+/// how containers without an address in the schedule are mapped to the available addresses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PlacementStrategy {
+    /// distribute containers over all machines in a round-robin fashion.
+    #[default]
+    Spread,
+    /// fill all addresses of a machine before moving on to the next one.
+    Binpack,
+    /// pick addresses at random, the `--seed` flag makes the placement reproducible.
+    Random,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "not enough free addresses, {required} containers need an address but only {available} are available"
+)]
+pub struct NotEnoughAddresses {
+    pub required: usize,
+    pub available: usize,
+}
+
+/// pick `count` addresses out of the available addresses of each machine.
+///
+/// `available` should contain every machine at most once. the order of the machines and of their
+/// addresses does not matter, both are sorted before placing so the result only depends on the
+/// strategy and on the seed.
+pub fn place(
+    strategy: PlacementStrategy,
+    seed: u64,
+    count: usize,
+    available: &[(Machine, Vec<Ipv4Addr>)],
+) -> Result<Vec<Ipv4Addr>, NotEnoughAddresses> {
+    let mut available = available.to_vec();
+    available.sort();
+    available
+        .iter_mut()
+        .for_each(|(_, addresses)| addresses.sort());
+
+    let total = available.iter().map(|(_, a)| a.len()).sum::<usize>();
+    if count > total {
+        return Err(NotEnoughAddresses {
+            required: count,
+            available: total,
+        });
+    }
+
+    let mut placed = Vec::with_capacity(count);
+    match strategy {
+        PlacementStrategy::Spread => {
+            let mut round = 0;
+            while placed.len() < count {
+                for (_, addresses) in available.iter() {
+                    if placed.len() < count
+                        && let Some(&address) = addresses.get(round)
+                    {
+                        placed.push(address);
+                    }
+                }
+                round += 1;
+            }
+        }
+        PlacementStrategy::Binpack => {
+            placed.extend(
+                available
+                    .iter()
+                    .flat_map(|(_, addresses)| addresses.iter().copied())
+                    .take(count),
+            );
+        }
+        PlacementStrategy::Random => {
+            let mut addresses = available
+                .into_iter()
+                .flat_map(|(_, addresses)| addresses)
+                .collect::<Vec<_>>();
+            let mut rng = SplitMix64(seed);
+            // fisher-yates, only the first `count` positions are needed
+            for i in 0..count {
+                let j = i + (rng.next() % (addresses.len() - i) as u64) as usize;
+                addresses.swap(i, j);
+            }
+            addresses.truncate(count);
+            placed = addresses;
+        }
+    }
+    Ok(placed)
+}
+
+/// small deterministic generator, the placement must be reproducible across versions so this
+/// should not be replaced by an external crate whose output might change.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn available() -> Vec<(Machine, Vec<Ipv4Addr>)> {
+        vec![
+            (
+                Machine::Gengar2,
+                vec![Ipv4Addr::new(10, 2, 0, 2), Ipv4Addr::new(10, 2, 0, 1)],
+            ),
+            (
+                Machine::Gengar1,
+                vec![
+                    Ipv4Addr::new(10, 1, 0, 1),
+                    Ipv4Addr::new(10, 1, 0, 2),
+                    Ipv4Addr::new(10, 1, 0, 3),
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_spread() {
+        let placed = place(PlacementStrategy::Spread, 0, 4, &available()).unwrap();
+        assert_eq!(
+            placed,
+            vec![
+                Ipv4Addr::new(10, 1, 0, 1),
+                Ipv4Addr::new(10, 2, 0, 1),
+                Ipv4Addr::new(10, 1, 0, 2),
+                Ipv4Addr::new(10, 2, 0, 2),
+            ]
+        );
+        let placed = place(PlacementStrategy::Spread, 0, 5, &available()).unwrap();
+        assert_eq!(placed[4], Ipv4Addr::new(10, 1, 0, 3));
+    }
+
+    #[test]
+    fn test_binpack() {
+        let placed = place(PlacementStrategy::Binpack, 0, 4, &available()).unwrap();
+        assert_eq!(
+            placed,
+            vec![
+                Ipv4Addr::new(10, 1, 0, 1),
+                Ipv4Addr::new(10, 1, 0, 2),
+                Ipv4Addr::new(10, 1, 0, 3),
+                Ipv4Addr::new(10, 2, 0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_random_is_reproducible() {
+        let a = place(PlacementStrategy::Random, 42, 5, &available()).unwrap();
+        let b = place(PlacementStrategy::Random, 42, 5, &available()).unwrap();
+        assert_eq!(a, b);
+        let mut sorted = a.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 5);
+    }
+
+    #[test]
+    fn test_not_enough_addresses() {
+        let err = place(PlacementStrategy::Spread, 0, 6, &available()).unwrap_err();
+        assert_eq!(err.required, 6);
+        assert_eq!(err.available, 5);
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    collections::BTreeMap,
+    io::{IsTerminal as _, Write},
+    sync::Mutex,
+    time::Instant,
+};
+
+use crate::{
+    machine::Machine,
+    timing::{self, PhaseTiming},
+};
+
+/// at most this many running machines are named in the progress line.
+const MAX_NAMED_RUNNING: usize = 5;
+
+/// the status line on the terminal, if one is drawn. logs written with [`stderr`] go above it.
+static STATUS: Mutex<Option<String>> = Mutex::new(None);
+
+/// clears the line of the cursor.
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+/// progress of an operation that runs on many machines at once.
+///
+/// when stderr is a terminal a single status line is redrawn on every change, otherwise only a
+/// summary is logged once the operation finishes. setting `OAR_P2P_PROGRESS=0` disables the
+/// status line.
+pub struct Progress {
+    label: String,
+    states: BTreeMap<Machine, MachineState>,
+    start: Instant,
+    /// when every machine started running
+    started: BTreeMap<Machine, Instant>,
+    /// seconds every machine that finished took
+    durations: BTreeMap<Machine, f64>,
+    draw: bool,
+}
+
+impl Progress {
+    pub fn new<'a>(label: &str, machines: impl IntoIterator<Item = &'a Machine>) -> Self {
+        let progress = Self {
+            label: label.to_string(),
+            states: machines
+                .into_iter()
+                .map(|&m| (m, MachineState::Pending))
+                .collect(),
+            start: Instant::now(),
+            started: BTreeMap::default(),
+            durations: BTreeMap::default(),
+            draw: enabled(),
+        };
+        progress.redraw();
+        progress
+    }
+
+    pub fn set(&mut self, machine: Machine, state: MachineState) {
+        match state {
+            MachineState::Running => {
+                self.started.insert(machine, Instant::now());
+            }
+            MachineState::Done => {
+                if let Some(started) = self.started.get(&machine) {
+                    let duration = started.elapsed().as_secs_f64();
+                    self.durations.insert(machine, duration);
+                }
+            }
+            _ => {}
+        }
+        self.states.insert(machine, state);
+        self.redraw();
+    }
+
+    fn count(&self, state: MachineState) -> usize {
+        self.states.values().filter(|&&s| s == state).count()
+    }
+
+    /// the status line, like `configuring machines: 38/40 done, 2 running (gengar-1, gengar-2)`.
+    pub fn line(&self) -> String {
+        let mut line = format!(
+            "{}: {}/{} done",
+            self.label,
+            self.count(MachineState::Done),
+            self.states.len()
+        );
+        let running = self
+            .states
+            .iter()
+            .filter(|(_, s)| **s == MachineState::Running)
+            .map(|(m, _)| m.hostname())
+            .collect::<Vec<_>>();
+        if !running.is_empty() {
+            line.push_str(&format!(", {} running", running.len()));
+            if running.len() <= MAX_NAMED_RUNNING {
+                line.push_str(&format!(" ({})", running.join(", ")));
+            }
+        }
+        let pending = self.count(MachineState::Pending);
+        if pending > 0 {
+            line.push_str(&format!(", {pending} pending"));
+        }
+        let failed = self
+            .states
+            .iter()
+            .filter(|(_, s)| **s == MachineState::Failed)
+            .map(|(m, _)| m.hostname())
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            line.push_str(&format!(
+                ", {} failed ({})",
+                failed.len(),
+                failed.join(", ")
+            ));
+        }
+        line
+    }
+
+    fn redraw(&self) {
+        if self.draw {
+            let line = self.line();
+            let mut status = STATUS.lock().unwrap();
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "{CLEAR_LINE}{line}");
+            let _ = stderr.flush();
+            *status = Some(line);
+        }
+    }
+}
+
+impl Drop for Progress {
+    /// ends the status line and records the timing of the phase, also when the operation is
+    /// cancelled.
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        timing::record(PhaseTiming {
+            phase: self.label.clone(),
+            duration: elapsed,
+            machines: std::mem::take(&mut self.durations),
+        });
+        if self.draw {
+            let mut status = STATUS.lock().unwrap();
+            eprintln!("{CLEAR_LINE}{} in {elapsed:.1}s", self.line());
+            *status = None;
+        } else {
+            tracing::info!("{} in {elapsed:.1}s", self.line());
+        }
+    }
+}
+
+/// writer for the logs that keeps the status line of a [`Progress`] below them, see
+/// [`stderr`].
+pub struct StderrWriter;
+
+/// the writer of the logs. the status line is cleared before every log line and drawn again
+/// after it, otherwise both would end up on the same line of the terminal.
+pub fn stderr() -> StderrWriter {
+    StderrWriter
+}
+
+impl Write for StderrWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let status = STATUS.lock().unwrap();
+        write_above_status(&mut std::io::stderr().lock(), status.as_deref(), buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+fn write_above_status(
+    out: &mut impl Write,
+    status: Option<&str>,
+    buf: &[u8],
+) -> std::io::Result<()> {
+    match status {
+        Some(line) => {
+            write!(out, "{CLEAR_LINE}")?;
+            out.write_all(buf)?;
+            write!(out, "{line}")?;
+        }
+        None => out.write_all(buf)?,
+    }
+    out.flush()
+}
+
+fn enabled() -> bool {
+    std::io::stderr().is_terminal() && std::env::var("OAR_P2P_PROGRESS").map_or(true, |v| v != "0")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line() {
+        let mut progress = Progress::new(
+            "configuring machines",
+            &[Machine::Gengar1, Machine::Gengar2, Machine::Gengar3],
+        );
+        assert_eq!(progress.line(), "configuring machines: 0/3 done, 3 pending");
+        progress.set(Machine::Gengar1, MachineState::Done);
+        progress.set(Machine::Gengar2, MachineState::Running);
+        assert_eq!(
+            progress.line(),
+            "configuring machines: 1/3 done, 1 running (gengar-2), 1 pending"
+        );
+        progress.set(Machine::Gengar3, MachineState::Failed);
+        assert_eq!(
+            progress.line(),
+            "configuring machines: 1/3 done, 1 running (gengar-2), 1 failed (gengar-3)"
+        );
+    }
+
+    #[test]
+    fn test_write_above_status() {
+        let mut out = Vec::default();
+        write_above_status(&mut out, None, b"INFO configured\n").unwrap();
+        assert_eq!(out, b"INFO configured\n");
+
+        let mut out = Vec::default();
+        write_above_status(
+            &mut out,
+            Some("configuring machines: 1/3 done"),
+            b"INFO configured\n",
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\r\x1b[2KINFO configured\nconfiguring machines: 1/3 done"
+        );
+    }
+}
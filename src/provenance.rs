@@ -0,0 +1,30 @@
+use serde::Serialize;
+
+use crate::machine::Machine;
+
+/// name of the file, inside the output directory, where the provenance is written.
+pub const PROVENANCE_FILE_NAME: &str = "provenance.json";
+
+/// name of the copy of the schedule inside the output directory.
+pub const SCHEDULE_FILE_NAME: &str = "schedule.json";
+
+/// name of the copy of the latency matrix inside the output directory.
+pub const LATENCY_MATRIX_FILE_NAME: &str = "latency-matrix.txt";
+
+/// what is needed to know how a result was produced, written at the start of a run so the output
+/// directory is self-describing even if the run does not finish.
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    /// version of oar-p2p, from `git describe`
+    pub version: String,
+    /// the command line `oar-p2p` was invoked with
+    pub command_line: Vec<String>,
+    /// unix timestamp, in seconds, of when the provenance was written
+    pub written_at: u64,
+    /// where the schedule was read from
+    pub schedule_source: String,
+    /// where the latency matrix was read from, if it was given
+    pub latency_matrix_source: Option<String>,
+    /// the machines of the job
+    pub machines: Vec<Machine>,
+}
@@ -0,0 +1,156 @@
+use std::net::Ipv4Addr;
+
+use serde::Serialize;
+
+use crate::{machine::Machine, runtime::ContainerRuntime};
+
+/// label set on every container created by `run`, its value is the container's address.
+pub const ADDRESS_LABEL: &str = "oar-p2p.address";
+
+/// a container created by `run` as listed by [`ps_script`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContainerStatus {
+    pub name: String,
+    pub machine: Machine,
+    pub address: Option<Ipv4Addr>,
+    /// `created`, `running`, `paused`, `restarting` or `exited`
+    pub state: String,
+    /// only known once the container exited
+    pub exit_code: Option<i64>,
+}
+
+/// script that prints one line per container created by `run`, parsed by [`parse_ps_output`].
+pub fn ps_script(runtime: ContainerRuntime) -> String {
+    format!(
+        "{runtime} ps -a --filter label={ADDRESS_LABEL} --format '{{{{.Names}}}}|{{{{.Status}}}}|{{{{.Labels}}}}'\n"
+    )
+}
+
+/// parse the output of [`ps_script`] on `machine`. lines that cannot be parsed are ignored.
+pub fn parse_ps_output(machine: Machine, output: &str) -> Vec<ContainerStatus> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim().splitn(3, '|');
+            let name = fields.next().filter(|n| !n.is_empty())?;
+            let (state, exit_code) = parse_status(fields.next()?);
+            let address = fields.next().and_then(|labels| {
+                labels
+                    .split(',')
+                    .find_map(|label| label.strip_prefix(ADDRESS_LABEL)?.strip_prefix('='))
+                    .and_then(|address| address.parse().ok())
+            });
+            Some(ContainerStatus {
+                name: name.to_string(),
+                machine,
+                address,
+                state,
+                exit_code,
+            })
+        })
+        .collect()
+}
+
+/// whether `name` matches the glob `pattern`, where `*` matches any sequence of characters and
+/// `?` matches a single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and of the name when it was reached, to backtrack to
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// the state and exit code out of a status like `Up 5 minutes` or `Exited (137) 2 hours ago`.
+fn parse_status(status: &str) -> (String, Option<i64>) {
+    let status = status.trim();
+    let code = || {
+        let (_, rest) = status.split_once('(')?;
+        let (code, _) = rest.split_once(')')?;
+        code.parse().ok()
+    };
+    if status.starts_with("Up") && status.contains("(Paused)") {
+        (String::from("paused"), None)
+    } else if status.starts_with("Up") {
+        (String::from("running"), None)
+    } else if status.starts_with("Exited") {
+        (String::from("exited"), code())
+    } else if status.starts_with("Restarting") {
+        (String::from("restarting"), code())
+    } else {
+        (
+            status
+                .split_whitespace()
+                .next()
+                .unwrap_or("unknown")
+                .to_ascii_lowercase(),
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ps_output() {
+        let output = "\
+c0|Up 5 minutes|oar-p2p.address=10.16.0.1
+c1|Exited (137) 2 hours ago|foo=bar,oar-p2p.address=10.16.0.2
+c2|Created|oar-p2p.address=10.16.0.3
+c3|Up 1 second (Paused)|
+garbage
+";
+        let containers = parse_ps_output(Machine::Gengar1, output);
+        assert_eq!(containers.len(), 4);
+        assert_eq!(
+            containers[0],
+            ContainerStatus {
+                name: String::from("c0"),
+                machine: Machine::Gengar1,
+                address: Some(Ipv4Addr::new(10, 16, 0, 1)),
+                state: String::from("running"),
+                exit_code: None,
+            }
+        );
+        assert_eq!(containers[1].state, "exited");
+        assert_eq!(containers[1].exit_code, Some(137));
+        assert_eq!(containers[1].address, Some(Ipv4Addr::new(10, 16, 0, 2)));
+        assert_eq!(containers[2].state, "created");
+        assert_eq!(containers[3].state, "paused");
+        assert_eq!(containers[3].address, None);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("node-*", "node-12"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("n?de-*-a", "node-1-2-a"));
+        assert!(glob_match("bootstrap", "bootstrap"));
+        assert!(!glob_match("node-*", "bootstrap-0"));
+        assert!(!glob_match("node-?", "node-12"));
+        assert!(!glob_match("*-a", "node-1-b"));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{machine::Machine, runtime::ContainerRuntime};
+
+/// name of the pull-through cache container.
+pub const REGISTRY_CONTAINER_NAME: &str = "oar-p2p-registry";
+
+/// image of the pull-through cache.
+pub const REGISTRY_IMAGE: &str = "registry:2";
+
+/// directory on the mirror machine where the cached images are stored.
+pub const REGISTRY_CACHE_DIR: &str = "/tmp/oar-p2p-registry-cache";
+
+/// directories of the host that must be mounted, at the same path, in the container that runs
+/// [`configure_script`] and [`unconfigure_script`].
+pub const HOST_CONFIG_DIRS: &[&str] = &["/etc/docker", "/etc/containerd"];
+
+/// the url other machines use to reach the mirror.
+pub fn mirror_url(machine: Machine, port: u16) -> String {
+    format!("http://{}:{port}", machine.hostname())
+}
+
+/// script that (re)starts the pull-through cache on the mirror machine.
+pub fn mirror_up_script(runtime: ContainerRuntime, port: u16, upstream: &str) -> String {
+    let mut script = String::default();
+    script.push_str("set -e\n");
+    script.push_str(&format!(
+        "{runtime} rm -f {REGISTRY_CONTAINER_NAME} >/dev/null 2>&1 || true\n"
+    ));
+    script.push_str(&format!("mkdir -p {REGISTRY_CACHE_DIR}\n"));
+    script.push_str(&format!(
+        "{runtime} run -d --name {REGISTRY_CONTAINER_NAME} --restart=always -p {port}:5000 -e REGISTRY_PROXY_REMOTEURL={} -v {REGISTRY_CACHE_DIR}:/var/lib/registry {REGISTRY_IMAGE} >/dev/null\n",
+        crate::shell::quote(upstream)
+    ));
+    script
+}
+
+/// script that removes the pull-through cache from the mirror machine.
+pub fn mirror_down_script(runtime: ContainerRuntime) -> String {
+    format!("{runtime} rm -f {REGISTRY_CONTAINER_NAME} >/dev/null 2>&1 || true\n")
+}
+
+/// the configuration file of the runtime that holds the registry mirrors.
+fn config_path(runtime: ContainerRuntime) -> &'static str {
+    match runtime {
+        ContainerRuntime::Docker => "/etc/docker/daemon.json",
+        ContainerRuntime::Nerdctl => "/etc/containerd/certs.d/docker.io/hosts.toml",
+    }
+}
+
+/// script that configures the runtime to pull docker hub images through the mirror.
+///
+/// runs inside the networking container, with the host's pid namespace so the docker daemon can
+/// be restarted. the original configuration is backed up once and every invocation starts from
+/// it, so configuring the mirror more than once is harmless.
+pub fn configure_script(runtime: ContainerRuntime, mirror_url: &str) -> String {
+    let path = config_path(runtime);
+    let mut script = String::default();
+    script.push_str("set -e\n");
+    script.push_str(&format!("f={path}\n"));
+    script.push_str("mkdir -p $(dirname $f)\n");
+    script.push_str("if [ ! -e $f.oar-p2p-backup ] && [ ! -e $f.oar-p2p-absent ] ; then\n");
+    script.push_str(
+        "\tif [ -e $f ] ; then cp $f $f.oar-p2p-backup ; else touch $f.oar-p2p-absent ; fi\n",
+    );
+    script.push_str("fi\n");
+    match runtime {
+        ContainerRuntime::Docker => {
+            script.push_str("if [ -e $f.oar-p2p-backup ] ; then src=$f.oar-p2p-backup ; else echo '{}' > $f.oar-p2p-empty ; src=$f.oar-p2p-empty ; fi\n");
+            script.push_str(&format!(
+                "jq --arg m {mirror_url} '.\"registry-mirrors\" = [$m] + ((.\"registry-mirrors\" // []) - [$m])' $src > $f.tmp\n"
+            ));
+            script.push_str("rm -f $f.oar-p2p-empty\n");
+            script.push_str("mv $f.tmp $f\n");
+            script.push_str(&restart_docker_script());
+        }
+        ContainerRuntime::Nerdctl => {
+            // nerdctl reads the hosts file on every pull, no restart is needed
+            script.push_str("cat << EOF > $f\n");
+            script.push_str("server = \"https://registry-1.docker.io\"\n\n");
+            script.push_str(&format!("[host.\"{mirror_url}\"]\n"));
+            script.push_str("  capabilities = [\"pull\", \"resolve\"]\n");
+            script.push_str("EOF\n");
+        }
+    }
+    script
+}
+
+/// script that restores the configuration from before [`configure_script`].
+pub fn unconfigure_script(runtime: ContainerRuntime) -> String {
+    let path = config_path(runtime);
+    let mut script = String::default();
+    script.push_str("set -e\n");
+    script.push_str(&format!("f={path}\n"));
+    script.push_str("if [ -e $f.oar-p2p-backup ] ; then\n");
+    script.push_str("\tmv $f.oar-p2p-backup $f\n");
+    script.push_str("elif [ -e $f.oar-p2p-absent ] ; then\n");
+    script.push_str("\trm -f $f $f.oar-p2p-absent\n");
+    script.push_str("else\n");
+    script.push_str("\texit 0\n");
+    script.push_str("fi\n");
+    if runtime == ContainerRuntime::Docker {
+        script.push_str(&restart_docker_script());
+    }
+    script
+}
+
+/// restarting the daemon also kills the container this runs in, so the restart is only queued
+/// and [`wait_ready_script`] must be used on the host to wait for the daemon to come back.
+fn restart_docker_script() -> String {
+    String::from("nsenter -t 1 -m -u -i -n -p -- systemctl restart --no-block docker\n")
+}
+
+/// script that waits for the runtime to be usable after a restart.
+pub fn wait_ready_script(runtime: ContainerRuntime) -> String {
+    let mut script = String::default();
+    script.push_str("sleep 1\n");
+    script.push_str("for _ in $(seq 60) ; do\n");
+    script.push_str(&format!("\t{runtime} info >/dev/null 2>&1 && exit 0\n"));
+    script.push_str("\tsleep 1\n");
+    script.push_str("done\n");
+    script.push_str(&format!(
+        "echo {runtime} did not come back after restarting\n"
+    ));
+    script.push_str("exit 1\n");
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configure_script() {
+        let url = mirror_url(Machine::Gengar1, 5000);
+        assert_eq!(url, "http://gengar-1:5000");
+        let script = configure_script(ContainerRuntime::Docker, &url);
+        assert!(script.contains("f=/etc/docker/daemon.json\n"));
+        assert!(script.contains(&format!("jq --arg m {url} ")));
+        assert!(script.contains("systemctl restart --no-block docker"));
+        let script = configure_script(ContainerRuntime::Nerdctl, &url);
+        assert!(script.contains(&format!("[host.\"{url}\"]")));
+        assert!(!script.contains("systemctl"));
+    }
+}
@@ -0,0 +1,208 @@
+use std::{process::Output, time::Duration};
+
+use eyre::Result;
+
+/// default number of times a command that failed with a transient error is retried.
+pub const DEFAULT_REMOTE_RETRIES: u32 = 3;
+
+/// errors printed by ssh, which exits with 255 when it fails itself, that are worth retrying.
+///
+/// only failures from before the remote command started are listed, while connecting, resolving
+/// the hostname or exchanging keys. a connection that drops later, with a broken pipe or a reset,
+/// may have run part of the script already and the scripts are not safe to run twice.
+const SSH_TRANSIENT_ERRORS: &[&str] = &[
+    "ssh: connect to host",
+    "could not resolve hostname",
+    "temporary failure in name resolution",
+    "kex_exchange_identification",
+    "ssh_exchange_identification",
+    "connection timed out during banner exchange",
+    // printed when the server drops the connection before authentication, a drop during the
+    // command is reported as `connection to <host> closed by remote host`
+    "connection closed by",
+];
+
+/// exit code of the container runtime when it failed itself, instead of the container or the
+/// command it ran.
+const RUNTIME_FAILURE_CODE: i32 = 125;
+
+/// errors printed by the container runtime when its daemon is unavailable or overloaded. only
+/// retried when the runtime exited with [`RUNTIME_FAILURE_CODE`] and the command is
+/// [`Retry::Runtime`].
+const RUNTIME_TRANSIENT_ERRORS: &[&str] = &[
+    "cannot connect to the docker daemon",
+    "error during connect",
+    "error while dialing",
+    "tls handshake timeout",
+    "i/o timeout",
+    "toomanyrequests",
+];
+
+/// which transient failures of a command are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    /// only failures of the connection from before the command started. used for scripts, which
+    /// run several commands and may have done part of their work when one of them fails.
+    Connection,
+    /// also failures of the container runtime. only for a command that is a single invocation of
+    /// the runtime, such as starting the networking container, since the runtime exits with
+    /// [`RUNTIME_FAILURE_CODE`] before anything ran.
+    Runtime,
+}
+
+/// whether a failed command failed because of the connection or, with [`Retry::Runtime`], the
+/// container runtime, instead of the command itself, so running it again might succeed.
+pub fn is_transient(output: &Output, retry: Retry) -> bool {
+    if output.status.success() {
+        return false;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).to_lowercase();
+    // ssh's verbose output mentions connections that are fine
+    let mut lines = stderr.lines().filter(|line| !line.starts_with("debug"));
+    let errors = match output.status.code() {
+        Some(255) => SSH_TRANSIENT_ERRORS,
+        Some(RUNTIME_FAILURE_CODE) if retry == Retry::Runtime => RUNTIME_TRANSIENT_ERRORS,
+        _ => return false,
+    };
+    lines.any(|line| errors.iter().any(|e| line.contains(e)))
+}
+
+/// delay before retry number `attempt`, counted from 1. starts at 2 seconds and doubles up to 32.
+pub fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(2u64.pow(attempt.min(5)))
+}
+
+/// run `f` again, with exponential backoff, while it fails with a transient error and less than
+/// `retries` retries were made. the output of the last attempt is returned, failed or not.
+pub async fn with_backoff<F, Fut>(
+    retries: u32,
+    what: &str,
+    retry: Retry,
+    mut f: F,
+) -> Result<Output>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<Output>>,
+{
+    let mut attempt = 0;
+    loop {
+        let output = f().await?;
+        if attempt >= retries || !is_transient(&output, retry) {
+            return Ok(output);
+        }
+        attempt += 1;
+        let delay = backoff(attempt);
+        tracing::warn!(
+            "{what} failed with a transient error, retrying in {}s ({attempt}/{retries}): {}",
+            delay.as_secs(),
+            String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .rfind(|line| !line.starts_with("debug"))
+                .unwrap_or_default()
+                .trim()
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::process::ExitStatusExt as _;
+
+    use super::*;
+
+    fn output(code: i32, stderr: &str) -> Output {
+        Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: Vec::default(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(
+            &output(
+                255,
+                "kex_exchange_identification: read: Connection reset by peer\n"
+            ),
+            Retry::Connection
+        ));
+        assert!(is_transient(
+            &output(
+                255,
+                "ssh: connect to host gengar-1 port 22: Connection timed out\n"
+            ),
+            Retry::Connection
+        ));
+        let daemon = output(
+            125,
+            "docker: Cannot connect to the Docker daemon at unix:///var/run/docker.sock. Is the docker daemon running?\n",
+        );
+        assert!(is_transient(&daemon, Retry::Runtime));
+        // a script may have run other commands before the runtime failed
+        assert!(!is_transient(&daemon, Retry::Connection));
+        // a script failing with the same message is not an ssh failure
+        assert!(!is_transient(
+            &output(1, "connection refused\n"),
+            Retry::Connection
+        ));
+        assert!(!is_transient(
+            &output(1, "dial tcp: i/o timeout\n"),
+            Retry::Connection
+        ));
+        // the connection dropped while the script was running, it may have done part of its work
+        assert!(!is_transient(
+            &output(255, "client_loop: send disconnect: Broken pipe\n"),
+            Retry::Connection
+        ));
+        assert!(!is_transient(
+            &output(255, "Connection to gengar-1 closed by remote host.\n"),
+            Retry::Connection
+        ));
+        assert!(!is_transient(
+            &output(
+                255,
+                "debug1: Connection established.\nuser@gengar-1: Permission denied (publickey).\n"
+            ),
+            Retry::Connection
+        ));
+        assert!(!is_transient(
+            &output(0, "Connection reset by peer\n"),
+            Retry::Connection
+        ));
+        assert!(!is_transient(
+            &output(2, "nft: syntax error\n"),
+            Retry::Connection
+        ));
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(2));
+        assert_eq!(backoff(2), Duration::from_secs(4));
+        assert_eq!(backoff(10), Duration::from_secs(32));
+    }
+
+    #[tokio::test]
+    async fn test_with_backoff() {
+        let mut attempts = 0;
+        let result = with_backoff(1, "test", Retry::Connection, || {
+            attempts += 1;
+            async { Ok(output(255, "Connection closed by 10.0.0.1 port 22\n")) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(result.status.code(), Some(255));
+        assert_eq!(attempts, 2);
+
+        let mut attempts = 0;
+        with_backoff(2, "test", Retry::Connection, || {
+            attempts += 1;
+            async { Ok(output(1, "")) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::BTreeMap,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{machine::Machine, manifest::Manifest};
+
+/// name of the file, inside the output directory, where the run state is persisted.
+pub const RUN_STATE_FILE_NAME: &str = "run-state.json";
+
+/// the progress of a run on a single machine. phases are ordered, a machine at a given phase has
+/// completed all the previous ones.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunPhase {
+    #[default]
+    Pending,
+    Created,
+    Started,
+    Exited,
+    LogsSaved,
+    LogsCopied,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContainerStatus {
+    #[default]
+    Pending,
+    Created,
+    Running,
+    Exited,
+}
+
+impl From<RunPhase> for ContainerStatus {
+    fn from(value: RunPhase) -> Self {
+        match value {
+            RunPhase::Pending => Self::Pending,
+            RunPhase::Created => Self::Created,
+            RunPhase::Started => Self::Running,
+            RunPhase::Exited | RunPhase::LogsSaved | RunPhase::LogsCopied => Self::Exited,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunState {
+    /// where the schedule was read from, only used for error messages
+    pub schedule_source: String,
+    /// the schedule's content
+    pub schedule: String,
+    /// signal specs in the `<signal>:<seconds>` format
+    pub signals: Vec<String>,
+    /// signals that were already delivered to all containers
+    pub signals_delivered: Vec<String>,
+    /// whether containers wait at the start barrier
+    #[serde(default)]
+    pub barrier: bool,
+    /// manifest of the run being retried, see `run --retry`
+    #[serde(default)]
+    pub retry: Option<Manifest>,
+    /// unix timestamp, in seconds, of when all containers finished starting.
+    /// signal delays are relative to this timestamp.
+    pub started_at: Option<u64>,
+    /// addresses given to the schedule items that did not specify one, keyed by item index
+    #[serde(default)]
+    pub assigned_addresses: BTreeMap<usize, Ipv4Addr>,
+    pub machines: BTreeMap<Machine, RunPhase>,
+    pub containers: BTreeMap<String, ContainerStatus>,
+    /// exit codes of the containers that exited on their own
+    #[serde(default)]
+    pub exit_codes: BTreeMap<String, i64>,
+}
+
+impl RunState {
+    pub fn new(schedule_source: String, schedule: String, signals: Vec<String>) -> Self {
+        Self {
+            schedule_source,
+            schedule,
+            signals,
+            signals_delivered: Default::default(),
+            barrier: false,
+            retry: None,
+            started_at: None,
+            assigned_addresses: Default::default(),
+            machines: Default::default(),
+            containers: Default::default(),
+            exit_codes: Default::default(),
+        }
+    }
+
+    pub fn machine_phase(&self, machine: Machine) -> RunPhase {
+        self.machines.get(&machine).copied().unwrap_or_default()
+    }
+
+    /// names of the containers that exited with a non-zero code.
+    pub fn failed_containers(&self) -> Vec<String> {
+        self.exit_codes
+            .iter()
+            .filter(|(_, code)| **code != 0)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// the overall phase of the run, which is the phase of the machine that is furthest behind.
+    pub fn phase(&self) -> RunPhase {
+        self.machines.values().copied().min().unwrap_or_default()
+    }
+}
+
+/// a run state that is written to disk every time it is updated.
+#[derive(Debug)]
+pub struct RunStateFile {
+    path: PathBuf,
+    state: Mutex<RunState>,
+}
+
+impl RunStateFile {
+    pub async fn create(path: PathBuf, state: RunState) -> Result<Self> {
+        let file = Self {
+            path,
+            state: Mutex::new(state),
+        };
+        file.save(&*file.state.lock().await).await?;
+        Ok(file)
+    }
+
+    pub async fn load(path: PathBuf) -> Result<Self> {
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("reading run state from {}", path.display()))?;
+        let state = serde_json::from_str(&content)
+            .with_context(|| format!("parsing run state from {}", path.display()))?;
+        Ok(Self {
+            path,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// a copy of the current state.
+    pub async fn snapshot(&self) -> RunState {
+        self.state.lock().await.clone()
+    }
+
+    pub async fn update(&self, f: impl FnOnce(&mut RunState)) -> Result<()> {
+        let mut state = self.state.lock().await;
+        f(&mut state);
+        self.save(&state).await
+    }
+
+    /// advance a machine, and the given containers on that machine, to `phase`.
+    pub async fn set_machine_phase<'a>(
+        &self,
+        machine: Machine,
+        phase: RunPhase,
+        containers: impl IntoIterator<Item = &'a str>,
+    ) -> Result<()> {
+        self.update(|state| {
+            state.machines.insert(machine, phase);
+            for container in containers {
+                state
+                    .containers
+                    .insert(container.to_string(), ContainerStatus::from(phase));
+            }
+        })
+        .await
+    }
+
+    /// the machines, out of `machines`, that have not yet reached `phase`.
+    pub async fn machines_before(&self, machines: &[Machine], phase: RunPhase) -> Vec<Machine> {
+        let state = self.state.lock().await;
+        machines
+            .iter()
+            .copied()
+            .filter(|&m| state.machine_phase(m) < phase)
+            .collect()
+    }
+
+    async fn save(&self, state: &RunState) -> Result<()> {
+        // write to a temporary file and rename so the state file is never left half written
+        let content = serde_json::to_string_pretty(state)?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, content)
+            .await
+            .with_context(|| format!("writing run state to {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, &self.path)
+            .await
+            .with_context(|| format!("renaming run state to {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_ordering() {
+        assert!(RunPhase::Pending < RunPhase::Created);
+        assert!(RunPhase::Created < RunPhase::Started);
+        assert!(RunPhase::Started < RunPhase::Exited);
+        assert!(RunPhase::Exited < RunPhase::LogsSaved);
+        assert!(RunPhase::LogsSaved < RunPhase::LogsCopied);
+    }
+
+    #[test]
+    fn test_overall_phase() {
+        let mut state = RunState::new(String::default(), String::default(), Vec::default());
+        assert_eq!(state.phase(), RunPhase::Pending);
+        state.machines.insert(Machine::Gengar1, RunPhase::Exited);
+        state.machines.insert(Machine::Gengar2, RunPhase::Started);
+        assert_eq!(state.phase(), RunPhase::Started);
+        assert_eq!(state.machine_phase(Machine::Gengar3), RunPhase::Pending);
+    }
+
+    #[test]
+    fn test_failed_containers() {
+        let mut state = RunState::new(String::default(), String::default(), Vec::default());
+        state.exit_codes.insert(String::from("c0"), 0);
+        state.exit_codes.insert(String::from("c1"), 1);
+        state.exit_codes.insert(String::from("c2"), 137);
+        assert_eq!(state.failed_containers(), vec!["c1", "c2"]);
+    }
+
+    #[test]
+    fn test_state_roundtrip() {
+        let mut state = RunState::new(
+            String::from("schedule.json"),
+            String::from("[]"),
+            vec![String::from("start:10")],
+        );
+        state.machines.insert(Machine::Gengar1, RunPhase::LogsSaved);
+        state
+            .containers
+            .insert(String::from("c0"), ContainerStatus::Exited);
+        state
+            .assigned_addresses
+            .insert(3, Ipv4Addr::new(10, 16, 0, 1));
+        let content = serde_json::to_string(&state).unwrap();
+        assert!(content.contains(r#""gengar-1":"logs-saved""#), "{content}");
+        let parsed: RunState = serde_json::from_str(&content).unwrap();
+        assert_eq!(parsed.machine_phase(Machine::Gengar1), RunPhase::LogsSaved);
+        assert_eq!(parsed.containers["c0"], ContainerStatus::Exited);
+        assert_eq!(parsed.assigned_addresses[&3], Ipv4Addr::new(10, 16, 0, 1));
+    }
+}
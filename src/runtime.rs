@@ -0,0 +1,27 @@
+/// the container runtime cli used on the machines.
+///
+/// every generated script invokes the runtime through its [`std::fmt::Display`] implementation,
+/// the supported runtimes accept the same subcommands and flags.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ContainerRuntime {
+    #[default]
+    Docker,
+    /// nerdctl, for machines where containerd is available without docker.
+    Nerdctl,
+}
+
+impl ContainerRuntime {
+    /// the name of the runtime's executable.
+    pub fn command(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Nerdctl => "nerdctl",
+        }
+    }
+}
+
+impl std::fmt::Display for ContainerRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.command())
+    }
+}
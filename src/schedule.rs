@@ -0,0 +1,854 @@
+use std::{collections::HashMap, net::Ipv4Addr, str::FromStr, time::Duration};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::signal::SignalSpec;
+
+#[derive(Debug, Error)]
+pub enum InvalidSchedule {
+    #[error("{source_name}: {message}")]
+    Syntax {
+        source_name: String,
+        message: String,
+    },
+    #[error("{source_name}: item {item}: {path}: {message}")]
+    Item {
+        source_name: String,
+        item: usize,
+        path: String,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleItem {
+    pub name: Option<String>,
+    /// the address of the container, when missing one is assigned by the placement strategy
+    pub address: Option<Ipv4Addr>,
+    pub image: String,
+    /// values can contain the placeholders in [`ENV_PLACEHOLDERS`], i.e. `{{index}}`
+    #[serde(default, deserialize_with = "deserialize_env")]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub volumes: Vec<Volume>,
+    /// local files or directories copied to the machine and mounted read-only in the container
+    #[serde(default)]
+    pub inputs: Vec<Input>,
+    /// arguments passed to the container, replacing the image's default command
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// overrides the image's entrypoint
+    pub entrypoint: Option<String>,
+    /// cpus the container is pinned to, in the format of docker's `--cpuset-cpus`. i.e. `0-3,8`
+    #[serde(default, deserialize_with = "deserialize_cpuset")]
+    pub cpuset: Option<String>,
+    /// memory limit of the container. i.e. `512m`
+    pub memory: Option<MemorySize>,
+    /// maximum number of cpus the container can use, can be fractional
+    #[serde(default, deserialize_with = "deserialize_cpus")]
+    pub cpus: Option<f64>,
+    /// names of the containers that must be running, or healthy if they have a healthcheck,
+    /// before this container is started
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// seconds, after the start of the run, at which the container is started. can be fractional
+    #[serde(default, deserialize_with = "deserialize_start_delay")]
+    pub start_delay: Option<Duration>,
+    /// signals delivered only to this container, in the `<signal>:<seconds>` format
+    #[serde(default)]
+    pub signals: Vec<SignalSpec>,
+    /// linux capabilities added to the container. i.e. `NET_ADMIN` or `NET_RAW`
+    #[serde(default, deserialize_with = "deserialize_capabilities")]
+    pub cap_add: Vec<String>,
+    /// linux capabilities dropped from the container, `ALL` drops every capability
+    #[serde(default, deserialize_with = "deserialize_capabilities")]
+    pub cap_drop: Vec<String>,
+    /// run the container with all capabilities and access to the host devices
+    #[serde(default)]
+    pub privileged: bool,
+    /// options passed to docker's `--security-opt`. i.e. `seccomp=unconfined`
+    #[serde(default)]
+    pub security_opt: Vec<String>,
+    /// in-memory mounts, in the format of docker's `--tmpfs`. i.e. `/data:size=1g`
+    #[serde(default, deserialize_with = "deserialize_tmpfs")]
+    pub tmpfs: Vec<String>,
+    /// size of `/dev/shm`. i.e. `256m`
+    pub shm_size: Option<MemorySize>,
+    /// what docker does when the container exits. i.e. `on-failure:3`
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// absolute paths of files or directories copied out of the container once it exits, can
+    /// contain glob patterns. i.e. `/data/*.csv`
+    #[serde(default, deserialize_with = "deserialize_artifacts")]
+    pub artifacts: Vec<String>,
+}
+
+/// placeholders that can be used in `env` values, expanded once the container has an address.
+pub const ENV_PLACEHOLDERS: &[&str] = &["address", "index", "machine", "name", "total"];
+
+#[derive(Debug, Error)]
+#[error(
+    "unknown placeholder '{{{{{0}}}}}', expected one of: address, index, machine, name or total"
+)]
+pub struct UnknownPlaceholder(String);
+
+/// replace every `{{placeholder}}` in `value` with its value given by `lookup`. whitespace around
+/// the placeholder name is ignored and a `{{` without a matching `}}` is kept as is.
+pub fn expand_env_value(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, UnknownPlaceholder> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut remaining = value;
+    while let Some(start) = remaining.find("{{") {
+        let Some(len) = remaining[start + 2..].find("}}") else {
+            break;
+        };
+        let placeholder = remaining[start + 2..start + 2 + len].trim();
+        let replacement =
+            lookup(placeholder).ok_or_else(|| UnknownPlaceholder(placeholder.to_string()))?;
+        expanded.push_str(&remaining[..start]);
+        expanded.push_str(&replacement);
+        remaining = &remaining[start + 2 + len + 2..];
+    }
+    expanded.push_str(remaining);
+    Ok(expanded)
+}
+
+fn deserialize_env<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error> {
+    let env = HashMap::<String, String>::deserialize(deserializer)?;
+    for value in env.values() {
+        expand_env_value(value, |p| {
+            ENV_PLACEHOLDERS.contains(&p).then(String::default)
+        })
+        .map_err(serde::de::Error::custom)?;
+    }
+    Ok(env)
+}
+
+fn deserialize_start_delay<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    Option::<f64>::deserialize(deserializer)?
+        .map(|secs| {
+            Duration::try_from_secs_f64(secs).map_err(|_| {
+                serde::de::Error::custom(format!(
+                    "invalid start delay '{secs}', expected a non-negative number of seconds"
+                ))
+            })
+        })
+        .transpose()
+}
+
+fn deserialize_cpus<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error> {
+    let value = Option::<f64>::deserialize(deserializer)?;
+    if let Some(cpus) = value
+        && !(cpus.is_finite() && cpus > 0.0)
+    {
+        return Err(serde::de::Error::custom(format!(
+            "invalid cpus '{cpus}', expected a positive number"
+        )));
+    }
+    Ok(value)
+}
+
+fn deserialize_capabilities<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    let value = Vec::<String>::deserialize(deserializer)?;
+    for capability in value.iter() {
+        if capability.is_empty()
+            || !capability
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(serde::de::Error::custom(format!(
+                "invalid capability '{capability}', expected a name like 'NET_ADMIN'"
+            )));
+        }
+    }
+    Ok(value)
+}
+
+fn deserialize_tmpfs<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    let value = Vec::<String>::deserialize(deserializer)?;
+    for mount in value.iter() {
+        let path = mount.split_once(':').map(|(p, _)| p).unwrap_or(mount);
+        if !path.starts_with('/') {
+            return Err(serde::de::Error::custom(format!(
+                "invalid tmpfs mount '{mount}', expected '<container path>[:<options>]' with an absolute path"
+            )));
+        }
+    }
+    Ok(value)
+}
+
+fn deserialize_artifacts<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    let value = Vec::<String>::deserialize(deserializer)?;
+    for artifact in value.iter() {
+        // glob patterns are expanded by the shell so anything else it would interpret is rejected
+        if !artifact.starts_with('/')
+            || artifact.split('/').any(|c| c == "..")
+            || !artifact
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "/._-*?[]+=,@%:~".contains(c))
+        {
+            return Err(serde::de::Error::custom(format!(
+                "invalid artifact '{artifact}', expected an absolute path like '/data/*.csv'"
+            )));
+        }
+    }
+    Ok(value)
+}
+
+fn deserialize_cpuset<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let value = Option::<String>::deserialize(deserializer)?;
+    if let Some(cpuset) = &value
+        && (cpuset.is_empty()
+            || !cpuset
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == ',' || c == '-'))
+    {
+        return Err(serde::de::Error::custom(format!(
+            "invalid cpuset '{cpuset}', expected a list of cpus or cpu ranges like '0-3,8'"
+        )));
+    }
+    Ok(value)
+}
+
+/// a bind mount from the machine into the container.
+///
+/// uses the same format as docker's `-v` flag: `<host path>:<container path>[:ro|rw]`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Volume {
+    pub host: String,
+    pub container: String,
+    pub read_only: bool,
+}
+
+impl std::fmt::Display for Volume {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.container)?;
+        if self.read_only {
+            f.write_str(":ro")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid volume '{0}', expected <host path>:<container path>[:ro|rw] with absolute paths")]
+pub struct InvalidVolume(String);
+
+impl FromStr for Volume {
+    type Err = InvalidVolume;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = || InvalidVolume(s.to_string());
+        let mut components = s.split(':');
+        let host = components.next().ok_or_else(err_fn)?;
+        let container = components.next().ok_or_else(err_fn)?;
+        let read_only = match components.next() {
+            None | Some("rw") => false,
+            Some("ro") => true,
+            Some(_) => return Err(err_fn()),
+        };
+        if components.next().is_some() || !host.starts_with('/') || !container.starts_with('/') {
+            return Err(err_fn());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            container: container.to_string(),
+            read_only,
+        })
+    }
+}
+
+impl TryFrom<String> for Volume {
+    type Error = InvalidVolume;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// a local file or directory that is copied to the machines and mounted in the container.
+///
+/// uses the format `<local path>:<container path>`, the local path can be relative to the
+/// current directory.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Input {
+    pub local: String,
+    pub container: String,
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "invalid input '{0}', expected <local path>:<container path> with an absolute container path"
+)]
+pub struct InvalidInput(String);
+
+impl FromStr for Input {
+    type Err = InvalidInput;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = || InvalidInput(s.to_string());
+        let (local, container) = s.rsplit_once(':').ok_or_else(err_fn)?;
+        if local.is_empty() || !container.starts_with('/') {
+            return Err(err_fn());
+        }
+        Ok(Self {
+            local: local.to_string(),
+            container: container.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for Input {
+    type Error = InvalidInput;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// an amount of memory in bytes.
+///
+/// parsed from a number followed by an optional unit (`b`, `k`, `m` or `g`), the units are powers
+/// of 1024 like in docker's `--memory` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct MemorySize(pub u64);
+
+impl std::fmt::Display for MemorySize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}b", self.0)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid memory size '{0}', expected a number followed by an optional unit (b, k, m, g)")]
+pub struct InvalidMemorySize(String);
+
+impl FromStr for MemorySize {
+    type Err = InvalidMemorySize;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = || InvalidMemorySize(s.to_string());
+        let lower = s.trim().to_ascii_lowercase();
+        let (number, multiplier) = match lower.strip_suffix(|c: char| c.is_ascii_alphabetic()) {
+            Some(number) => {
+                let multiplier: u64 = match lower.chars().last() {
+                    Some('b') => 1,
+                    Some('k') => 1 << 10,
+                    Some('m') => 1 << 20,
+                    Some('g') => 1 << 30,
+                    _ => return Err(err_fn()),
+                };
+                (number, multiplier)
+            }
+            None => (lower.as_str(), 1),
+        };
+        let number = number.parse::<u64>().map_err(|_| err_fn())?;
+        match number.checked_mul(multiplier) {
+            Some(0) | None => Err(err_fn()),
+            Some(bytes) => Ok(Self(bytes)),
+        }
+    }
+}
+
+impl TryFrom<String> for MemorySize {
+    type Error = InvalidMemorySize;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// restart policy of a container.
+///
+/// only policies under which a container eventually stops are supported, otherwise the run would
+/// never finish.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum RestartPolicy {
+    #[default]
+    No,
+    /// restart the container when it exits with a non-zero code, at most this many times if given
+    OnFailure(Option<u32>),
+}
+
+impl std::fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestartPolicy::No => f.write_str("no"),
+            RestartPolicy::OnFailure(None) => f.write_str("on-failure"),
+            RestartPolicy::OnFailure(Some(retries)) => write!(f, "on-failure:{retries}"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid restart policy '{0}', expected 'no', 'on-failure' or 'on-failure:<retries>'")]
+pub struct InvalidRestartPolicy(String);
+
+impl FromStr for RestartPolicy {
+    type Err = InvalidRestartPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = || InvalidRestartPolicy(s.to_string());
+        match s.trim().split_once(':') {
+            None if s.trim() == "no" => Ok(Self::No),
+            None if s.trim() == "on-failure" => Ok(Self::OnFailure(None)),
+            Some(("on-failure", retries)) => Ok(Self::OnFailure(Some(
+                retries.trim().parse().map_err(|_| err_fn())?,
+            ))),
+            _ => Err(err_fn()),
+        }
+    }
+}
+
+impl TryFrom<String> for RestartPolicy {
+    type Error = InvalidRestartPolicy;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// parse a schedule from its json content.
+///
+/// `source_name` is only used to produce error messages, it should be the path of the schedule
+/// file or something like `<stdin>`.
+pub fn parse(source_name: &str, content: &str) -> Result<Vec<ScheduleItem>, InvalidSchedule> {
+    let deserializer = &mut serde_json::Deserializer::from_str(content);
+    serde_path_to_error::deserialize::<_, Vec<ScheduleItem>>(deserializer).map_err(|err| {
+        let mut segments = err.path().iter();
+        let message = err.inner().to_string();
+        match segments.next() {
+            Some(serde_path_to_error::Segment::Seq { index }) => {
+                let mut path = String::default();
+                for segment in segments {
+                    match segment {
+                        serde_path_to_error::Segment::Seq { index } => {
+                            path.push_str(&format!("[{index}]"))
+                        }
+                        serde_path_to_error::Segment::Map { key } => {
+                            if !path.is_empty() {
+                                path.push('.');
+                            }
+                            path.push_str(key);
+                        }
+                        _ => {}
+                    }
+                }
+                InvalidSchedule::Item {
+                    source_name: source_name.to_string(),
+                    item: *index,
+                    path: if path.is_empty() {
+                        String::from("<item>")
+                    } else {
+                        path
+                    },
+                    message,
+                }
+            }
+            _ => InvalidSchedule::Syntax {
+                source_name: source_name.to_string(),
+                message,
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_valid() {
+        let items = parse(
+            "schedule.json",
+            r#"[
+                { "address": "10.16.0.1", "image": "alpine", "env": { "FOO": "bar" } },
+                { "name": "second", "address": "10.17.0.1", "image": "alpine" }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].env["FOO"], "bar");
+        assert_eq!(items[1].name.as_deref(), Some("second"));
+        assert!(items[1].env.is_empty());
+    }
+
+    #[test]
+    fn test_parse_command_and_entrypoint() {
+        let items = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "entrypoint": "/bin/sh", "command": ["-c", "echo hello"] }]"#,
+        )
+        .unwrap();
+        assert_eq!(items[0].entrypoint.as_deref(), Some("/bin/sh"));
+        assert_eq!(items[0].command, vec!["-c", "echo hello"]);
+    }
+
+    #[test]
+    fn test_parse_cpuset() {
+        let items = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "cpuset": "0-3,8" }]"#,
+        )
+        .unwrap();
+        assert_eq!(items[0].cpuset.as_deref(), Some("0-3,8"));
+
+        let err = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "cpuset": "all" }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("item 0: cpuset: invalid cpuset"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_parse_resource_limits() {
+        let items = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "memory": "512m", "cpus": 1.5 }]"#,
+        )
+        .unwrap();
+        assert_eq!(items[0].memory, Some(MemorySize(512 << 20)));
+        assert_eq!(items[0].cpus, Some(1.5));
+
+        let err = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "cpus": 0 }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("item 0: cpus: invalid cpus"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_parse_security_options() {
+        let items = parse(
+            "schedule.json",
+            r#"[{
+                "address": "10.16.0.1",
+                "image": "alpine",
+                "cap_add": ["NET_ADMIN", "NET_RAW"],
+                "cap_drop": ["ALL"],
+                "privileged": true,
+                "security_opt": ["seccomp=unconfined"]
+            }]"#,
+        )
+        .unwrap();
+        assert_eq!(items[0].cap_add, vec!["NET_ADMIN", "NET_RAW"]);
+        assert_eq!(items[0].cap_drop, vec!["ALL"]);
+        assert!(items[0].privileged);
+        assert_eq!(items[0].security_opt, vec!["seccomp=unconfined"]);
+
+        let err = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "cap_add": ["NET ADMIN"] }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("item 0: cap_add: invalid capability"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_parse_tmpfs_and_shm_size() {
+        let items = parse(
+            "schedule.json",
+            r#"[{
+                "address": "10.16.0.1",
+                "image": "alpine",
+                "tmpfs": ["/data:size=1g", "/scratch"],
+                "shm_size": "256m"
+            }]"#,
+        )
+        .unwrap();
+        assert_eq!(items[0].tmpfs, vec!["/data:size=1g", "/scratch"]);
+        assert_eq!(items[0].shm_size, Some(MemorySize(256 << 20)));
+
+        let err = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "tmpfs": ["data"] }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("item 0: tmpfs: invalid tmpfs mount 'data'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_restart_policy_parsing() {
+        assert_eq!("no".parse::<RestartPolicy>().unwrap(), RestartPolicy::No);
+        assert_eq!(
+            "on-failure".parse::<RestartPolicy>().unwrap(),
+            RestartPolicy::OnFailure(None)
+        );
+        assert_eq!(
+            "on-failure:3".parse::<RestartPolicy>().unwrap(),
+            RestartPolicy::OnFailure(Some(3))
+        );
+        assert_eq!(
+            RestartPolicy::OnFailure(Some(3)).to_string(),
+            "on-failure:3"
+        );
+        assert!("always".parse::<RestartPolicy>().is_err());
+        assert!("unless-stopped".parse::<RestartPolicy>().is_err());
+        assert!("on-failure:x".parse::<RestartPolicy>().is_err());
+        assert!("no:3".parse::<RestartPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_parse_artifacts() {
+        let items = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "artifacts": ["/data/metrics.db", "/data/*.csv"] }]"#,
+        )
+        .unwrap();
+        assert_eq!(items[0].artifacts, vec!["/data/metrics.db", "/data/*.csv"]);
+
+        for artifact in ["data/x", "/data/../etc", "/data/$(reboot)", "/data/a b"] {
+            let content = format!(
+                r#"[{{ "address": "10.16.0.1", "image": "alpine", "artifacts": ["{artifact}"] }}]"#
+            );
+            let err = parse("schedule.json", &content).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("item 0: artifacts: invalid artifact"),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_memory_size_parsing() {
+        assert_eq!("1024".parse::<MemorySize>().unwrap(), MemorySize(1024));
+        assert_eq!("1024b".parse::<MemorySize>().unwrap(), MemorySize(1024));
+        assert_eq!("4k".parse::<MemorySize>().unwrap(), MemorySize(4096));
+        assert_eq!("2M".parse::<MemorySize>().unwrap(), MemorySize(2 << 20));
+        assert_eq!("1g".parse::<MemorySize>().unwrap(), MemorySize(1 << 30));
+        assert_eq!(MemorySize(1 << 30).to_string(), "1073741824b");
+        assert!("".parse::<MemorySize>().is_err());
+        assert!("0m".parse::<MemorySize>().is_err());
+        assert!("1t".parse::<MemorySize>().is_err());
+        assert!("1.5g".parse::<MemorySize>().is_err());
+        assert!("g".parse::<MemorySize>().is_err());
+        assert!("-1".parse::<MemorySize>().is_err());
+    }
+
+    #[test]
+    fn test_parse_depends_on() {
+        let items = parse(
+            "schedule.json",
+            r#"[
+                { "name": "seed", "address": "10.16.0.1", "image": "alpine" },
+                { "address": "10.16.0.2", "image": "alpine", "depends_on": ["seed"] }
+            ]"#,
+        )
+        .unwrap();
+        assert!(items[0].depends_on.is_empty());
+        assert_eq!(items[1].depends_on, vec!["seed"]);
+    }
+
+    #[test]
+    fn test_parse_start_delay() {
+        let items = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "start_delay": 1.5 }]"#,
+        )
+        .unwrap();
+        assert_eq!(items[0].start_delay, Some(Duration::from_millis(1500)));
+
+        let err = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "start_delay": -1 }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("item 0: start_delay: invalid start delay"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_parse_signals() {
+        let items = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "signals": ["churn:300", "SIGTERM:600"] }]"#,
+        )
+        .unwrap();
+        assert_eq!(items[0].signals.len(), 2);
+        assert_eq!(items[0].signals[0].to_string(), "churn:300");
+        assert!(items[0].signals[1].signal.is_process_signal());
+
+        let err = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "signals": ["churn"] }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("item 0: signals[0]: invalid signal spec"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_expand_env_value() {
+        let lookup = |p: &str| match p {
+            "index" => Some(String::from("3")),
+            "total" => Some(String::from("10")),
+            _ => None,
+        };
+        assert_eq!(
+            expand_env_value("node-{{index}}/{{ total }}", lookup).unwrap(),
+            "node-3/10"
+        );
+        assert_eq!(expand_env_value("plain", lookup).unwrap(), "plain");
+        assert_eq!(expand_env_value("{{index", lookup).unwrap(), "{{index");
+        assert!(expand_env_value("{{nope}}", lookup).is_err());
+    }
+
+    #[test]
+    fn test_parse_unknown_env_placeholder() {
+        let err = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "env": { "ID": "{{idx}}" } }]"#,
+        )
+        .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("unknown placeholder '{{idx}}'"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_env_value() {
+        let err = parse(
+            "schedule.json",
+            r#"[
+                { "address": "10.16.0.1", "image": "alpine" },
+                { "address": "10.16.0.2", "image": "alpine", "env": { "FOO": 1 } }
+            ]"#,
+        )
+        .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("schedule.json: item 1: env.FOO: invalid type"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_field() {
+        let err = parse(
+            "<stdin>",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "imag": "typo" }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, InvalidSchedule::Item { item: 0, .. }),
+            "{err}"
+        );
+        assert!(err.to_string().contains("unknown field `imag`"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_missing_address() {
+        let items = parse("<stdin>", r#"[{ "image": "alpine" }]"#).unwrap();
+        assert_eq!(items[0].address, None);
+    }
+
+    #[test]
+    fn test_parse_missing_field() {
+        let err = parse("<stdin>", r#"[{ "address": "10.16.0.1" }]"#).unwrap_err();
+        assert!(err.to_string().contains("missing field `image`"), "{err}");
+    }
+
+    #[test]
+    fn test_volume_parsing() {
+        assert_eq!(
+            "/data:/mnt/data".parse::<Volume>().unwrap(),
+            Volume {
+                host: String::from("/data"),
+                container: String::from("/mnt/data"),
+                read_only: false,
+            }
+        );
+        assert!("/data:/mnt/data:ro".parse::<Volume>().unwrap().read_only);
+        assert!(!"/data:/mnt/data:rw".parse::<Volume>().unwrap().read_only);
+        assert_eq!(
+            "/data:/mnt/data:ro".parse::<Volume>().unwrap().to_string(),
+            "/data:/mnt/data:ro"
+        );
+        assert!("/data".parse::<Volume>().is_err());
+        assert!("data:/mnt".parse::<Volume>().is_err());
+        assert!("/data:mnt".parse::<Volume>().is_err());
+        assert!("/data:/mnt:rx".parse::<Volume>().is_err());
+        assert!("/data:/mnt:ro:rw".parse::<Volume>().is_err());
+    }
+
+    #[test]
+    fn test_input_parsing() {
+        assert_eq!(
+            "datasets/a:/data".parse::<Input>().unwrap(),
+            Input {
+                local: String::from("datasets/a"),
+                container: String::from("/data"),
+            }
+        );
+        assert!("datasets/a".parse::<Input>().is_err());
+        assert!(":/data".parse::<Input>().is_err());
+        assert!("datasets/a:data".parse::<Input>().is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid_volume() {
+        let err = parse(
+            "schedule.json",
+            r#"[{ "address": "10.16.0.1", "image": "alpine", "volumes": ["/a:/b", "relative:/b"] }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("schedule.json: item 0: volumes[1]: invalid volume"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_parse_syntax_error() {
+        let err = parse("schedule.json", "not json").unwrap_err();
+        assert!(matches!(err, InvalidSchedule::Syntax { .. }), "{err}");
+    }
+}
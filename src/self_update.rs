@@ -0,0 +1,267 @@
+use std::path::Path;
+
+use eyre::{Context as _, Result};
+use serde::Deserialize;
+use tokio::process::Command;
+
+/// the github api endpoint with the releases of oar-p2p.
+pub const RELEASES_URL: &str = "https://api.github.com/repos/diogo464/oar-p2p/releases";
+
+/// file of every release with the sha256 of its assets, in the format of `sha256sum`.
+pub const CHECKSUMS_ASSET: &str = "checksums-sha256.txt";
+
+/// platform of the frontend and the cluster machines.
+pub const CLUSTER_PLATFORM: &str = "linux-x86_64";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+impl Release {
+    pub fn parse(content: &str) -> Result<Self> {
+        serde_json::from_str(content).context("parsing release")
+    }
+
+    pub fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| eyre::eyre!("release {} has no asset {name}", self.tag_name))
+    }
+}
+
+/// the platform name used in the release assets for the given `std::env::consts` os and arch.
+pub fn platform(os: &str, arch: &str) -> Option<&'static str> {
+    match (os, arch) {
+        ("linux", "x86_64") => Some("linux-x86_64"),
+        ("macos", "x86_64") => Some("macos-x86_64"),
+        ("macos", "aarch64") => Some("macos-aarch64"),
+        _ => None,
+    }
+}
+
+/// name of the tarball with the binary of `platform`.
+pub fn asset_name(tag: &str, platform: &str) -> String {
+    format!("oar-p2p-{tag}-{platform}.tar.gz")
+}
+
+/// the checksum of `asset` in the content of [`CHECKSUMS_ASSET`].
+pub fn checksum_for(checksums: &str, asset: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (checksum, name) = line.split_once(char::is_whitespace)?;
+        // sha256sum marks files read in binary mode with a `*`
+        let name = name.trim().trim_start_matches('*');
+        (name == asset).then(|| checksum.to_lowercase())
+    })
+}
+
+/// whether `version`, as printed by `git describe`, is exactly a release tag instead of a build
+/// from a commit after it.
+pub fn is_release_version(version: &str) -> bool {
+    let Some(numbers) = version.strip_prefix('v') else {
+        return false;
+    };
+    let parts = numbers.split('.').collect::<Vec<_>>();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// the release with `tag`, or the latest one.
+pub async fn fetch_release(releases_url: &str, tag: Option<&str>) -> Result<Release> {
+    let url = match tag {
+        Some(tag) => format!("{}/tags/{tag}", releases_url.trim_end_matches('/')),
+        None => format!("{}/latest", releases_url.trim_end_matches('/')),
+    };
+    let content = download(&url).await?;
+    Release::parse(&String::from_utf8(content)?)
+        .with_context(|| format!("reading release from {url}"))
+}
+
+/// the content of `url`, following redirects.
+pub async fn download(url: &str) -> Result<Vec<u8>> {
+    static CLIENT: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+
+    tracing::debug!("downloading {url}");
+    // the github api rejects requests without a user agent
+    let client = CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .user_agent(concat!("oar-p2p/", env!("GIT_VERSION")))
+            .build()
+            .expect("building http client")
+    });
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("downloading {url} failed"))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(eyre::eyre!("downloading {url} failed with {status}"));
+    }
+    let content = response
+        .bytes()
+        .await
+        .with_context(|| format!("downloading {url} failed"))?;
+    Ok(content.to_vec())
+}
+
+/// the sha256 of `content` as lowercase hex, like `sha256sum` prints it.
+pub fn sha256(content: &[u8]) -> String {
+    use sha2::Digest as _;
+
+    sha2::Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// download the binary of `platform` from `release` into `dir`, verifying the tarball against
+/// the release checksums. returns the path of the extracted binary.
+pub async fn download_binary(
+    release: &Release,
+    platform: &str,
+    dir: &Path,
+) -> Result<std::path::PathBuf> {
+    let name = asset_name(&release.tag_name, platform);
+    let asset = release.asset(&name)?;
+    let checksums = download(&release.asset(CHECKSUMS_ASSET)?.browser_download_url).await?;
+    let expected = checksum_for(&String::from_utf8_lossy(&checksums), &name)
+        .ok_or_else(|| eyre::eyre!("{CHECKSUMS_ASSET} has no checksum for {name}"))?;
+
+    let platform_dir = dir.join(platform);
+    tokio::fs::create_dir_all(&platform_dir).await?;
+    let tarball = platform_dir.join(&name);
+    tracing::info!("downloading {name}");
+    let content = download(&asset.browser_download_url).await?;
+    let actual = sha256(&content);
+    if actual != expected {
+        return Err(eyre::eyre!(
+            "checksum mismatch for {name}: expected {expected}, got {actual}"
+        ));
+    }
+    tokio::fs::write(&tarball, &content)
+        .await
+        .with_context(|| format!("writing {}", tarball.display()))?;
+
+    let output = Command::new("tar")
+        .arg("-xzf")
+        .arg(&tarball)
+        .arg("-C")
+        .arg(&platform_dir)
+        .output()
+        .await
+        .context("spawning tar")?;
+    output
+        .status
+        .exit_ok()
+        .map_err(|_| eyre::eyre!("{}", String::from_utf8_lossy(&output.stderr).trim()))
+        .with_context(|| format!("extracting {name}"))?;
+    let binary = platform_dir.join("oar-p2p");
+    if !tokio::fs::try_exists(&binary).await? {
+        return Err(eyre::eyre!("{name} does not contain an oar-p2p binary"));
+    }
+    Ok(binary)
+}
+
+/// replace the executable at `target` with `binary`. the new binary is copied next to the target
+/// and renamed over it, so the running process keeps its old copy and a failed copy leaves the
+/// target untouched.
+pub async fn replace_executable(binary: &Path, target: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let mut staging = target.as_os_str().to_owned();
+    staging.push(".new");
+    let staging = std::path::PathBuf::from(staging);
+    tokio::fs::copy(binary, &staging)
+        .await
+        .with_context(|| format!("copying the new binary to {}", staging.display()))?;
+    tokio::fs::set_permissions(&staging, std::fs::Permissions::from_mode(0o755)).await?;
+    if let Err(err) = tokio::fs::rename(&staging, target).await {
+        let _ = tokio::fs::remove_file(&staging).await;
+        return Err(err).with_context(|| format!("replacing {}", target.display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release() {
+        let release = Release::parse(
+            r#"{
+                "tag_name": "v0.2.25",
+                "name": "v0.2.25",
+                "assets": [
+                    {"name": "oar-p2p-v0.2.25-linux-x86_64.tar.gz", "size": 1, "browser_download_url": "https://example.com/a"},
+                    {"name": "checksums-sha256.txt", "browser_download_url": "https://example.com/c"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let name = asset_name(&release.tag_name, platform("linux", "x86_64").unwrap());
+        assert_eq!(
+            release.asset(&name).unwrap().browser_download_url,
+            "https://example.com/a"
+        );
+        assert!(
+            release
+                .asset(&asset_name("v0.2.25", "macos-aarch64"))
+                .is_err()
+        );
+        assert_eq!(platform("windows", "x86_64"), None);
+
+        let checksums = "ABC123  oar-p2p-v0.2.25-linux-x86_64.tar.gz\ndef456 *oar-p2p-v0.2.25-macos-x86_64.tar.gz\n";
+        assert_eq!(checksum_for(checksums, &name).as_deref(), Some("abc123"));
+        assert_eq!(
+            checksum_for(checksums, "oar-p2p-v0.2.25-macos-x86_64.tar.gz").as_deref(),
+            Some("def456")
+        );
+        assert_eq!(checksum_for(checksums, "oar-p2p.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_is_release_version() {
+        assert!(is_release_version("v0.2.24"));
+        assert!(!is_release_version("v0.2.24-3-g1a2b3c4"));
+        assert!(!is_release_version("v0.2.24-dirty"));
+        assert!(!is_release_version("1a2b3c4"));
+    }
+
+    #[tokio::test]
+    async fn test_replace_executable() {
+        let dir = std::env::temp_dir().join(format!("oar-p2p-self-update-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let binary = dir.join("new");
+        let target = dir.join("oar-p2p");
+        tokio::fs::write(&binary, "new").await.unwrap();
+        tokio::fs::write(&target, "old").await.unwrap();
+        replace_executable(&binary, &target).await.unwrap();
+        assert_eq!(tokio::fs::read_to_string(&target).await.unwrap(), "new");
+        assert!(
+            !tokio::fs::try_exists(dir.join("oar-p2p.new"))
+                .await
+                .unwrap()
+        );
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -1,5 +1,11 @@
 use std::{str::FromStr, time::Duration};
 
+use crate::{ps::ADDRESS_LABEL, runtime::ContainerRuntime};
+
+/// directory inside a container where it creates a file named after a signal to emit it, see
+/// `signal wait`.
+pub const EMIT_DIR: &str = "/tmp/oar-p2p-emit";
+
 const SIGNAL_MIN_LEN: usize = 1;
 const SIGNAL_MAX_LEN: usize = 64;
 
@@ -16,6 +22,16 @@ impl Signal {
     pub fn as_str(&self) -> &str {
         &self.0
     }
+
+    /// whether this is a process signal, like `SIGTERM` or `SIGUSR1`, that should be delivered to
+    /// the container's main process instead of being exposed as a file.
+    pub fn is_process_signal(&self) -> bool {
+        self.0.strip_prefix("SIG").is_some_and(|s| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -52,12 +68,19 @@ fn is_valid_signal_char(c: char) -> bool {
     c.is_alphanumeric() || c == '_' || c == '-'
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(try_from = "String")]
 pub struct SignalSpec {
     pub signal: Signal,
     pub delay: Duration,
 }
 
+impl std::fmt::Display for SignalSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.signal, self.delay.as_secs())
+    }
+}
+
 #[derive(Debug)]
 pub struct InvalidSignalSpec(String);
 
@@ -85,6 +108,63 @@ impl FromStr for SignalSpec {
     }
 }
 
+impl TryFrom<String> for SignalSpec {
+    type Error = InvalidSignalSpec;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// a signal of `run --signal`, optionally restricted to the containers whose name matches a glob
+/// with the format `<signal>:<seconds>@<glob>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RunSignalSpec {
+    pub spec: SignalSpec,
+    pub containers: Option<String>,
+}
+
+impl std::fmt::Display for RunSignalSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.containers {
+            Some(containers) => write!(f, "{}@{containers}", self.spec),
+            None => self.spec.fmt(f),
+        }
+    }
+}
+
+impl FromStr for RunSignalSpec {
+    type Err = InvalidSignalSpec;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('@') {
+            Some((_, "")) => Err(InvalidSignalSpec(s.to_string())),
+            Some((spec, containers)) => Ok(Self {
+                spec: spec.parse().map_err(|_| InvalidSignalSpec(s.to_string()))?,
+                containers: Some(containers.to_string()),
+            }),
+            None => Ok(Self {
+                spec: s.parse()?,
+                containers: None,
+            }),
+        }
+    }
+}
+
+/// script that prints the name of every running container of the run that emitted `signal`.
+pub fn emitted_script(runtime: ContainerRuntime, signal: &Signal) -> String {
+    let mut script = String::default();
+    script.push_str(&format!(
+        "for c in $({runtime} ps --filter label={ADDRESS_LABEL} --filter status=running --format '{{{{.Names}}}}') ; do\n"
+    ));
+    script.push_str(&format!(
+        "\t{runtime} exec $c test -e {EMIT_DIR}/{signal} 2>/dev/null && echo $c\n"
+    ));
+    script.push_str("done\n");
+    script.push_str("exit 0\n");
+    script
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -142,6 +222,48 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_is_process_signal() {
+        for signal in ["SIGTERM", "SIGUSR1", "SIGRTMIN"] {
+            assert!(
+                signal.parse::<Signal>().unwrap().is_process_signal(),
+                "{signal}"
+            );
+        }
+        for signal in ["start", "SIG", "sigterm", "SIG_TERM", "churn"] {
+            assert!(
+                !signal.parse::<Signal>().unwrap().is_process_signal(),
+                "{signal}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_signal_spec() {
+        let spec = "start:10".parse::<RunSignalSpec>().unwrap();
+        assert_eq!(spec.containers, None);
+        assert_eq!(spec.to_string(), "start:10");
+
+        let spec = "SIGTERM:600@node-*".parse::<RunSignalSpec>().unwrap();
+        assert_eq!(spec.spec.signal.as_str(), "SIGTERM");
+        assert_eq!(spec.spec.delay, Duration::from_secs(600));
+        assert_eq!(spec.containers.as_deref(), Some("node-*"));
+        assert_eq!(spec.to_string(), "SIGTERM:600@node-*");
+
+        assert!("start:10@".parse::<RunSignalSpec>().is_err());
+        assert!("start@node-*".parse::<RunSignalSpec>().is_err());
+    }
+
+    #[test]
+    fn test_emitted_script() {
+        let signal = "bootstrapped".parse::<Signal>().unwrap();
+        let script = emitted_script(ContainerRuntime::Docker, &signal);
+        assert!(
+            script.contains("docker ps --filter label=oar-p2p.address --filter status=running")
+        );
+        assert!(script.contains("docker exec $c test -e /tmp/oar-p2p-emit/bootstrapped"));
+    }
+
     #[test]
     fn test_signal_clone_and_equality() {
         let signal1: Signal = "test-signal".parse().unwrap();
@@ -247,6 +369,13 @@ mod tests {
         assert!(!is_valid_signal_char('!'));
     }
 
+    #[test]
+    fn test_signal_spec_display_roundtrip() {
+        let spec: SignalSpec = "start:10".parse().unwrap();
+        assert_eq!(spec.to_string(), "start:10");
+        assert_eq!(spec.to_string().parse::<SignalSpec>().unwrap(), spec);
+    }
+
     #[test]
     fn test_signal_spec_zero_delay() {
         let spec: SignalSpec = "test:0".parse().unwrap();
@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use eyre::{Context as _, Result};
+use tokio::sync::OnceCell;
+
+use crate::machine::Machine;
+
+/// seconds a master connection stays open after its last command.
+pub const CONTROL_PERSIST: u64 = 30;
+
+/// the ssh master connections of a context, one per machine, so all commands sent to a machine
+/// reuse a single authenticated connection instead of going through the jump host every time.
+///
+/// the control sockets live in a directory private to the process that is removed when the last
+/// clone of the context is dropped, the masters then exit once `CONTROL_PERSIST` elapses.
+#[derive(Debug)]
+pub struct SshControl {
+    dir: PathBuf,
+    /// `ControlPath=<dir>/%C`, `%C` is a hash of the connection so the path stays short
+    control_path_option: String,
+    masters: Mutex<HashMap<Machine, Arc<OnceCell<bool>>>>,
+}
+
+impl SshControl {
+    pub fn new() -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("oar-p2p-ssh-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("creating ssh control directory {}", dir.display()))?;
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))
+                .context("restricting ssh control directory")?;
+        }
+        Ok(Self {
+            control_path_option: format!("ControlPath={}/%C", dir.display()),
+            dir,
+            masters: Mutex::default(),
+        })
+    }
+
+    pub fn control_path_option(&self) -> &str {
+        &self.control_path_option
+    }
+
+    /// run `start` the first time a command is sent to `machine`, every other caller waits for it.
+    /// returns whether the master is up.
+    pub async fn ensure_master<F>(&self, machine: Machine, start: F) -> bool
+    where
+        F: std::future::Future<Output = bool>,
+    {
+        let cell = self
+            .masters
+            .lock()
+            .unwrap()
+            .entry(machine)
+            .or_default()
+            .clone();
+        *cell.get_or_init(|| start).await
+    }
+}
+
+impl Drop for SshControl {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_master_started_once() {
+        let control = SshControl::new().unwrap();
+        assert!(control.control_path_option().ends_with("/%C"));
+        let started = std::sync::atomic::AtomicUsize::new(0);
+        let start = || async {
+            started.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            true
+        };
+        let (a, b) = tokio::join!(
+            control.ensure_master(Machine::Gengar1, start()),
+            control.ensure_master(Machine::Gengar1, start()),
+        );
+        assert!(a && b);
+        assert!(control.ensure_master(Machine::Gengar2, start()).await);
+        assert_eq!(started.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let dir = control.dir.clone();
+        assert!(dir.exists());
+        drop(control);
+        assert!(!dir.exists());
+    }
+}
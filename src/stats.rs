@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use crate::{machine::Machine, runtime::ContainerRuntime};
+
+/// header of the per-container csv files.
+pub const CSV_HEADER: &str = "timestamp,cpu_percent,memory_bytes,net_rx_bytes,net_tx_bytes";
+
+/// a single resource usage sample of a container.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerStats {
+    pub name: String,
+    pub cpu_percent: f64,
+    pub memory_bytes: u64,
+    pub net_rx_bytes: u64,
+    pub net_tx_bytes: u64,
+}
+
+impl ContainerStats {
+    /// the sample as a csv row, matching [`CSV_HEADER`].
+    pub fn csv_row(&self, timestamp: u64) -> String {
+        format!(
+            "{timestamp},{},{},{},{}",
+            self.cpu_percent, self.memory_bytes, self.net_rx_bytes, self.net_tx_bytes
+        )
+    }
+}
+
+/// script that prints one line per running container, parsed by [`parse_stats_output`].
+pub fn stats_script(runtime: ContainerRuntime) -> String {
+    format!(
+        "{runtime} stats --no-stream --format '{{{{.Name}}}}|{{{{.CPUPerc}}}}|{{{{.MemUsage}}}}|{{{{.NetIO}}}}'\n"
+    )
+}
+
+/// parse the output of [`stats_script`]. lines that cannot be parsed are ignored.
+pub fn parse_stats_output(output: &str) -> Vec<ContainerStats> {
+    output.lines().filter_map(parse_stats_line).collect()
+}
+
+fn parse_stats_line(line: &str) -> Option<ContainerStats> {
+    let mut fields = line.trim().split('|');
+    let name = fields.next()?;
+    let cpu_percent = fields.next()?.trim().strip_suffix('%')?.parse().ok()?;
+    let (memory, _) = fields.next()?.split_once('/')?;
+    let (rx, tx) = fields.next()?.split_once('/')?;
+    Some(ContainerStats {
+        name: name.to_string(),
+        cpu_percent,
+        memory_bytes: parse_size(memory)?,
+        net_rx_bytes: parse_size(rx)?,
+        net_tx_bytes: parse_size(tx)?,
+    })
+}
+
+/// parse a size as printed by docker, ex: `12.5MiB` or `3.4kB`.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let unit_start = size
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(unit_start);
+    let multiplier: f64 = match unit {
+        "" | "B" => 1.0,
+        "kB" | "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    let number = number.parse::<f64>().ok()?;
+    Some((number * multiplier).round() as u64)
+}
+
+/// name of the container that samples the host metrics on each machine.
+pub const HOST_METRICS_CONTAINER_NAME: &str = "oar-p2p-host-metrics";
+
+/// header of the per-machine host metrics csv files.
+///
+/// `net_*` are the counters of the machine's experiment interface and `lo_*` the counters of the
+/// loopback interface, used by containers on the same machine.
+pub const HOST_METRICS_CSV_HEADER: &str = "timestamp,cpu_percent,memory_used_bytes,memory_total_bytes,net_rx_bytes,net_tx_bytes,lo_rx_bytes,lo_tx_bytes";
+
+/// script that starts the host metrics sampler on `machine`, unless it is already running.
+///
+/// the sampler runs in the networking container, `image`, and appends one row every `interval`
+/// to `/tmp/oar-p2p-logs/host/<machine>.csv` so it is copied together with the container logs.
+/// the cpu usage of the first row is left empty since it needs a previous sample.
+pub fn host_metrics_start_script(
+    runtime: ContainerRuntime,
+    image: &str,
+    machine: Machine,
+    interval: Duration,
+) -> String {
+    let interface = machine.interface();
+    let sampler = format!(
+        r#"f=/out/{machine}.csv
+[ -s "$f" ] || echo '{HOST_METRICS_CSV_HEADER}' > "$f"
+prev_total=0
+prev_idle=0
+while true ; do
+	read -r _ user nice system idle iowait irq softirq steal _ < /proc/stat
+	total=$((user + nice + system + idle + iowait + irq + softirq + steal))
+	idle=$((idle + iowait))
+	cpu=$(awk -v p=$prev_total -v t=$((total - prev_total)) -v i=$((idle - prev_idle)) 'BEGIN {{ if (p > 0 && t > 0) printf "%.2f", 100 * (t - i) / t }}')
+	prev_total=$total
+	prev_idle=$idle
+	mem=$(awk '/^MemTotal:/ {{ t = $2 }} /^MemAvailable:/ {{ a = $2 }} END {{ printf "%.0f,%.0f", (t - a) * 1024, t * 1024 }}' /proc/meminfo)
+	net=$(sed 's/:/ /' /proc/net/dev | awk '$1 == "{interface}" {{ printf "%s,%s", $2, $10 }}')
+	lo=$(sed 's/:/ /' /proc/net/dev | awk '$1 == "lo" {{ printf "%s,%s", $2, $10 }}')
+	echo "$(date +%s),$cpu,$mem,$net,$lo" >> "$f"
+	sleep {interval}
+done
+"#,
+        interval = interval.as_secs_f64(),
+    );
+    let mut script = String::default();
+    script.push_str("mkdir -p /tmp/oar-p2p-logs/host\n");
+    script.push_str(&format!(
+        "{runtime} container inspect {HOST_METRICS_CONTAINER_NAME} >/dev/null 2>&1 && exit 0\n"
+    ));
+    // samples from a previous run would otherwise be appended to
+    script.push_str(&format!("rm -f /tmp/oar-p2p-logs/host/{machine}.csv\n"));
+    script.push_str(&format!(
+        "{runtime} run -d --name {HOST_METRICS_CONTAINER_NAME} --net=host --privileged -v /tmp/oar-p2p-logs/host:/out {image} bash -c {} >/dev/null\n",
+        crate::shell::quote(&sampler)
+    ));
+    script
+}
+
+/// script that stops the host metrics sampler, if it is running.
+pub fn host_metrics_stop_script(runtime: ContainerRuntime) -> String {
+    format!("{runtime} rm -f {HOST_METRICS_CONTAINER_NAME} >/dev/null 2>&1 || true\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0B"), Some(0));
+        assert_eq!(parse_size("1.5kB"), Some(1500));
+        assert_eq!(parse_size("2MiB"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_size(" 1GB "), Some(1_000_000_000));
+        assert_eq!(parse_size("1XB"), None);
+        assert_eq!(parse_size("abc"), None);
+    }
+
+    #[test]
+    fn test_parse_stats_output() {
+        let output = "\
+c0|12.34%|12.5MiB / 15.5GiB|1.2kB / 3.4MB
+c1|0.00%|0B / 0B|0B / 0B
+garbage
+";
+        let stats = parse_stats_output(output);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats[0],
+            ContainerStats {
+                name: String::from("c0"),
+                cpu_percent: 12.34,
+                memory_bytes: 13107200,
+                net_rx_bytes: 1200,
+                net_tx_bytes: 3400000,
+            }
+        );
+        assert_eq!(stats[0].csv_row(100), "100,12.34,13107200,1200,3400000");
+        assert_eq!(stats[1].memory_bytes, 0);
+    }
+
+    #[test]
+    fn test_host_metrics_start_script() {
+        let script = host_metrics_start_script(
+            ContainerRuntime::Docker,
+            "local/oar-p2p-networking",
+            Machine::Gengar1,
+            Duration::from_secs(5),
+        );
+        assert!(script.contains(&format!(
+            "docker container inspect {HOST_METRICS_CONTAINER_NAME}"
+        )));
+        assert!(script.contains(&format!("f=/out/{}.csv", Machine::Gengar1)));
+        assert!(script.contains(&format!("$1 == \"{}\"", Machine::Gengar1.interface())));
+        assert!(script.contains("sleep 5\n"));
+    }
+}
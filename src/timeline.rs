@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{machine::Machine, runtime::ContainerRuntime, shell};
+
+/// name of the file, in the output directory, with the lifecycle events of a run.
+pub const TIMELINE_FILE_NAME: &str = "timeline.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimelineEventKind {
+    Created,
+    Started,
+    Exited,
+    /// the container was stopped by `run --churn`
+    ChurnStop,
+    /// the container was restarted by `run --churn`
+    ChurnStart,
+    /// a signal was delivered to the container, or to every container without one
+    Signal,
+}
+
+/// a line of the timeline file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    /// unix timestamp, in milliseconds
+    pub timestamp: u64,
+    pub event: TimelineEventKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine: Option<Machine>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+}
+
+impl TimelineEvent {
+    pub fn new(timestamp: u64, event: TimelineEventKind) -> Self {
+        Self {
+            timestamp,
+            event,
+            container: None,
+            machine: None,
+            exit_code: None,
+            signal: None,
+        }
+    }
+}
+
+/// script that prints a `<name>|<created>|<started>|<finished>|<exit code>|<status>` line per
+/// container, the times as rfc 3339 timestamps.
+pub fn inspect_script(runtime: ContainerRuntime, names: &[&str]) -> String {
+    let names = names
+        .iter()
+        .map(|n| shell::quote(n))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "{runtime} inspect -f '{{{{.Name}}}}|{{{{.Created}}}}|{{{{.State.StartedAt}}}}|{{{{.State.FinishedAt}}}}|{{{{.State.ExitCode}}}}|{{{{.State.Status}}}}' {names} || true\n"
+    )
+}
+
+/// the created, started and exited events of the containers in the output of [`inspect_script`].
+///
+/// the runtime only keeps the last start and exit of a container, earlier ones of containers
+/// restarted by the churn are only in the churn events.
+pub fn parse_inspect_output(output: &str, machine: Machine) -> Vec<TimelineEvent> {
+    let mut events = Vec::default();
+    for line in output.lines() {
+        let parts = line.trim().split('|').collect::<Vec<_>>();
+        let [name, created, started, finished, exit_code, status] = parts[..] else {
+            continue;
+        };
+        let container = name.trim_start_matches('/').to_string();
+        let event = |timestamp, kind| TimelineEvent {
+            container: Some(container.clone()),
+            machine: Some(machine),
+            ..TimelineEvent::new(timestamp, kind)
+        };
+        if let Some(timestamp) = parse_rfc3339_millis(created) {
+            events.push(event(timestamp, TimelineEventKind::Created));
+        }
+        if let Some(timestamp) = parse_rfc3339_millis(started) {
+            events.push(event(timestamp, TimelineEventKind::Started));
+        }
+        if status == "exited"
+            && let Some(timestamp) = parse_rfc3339_millis(finished)
+        {
+            events.push(TimelineEvent {
+                exit_code: exit_code.parse().ok(),
+                ..event(timestamp, TimelineEventKind::Exited)
+            });
+        }
+    }
+    events
+}
+
+/// unix timestamp in milliseconds of an rfc 3339 timestamp like `2024-05-01T10:20:30.123456789Z`.
+///
+/// the runtimes report zero times, `0001-01-01T00:00:00Z`, for events that never happened, those
+/// and anything before the unix epoch are `None`.
+pub fn parse_rfc3339_millis(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (date, time) = s.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+    // the offset is either `Z` or `+hh:mm`/`-hh:mm`
+    let (time, offset_seconds) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else {
+        let idx = time.rfind(['+', '-'])?;
+        let (time, offset) = time.split_at(idx);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        (
+            time,
+            sign * (hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60),
+        )
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    let millis = match fraction {
+        "" => 0,
+        fraction => {
+            let digits = fraction.get(..3.min(fraction.len()))?;
+            digits.parse::<i64>().ok()? * 10i64.pow(3 - digits.len() as u32)
+        }
+    };
+
+    // days since the epoch of a proleptic gregorian date, from howard hinnant's `days_from_civil`
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second - offset_seconds;
+    u64::try_from(seconds * 1000 + millis).ok()
+}
+
+/// merge `events` with the lines of an existing timeline, ordered by time.
+pub fn merge(existing: &str, events: Vec<TimelineEvent>) -> Result<String, serde_json::Error> {
+    let mut all = existing
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(serde_json::from_str::<TimelineEvent>)
+        .collect::<Result<Vec<_>, _>>()?;
+    all.extend(events);
+    all.sort_by_key(|e| e.timestamp);
+    all.dedup();
+    let mut content = String::default();
+    for event in all {
+        content.push_str(&serde_json::to_string(&event)?);
+        content.push('\n');
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rfc3339() {
+        assert_eq!(parse_rfc3339_millis("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_rfc3339_millis("2024-05-01T10:20:30.123456789Z"),
+            Some(1_714_558_830_123)
+        );
+        assert_eq!(
+            parse_rfc3339_millis("2024-05-01T11:20:30.5+01:00"),
+            Some(1_714_558_830_500)
+        );
+        assert_eq!(parse_rfc3339_millis("0001-01-01T00:00:00Z"), None);
+        assert_eq!(parse_rfc3339_millis("yesterday"), None);
+    }
+
+    #[test]
+    fn test_parse_inspect_output() {
+        let output = "\
+/c0|2024-05-01T10:20:00Z|2024-05-01T10:20:01Z|2024-05-01T10:21:00Z|3|exited
+/c1|2024-05-01T10:20:00Z|2024-05-01T10:20:01Z|0001-01-01T00:00:00Z|0|running
+garbage
+";
+        let events = parse_inspect_output(output, Machine::Gengar1);
+        let kinds = events
+            .iter()
+            .map(|e| (e.container.as_deref().unwrap(), e.event))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                ("c0", TimelineEventKind::Created),
+                ("c0", TimelineEventKind::Started),
+                ("c0", TimelineEventKind::Exited),
+                ("c1", TimelineEventKind::Created),
+                ("c1", TimelineEventKind::Started),
+            ]
+        );
+        assert_eq!(events[2].exit_code, Some(3));
+        assert_eq!(events[2].timestamp, 1_714_558_860_000);
+    }
+
+    #[test]
+    fn test_merge() {
+        let signal = TimelineEvent {
+            signal: Some(String::from("phase-2")),
+            ..TimelineEvent::new(2000, TimelineEventKind::Signal)
+        };
+        let existing = format!("{}\n", serde_json::to_string(&signal).unwrap());
+        assert_eq!(
+            existing,
+            "{\"timestamp\":2000,\"event\":\"signal\",\"signal\":\"phase-2\"}\n"
+        );
+        let created = TimelineEvent {
+            container: Some(String::from("c0")),
+            ..TimelineEvent::new(1000, TimelineEventKind::Created)
+        };
+        let merged = merge(&existing, vec![created.clone(), signal]).unwrap();
+        let lines = merged.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], serde_json::to_string(&created).unwrap());
+    }
+}
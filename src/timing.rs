@@ -0,0 +1,95 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use serde::Serialize;
+
+use crate::machine::Machine;
+
+/// name of the file, in the output directory, with the timing of every phase of a run.
+pub const TIMINGS_FILE_NAME: &str = "timings.json";
+
+static TIMINGS: Mutex<Vec<PhaseTiming>> = Mutex::new(Vec::new());
+
+/// how long a phase that runs on many machines took, recorded by [`crate::progress::Progress`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PhaseTiming {
+    pub phase: String,
+    /// seconds
+    pub duration: f64,
+    /// seconds each machine took, machines that failed or were cancelled are missing
+    pub machines: BTreeMap<Machine, f64>,
+}
+
+pub fn record(timing: PhaseTiming) {
+    TIMINGS.lock().unwrap().push(timing);
+}
+
+/// every phase recorded so far by this invocation, in the order they finished.
+pub fn snapshot() -> Vec<PhaseTiming> {
+    TIMINGS.lock().unwrap().clone()
+}
+
+/// table with the duration of every phase and the spread of its machines.
+pub fn summary(timings: &[PhaseTiming]) -> String {
+    let width = timings
+        .iter()
+        .map(|t| t.phase.len())
+        .chain(std::iter::once("PHASE".len()))
+        .max()
+        .unwrap_or_default();
+    let mut summary = format!(
+        "{:<width$} {:>8} {:>8} {:>8} {:>8} SLOWEST\n",
+        "PHASE", "TOTAL", "MIN", "MEDIAN", "MAX"
+    );
+    for timing in timings {
+        let mut durations = timing.machines.values().copied().collect::<Vec<_>>();
+        durations.sort_by(f64::total_cmp);
+        let seconds = |d: Option<&f64>| d.map_or(String::from("-"), |d| format!("{d:.1}s"));
+        let slowest = timing
+            .machines
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map_or("-", |(machine, _)| machine.hostname());
+        summary.push_str(&format!(
+            "{:<width$} {:>8} {:>8} {:>8} {:>8} {slowest}\n",
+            timing.phase,
+            seconds(Some(&timing.duration)),
+            seconds(durations.first()),
+            seconds(durations.get(durations.len() / 2)),
+            seconds(durations.last()),
+        ));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let timings = [
+            PhaseTiming {
+                phase: String::from("configuring machines"),
+                duration: 12.34,
+                machines: BTreeMap::from([
+                    (Machine::Gengar1, 8.0),
+                    (Machine::Gengar2, 12.2),
+                    (Machine::Gengar3, 9.0),
+                ]),
+            },
+            PhaseTiming {
+                phase: String::from("saving logs"),
+                duration: 0.5,
+                machines: BTreeMap::default(),
+            },
+        ];
+        assert_eq!(
+            summary(&timings),
+            "PHASE                   TOTAL      MIN   MEDIAN      MAX SLOWEST\n\
+             configuring machines    12.3s     8.0s     9.0s    12.2s gengar-2\n\
+             saving logs              0.5s        -        -        - -\n"
+        );
+        let json = serde_json::to_value(&timings[0]).unwrap();
+        assert_eq!(json["machines"]["gengar-3"], 9.0);
+    }
+}
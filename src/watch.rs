@@ -0,0 +1,106 @@
+use serde::Serialize;
+
+use crate::{machine::Machine, ps::ContainerStatus};
+
+/// the state of a run at one poll of `run watch`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WatchSummary {
+    /// unix timestamp, in seconds
+    pub timestamp: u64,
+    pub running: usize,
+    /// containers created but not started yet, paused or restarting
+    pub waiting: usize,
+    /// containers that exited with code 0
+    pub succeeded: usize,
+    /// containers that exited with a non-zero code
+    pub failed: Vec<String>,
+    /// machines that could not be polled
+    pub unreachable: Vec<Machine>,
+}
+
+impl WatchSummary {
+    pub fn new(timestamp: u64, containers: &[ContainerStatus], unreachable: Vec<Machine>) -> Self {
+        let mut summary = Self {
+            timestamp,
+            running: 0,
+            waiting: 0,
+            succeeded: 0,
+            failed: Vec::default(),
+            unreachable,
+        };
+        for container in containers {
+            match (container.state.as_str(), container.exit_code) {
+                ("running", _) => summary.running += 1,
+                ("exited", Some(0)) => summary.succeeded += 1,
+                ("exited", _) => summary.failed.push(container.name.clone()),
+                _ => summary.waiting += 1,
+            }
+        }
+        summary
+    }
+
+    /// number of failed containers and unreachable machines, compared against the threshold.
+    pub fn failures(&self) -> usize {
+        self.failed.len() + self.unreachable.len()
+    }
+
+    /// every container exited and every machine answered, there is nothing left to watch.
+    pub fn is_finished(&self) -> bool {
+        self.running == 0 && self.waiting == 0 && self.unreachable.is_empty()
+    }
+
+    pub fn line(&self) -> String {
+        let mut line = format!(
+            "{} running, {} waiting, {} succeeded, {} failed",
+            self.running,
+            self.waiting,
+            self.succeeded,
+            self.failed.len()
+        );
+        if !self.unreachable.is_empty() {
+            let machines = self
+                .unreachable
+                .iter()
+                .map(Machine::hostname)
+                .collect::<Vec<_>>();
+            line.push_str(&format!(", unreachable: {}", machines.join(", ")));
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(name: &str, state: &str, exit_code: Option<i64>) -> ContainerStatus {
+        ContainerStatus {
+            name: name.to_string(),
+            machine: Machine::Gengar1,
+            address: None,
+            state: state.to_string(),
+            exit_code,
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let containers = [
+            status("c0", "running", None),
+            status("c1", "exited", Some(0)),
+            status("c2", "exited", Some(137)),
+            status("c3", "created", None),
+        ];
+        let summary = WatchSummary::new(10, &containers, vec![Machine::Gengar2]);
+        assert_eq!(summary.failed, ["c2"]);
+        assert_eq!(summary.failures(), 2);
+        assert!(!summary.is_finished());
+        assert_eq!(
+            summary.line(),
+            "1 running, 1 waiting, 1 succeeded, 1 failed, unreachable: gengar-2"
+        );
+
+        let summary = WatchSummary::new(10, &containers[1..3], Vec::default());
+        assert!(summary.is_finished());
+    }
+}